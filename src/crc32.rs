@@ -0,0 +1,268 @@
+use crc::{Crc, Table, CRC_32_ISO_HDLC};
+
+// Slicing-by-16 tables for the header and data CRCs, built at compile time. They do all
+// the work unless the crc-simd feature finds the processor can do it faster, and even
+// then take the bytes left over at the end of a buffer
+static CRC32: Crc<u32, Table<16>> = Crc::<u32, Table<16>>::new(&CRC_32_ISO_HDLC);
+
+// Carry on crc, the CRC-32 of what came before, over data
+pub(crate) fn update(crc: u32, data: &[u8]) -> u32 {
+    #[cfg(all(feature = "crc-simd", target_arch = "x86_64"))]
+    if data.len() >= pclmul::MIN_LEN && pclmul::available() {
+        // SAFETY: the instructions it is compiled for are there
+        let hardware = unsafe { pclmul::update(crc, data) };
+        #[cfg(debug_assertions)]
+        assert_eq!(hardware, update_table(crc, data), "pclmulqdq CRC of {} bytes", data.len());
+        return hardware;
+    }
+    #[cfg(all(feature = "crc-simd", target_arch = "aarch64"))]
+    if data.len() >= 8 && std::arch::is_aarch64_feature_detected!("crc") {
+        // SAFETY: the CRC extension is there
+        let hardware = unsafe { arm::update(crc, data) };
+        #[cfg(debug_assertions)]
+        assert_eq!(hardware, update_table(crc, data), "crc32x CRC of {} bytes", data.len());
+        return hardware;
+    }
+    update_table(crc, data)
+}
+
+// The CRC-32 of two pieces of data one after the other, from crc_a of the first and crc_b
+// and len_b of the second, as zlib's crc32_combine works it out: crc_a is carried on over
+// len_b zero bytes by a 32x32 matrix over GF(2), squared for each bit of len_b, and crc_b
+// is added in
+pub(crate) fn combine(mut crc_a: u32, crc_b: u32, mut len_b: u64) -> u32 {
+    if len_b == 0 {
+        return crc_a;
+    }
+    // The operator for one zero bit, then those for two and four
+    let mut odd = [0u32; 32];
+    odd[0] = 0xedb8_8320; // The polynomial, reflected
+    for (n, row) in odd.iter_mut().enumerate().skip(1) {
+        *row = 1 << (n - 1);
+    }
+    let mut even = [0u32; 32];
+    gf2_matrix_square(&mut even, &odd);
+    gf2_matrix_square(&mut odd, &even);
+
+    // From one zero byte on, each square is the operator for twice as many
+    loop {
+        gf2_matrix_square(&mut even, &odd);
+        if len_b & 1 != 0 {
+            crc_a = gf2_matrix_times(&even, crc_a);
+        }
+        len_b >>= 1;
+        if len_b == 0 {
+            break;
+        }
+        gf2_matrix_square(&mut odd, &even);
+        if len_b & 1 != 0 {
+            crc_a = gf2_matrix_times(&odd, crc_a);
+        }
+        len_b >>= 1;
+        if len_b == 0 {
+            break;
+        }
+    }
+    crc_a ^ crc_b
+}
+
+fn gf2_matrix_times(matrix: &[u32; 32], mut vector: u32) -> u32 {
+    let mut sum = 0;
+    let mut row = 0;
+    while vector != 0 {
+        if vector & 1 != 0 {
+            sum ^= matrix[row];
+        }
+        vector >>= 1;
+        row += 1;
+    }
+    sum
+}
+
+fn gf2_matrix_square(square: &mut [u32; 32], matrix: &[u32; 32]) {
+    for (row, &column) in square.iter_mut().zip(matrix) {
+        *row = gf2_matrix_times(matrix, column);
+    }
+}
+
+// The table code, picked up from crc. The digest holds the CRC register, which is the
+// CRC inverted, and reflected once more as it is taken in
+fn update_table(crc: u32, data: &[u8]) -> u32 {
+    let mut digest = CRC32.digest_with_initial((!crc).reverse_bits());
+    digest.update(data);
+    digest.finalize()
+}
+
+// x86-64: the carry-less multiply folding of Intel's "Fast CRC Computation for Generic
+// Polynomials Using PCLMULQDQ Instruction", for the bit-reflected gzip polynomial. (The
+// crc32 instruction of SSE 4.2 is no use here: it computes the Castagnoli CRC.) Four
+// 128-bit lanes are folded 64 bytes at a time, then folded into one, reduced to 64 bits
+// and by a Barrett reduction to the 32-bit CRC
+#[cfg(all(feature = "crc-simd", target_arch = "x86_64"))]
+mod pclmul {
+    use std::arch::x86_64::*;
+
+    // The four lanes take this much to start
+    pub(super) const MIN_LEN: usize = 64;
+
+    // x^(32*n) mod P(x) for the folding distances, reflected and shifted left by one
+    const K1: i64 = 0x1_5444_2bd4; // 4*128+32
+    const K2: i64 = 0x1_c6e4_1596; // 4*128-32
+    const K3: i64 = 0x1_7519_97d0; // 128+32
+    const K4: i64 = 0x0_ccaa_009e; // 128-32
+    const K5: i64 = 0x1_63cd_6124; // 64
+    const P_X: i64 = 0x1_db71_0641; // P(x), reflected
+    const U_PRIME: i64 = 0x1_f701_1641; // x^64 / P(x), reflected
+
+    pub(super) fn available() -> bool {
+        is_x86_feature_detected!("pclmulqdq") && is_x86_feature_detected!("sse4.1")
+    }
+
+    #[target_feature(enable = "pclmulqdq", enable = "sse2", enable = "sse4.1")]
+    pub(super) unsafe fn update(crc: u32, mut data: &[u8]) -> u32 {
+        let mut x3 = load(&mut data);
+        let mut x2 = load(&mut data);
+        let mut x1 = load(&mut data);
+        let mut x0 = load(&mut data);
+        x3 = _mm_xor_si128(x3, _mm_cvtsi32_si128(!crc as i32));
+
+        let k1k2 = _mm_set_epi64x(K2, K1);
+        while data.len() >= 64 {
+            x3 = fold(x3, load(&mut data), k1k2);
+            x2 = fold(x2, load(&mut data), k1k2);
+            x1 = fold(x1, load(&mut data), k1k2);
+            x0 = fold(x0, load(&mut data), k1k2);
+        }
+
+        let k3k4 = _mm_set_epi64x(K4, K3);
+        let mut x = fold(x3, x2, k3k4);
+        x = fold(x, x1, k3k4);
+        x = fold(x, x0, k3k4);
+        while data.len() >= 16 {
+            x = fold(x, load(&mut data), k3k4);
+        }
+
+        // 128 bits to 96, then to 64
+        let low32 = _mm_set_epi32(0, 0, 0, !0);
+        let x = _mm_xor_si128(_mm_clmulepi64_si128(x, k3k4, 0x10), _mm_srli_si128(x, 8));
+        let x = _mm_xor_si128(
+            _mm_clmulepi64_si128(_mm_and_si128(x, low32), _mm_set_epi64x(0, K5), 0x00),
+            _mm_srli_si128(x, 4),
+        );
+
+        // Barrett reduction: T1 = (R mod x^32) * u, T2 = (T1 mod x^32) * P, and the CRC is
+        // the upper half of R ^ T2 as the bits are reflected
+        let pu = _mm_set_epi64x(U_PRIME, P_X);
+        let t1 = _mm_clmulepi64_si128(_mm_and_si128(x, low32), pu, 0x10);
+        let t2 = _mm_clmulepi64_si128(_mm_and_si128(t1, low32), pu, 0x00);
+        let crc = !(_mm_extract_epi32(_mm_xor_si128(x, t2), 1) as u32);
+
+        super::update_table(crc, data)
+    }
+
+    // a carried 128 bits on past b
+    #[target_feature(enable = "pclmulqdq", enable = "sse2")]
+    unsafe fn fold(a: __m128i, b: __m128i, keys: __m128i) -> __m128i {
+        let low = _mm_clmulepi64_si128(a, keys, 0x00);
+        let high = _mm_clmulepi64_si128(a, keys, 0x11);
+        _mm_xor_si128(_mm_xor_si128(b, low), high)
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn load(data: &mut &[u8]) -> __m128i {
+        let (block, rest) = data.split_at(16);
+        *data = rest;
+        _mm_loadu_si128(block.as_ptr() as *const __m128i)
+    }
+}
+
+// aarch64: the CRC extension's crc32x and friends compute this very CRC, eight bytes an
+// instruction
+#[cfg(all(feature = "crc-simd", target_arch = "aarch64"))]
+mod arm {
+    use std::arch::aarch64::__crc32d;
+
+    #[target_feature(enable = "crc")]
+    pub(super) unsafe fn update(crc: u32, data: &[u8]) -> u32 {
+        let mut register = !crc;
+        let mut words = data.chunks_exact(8);
+        for word in &mut words {
+            register = __crc32d(register, u64::from_le_bytes(word.try_into().unwrap()));
+        }
+        super::update_table(!register, words.remainder())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Random bytes, the same on every run
+    fn noise(len: usize) -> Vec<u8> {
+        let mut seed = 0x2545_f491u32;
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn check_values() {
+        assert_eq!(update(0, b""), 0);
+        assert_eq!(update(0, b"123456789"), 0xcbf4_3926);
+        // Carrying on from one piece to the next is the same as the whole at once
+        assert_eq!(update(update(0, b"1234"), b"56789"), 0xcbf4_3926);
+    }
+
+    // A buffer long enough for every path through update, against crc's own CRC-32
+    #[test]
+    fn matches_the_crc_crate() {
+        let data = noise(1 << 20);
+        let reference = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+        assert_eq!(update(0, &data), reference.checksum(&data));
+        for len in [1, 15, 16, 63, 64, 65, 1000, 4097] {
+            assert_eq!(update(0, &data[..len]), reference.checksum(&data[..len]), "{} bytes", len);
+        }
+        let mut crc = 0;
+        for piece in data.chunks(777) {
+            crc = update(crc, piece);
+        }
+        assert_eq!(crc, reference.checksum(&data));
+    }
+
+    #[test]
+    fn combine_matches_the_crc_of_both() {
+        let data = noise(100_000);
+        let mut seed = 7u32;
+        for _ in 0..500 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let end = (seed >> 8) as usize % data.len();
+            let split = (seed >> 4) as usize % (end + 1);
+            let (a, b) = (&data[..split], &data[split..end]);
+            let combined = combine(update(0, a), update(0, b), b.len() as u64);
+            assert_eq!(combined, update(0, &data[..end]), "{} + {}", a.len(), b.len());
+        }
+        // Nothing after, and nothing before
+        let crc = update(0, &data);
+        assert_eq!(combine(crc, update(0, &[]), 0), crc);
+        assert_eq!(combine(0, crc, data.len() as u64), crc);
+    }
+
+    // combine(crc, 0, n) carries crc on over n zero bytes. For lengths that can be read
+    // it is checked against the zeros themselves, and past 2^32 as two shorter runs
+    #[test]
+    fn combine_over_long_runs_of_zeros() {
+        let crc = update(0, b"before the zeros");
+        let zeros = vec![0; 1 << 20];
+        for len in [1, 2, 3, 1000, 65536, 1 << 20] {
+            let zeros = &zeros[..len];
+            assert_eq!(combine(crc, update(0, zeros), len as u64), update(crc, zeros), "{} zeros", len);
+        }
+        for (first, second) in [(1 << 31, (1 << 31) + 5), (1 << 32, 1 << 20), (3 << 31, 12_345_678_901)] {
+            let whole = combine(combine(crc, 0, first), 0, second);
+            assert_eq!(combine(crc, 0, first + second), whole, "{} + {} zeros", first, second);
+        }
+    }
+}
@@ -1,19 +1,22 @@
-use std::io;
-use crate::GzipState;
+use std::io::{self, Read, Write};
+use crate::mapped::Input;
+use crate::{GzipState, TRACE_SYMBOLS};
 use crate::trees::Trees;
 
 const NIL: u16 = 0;
-const HASH_SIZE: usize = 1 << 15; // Assuming HASH_BITS == 15
+const MAX_HASH_SIZE: usize = 1 << 16; // head holds the largest table a HashConfig uses
 const WSIZE: usize = 32 * 1024; // Window size (32K)
 const WMASK: usize = WSIZE - 1;
 const MIN_LOOKAHEAD: usize = 262; // Minimum lookahead for deflate
+const MAX_STORED: usize = 0xffff; // Most a stored block holds, as its LEN is 16 bits
 pub(crate) const MIN_MATCH: usize = 3;
+const TOO_FAR: usize = 4096; // Matches of length 3 are discarded if their distance exceeds this
 const FAST: u16 = 0x04;
 const SLOW: u16 = 0x02;
-pub(crate) const MAX_DIST: usize = 16384;
+pub(crate) const MAX_DIST: usize = WSIZE - MIN_LOOKAHEAD; // Farthest match distance kept in the window
 pub(crate) const MAX_MATCH: usize = 258;
 const HASH_BITS: usize = 15;
-const HASH_MASK: u32 = (HASH_SIZE as u32) - 1;
+const HASH_MASK: u32 = (1 << HASH_BITS) - 1;
 const WINDOW_SIZE: usize = 2*WSIZE;
 const H_SHIFT: u32 = ((HASH_BITS + MIN_MATCH - 1) / MIN_MATCH) as u32; // 5
 const CONFIGURATION_TABLE: [Config; 10] = [
@@ -28,30 +31,116 @@ const CONFIGURATION_TABLE: [Config; 10] = [
     /* 8 */ Config::new(32, 128, 258, 1024),
     /* 9 */ Config::new(32, 258, 258, 4096)];
 
+// How strings are hashed into head. Up to level 5 it is gzip's rolling hash of three
+// bytes; from level 6 on, where chains are searched deeper, a multiplicative hash of
+// four bytes spreads common data over more and shorter chains in a table twice the size
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum HashConfig {
+    Rolling3,
+    Multiply4,
+}
+
+impl HashConfig {
+    fn for_level(level: i32) -> Self {
+        if level >= 6 {
+            HashConfig::Multiply4
+        } else {
+            HashConfig::Rolling3
+        }
+    }
+
+    // Entries of head in use
+    fn size(self) -> usize {
+        match self {
+            HashConfig::Rolling3 => 1 << HASH_BITS,
+            HashConfig::Multiply4 => 1 << 16,
+        }
+    }
+
+    // Bytes of the string that go into its hash
+    fn span(self) -> usize {
+        match self {
+            HashConfig::Rolling3 => MIN_MATCH,
+            HashConfig::Multiply4 => 4,
+        }
+    }
+}
+
 #[derive(Default)]
 struct Config {
-    max_lazy: i32,
-    good_length: i32,
-    nice_length: i32,
+    good_length: i32, // reduce lazy search above this match length
+    max_lazy: i32,    // do not perform lazy search above this match length
+    nice_length: i32, // quit search above this match length
     max_chain: i32,
 }
 
 impl Config {
-    const fn new(max_lazy: i32,
-                 good_length: i32,
+    const fn new(good_length: i32,
+                 max_lazy: i32,
                  nice_length: i32,
                  max_chain: i32) -> Config {
         Self {
-            max_lazy,
             good_length,
+            max_lazy,
             nice_length,
             max_chain,
         }
     }
 }
+// How far the strings at scan and cand agree, counting from start and stopping at max_len.
+// Eight bytes are compared at a time, and the first that differs is found from the
+// zero bits of their XOR; the last few bytes before max_len go one at a time
+fn match_length(window: &[u8], scan: usize, cand: usize, start: usize, max_len: usize) -> usize {
+    let word = |pos: usize| u64::from_ne_bytes(window[pos..pos + 8].try_into().unwrap());
+    let mut len = start;
+    while len + 8 <= max_len {
+        let diff = word(scan + len) ^ word(cand + len);
+        if diff != 0 {
+            #[cfg(target_endian = "little")]
+            let same_bits = diff.trailing_zeros();
+            #[cfg(target_endian = "big")]
+            let same_bits = diff.leading_zeros();
+            return len + (same_bits / 8) as usize;
+        }
+        len += 8;
+    }
+    while len < max_len && window[scan + len] == window[cand + len] {
+        len += 1;
+    }
+    len
+}
+
+// match_length a byte at a time, which debug builds check it against
+#[cfg(debug_assertions)]
+fn match_length_bytewise(window: &[u8], scan: usize, cand: usize, start: usize, max_len: usize) -> usize {
+    let mut len = start;
+    while len < max_len && window[scan + len] == window[cand + len] {
+        len += 1;
+    }
+    len
+}
+
+// Where a read of the input stopped: after what the input had, at a pause in it, or at
+// its end, with nothing read
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ReadEnd {
+    Data,
+    Pause,
+    Eof,
+}
+
+// How far flush takes the output: the end of the stream, or with Sync a byte boundary
+// the decoder can stop at and the stream can go on from
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Flush {
+    Sync,
+    Finish,
+}
+
 pub struct Deflate {
     compr_level: i32,
-    head: [u16; HASH_SIZE],
+    head: [u16; MAX_HASH_SIZE],
+    hash: HashConfig,
     max_lazy_match: i32,
     good_match: i32,
     nice_match: i32,
@@ -66,13 +155,16 @@ pub struct Deflate {
     prev_length: usize,
     match_start: usize,
     max_insert_length: usize,
+    dictionary: Vec<u8>, // Preset dictionary matches may refer to, at most MAX_DIST bytes
+    sync_pending: bool,  // --sync-flush: the input paused, so flush what came before it
 }
 
 impl Deflate {
     pub fn new() -> Self {
         Self {
             compr_level: 0,
-            head: [NIL; HASH_SIZE],
+            head: [NIL; MAX_HASH_SIZE],
+            hash: HashConfig::Rolling3,
             max_lazy_match: 0,
             good_match: 0,
             nice_match: 0,
@@ -86,23 +178,35 @@ impl Deflate {
             prev: vec![0; WSIZE],
             prev_length: 0,
             match_start: 0,
-            max_insert_length: 0
+            max_insert_length: 0,
+            dictionary: Vec::new(),
+            sync_pending: false,
         }
     }
 
-    pub fn lm_init(&mut self, state: &mut GzipState, pack_level: i32, flags: &mut u16) {
-        if pack_level < 1 || pack_level > 9 {
-            state.gzip_error("bad pack level");
+    // Start with a preset dictionary in the window (--dictionary). Only its last
+    // MAX_DIST bytes are within reach of the first match, so only those are kept
+    pub fn with_dictionary(mut self, dictionary: &[u8]) -> Self {
+        self.dictionary = dictionary[dictionary.len().saturating_sub(MAX_DIST)..].to_vec();
+        self
+    }
+
+    pub fn lm_init(&mut self, state: &mut GzipState, pack_level: i32, flags: &mut u16) -> io::Result<()> {
+        if !(0..CONFIGURATION_TABLE.len() as i32).contains(&pack_level) {
+            return Err(state.gzip_error("bad pack level"));
         }
         self.compr_level = pack_level;
 
         // Initialize the hash table.
-        self.head.fill(NIL);
+        self.hash = HashConfig::for_level(pack_level);
+        self.head[..self.hash.size()].fill(NIL);
 
         // prev will be initialized on the fly
 
         // Set the default configuration parameters:
         self.max_lazy_match = CONFIGURATION_TABLE[pack_level as usize].max_lazy;
+        // deflate_fast reuses the lazy threshold as its insertion limit
+        self.max_insert_length = self.max_lazy_match as usize;
         self.good_match = CONFIGURATION_TABLE[pack_level as usize].good_length;
         #[cfg(not(FULL_SEARCH))]
         {
@@ -116,71 +220,113 @@ impl Deflate {
             *flags |= SLOW;
         }
 
-        self.strstart = 0;
-        self.block_start = 0;
+        // The dictionary goes at the start of the window, as history that is never output
+        let dict_len = self.dictionary.len();
+        self.window[..dict_len].copy_from_slice(&self.dictionary);
+        self.strstart = dict_len;
+        self.block_start = dict_len as i64;
 
-        (self.lookahead, self.eofile) = Self::read_buf(state, &mut self.window, 2 * WSIZE);
+        let end;
+        (self.lookahead, end) = Self::read_buf(state, &mut self.window[dict_len..], 2 * WSIZE - dict_len)?;
 
         if self.lookahead == 0 {
             self.eofile = true;
             self.lookahead = 0;
-            return;
+            return Ok(());
         }
         self.eofile = false;
+        self.sync_pending = end == ReadEnd::Pause;
 
-        while self.lookahead < MIN_LOOKAHEAD && !self.eofile {
-            self.fill_window(state);
+        while self.lookahead < MIN_LOOKAHEAD && !self.eofile && !self.sync_pending {
+            self.fill_window(state)?;
+        }
+
+        // Hash every string of the dictionary; those near its end run on into the input
+        if dict_len > 0 {
+            self.ins_h = 0;
+            for j in 0..(MIN_MATCH - 1) {
+                self.ins_h = self.update_hash(self.ins_h, self.window[j]);
+            }
+            for s in 0..dict_len {
+                self.insert_string(s);
+            }
         }
 
         self.ins_h = 0;
         for j in 0..(MIN_MATCH - 1) {
-            self.ins_h = self.update_hash(self.ins_h, self.window[j]);
+            self.ins_h = self.update_hash(self.ins_h, self.window[self.strstart + j]);
         }
+        Ok(())
     }
 
+    // Implements UPDATE_HASH macro from C code. The 4-byte hash is taken whole from the
+    // window at each string, so it keeps no running value and h is left as it is
     fn update_hash(&self, h: u32, c: u8) -> u32 {
-        // Implements UPDATE_HASH macro from C code
-        (((h) << H_SHIFT) ^ (c as u32)) & HASH_MASK
+        match self.hash {
+            HashConfig::Rolling3 => ((h << H_SHIFT) ^ (c as u32)) & HASH_MASK,
+            HashConfig::Multiply4 => h,
+        }
     }
 
-    fn read_buf(state: &mut GzipState, buf: &mut [u8], size: usize) -> (usize, bool) {
+    // Read up to size bytes, as many as the input has. With --sync-flush one read is taken
+    // as it comes, so a pause in the input shows as a short read; a reader can also mark
+    // one by failing with WouldBlock, as GzipEncoder::flush_sync has its input do
+    fn read_buf(state: &mut GzipState, buf: &mut [u8], size: usize) -> io::Result<(usize, ReadEnd)> {
         if let Some(ref mut input) = state.ifd {
-            match input.read(&mut buf[..size]) {
-                Ok(bytes_read) => {
-                    if bytes_read > 0 {
-                        if state.first_call {
-                            // Initialize CRC
-                            state.updcrc(None, 0);
-                            state.first_call = false; // Set to false after the first call
+            let mut bytes_read = 0;
+            let mut end = ReadEnd::Data;
+            while bytes_read < size {
+                let read = match input {
+                    // A mapped file fills the window straight from the mapping
+                    Input::Mapped(mapping) => mapping.next_slice(size - bytes_read).map(|taken| {
+                        buf[bytes_read..bytes_read + taken.len()].copy_from_slice(taken);
+                        taken.len()
+                    }),
+                    Input::Reader(reader) => reader.read(&mut buf[bytes_read..size]),
+                };
+                match read {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        bytes_read += n;
+                        if state.opts.sync_flush && bytes_read < size {
+                            end = ReadEnd::Pause;
+                            break;
                         }
-                        // 计算 CRC
-                        state.crc16_digest = state.updcrc(Some(&buf[..bytes_read]), bytes_read);
-                    }else{
-                        buf.fill(0);
                     }
-                    state.bytes_in += bytes_read as i64;
-                    (bytes_read, bytes_read == 0)
-                }
-                Err(e) => {
-                    state.gzip_error(&format!("Error reading input: {}", e));
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        end = ReadEnd::Pause;
+                        break;
+                    }
+                    Err(e) => return Err(state.gzip_error(&format!("Error reading input: {}", e))),
                 }
             }
+            if bytes_read > 0 {
+                state.update_check(&buf[..bytes_read]);
+            } else if end == ReadEnd::Data {
+                end = ReadEnd::Eof;
+            }
+            state.bytes_in += bytes_read as i64;
+            state.progress();
+            Ok((bytes_read, end))
         } else {
             buf.fill(0);
-            (0, true)
+            Ok((0, ReadEnd::Eof))
         }
     }
 
-    fn fill_window(&mut self, state: &mut GzipState) {
-        // Move the existing data if necessary
+    fn fill_window(&mut self, state: &mut GzipState) -> io::Result<()> {
+        let mut more = WINDOW_SIZE - self.lookahead - self.strstart;
+
+        // Slide the upper half of the window down, keeping MAX_DIST bytes of history
         if self.strstart >= WSIZE + MAX_DIST {
-            // Shift the window
             self.window.copy_within(WSIZE..2 * WSIZE, 0);
+            self.match_start = self.match_start.saturating_sub(WSIZE);
             self.strstart -= WSIZE;
             self.block_start -= WSIZE as i64;
 
             // Adjust the hash table
-            for i in 0..HASH_SIZE {
+            for i in 0..self.hash.size() {
                 let h = self.head[i];
                 if h as usize >= WSIZE {
                     self.head[i] = h - WSIZE as u16;
@@ -198,28 +344,75 @@ impl Deflate {
                     self.prev[i] = NIL;
                 }
             }
+            more += WSIZE;
         }
 
-        // Read new data into the window
-        let available_space = (2 * WSIZE) - self.lookahead - self.strstart;
-        let (n, eof) = Self::read_buf(
-            state,
-            &mut self.window[self.strstart + self.lookahead..],
-            available_space,
-        );
-        self.lookahead += n;
-        self.eofile = eof;
-        if n == 0 {
-            self.eofile = true;
+        // Read until there is MIN_LOOKAHEAD of input or the input really ends;
+        // a short read (pipes, terminals) is not the end of the input
+        while !self.eofile && more > 0 {
+            let start = self.strstart + self.lookahead;
+            let (n, end) = Self::read_buf(state, &mut self.window[start..], more)?;
+            if end == ReadEnd::Eof {
+                // The last hash and match comparisons may read span-1 bytes past the input
+                self.eofile = true;
+                self.window[start..(start + self.hash.span() - 1).min(WINDOW_SIZE)].fill(0);
+                break;
+            }
+            self.lookahead += n;
+            // A pause: this is all the input there is for now
+            if end == ReadEnd::Pause {
+                self.sync_pending = true;
+                break;
+            }
+            more -= n;
+            if self.lookahead >= MIN_LOOKAHEAD {
+                break;
+            }
         }
+        Ok(())
     }
 
     pub fn deflate(&mut self, trees: &mut Trees, state: &mut GzipState) -> io::Result<()> {
+        if self.compr_level == 0 {
+            return self.deflate_stored(trees, state);
+        }
         if self.compr_level <= 3 {
             return self.deflate_fast(trees, state);
         }
+        self.deflate_lazy(trees, state)
+    }
 
-        unimplemented!()
+    // Level 0: the input goes out as it is in stored blocks, with no match search. The
+    // last byte is held back until the input is known to go on, so that the block that
+    // ends the input can be marked final
+    fn deflate_stored(&mut self, trees: &mut Trees, state: &mut GzipState) -> io::Result<()> {
+        let mut finished = false;
+        loop {
+            // Everything before a pause is already sent; mark the flush and wait for more
+            if self.lookahead == 0 && self.sync_pending && !self.eofile {
+                self.flush(trees, state, Flush::Sync)?;
+                self.sync_pending = false;
+            }
+            while self.lookahead < MIN_LOOKAHEAD && !self.eofile && !self.sync_pending {
+                self.fill_window(state)?;
+            }
+            if self.lookahead == 0 {
+                break;
+            }
+
+            let ahead = if self.eofile || self.sync_pending { self.lookahead } else { self.lookahead - 1 };
+            let len = ahead.min(MAX_STORED);
+            self.strstart += len;
+            self.lookahead -= len;
+            finished = self.eofile && self.lookahead == 0;
+            trees.flush_stored_block(state, &self.window[self.block_start as usize..self.strstart], finished)?;
+            self.block_start = self.strstart as i64;
+        }
+        // An empty input still needs a final block
+        if !finished {
+            trees.flush_stored_block(state, &[], true)?;
+        }
+        Ok(())
     }
 
     pub fn deflate_fast(&mut self, tree: &mut Trees, state: &mut GzipState) -> io::Result<()> {
@@ -228,10 +421,25 @@ impl Deflate {
         let mut match_length: usize = 0; // Length of best match
 
         self.prev_length = MIN_MATCH - 1;
-        while self.lookahead != 0 {
+        loop {
+            // Once the input before a pause is all tallied, flush it and wait for more
+            if self.lookahead == 0 && self.sync_pending && !self.eofile {
+                self.flush(tree, state, Flush::Sync)?;
+                self.sync_pending = false;
+                self.fill_window(state)?;
+            }
+            if self.lookahead == 0 {
+                break;
+            }
+
             // Insert the string window[strstart .. strstart+2] into the dictionary
-            // and set hash_head to the head of the hash chain
-            hash_head = self.insert_string(self.strstart);
+            // and set hash_head to the head of the hash chain. The last MIN_MATCH-1
+            // bytes of the input can only be literals.
+            hash_head = if self.lookahead >= MIN_MATCH {
+                self.insert_string(self.strstart)
+            } else {
+                NIL as usize
+            };
 
             // Find the longest match, discarding those <= prev_length
             // At this point, we always have match_length < MIN_MATCH
@@ -248,14 +456,16 @@ impl Deflate {
                 }
             }
             if match_length >= MIN_MATCH {
-                self.check_match(state, self.strstart, self.match_start, match_length);
+                #[cfg(debug_assertions)]
+                self.check_match(state, self.strstart, self.match_start, match_length)?;
 
                 flush = tree.ct_tally(self, state, self.strstart - self.match_start, match_length - MIN_MATCH);
 
                 self.lookahead -= match_length;
 
-                // Insert new strings in the hash table only if the match length is not too large
-                if match_length <= self.max_insert_length {
+                // Insert new strings in the hash table only if the match length is not too
+                // large and every string inserted still has MIN_MATCH bytes of input
+                if match_length <= self.max_insert_length && self.lookahead >= MIN_MATCH {
                     match_length -= 1; // String at strstart already in hash table
                     while match_length != 0 {
                         self.strstart += 1;
@@ -266,12 +476,11 @@ impl Deflate {
                 } else {
                     self.strstart += match_length;
                     match_length = 0;
-                    self.ins_h = self.window[self.strstart] as u32;
-                    self.ins_h = self.update_hash(self.ins_h, self.window[self.strstart + 1]);
-                    // If MIN_MATCH != 3, call update_hash() MIN_MATCH - 3 more times
-                    #[cfg(not(feature = "MIN_MATCH_3"))]
-                    {
-                        for i in 2..MIN_MATCH {
+                    // Prime the hash with the first MIN_MATCH-1 bytes of the next string;
+                    // with less input left than that nothing more is hashed
+                    if self.lookahead >= MIN_MATCH {
+                        self.ins_h = self.window[self.strstart] as u32;
+                        for i in 1..MIN_MATCH - 1 {
                             self.ins_h = self.update_hash(self.ins_h, self.window[self.strstart + i]);
                         }
                     }
@@ -283,20 +492,146 @@ impl Deflate {
                 self.strstart += 1;
             }
             if flush {
-                self.flush_block_wrapper(tree, state, false);
+                self.flush_block_wrapper(tree, state, false)?;
                 self.block_start = self.strstart as i64;
             }
 
             // Ensure that we always have enough lookahead
-            while self.lookahead < MIN_LOOKAHEAD && !self.eofile {
-                self.fill_window(state);
+            while self.lookahead < MIN_LOOKAHEAD && !self.eofile && !self.sync_pending {
+                self.fill_window(state)?;
+            }
+        }
+        self.flush(tree, state, Flush::Finish)
+    }
+
+    // Levels 4-9: a match is only taken once the string after it is known to match no
+    // longer, otherwise its first byte goes out as a literal. Corresponds to deflate()
+    fn deflate_lazy(&mut self, tree: &mut Trees, state: &mut GzipState) -> io::Result<()> {
+        let mut hash_head: usize;        // Head of the hash chain
+        let mut flush: bool;             // Set if current block must be flushed
+        let mut match_available = false; // Set if the previous match is still to be decided
+        let mut match_length = MIN_MATCH - 1; // Length of best match
+
+        loop {
+            // Once the input before a pause is all tallied, flush it and wait for more
+            if self.lookahead == 0 && self.sync_pending && !self.eofile {
+                if match_available {
+                    tree.ct_tally(self, state, 0, self.window[self.strstart - 1] as usize);
+                    match_available = false;
+                }
+                match_length = MIN_MATCH - 1;
+                self.flush(tree, state, Flush::Sync)?;
+                self.sync_pending = false;
+                self.fill_window(state)?;
+            }
+            if self.lookahead == 0 {
+                break;
+            }
+
+            // Insert the string window[strstart .. strstart+2] into the dictionary
+            // and set hash_head to the head of the hash chain
+            hash_head = if self.lookahead >= MIN_MATCH {
+                self.insert_string(self.strstart)
+            } else {
+                NIL as usize
+            };
+
+            // Find the longest match, discarding those <= prev_length
+            self.prev_length = match_length;
+            let prev_match = self.match_start;
+            match_length = MIN_MATCH - 1;
+
+            if hash_head != NIL.into()
+                && self.prev_length < self.max_lazy_match as usize
+                && self.strstart > hash_head
+                && self.strstart - hash_head <= MAX_DIST
+                && self.strstart <= WINDOW_SIZE - MIN_LOOKAHEAD
+            {
+                match_length = self.longest_match(hash_head);
+                if match_length > self.lookahead {
+                    match_length = self.lookahead;
+                }
+                // Ignore a length 3 match if it is too distant
+                if match_length == MIN_MATCH && self.strstart - self.match_start > TOO_FAR {
+                    match_length -= 1;
+                }
+            }
+
+            // If there was a match at the previous step and the current match is not
+            // better, output the previous match
+            if self.prev_length >= MIN_MATCH && match_length <= self.prev_length {
+                #[cfg(debug_assertions)]
+                self.check_match(state, self.strstart - 1, prev_match, self.prev_length)?;
+
+                flush = tree.ct_tally(self, state, self.strstart - 1 - prev_match, self.prev_length - MIN_MATCH);
+
+                // Insert the strings of the match, strstart-1 and strstart being already
+                // in, while each still has MIN_MATCH bytes of input
+                let end = self.strstart + self.lookahead;
+                self.lookahead -= self.prev_length - 1;
+                let mut remaining = self.prev_length - 2;
+                while remaining != 0 {
+                    self.strstart += 1;
+                    if self.strstart + MIN_MATCH <= end {
+                        self.insert_string(self.strstart);
+                    }
+                    remaining -= 1;
+                }
+                match_available = false;
+                match_length = MIN_MATCH - 1;
+                self.strstart += 1;
+                if flush {
+                    self.flush_block_wrapper(tree, state, false)?;
+                    self.block_start = self.strstart as i64;
+                }
+            } else if match_available {
+                // No better match: output the previous byte as a literal, and try
+                // the string here against the one after it
+                flush = tree.ct_tally(self, state, 0, self.window[self.strstart - 1] as usize);
+                if flush {
+                    self.flush_block_wrapper(tree, state, false)?;
+                    self.block_start = self.strstart as i64;
+                }
+                self.strstart += 1;
+                self.lookahead -= 1;
+            } else {
+                // Nothing to output yet; wait for the next step to decide
+                match_available = true;
+                self.strstart += 1;
+                self.lookahead -= 1;
+            }
+
+            // Ensure that we always have enough lookahead
+            while self.lookahead < MIN_LOOKAHEAD && !self.eofile && !self.sync_pending {
+                self.fill_window(state)?;
             }
         }
-        self.flush_block_wrapper(tree, state, true);
+        if match_available {
+            tree.ct_tally(self, state, 0, self.window[self.strstart - 1] as usize);
+        }
+        self.flush(tree, state, Flush::Finish)
+    }
+
+    // Send what is tallied. A sync flush adds an empty stored block, which ends on a byte
+    // boundary (00 00 ff ff), and hands the output on at once
+    pub(crate) fn flush(&mut self, trees: &mut Trees, state: &mut GzipState, how: Flush) -> io::Result<()> {
+        if how == Flush::Sync {
+            if self.strstart as i64 > self.block_start {
+                self.flush_block_wrapper(trees, state, false)?;
+                self.block_start = self.strstart as i64;
+            }
+            trees.send_empty_stored_block(state)?;
+            state.flush_outbuf()?;
+            if let Some(ofd) = state.ofd.as_mut() {
+                ofd.flush()?;
+            }
+        } else {
+            self.flush_block_wrapper(trees, state, true)?;
+        }
         Ok(())
     }
 
-    fn flush_block_wrapper(&mut self, trees: &mut Trees, state: &mut GzipState, eof: bool) -> i64 {
+    fn flush_block_wrapper(&mut self, trees: &mut Trees, state: &mut GzipState, eof: bool) -> io::Result<i64> {
         if self.block_start >= 0 {
             let start = self.block_start as usize;
             let end = self.strstart;
@@ -319,8 +654,20 @@ impl Deflate {
 
     fn insert_string(&mut self, s: usize) -> usize {
         // Corresponds to the INSERT_STRING macro
-        self.ins_h = self.update_hash(self.ins_h, self.window[s + MIN_MATCH - 1]);
-        let ins_h = self.ins_h as usize;
+        let ins_h = match self.hash {
+            HashConfig::Rolling3 => {
+                self.ins_h = self.update_hash(self.ins_h, self.window[s + MIN_MATCH - 1]);
+                self.ins_h as usize
+            }
+            // Knuth's multiplicative hash; the top 16 bits of the product are the best mixed.
+            // A string at the very end of the window has its missing byte taken as zero
+            HashConfig::Multiply4 => {
+                let mut bytes = [0; 4];
+                let end = (s + 4).min(WINDOW_SIZE);
+                bytes[..end - s].copy_from_slice(&self.window[s..end]);
+                (u32::from_le_bytes(bytes).wrapping_mul(2654435761) >> 16) as usize
+            }
+        };
         let match_head = self.head[ins_h] as usize;
         self.prev[s & WMASK] = match_head as u16;
         self.head[ins_h] = s as u16;
@@ -349,10 +696,14 @@ impl Deflate {
         );
 
         let window = &self.window;
-        let window_size = WINDOW_SIZE;
         let mut nice_match = self.nice_match as usize;
 
-        let strend = self.strstart + MAX_MATCH;
+        // Do not look for matches beyond the end of the input
+        if nice_match > self.lookahead {
+            nice_match = self.lookahead;
+        }
+        let max_len = MAX_MATCH.min(self.lookahead);
+
         let mut scan_end1 = window[scan + best_len - 1];
         let mut scan_end = window[scan + best_len];
 
@@ -372,12 +723,9 @@ impl Deflate {
             }
 
             // Now, try to match as much as possible
-            let mut len = 2;
-            while len < MAX_MATCH
-                && window[scan + len] == window[match_index + len]
-            {
-                len += 1;
-            }
+            let len = match_length(window, scan, match_index, 2, max_len);
+            #[cfg(debug_assertions)]
+            assert_eq!(len, match_length_bytewise(window, scan, match_index, 2, max_len), "match_length at {}", scan);
 
             if len > best_len {
                 self.match_start = match_index;
@@ -385,10 +733,8 @@ impl Deflate {
                 if len >= nice_match {
                     break;
                 }
-                if len >= scan + best_len {
-                    scan_end1 = window[scan + best_len - 1];
-                    scan_end = window[scan + best_len];
-                }
+                scan_end1 = window[scan + best_len - 1];
+                scan_end = window[scan + best_len];
             }
 
             cur_match = self.prev[cur_match & WMASK] as usize;
@@ -397,26 +743,174 @@ impl Deflate {
         best_len
     }
 
-    fn check_match(&self, state: &GzipState, start: usize, match_pos: usize, length: usize) {
-        // Check that the match is indeed a match
+    // Debug builds only: verify that the bytes at match_pos really repeat those at start
+    #[cfg(debug_assertions)]
+    fn check_match(&self, state: &GzipState, start: usize, match_pos: usize, length: usize) -> io::Result<()> {
         let window = &self.window;
 
-        // Ensure indices are within bounds
-        if start + length > window.len() || match_pos + length > window.len() {
-            eprintln!("Index out of bounds in check_match");
-            state.gzip_error("invalid match");
-        }
-
-        if &window[match_pos..match_pos + length] != &window[start..start + length] {
+        if start + length > window.len()
+            || match_pos + length > window.len()
+            || window[match_pos..match_pos + length] != window[start..start + length]
+        {
             eprintln!(" start {}, match {}, length {}", start, match_pos, length);
-            state.gzip_error("invalid match");
+            eprintln!("{}: {}: invalid match", state.program_name, state.ifname.display());
+            return Err(io::Error::other("invalid match"));
         }
 
-        if state.verbose > 1 {
+        if state.opts.verbose >= TRACE_SYMBOLS {
             eprint!("\\[{},{}]", start - match_pos, length);
             for &byte in &window[start..start + length] {
-                eprint!("{}", byte as char);
+                eprint!(" {:02x}", byte);
+            }
+        }
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bytes from a small alphabet, the same on every run
+    fn noise(len: usize, alphabet: u8) -> Vec<u8> {
+        let mut seed = 0x2545_f491u32;
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                b'a' + (seed >> 16) as u8 % alphabet
+            })
+            .collect()
+    }
+
+    // A Deflate with data in its window and no limit on the chain, so only the match
+    // rules themselves decide what longest_match finds
+    fn matcher(data: &[u8]) -> Deflate {
+        let mut deflate = Deflate::new();
+        deflate.window[..data.len()].copy_from_slice(data);
+        deflate.max_chain_length = i32::MAX;
+        deflate.good_match = MAX_MATCH as i32;
+        deflate.nice_match = MAX_MATCH as i32;
+        deflate.ins_h = 0;
+        for j in 0..(MIN_MATCH - 1) {
+            deflate.ins_h = deflate.update_hash(deflate.ins_h, deflate.window[j]);
+        }
+        deflate
+    }
+
+    // What longest_match gives at strstart, once every string before it is hashed: the
+    // length and, if it beat prev_length, where the match starts
+    fn longest_at(deflate: &mut Deflate, strstart: usize, end: usize) -> (usize, Option<usize>) {
+        let cur_match = deflate.insert_string(strstart);
+        deflate.strstart = strstart;
+        deflate.lookahead = end - strstart;
+        deflate.prev_length = MIN_MATCH - 1;
+        deflate.match_start = usize::MAX;
+        let len = deflate.longest_match(cur_match);
+        (len, (len > deflate.prev_length).then_some(deflate.match_start))
+    }
+
+    // The same answer found by trying every position in reach, nearest first as the hash
+    // chain runs, up to MAX_MATCH and the end of the input
+    fn brute_force(window: &[u8], strstart: usize, end: usize) -> (usize, Option<usize>) {
+        let max_len = MAX_MATCH.min(end - strstart);
+        let limit = strstart.saturating_sub(MAX_DIST);
+        let mut best = (MIN_MATCH - 1, None);
+        for cand in (limit + 1..strstart).rev() {
+            let len = (0..max_len).take_while(|&i| window[cand + i] == window[strstart + i]).count();
+            if len > best.0 {
+                best = (len, Some(cand));
+            }
+        }
+        best
+    }
+
+    // Check the positions in range against brute_force
+    fn check_range(data: &[u8], range: std::ops::Range<usize>) {
+        let mut deflate = matcher(data);
+        for s in 0..range.start {
+            deflate.insert_string(s);
+        }
+        for strstart in range {
+            let found = longest_at(&mut deflate, strstart, data.len());
+            assert_eq!(found, brute_force(&deflate.window, strstart, data.len()), "at {}", strstart);
+        }
+    }
+
+    #[test]
+    fn longest_match_on_repetitive_data() {
+        check_range(&[b'a'; 1000], 0..1000);
+        check_range(&b"abc".repeat(400), 0..1200);
+        check_range(&b"abcabcabd".repeat(150), 0..1350);
+
+        // Matches stop at MAX_MATCH, and at the end of the input however far the data repeats
+        let data = [b'z'; 600];
+        let mut deflate = matcher(&data);
+        assert_eq!(longest_at(&mut deflate, 0, 600), (MIN_MATCH - 1, None));
+        // Position 0 is never matched, as in gzip
+        assert_eq!(longest_at(&mut deflate, 1, 600), (MIN_MATCH - 1, None));
+        assert_eq!(longest_at(&mut deflate, 2, 600), (MAX_MATCH, Some(1)));
+        for s in 3..595 {
+            deflate.insert_string(s);
+        }
+        assert_eq!(longest_at(&mut deflate, 595, 600), (5, Some(594)));
+    }
+
+    #[test]
+    fn longest_match_across_the_window_midpoint() {
+        let mut data = noise(WSIZE + 3000, 26);
+        // A run from either side of the midpoint, and one just too far back to reach
+        data.copy_within(WSIZE - 150..WSIZE + 150, WSIZE + 1000);
+        let far = WSIZE + 2500;
+        data.copy_within(far - MAX_DIST..far - MAX_DIST + 50, far);
+        check_range(&data, WSIZE - 300..WSIZE + 300);
+        check_range(&data, WSIZE + 900..WSIZE + 1400);
+        check_range(&data, far - 10..far + 60);
+
+        let mut deflate = matcher(&data);
+        for s in 0..WSIZE + 1000 {
+            deflate.insert_string(s);
+        }
+        assert_eq!(longest_at(&mut deflate, WSIZE + 1000, data.len()), (MAX_MATCH, Some(WSIZE - 150)));
+        for s in WSIZE + 1001..far {
+            deflate.insert_string(s);
+        }
+        assert!(longest_at(&mut deflate, far, data.len()).0 < 50);
+    }
+
+    // Every position of a stretch of data with plenty of short, overlapping matches
+    #[test]
+    fn longest_match_against_brute_force() {
+        check_range(&noise(6000, 4), 0..6000);
+        check_range(&noise(3000, 2), 0..3000);
+    }
+
+    // With the 4-byte hash a chain only holds strings that share four bytes, so every
+    // match brute_force finds of four bytes or more is found, and no longer one
+    #[test]
+    fn longest_match_with_the_four_byte_hash() {
+        let data = noise(6000, 4);
+        let mut deflate = matcher(&data);
+        deflate.hash = HashConfig::Multiply4;
+        for strstart in 0..data.len() - 4 {
+            let found = longest_at(&mut deflate, strstart, data.len());
+            let expected = brute_force(&deflate.window, strstart, data.len());
+            if expected.0 >= 4 {
+                assert_eq!(found, expected, "at {}", strstart);
+            } else {
+                assert!(found.0 <= expected.0, "at {}", strstart);
             }
         }
     }
-}
\ No newline at end of file
+
+    // A match whose bytes do not repeat, or that runs off the window, is an error in
+    // debug builds rather than a stream that decodes to the wrong data
+    #[cfg(debug_assertions)]
+    #[test]
+    fn check_match_refuses_a_corrupted_match() {
+        let state = GzipState::with_program_name("gzip");
+        let mut deflate = matcher(b"abcdefabcdef");
+        assert!(deflate.check_match(&state, 6, 0, 6).is_ok());
+        deflate.window[2] = b'x';
+        assert_eq!(deflate.check_match(&state, 6, 0, 6).unwrap_err().to_string(), "invalid match");
+        assert!(deflate.check_match(&state, WINDOW_SIZE - 4, 0, 6).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+// The patterns of --exclude and --include, matched against the path of a file below the
+// directory -r started from. A pattern without a / is matched against the last part of
+// the path, at any depth; one with a / against the whole of it. * and ? and [...] stay
+// within one part of the path, while ** as a whole part spans any number of them, and a
+// trailing /** takes in the directory itself, so that it can be left out of the walk.
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Literal(u8),
+    Any,  // ?
+    Star, // *
+    Class { negated: bool, ranges: Vec<(u8, u8)> }, // [a-z], [!a-z] or [^a-z]
+    Dirs, // **/ : nothing, or parts each ending in /
+    Rest, // /** at the end: nothing, or / and anything
+    All,  // ** as the whole pattern
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Glob {
+    tokens: Vec<Token>,
+    whole_path: bool,
+}
+
+impl Glob {
+    // Compile a pattern, or say what is wrong with it, for the option parser to report
+    pub(crate) fn new(pattern: &str) -> Result<Glob, String> {
+        let bytes = pattern.as_bytes();
+        if bytes.is_empty() {
+            return Err("empty pattern".to_string());
+        }
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            let at_part_start = i == 0 || bytes[i - 1] == b'/';
+            match bytes[i] {
+                b'*' if bytes.get(i + 1) == Some(&b'*') && at_part_start => {
+                    match bytes.get(i + 2) {
+                        None if i == 0 => tokens.push(Token::All),
+                        // The / before it was a literal; it belongs to the Rest instead
+                        None => {
+                            tokens.pop();
+                            tokens.push(Token::Rest);
+                        }
+                        Some(b'/') => {
+                            tokens.push(Token::Dirs);
+                            i += 1;
+                        }
+                        // a/**b is two stars in one part, which is one star
+                        Some(_) => tokens.push(Token::Star),
+                    }
+                    i += 2;
+                }
+                b'*' => {
+                    if tokens.last() != Some(&Token::Star) {
+                        tokens.push(Token::Star);
+                    }
+                    i += 1;
+                }
+                b'?' => {
+                    tokens.push(Token::Any);
+                    i += 1;
+                }
+                b'[' => {
+                    let (token, next) = class(bytes, i)?;
+                    tokens.push(token);
+                    i = next;
+                }
+                b'\\' => {
+                    let Some(&c) = bytes.get(i + 1) else {
+                        return Err("trailing backslash".to_string());
+                    };
+                    tokens.push(Token::Literal(c));
+                    i += 2;
+                }
+                c => {
+                    tokens.push(Token::Literal(c));
+                    i += 1;
+                }
+            }
+        }
+        Ok(Glob { tokens, whole_path: bytes.contains(&b'/') })
+    }
+
+    // Whether path, relative to where the walk started and with / between its parts,
+    // is one the pattern names
+    pub(crate) fn matches(&self, path: &[u8]) -> bool {
+        if self.whole_path {
+            return match_tokens(&self.tokens, path);
+        }
+        let base = path.rsplit(|&c| c == b'/').next().unwrap_or(path);
+        match_tokens(&self.tokens, base)
+    }
+}
+
+// A bracket expression starting at bytes[start]: a ] first is taken as itself, and a
+// range must not run backwards. Returns the token and where the pattern goes on
+fn class(bytes: &[u8], start: usize) -> Result<(Token, usize), String> {
+    let mut i = start + 1;
+    let negated = matches!(bytes.get(i), Some(b'!' | b'^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let Some(&c) = bytes.get(i) else {
+            return Err("unmatched [".to_string());
+        };
+        if c == b']' && !first {
+            return Ok((Token::Class { negated, ranges }, i + 1));
+        }
+        first = false;
+        let low = if c == b'\\' {
+            i += 1;
+            *bytes.get(i).ok_or_else(|| "unmatched [".to_string())?
+        } else {
+            c
+        };
+        i += 1;
+        if bytes.get(i) == Some(&b'-') && bytes.get(i + 1).is_some_and(|&c| c != b']') {
+            let high = bytes[i + 1];
+            if high < low {
+                return Err(format!("invalid range {}-{}", low as char, high as char));
+            }
+            ranges.push((low, high));
+            i += 2;
+        } else {
+            ranges.push((low, low));
+        }
+    }
+}
+
+fn match_tokens(tokens: &[Token], s: &[u8]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return s.is_empty();
+    };
+    match token {
+        Token::Literal(c) => s.first() == Some(c) && match_tokens(rest, &s[1..]),
+        Token::Any => s.first().is_some_and(|&c| c != b'/') && match_tokens(rest, &s[1..]),
+        Token::Class { negated, ranges } => {
+            s.first().is_some_and(|&c| {
+                c != b'/' && ranges.iter().any(|&(low, high)| (low..=high).contains(&c)) != *negated
+            }) && match_tokens(rest, &s[1..])
+        }
+        Token::Star => {
+            let part = s.iter().position(|&c| c == b'/').unwrap_or(s.len());
+            (0..=part).any(|skip| match_tokens(rest, &s[skip..]))
+        }
+        Token::Dirs => {
+            match_tokens(rest, s)
+                || s.iter().enumerate().any(|(i, &c)| c == b'/' && match_tokens(rest, &s[i + 1..]))
+        }
+        Token::Rest => s.is_empty() || s[0] == b'/',
+        Token::All => true,
+    }
+}
@@ -0,0 +1,330 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use crate::{Crc32, COMMENT, DEFLATED, ENCRYPTED, EXTRA_FIELD, GZIP_MAGIC, HEADER_CRC, OLD_GZIP_MAGIC, ORIG_NAME, RESERVED};
+
+const MAX_TEXT_LEN: usize = 1 << 16; // Longest name or comment read, without its zero
+
+// The header of one gzip member (RFC 1952 section 2.3), read or written without touching
+// the deflate data after it. The name and comment are kept without their terminating zero.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GzipHeader {
+    pub old_magic: bool, // 1f 9e, from gzip 0.5, rather than 1f 8b
+    pub method: u8,
+    // FTEXT, FHCRC and any reserved bits. write sets FEXTRA, FNAME and FCOMMENT itself,
+    // from whether extra, name and comment are there
+    pub flags: u8,
+    pub mtime: u32,
+    pub xfl: u8,
+    pub os: u8,
+    pub extra: Option<Vec<u8>>, // Subfields of a two-byte ID, a two-byte length and the data
+    pub name: Option<Vec<u8>>,
+    pub comment: Option<Vec<u8>>,
+    pub header_crc: Option<u16>, // FHCRC as read; write computes it afresh
+}
+
+// Why a header was refused. It travels inside an io::Error of kind InvalidData
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderError {
+    NotGzip,
+    UnknownMethod(u8),
+    Encrypted,
+    ReservedFlags(u8),
+    Checksum { stored: u16, computed: u16 },
+    TooLong,
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderError::NotGzip => write!(f, "not in gzip format"),
+            HeaderError::UnknownMethod(method) => write!(f, "unknown method {} -- not supported", method),
+            HeaderError::Encrypted => write!(f, "encrypted -- not supported"),
+            HeaderError::ReservedFlags(flags) => write!(f, "flags 0x{:x} -- not supported", flags),
+            HeaderError::Checksum { stored, computed } => {
+                write!(f, "header checksum 0x{:04x} != computed checksum 0x{:04x}", stored, computed)
+            }
+            HeaderError::TooLong => write!(f, "corrupted input -- name or comment too long"),
+        }
+    }
+}
+
+impl Error for HeaderError {}
+
+impl From<HeaderError> for io::Error {
+    fn from(err: HeaderError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+impl GzipHeader {
+    // Read a header and check it: deflate, no reserved flags, and a matching FHCRC if
+    // there is one. Gives the header and the number of bytes it took
+    pub fn parse<R: Read>(r: &mut R) -> io::Result<(Self, usize)> {
+        let (header, len) = Self::read(r, false)?;
+        if let Some(stored) = header.header_crc {
+            let computed = header.computed_crc();
+            if stored != computed {
+                return Err(HeaderError::Checksum { stored, computed }.into());
+            }
+        }
+        Ok((header, len))
+    }
+
+    // parse without the FHCRC check, which gzip reports and then gets past with -f -f, as
+    // it does reserved flags when allow_reserved is set. Stops at an unknown method or an
+    // encrypted member, whose layout is not known
+    pub(crate) fn read<R: Read>(r: &mut R, allow_reserved: bool) -> io::Result<(Self, usize)> {
+        let mut fixed = [0u8; 10];
+        read_bytes(r, &mut fixed)?;
+        if fixed[0..2] != GZIP_MAGIC[..] && fixed[0..2] != OLD_GZIP_MAGIC[..] {
+            return Err(HeaderError::NotGzip.into());
+        }
+        if fixed[2] as i32 != DEFLATED {
+            return Err(HeaderError::UnknownMethod(fixed[2]).into());
+        }
+        let flags = fixed[3];
+        if flags & ENCRYPTED != 0 {
+            return Err(HeaderError::Encrypted.into());
+        }
+        if flags & RESERVED != 0 && !allow_reserved {
+            return Err(HeaderError::ReservedFlags(flags).into());
+        }
+
+        let mut header = GzipHeader {
+            old_magic: fixed[0..2] == OLD_GZIP_MAGIC[..],
+            method: fixed[2],
+            flags: flags & !(EXTRA_FIELD | ORIG_NAME | COMMENT),
+            mtime: u32::from_le_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]),
+            xfl: fixed[8],
+            os: fixed[9],
+            ..GzipHeader::default()
+        };
+        let mut len = fixed.len();
+        if flags & EXTRA_FIELD != 0 {
+            let mut xlen = [0u8; 2];
+            read_bytes(r, &mut xlen)?;
+            let mut extra = vec![0u8; u16::from_le_bytes(xlen) as usize];
+            read_bytes(r, &mut extra)?;
+            len += 2 + extra.len();
+            header.extra = Some(extra);
+        }
+        if flags & ORIG_NAME != 0 {
+            let name = read_zero_terminated(r)?;
+            len += name.len() + 1;
+            header.name = Some(name);
+        }
+        if flags & COMMENT != 0 {
+            let comment = read_zero_terminated(r)?;
+            len += comment.len() + 1;
+            header.comment = Some(comment);
+        }
+        if flags & HEADER_CRC != 0 {
+            let mut crc = [0u8; 2];
+            read_bytes(r, &mut crc)?;
+            len += 2;
+            header.header_crc = Some(u16::from_le_bytes(crc));
+        }
+        Ok((header, len))
+    }
+
+    // Write the header, with an FHCRC of its own if flags ask for one. Gives the number
+    // of bytes written
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<usize> {
+        let bytes = self.to_bytes()?;
+        w.write_all(&bytes)?;
+        Ok(bytes.len())
+    }
+
+    // FHCRC: the low 16 bits of the CRC-32 of the header up to it
+    pub fn computed_crc(&self) -> u16 {
+        let mut crc = Crc32::new();
+        crc.update(&self.fields_bytes());
+        (crc.finalize() & 0xffff) as u16
+    }
+
+    fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        if self.extra.as_ref().is_some_and(|extra| extra.len() > u16::MAX as usize) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "extra field longer than 65535 bytes"));
+        }
+        let zero_in = |field: &Option<Vec<u8>>| field.as_ref().is_some_and(|bytes| bytes.contains(&0));
+        if zero_in(&self.name) || zero_in(&self.comment) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "zero byte in a name or comment"));
+        }
+        let mut bytes = self.fields_bytes();
+        if self.flags & HEADER_CRC != 0 {
+            bytes.extend_from_slice(&self.computed_crc().to_le_bytes());
+        }
+        Ok(bytes)
+    }
+
+    // FLG as written: flags, with FEXTRA, FNAME and FCOMMENT for the fields that are there
+    pub fn flag_byte(&self) -> u8 {
+        let mut flags = self.flags & !(EXTRA_FIELD | ORIG_NAME | COMMENT);
+        for (field, flag) in [(&self.extra, EXTRA_FIELD), (&self.name, ORIG_NAME), (&self.comment, COMMENT)] {
+            if field.is_some() {
+                flags |= flag;
+            }
+        }
+        flags
+    }
+
+    // Everything FHCRC covers, which is the whole header but FHCRC itself
+    fn fields_bytes(&self) -> Vec<u8> {
+        let magic = if self.old_magic { OLD_GZIP_MAGIC } else { GZIP_MAGIC };
+        let mut bytes = vec![magic[0], magic[1], self.method, self.flag_byte()];
+        bytes.extend_from_slice(&self.mtime.to_le_bytes());
+        bytes.push(self.xfl);
+        bytes.push(self.os);
+        if let Some(extra) = &self.extra {
+            bytes.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(extra);
+        }
+        for text in [&self.name, &self.comment].into_iter().flatten() {
+            bytes.extend_from_slice(text);
+            bytes.push(0);
+        }
+        bytes
+    }
+}
+
+// read_exact, with the message gzip gives for a header cut short
+fn read_bytes<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<()> {
+    r.read_exact(buf).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of file"),
+        _ => err,
+    })
+}
+
+// A name or comment, up to the zero that ends it. One that runs on past MAX_TEXT_LEN is
+// taken for damage rather than read on until the input gives out
+fn read_zero_terminated<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let mut text = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        read_bytes(r, &mut byte)?;
+        if byte[0] == 0 {
+            return Ok(text);
+        }
+        if text.len() == MAX_TEXT_LEN {
+            return Err(HeaderError::TooLong.into());
+        }
+        text.push(byte[0]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind_of(err: io::Error) -> Option<HeaderError> {
+        err.get_ref().and_then(|inner| inner.downcast_ref::<HeaderError>()).cloned()
+    }
+
+    #[test]
+    fn minimal_header() {
+        let bytes = [0x1f, 0x8b, 8, 0, 0x78, 0x56, 0x34, 0x12, 0, 3];
+        let (header, len) = GzipHeader::parse(&mut &bytes[..]).unwrap();
+        assert_eq!(len, bytes.len());
+        let expected = GzipHeader { method: 8, mtime: 0x1234_5678, os: 3, ..GzipHeader::default() };
+        assert_eq!(header, expected);
+        let mut written = Vec::new();
+        assert_eq!(header.write(&mut written).unwrap(), bytes.len());
+        assert_eq!(written, bytes);
+    }
+
+    #[test]
+    fn header_with_every_field() {
+        let header = GzipHeader {
+            method: 8,
+            flags: 0x01 | HEADER_CRC, // FTEXT and FHCRC
+            mtime: 1,
+            xfl: 2,
+            os: 3,
+            extra: Some(b"AB\x02\x00xy".to_vec()),
+            name: Some(b"name".to_vec()),
+            comment: Some(b"comment".to_vec()),
+            ..GzipHeader::default()
+        };
+        let mut bytes = Vec::new();
+        header.write(&mut bytes).unwrap();
+        let mut fields = vec![0x1f, 0x8b, 8, 0x1f, 1, 0, 0, 0, 2, 3, 6, 0];
+        fields.extend_from_slice(b"AB\x02\x00xyname\0comment\0");
+        let crc = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC).checksum(&fields) as u16;
+        assert_eq!(bytes, [&fields[..], &crc.to_le_bytes()].concat());
+
+        let (read, len) = GzipHeader::parse(&mut &bytes[..]).unwrap();
+        assert_eq!(len, bytes.len());
+        assert_eq!(read, GzipHeader { header_crc: Some(crc), ..header });
+
+        // A header CRC that does not match is refused, but read gets past it
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        let err = GzipHeader::parse(&mut &bytes[..]).unwrap_err();
+        assert!(matches!(kind_of(err), Some(HeaderError::Checksum { .. })));
+        assert!(GzipHeader::read(&mut &bytes[..], false).is_ok());
+    }
+
+    #[test]
+    fn reserved_flags_are_refused() {
+        for flag in [0x40, 0x80, RESERVED] {
+            let bytes = [0x1f, 0x8b, 8, flag, 0, 0, 0, 0, 0, 3];
+            let err = GzipHeader::parse(&mut &bytes[..]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(kind_of(err), Some(HeaderError::ReservedFlags(flag)));
+            let (header, _) = GzipHeader::read(&mut &bytes[..], true).unwrap();
+            assert_eq!(header.flags, flag);
+        }
+    }
+
+    // Headers with every mix of the optional fields, of random contents and lengths,
+    // read back as they were written
+    #[test]
+    fn random_headers_round_trip() {
+        let mut seed = 0x2545_f491u32;
+        let mut next = move |below: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 8) % below
+        };
+        for _ in 0..2000 {
+            let mut bytes = |len: u32, zeros: bool| {
+                let len = next(len);
+                (0..len).map(|_| if zeros { next(256) as u8 } else { 1 + next(255) as u8 }).collect::<Vec<u8>>()
+            };
+            let extra = bytes(300, true);
+            let name = bytes(40, false);
+            let comment = bytes(40, false);
+            let mut header = GzipHeader {
+                old_magic: next(4) == 0,
+                method: 8,
+                flags: next(4) as u8 & (0x01 | HEADER_CRC),
+                mtime: next(u32::MAX),
+                xfl: next(256) as u8,
+                os: next(256) as u8,
+                ..GzipHeader::default()
+            };
+            let present = next(8);
+            if present & 1 != 0 {
+                header.extra = Some(extra);
+            }
+            if present & 2 != 0 {
+                header.name = Some(name);
+            }
+            if present & 4 != 0 {
+                header.comment = Some(comment);
+            }
+
+            let mut written = Vec::new();
+            let len = header.write(&mut written).unwrap();
+            written.extend_from_slice(b"data after the header");
+            let mut input = &written[..];
+            let (read, read_len) = GzipHeader::parse(&mut input).unwrap();
+            assert_eq!(read_len, len);
+            assert_eq!(input, b"data after the header");
+            if header.flags & HEADER_CRC != 0 {
+                header.header_crc = Some(header.computed_crc());
+            }
+            assert_eq!(read, header);
+        }
+    }
+}
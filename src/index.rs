@@ -0,0 +1,203 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use crate::inflate::Inflate;
+use crate::mapped::Input;
+use crate::unzip::{check_inflate, read_trailer};
+use crate::{exit_status, strerror, GzipState, SharedOutput};
+
+// Output between checkpoints when --index-span is not given
+pub(crate) const INDEX_SPAN: u64 = 1 << 20;
+
+const INDEX_MAGIC: &[u8; 4] = b"GZIX";
+const INDEX_VERSION: u32 = 1;
+
+// A place inflate can start from: the block that begins bits bits into the compressed
+// file and decompresses to the bytes from out on, with the up to 32K of output before
+// it that its distances may reach back into
+pub(crate) struct Checkpoint {
+    pub(crate) out: u64,
+    pub(crate) bits: u64,
+    pub(crate) window: Vec<u8>,
+}
+
+// What --index keeps of a .gz, zran-style: checkpoints through the whole file, and the
+// sizes of the file they were taken from, so that an index of another file is refused
+pub(crate) struct Index {
+    span: u64,
+    compressed_size: u64,
+    uncompressed_size: u64,
+    points: Vec<Checkpoint>,
+}
+
+impl Index {
+    pub(crate) fn new(span: u64) -> Self {
+        Index { span, compressed_size: 0, uncompressed_size: 0, points: Vec::new() }
+    }
+
+    pub(crate) fn span(&self) -> u64 {
+        self.span
+    }
+
+    // Take on the checkpoints of a member just decompressed, which only know their
+    // place in the member's own output
+    pub(crate) fn add_member(&mut self, points: Vec<Checkpoint>, size: u64) {
+        for mut point in points {
+            point.out += self.uncompressed_size;
+            self.points.push(point);
+        }
+        self.uncompressed_size += size;
+    }
+
+    // Write the index of a .gz of compressed_size bytes. All numbers are little-endian:
+    // the magic, a version, the two sizes, the span and the number of checkpoints, then
+    // for each its output offset, its bit offset, and its window with the length first
+    pub(crate) fn save(&mut self, path: &Path, compressed_size: u64) -> io::Result<()> {
+        self.compressed_size = compressed_size;
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(INDEX_MAGIC)?;
+        out.write_all(&INDEX_VERSION.to_le_bytes())?;
+        for n in [self.compressed_size, self.uncompressed_size, self.span, self.points.len() as u64] {
+            out.write_all(&n.to_le_bytes())?;
+        }
+        for point in &self.points {
+            out.write_all(&point.out.to_le_bytes())?;
+            out.write_all(&point.bits.to_le_bytes())?;
+            out.write_all(&(point.window.len() as u32).to_le_bytes())?;
+            out.write_all(&point.window)?;
+        }
+        out.flush()
+    }
+
+    fn load(path: &Path) -> io::Result<Self> {
+        let mut input = BufReader::new(File::open(path)?);
+        let not_an_index = || io::Error::new(io::ErrorKind::InvalidData, "not a gzip index");
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic).map_err(|_| not_an_index())?;
+        if magic[..4] != INDEX_MAGIC[..] || magic[4..] != INDEX_VERSION.to_le_bytes() {
+            return Err(not_an_index());
+        }
+        let read_u64 = |input: &mut BufReader<File>| -> io::Result<u64> {
+            let mut bytes = [0u8; 8];
+            input.read_exact(&mut bytes).map_err(|_| not_an_index())?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+        let compressed_size = read_u64(&mut input)?;
+        let uncompressed_size = read_u64(&mut input)?;
+        let span = read_u64(&mut input)?;
+        let count = read_u64(&mut input)?;
+        let mut points = Vec::new();
+        for _ in 0..count {
+            let out = read_u64(&mut input)?;
+            let bits = read_u64(&mut input)?;
+            let mut len = [0u8; 4];
+            input.read_exact(&mut len).map_err(|_| not_an_index())?;
+            let len = u32::from_le_bytes(len) as usize;
+            if len > crate::WSIZE || points.last().is_some_and(|last: &Checkpoint| last.out > out) {
+                return Err(not_an_index());
+            }
+            let mut window = vec![0u8; len];
+            input.read_exact(&mut window).map_err(|_| not_an_index())?;
+            points.push(Checkpoint { out, bits, window });
+        }
+        Ok(Index { span, compressed_size, uncompressed_size, points })
+    }
+
+    // The last checkpoint at or before offset
+    fn point_before(&self, offset: u64) -> Option<&Checkpoint> {
+        self.points.iter().rev().find(|point| point.out <= offset)
+    }
+}
+
+// Output of a resumed inflate: what comes before the range and after it is dropped,
+// and the range itself goes to standard output. The counts are of bytes still to skip,
+// then still to write
+#[derive(Clone)]
+struct RangeOutput(Arc<Mutex<(u64, u64)>>, SharedOutput);
+
+impl RangeOutput {
+    // Output that inflate still has to produce for the range to be complete
+    fn wanted(&self) -> u64 {
+        let range = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        range.0.saturating_add(range.1)
+    }
+}
+
+impl Write for RangeOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut range = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let skip = range.0.min(buf.len() as u64) as usize;
+        range.0 -= skip as u64;
+        let take = range.1.min((buf.len() - skip) as u64) as usize;
+        self.1.write_all(&buf[skip..skip + take])?;
+        range.1 -= take as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.1.flush()
+    }
+}
+
+// --seek and --length: write length bytes of the decompressed data from offset on to
+// stdout, decompressing from the last checkpoint before them rather than from the start.
+// Member trailers on the way are skipped unchecked, as only a whole member has its CRC
+pub(crate) fn extract(state: &mut GzipState, path: &Path, index_path: &Path, offset: u64, length: u64) -> io::Result<()> {
+    match extract_range(state, path, index_path, offset, length) {
+        Ok(()) => {}
+        // A write error stops the run
+        Err(err) if exit_status(&err).is_some() => return Err(err),
+        // A data error has been reported already, and set the exit code
+        Err(_) if state.exit_code == crate::ERROR => {}
+        Err(err) => {
+            eprintln!("{}: {}", state.program_name, err);
+            state.exit_code = crate::ERROR;
+        }
+    }
+    Ok(())
+}
+
+fn extract_range(state: &mut GzipState, path: &Path, index_path: &Path, offset: u64, length: u64) -> io::Result<()> {
+    let in_file = |what: &Path, err: io::Error| io::Error::new(err.kind(), format!("{}: {}", what.display(), strerror(&err)));
+    let index = Index::load(index_path).map_err(|err| in_file(index_path, err))?;
+    let mut file = File::open(path).map_err(|err| in_file(path, err))?;
+    if file.metadata()?.len() != index.compressed_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: index does not match {}", index_path.display(), path.display()),
+        ));
+    }
+    let point = match index.point_before(offset) {
+        Some(point) if length > 0 && offset < index.uncompressed_size => point,
+        _ => return Ok(()),
+    };
+
+    state.ifname = path.to_path_buf();
+    state.clear_bufs();
+    file.seek(SeekFrom::Start(point.bits / 8))?;
+    state.bytes_in = (point.bits / 8) as i64;
+    state.ifd = Some(Input::Reader(Box::new(file)));
+    state.part_nb = 1;
+    let output = RangeOutput(Arc::new(Mutex::new((offset - point.out, length))), state.std_streams.output.clone());
+    state.ofd = Some(Box::new(output.clone()));
+
+    let mut inflate = Inflate::new().with_dictionary(&point.window).with_start_bits((point.bits % 8) as u32);
+    loop {
+        inflate = inflate.with_output_limit(output.wanted());
+        let res = inflate.inflate(state);
+        check_inflate(&mut inflate, state, res)?;
+        if output.wanted() == 0 {
+            break;
+        }
+        // The range goes on into the next member, if there is one
+        let mut trailer = [0u8; 8];
+        read_trailer(&mut inflate, state, &mut trailer)?;
+        if state.input_eof()? || state.get_method()?.is_none() {
+            break;
+        }
+        inflate = Inflate::new();
+    }
+    state.ifd = None;
+    Ok(())
+}
@@ -1,9 +1,10 @@
 use std::io;
 use std::ptr::null_mut;
 use crate::GzipState;
+use crate::index::Checkpoint;
 use crate::trees::Trees;
-use crate::{OK, ERROR, STORED, WSIZE, INBUFSIZ};
-use std::io::{stdout, Read, Write, Cursor};
+use crate::{OK, ERROR, STORED, WSIZE, TRACE_BLOCKS};
+use std::sync::OnceLock;
 
 #[derive(Debug)]
 #[derive(Clone)]
@@ -36,25 +37,75 @@ impl Default for Huft {
     }
 }
 
-fn huft_free(t: Option<&Huft>) -> usize {
-    if let Some(huft) = t {
-        match &huft.v {
-            HuftValue::T(sub_table) => {
-                // 遍历子表并递归计算释放的节点数量
-                sub_table.iter().map(|sub_huft| huft_free(Some(sub_huft))).sum::<usize>() + 1
+// Sub-tables are handed to huft_free, so dropping a table never recurses once per level
+impl Drop for Huft {
+    fn drop(&mut self) {
+        if let HuftValue::T(table) = std::mem::take(&mut self.v) {
+            huft_free(Some(table));
+        }
+    }
+}
+
+// Free a table and all its sub-tables, returning the number of entries freed.
+// Sub-tables are detached onto a worklist before their parent is dropped.
+fn huft_free(t: Option<Box<[Huft]>>) -> usize {
+    let mut freed = 0;
+    let mut pending: Vec<Box<[Huft]>> = t.into_iter().collect();
+    while let Some(mut table) = pending.pop() {
+        freed += table.len();
+        for entry in table.iter_mut() {
+            if let HuftValue::T(sub_table) = std::mem::take(&mut entry.v) {
+                pending.push(sub_table);
             }
-            HuftValue::N(_) => 1, // 叶子节点直接返回 1
         }
+    }
+    freed
+}
+
+// Decoding tables for fixed Huffman blocks, built once per process and never freed.
+// The literal/length codes are 7 to 9 bits, so huft_build turns any lbits into one
+// of three table sizes; the distance codes are all 5 bits
+struct FixedTables {
+    tl: Box<[Huft]>, // Literal/length table
+    td: Box<[Huft]>, // Distance table
+    bl: i32,         // Lookup bits for `tl`
+    bd: i32,         // Lookup bits for `td`
+}
+
+static FIXED_TABLES: [OnceLock<FixedTables>; 3] = [OnceLock::new(), OnceLock::new(), OnceLock::new()];
+
+// The error for input that stops before the compressed data does
+fn unexpected_eof() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of file")
+}
+
+// Map a huft_build failure for a dynamic block to the inflate() result
+fn code_set_error(result: u32) -> i32 {
+    if result == 1 {
+        INCOMPLETE_CODE_SET
     } else {
-        0 // None 时返回 0
+        OVERSUBSCRIBED_CODE_SET
     }
 }
 
+// RFC 1951 allows an incomplete literal/length or distance code only when it
+// holds a single one-bit code
+fn lone_code(lengths: &[u32]) -> bool {
+    let mut codes = lengths.iter().filter(|&&len| len != 0);
+    matches!((codes.next(), codes.next()), (Some(1), None))
+}
 
-fn find_huft_entry(current: &Huft, index: usize) -> Option<&Huft> {
-    match &current.v {
-        HuftValue::T(sub_table) => sub_table.get(index),
-        HuftValue::N(_) => None,
+// Link a finished sub-table into its parent: the top of the table stack goes
+// into the parent's entry at `index`, with `e` giving the sub-table's bits
+fn huft_link(u: &mut Vec<(Vec<Huft>, usize, u8)>, l: i32) {
+    if let Some((table, index, e)) = u.pop() {
+        if let Some((parent, _, _)) = u.last_mut() {
+            parent[index] = Huft {
+                v: HuftValue::T(table.into_boxed_slice()),
+                e, // Bits in this table, plus 16
+                b: l as u8, // Bits to dump before this table
+            };
+        }
     }
 }
 
@@ -103,6 +154,16 @@ static mask_bits: [u32; 17] = [
 // Constants
 const BMAX: i32 = 16;      // maximum bit length of any code (16 for explode)
 const N_MAX: i32 = 288;    // maximum number of codes in any set
+const MAX_TABLE_DEPTH: usize = BMAX as usize; // most table levels one code may need, at one bit per level
+
+// inflate() result for a match that reaches back before the first byte of output
+pub const DISTANCE_TOO_FAR: i32 = 5;
+// inflate() results for dynamic block code lengths that leave codes unused,
+// or that describe more codes than the bit lengths allow
+pub const INCOMPLETE_CODE_SET: i32 = 6;
+pub const OVERSUBSCRIBED_CODE_SET: i32 = 7;
+// inflate() result when the input ends or fails mid-stream; the reason is in take_input_error()
+pub const INPUT_ERROR: i32 = 8;
 
 // Function prototypes
 // static mut HUFT_FREE: fn(*mut Huft) -> i32 = huft_free;
@@ -111,10 +172,20 @@ pub struct Inflate {
     bb: u32,
     bk: u32,
     wp: usize,
+    written: usize, // Bytes of slide[..wp] already written out ahead of a read (--sync-flush)
     lbits: i32,
     dbits: i32,
     hufts: u32,
+    total_out: u64, // History a distance may reach into: the dictionary and the bytes flushed so far
     slide: [u8; 2 * WSIZE],
+    dictionary: Vec<u8>, // Preset dictionary, at most WSIZE bytes, that comes before the output
+    output_error: Option<io::Error>, // Why flush_output refused the data, behind result 4
+    input_error: Option<io::Error>, // Why need_bits ran out of input, behind INPUT_ERROR
+    padding: u32, // Zero bytes need_bits made up past the end of the input
+    start_bits: u32, // Bits of the first byte that belong to the data before a resumed start
+    output_limit: Option<u64>, // Stop at the first block boundary past this much output
+    checkpoint_span: Option<u64>, // Output between the checkpoints kept for --index
+    checkpoints: Vec<Checkpoint>,
 }
 
 impl Inflate {
@@ -123,79 +194,137 @@ impl Inflate {
             bb: 0,
             bk: 0,
             wp: 0,
+            written: 0,
             lbits: 9,
             dbits: 6,
             hufts: 0,
+            total_out: 0,
             slide: [0; 2 * WSIZE],
+            dictionary: Vec::new(),
+            output_error: None,
+            input_error: None,
+            padding: 0,
+            start_bits: 0,
+            output_limit: None,
+            checkpoint_span: None,
+            checkpoints: Vec::new(),
         }
     }
 
-    pub fn fill_inbuf<R: Read>(&mut self, input: &mut R, eof_ok: bool, state: &mut GzipState) -> io::Result<u8> {
-        state.insize = 0;
-        loop {
-            let len = self.read_buffer(input, state)?;
-            if len == 0 {
-                break;
-            }
-            state.insize += len;
-            if state.insize >= INBUFSIZ {
-                break;
-            }
-        }
+    // Set the lookup bits for the first level of the literal/length and distance
+    // tables (--inflate-bits): wider tables use more memory but walk fewer sub-tables
+    pub fn with_table_bits(mut self, lbits: i32, dbits: i32) -> Self {
+        self.lbits = lbits;
+        self.dbits = dbits;
+        self
+    }
 
-        if state.insize == 0 {
-            if eof_ok {
-                return Ok(0xFF);
-            }
-            self.flush_window(state)?;
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Unexpected EOF"));
-        }
-        state.bytes_in += state.insize as i64;
-        state.inptr = 1;
-        Ok(state.inbuf[0])
+    // Let distances reach back into a preset dictionary (--dictionary). Only its last
+    // WSIZE bytes can be referred to, so only those are kept
+    pub fn with_dictionary(mut self, dictionary: &[u8]) -> Self {
+        self.dictionary = dictionary[dictionary.len().saturating_sub(WSIZE)..].to_vec();
+        self
     }
 
-    pub fn read_buffer<R: Read>(&mut self, input: &mut R, state: &mut GzipState) -> io::Result<usize> {
-        let buffer = &mut state.inbuf[state.insize..INBUFSIZ];
-        let len = input.read(buffer)?;
+    // Start at a block that begins this many bits into the first byte read, as a
+    // checkpoint of --index may. The window that comes with it is a with_dictionary
+    pub fn with_start_bits(mut self, bits: u32) -> Self {
+        self.start_bits = bits;
+        self
+    }
 
-        // Output the data read and its length for debugging
-        if len > 0 {
-            print!("Read {} bytes: ", len);
-            for byte in &buffer[..len] {
-                print!("{:02x} ", byte);
-            }
-            println!();
-        } else if len == 0 {
-            println!("Reached end of file");
-        }
+    // Stop once a block takes the output to limit bytes; the whole block is still written
+    pub fn with_output_limit(mut self, limit: u64) -> Self {
+        self.output_limit = Some(limit);
+        self
+    }
 
-        Ok(len)
+    // Keep a checkpoint at the start and then at the first block boundary every span
+    // bytes of output, for take_checkpoints
+    pub fn with_checkpoints(mut self, span: u64) -> Self {
+        self.checkpoint_span = Some(span);
+        self
     }
 
-    pub fn flush_window(&mut self, state: &mut GzipState) -> std::io::Result<()> {
-        if state.outcnt == 0 {
-            return Ok(());
-        }
+    pub fn take_checkpoints(&mut self) -> Vec<Checkpoint> {
+        std::mem::take(&mut self.checkpoints)
+    }
 
-        state.updcrc(Some(&state.window.clone()), state.outcnt);
+    // Where the next block starts, and the window it may refer back to, oldest byte first
+    fn checkpoint(&self, state: &GzipState, out: u64) -> Checkpoint {
+        let mut window = Vec::new();
+        if self.total_out > 0 {
+            window.extend_from_slice(&self.slide[self.wp..WSIZE]);
+        }
+        window.extend_from_slice(&self.slide[..self.wp]);
+        Checkpoint { out, bits: self.bits_read(state) as u64, window }
+    }
 
-        if !state.test {
-            state.ofd.as_mut().expect("REASON").write_all(&state.window[0..state.outcnt])?;
-//             state.write_buf(&mut state.ofd, &state.window[0..state.outcnt], state.outcnt);
+    // Append a byte to the window for the pkzip stored method, writing the window
+    // out when it fills up
+    pub fn put_byte(&mut self, c: u8, state: &mut GzipState) -> io::Result<()> {
+        self.slide[self.wp] = c;
+        self.wp += 1;
+        if self.wp == WSIZE {
+            self.flush_window(state)?;
         }
+        Ok(())
+    }
 
-        state.bytes_out += state.outcnt as i64;
-        state.outcnt = 0;
+    // Write out whatever the window holds (flush_window in C)
+    pub fn flush_window(&mut self, state: &mut GzipState) -> io::Result<()> {
+        let w = self.wp;
+        state.write_decoded(&self.slide[self.written..w])?;
+        self.total_out += w as u64;
+        self.wp = 0;
+        self.written = 0;
         Ok(())
     }
 
     // Function to flush output (equivalent to macro flush_output in C)
-    pub fn flush_output(&mut self, state: &mut GzipState, w: usize) {
-        unsafe {
-            self.wp = w;
+    // A failure is kept in output_error for unzip, and the caller returns 4
+    pub fn flush_output(&mut self, state: &mut GzipState, w: usize) -> bool {
+        self.wp = w;
+        match state.write_decoded(&self.slide[self.written..w]) {
+            Ok(()) => {
+                self.total_out += w as u64;
+                // A full window is written from its start again
+                self.written = w % WSIZE;
+                true
+            }
+            Err(err) => {
+                self.output_error = Some(err);
+                false
+            }
+        }
+    }
+
+    // With --sync-flush, write out what is decoded before waiting for more input, so that
+    // the output keeps up with a stream that pauses. The window keeps it for distances
+    fn write_ahead(&mut self, state: &mut GzipState, w: usize) -> io::Result<()> {
+        if state.opts.sync_flush && w > self.written {
+            let written = state.write_decoded(&self.slide[self.written..w])
+                .and_then(|_| state.ofd.as_mut().map_or(Ok(()), |ofd| ofd.flush()));
+            if let Err(err) = written {
+                self.output_error = Some(err);
+                return Err(io::Error::other("write error"));
+            }
+            self.written = w;
+        }
+        Ok(())
+    }
+
+    pub fn take_output_error(&mut self) -> io::Error {
+        self.output_error.take().unwrap_or_else(|| io::Error::other("write error"))
+    }
+
+    // Refill inbuf from the input being decompressed and return its first byte
+    fn refill(&mut self, eof_ok: bool, state: &mut GzipState) -> io::Result<u8> {
+        match state.fill_inbuf()? {
+            Some(byte) => Ok(byte),
+            None if eof_ok => Ok(0xFF),
+            None => Err(unexpected_eof()),
         }
-        self.flush_window(state);
     }
 
     pub fn get_byte(&mut self, state: &mut GzipState) -> io::Result<u8> {
@@ -204,8 +333,7 @@ impl Inflate {
             state.inptr += 1;                // Increment the pointer
             Ok(byte)
         } else {
-            let mut input = Cursor::new(vec![0; 1]);
-            Ok(self.fill_inbuf(&mut input, true, state)?)
+            self.refill(false, state)
         }
     }
 
@@ -216,8 +344,7 @@ impl Inflate {
             state.inptr += 1;                // Increment the pointer
             Ok(byte)
         } else {
-            let mut input = Cursor::new(vec![1; 1]);
-            Ok(self.fill_inbuf(&mut input, true, state)?)
+            self.refill(true, state)
         }
     }
 
@@ -228,10 +355,9 @@ impl Inflate {
             state.inptr += 1;
             Ok(byte)
         } else {
-            self.wp = w; // This part needs clarification based on your code
-//             let mut input = Cursor::new(vec![0; 1]);
-//             self.fill_inbuf(&mut input, true, state)?;
-            Ok(0) // Placeholder, adjust logic as per the context
+            self.wp = w;
+            self.write_ahead(state, w)?;
+            self.refill(false, state)
         }
     }
 
@@ -240,16 +366,34 @@ impl Inflate {
         self.Get_Byte(state, w)
     }
 
-    // Equivalent to the NEEDBITS macro (requiring more information to be fully accurate)
-    pub fn need_bits(&mut self, state: &mut GzipState,k: &mut u32, b: &mut u32, n: u32, w: usize)  {
+    // Equivalent to the NEEDBITS macro
+    // A failed read is kept in input_error for unzip, and the caller returns INPUT_ERROR
+    pub fn need_bits(&mut self, state: &mut GzipState,k: &mut u32, b: &mut u32, n: u32, w: usize) -> bool {
+        // Bits made up past the end of the input may be looked at, but never used
+        if *k < 8 * self.padding {
+            self.input_error = Some(unexpected_eof());
+            return false;
+        }
         while *k < n {
-                let byte = match self.next_byte(state, w) {
-                    Ok(value) => value, Err(_) => todo!(),
-                };
-                *b |= (u32::from(byte)) << *k;
-
-                *k += 8;
+            match self.next_byte(state, w) {
+                Ok(byte) => *b |= (u32::from(byte)) << *k,
+                // A table lookup can want more bits than the last code of a raw stream
+                // has after it; zero bytes stand in for them
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof && self.padding < 4 => {
+                    self.padding += 1;
+                }
+                Err(err) => {
+                    self.input_error = Some(err);
+                    return false;
+                }
             }
+            *k += 8;
+        }
+        true
+    }
+
+    pub fn take_input_error(&mut self) -> io::Error {
+        self.input_error.take().unwrap_or_else(unexpected_eof)
     }
 
     // Equivalent to DUMPBITS macro
@@ -258,6 +402,11 @@ impl Inflate {
         *k = *k - n;
     }
 
+    // Given a list of code lengths and a maximum table size, make a set of
+    // tables to decode that set of codes. Return zero on success, one if the
+    // given code set is incomplete (the tables are still built in this case),
+    // and two if the input is invalid (an oversubscribed set of lengths or
+    // tables nested deeper than MAX_TABLE_DEPTH).
     pub fn huft_build(
         &mut self,
         b: &[u32],   // Code lengths in bits
@@ -265,241 +414,171 @@ impl Inflate {
         s: usize,    // Number of simple-valued codes (0..s-1)
         d: &[u16],   // List of base values for non-simple codes
         e: &[u16],   // List of extra bits for non-simple codes
-        t: &mut Option<Box<Huft>>, // Result: starting table
+        t: &mut Option<Box<[Huft]>>, // Result: starting table
         m: &mut i32, // Maximum lookup bits, returns actual
     ) -> u32 {
-        let mut c = [0u32; BMAX as usize + 1];
-        let mut x = [0u32; BMAX as usize + 1];
-        let mut v = [0u32; N_MAX as usize];
-        let mut u: Vec<Box<[Huft]>> = Vec::new();
-
-        let mut a;
-        let mut f: u32;
-        let mut g: u32;
-        let mut h: i32 = -1;
-        let mut l = *m;
-        let mut w = -l;
-
-        // Debugging: Initial inputs
-        println!("huft_build called with:");
-        println!("b = {:?}, n = {}, s = {}, d = {:?}, e = {:?}", b, n, s, d, e);
+        let mut c = [0u32; BMAX as usize + 1]; // Bit length count table
+        let mut v = [0u32; N_MAX as usize];    // Values in order of bit length
+        let mut x = [0u32; BMAX as usize + 1]; // Bit offsets, then code stack
 
         // Generate counts for each bit length
         for &bit in b.iter().take(n) {
             c[bit as usize] += 1;
         }
-
-        // Debugging: After counting the bits
-        println!("Bit counts (c): {:?}", c);
-
         if c[0] == n as u32 {
-            *t = Some(Box::new(Huft {
-                v: HuftValue::T(Box::new([])),
-                e: 99,
-                b: 1,
-            }));
+            // Null input--all zero length codes
+            *t = Some(vec![Huft { v: HuftValue::N(0), e: 99, b: 1 }; 2].into_boxed_slice());
+            self.hufts += 2;
             *m = 1;
             return 0;
         }
 
-        // Find minimum and maximum length
-        let mut k = c.iter().position(|&x| x != 0).unwrap_or(0) as i32;
-        g = (1..=BMAX).rev().find(|&x| c[x as usize] != 0).unwrap_or(0) as u32 ;
-        l = l.clamp(k, g as i32);
+        // Find minimum and maximum length, bound *m by those
+        let k = (1..=BMAX).find(|&j| c[j as usize] != 0).unwrap_or(BMAX); // Minimum code length
+        let g = (1..=BMAX).rev().find(|&j| c[j as usize] != 0).unwrap_or(0); // Maximum code length
+        let l = (*m).max(k).min(g); // Bits per table
         *m = l;
 
-        // Debugging: Min and max lengths
-        println!("Minimum length k = {}, Maximum length g = {}, Clamped l = {}", k, g, l);
-
-        // Adjust last length count
-        let mut y = 1 << k;
-        for j in k..g as i32 {
-            y -= c[j as usize];
+        // Adjust last length count to fill out codes, if needed
+        let mut y: i32 = 1 << k; // Number of dummy codes added
+        for j in k..g {
+            y -= c[j as usize] as i32;
             if y < 0 {
-                return 2;
+                return 2; // Bad input: more codes than bits
             }
             y <<= 1;
         }
-
-        y -= c[g as usize];
+        y -= c[g as usize] as i32;
         if y < 0 {
             return 2;
         }
-        c[g as usize] += y;
+        c[g as usize] += y as u32;
 
-        // Generate starting offsets
-        let mut j = 0;
-        for i in 1..=BMAX {
-            x[i as usize] = j;
-            j += c[i as usize] as u32;
+        // Generate starting offsets into the value table for each length
+        x[1] = 0;
+        for j in 2..=g as usize {
+            x[j] = x[j - 1] + c[j - 1];
         }
 
-        // Debugging: Offsets after initial calculation
-        println!("Offsets (x): {:?}", x);
-
-        // Populate values array
+        // Make a table of values in order of bit lengths
         for (i, &bit) in b.iter().enumerate().take(n) {
             if bit != 0 {
                 v[x[bit as usize] as usize] = i as u32;
                 x[bit as usize] += 1;
             }
         }
+        let n = x[g as usize] as usize; // Set n to length of v
 
-        // Debugging: Values after population
-        println!("Values (v): {:?}", v);
-
-        let n = x[g as usize] as usize;
-
-        // Generate Huffman codes and build tables
+        // Generate the Huffman codes and for each, make the table entries
         x[0] = 0;
-        let mut i = 0;
-        let mut p = v.iter();
-        let mut z = 0;
-
-        for k in k..=g as i32 {
-            a = c[k as usize];
-
-
+        let mut i: u32 = 0; // The Huffman code of length k bits for value v[p]
+        let mut p = 0;      // Next value in v
+        let mut h: i32 = -1; // Table level, no tables yet
+        let mut w: i32 = -l; // Bits decoded == (l * h)
+        // Table stack: each table being filled, with the entry and e that will link it into its parent
+        let mut u: Vec<(Vec<Huft>, usize, u8)> = Vec::new();
+
+        // Go through the bit lengths (k already is bits in shortest code)
+        for k in k..=g {
+            let mut a = c[k as usize]; // Counter for codes of length k
             while a > 0 {
-//                 println!("--- k: {}, a: {}, w: {}， l: {}", k, a, w, l);  // Debug: output current k and a
+                a -= 1;
+
+                // Make tables up to required level
                 while k > w + l {
                     h += 1;
-                    w += l;  // Previous table always l bits
+                    w += l; // Previous table always l bits
+                    if h as usize >= MAX_TABLE_DEPTH {
+                        return 2;
+                    }
 
                     // Compute minimum size table less than or equal to l bits
-                    let z = if (g as i32 - w ) as u32 > l as u32 { l as u32 } else { (g as i32 - w ) as u32 };
-                    j = (k - w ) as u32;
-                    let mut f = 1 << j; // Try a k-w bit table
-                    println!("    f: {}, z: {}", f, z);  // Debug: output f and z
-
+                    let z = ((g - w) as u32).min(l as u32); // Upper limit on table size
+                    // A code more than l bits past this level gets an l-bit table here and
+                    // continues in the next level, so no table is ever wider than l bits
+                    let mut j = ((k - w) as u32).min(z);
+                    let mut f: u32 = 1 << j; // Try a k-w bit table
                     if f > a + 1 {
-                        f -= a + 1;  // Deduct codes from patterns left
-                        let mut xp_index = k as usize;  // Start at index k
-                        let mut j = 0;
-
-                        if j < z {
-                            while j < z {
-                                f <<= 1;
-                                if f <= c[xp_index] {
-                                    break;  // Enough codes to use up j bits
-                                }
-                                f -= c[xp_index];
-                                j += 1;
-                                xp_index += 1;  // Increment the index to move to the next element
+                        // Too few codes for k-w bit table
+                        f -= a + 1; // Deduct codes from patterns left
+                        let mut xp = k as usize;
+                        while j < z {
+                            // Try smaller tables up to z bits
+                            j += 1;
+                            if j == z {
+                                break;
+                            }
+                            f <<= 1;
+                            xp += 1;
+                            if f <= c[xp] {
+                                break; // Enough codes to use up j bits
                             }
+                            f -= c[xp]; // Else deduct codes from patterns
                         }
                     }
-                    let z = 1 << j;  // Table entries for j-bit table
-                    println!("    New z: {}", z);  // Debug: output new z
-
-                    // Allocate and link in new table
-                    let mut q: Box<[Huft]> = vec![Huft::default(); (z + 1) as usize].into_boxed_slice();
-                    self.hufts += z + 1;  // Track memory usage
-
-                    // Link to list for huft_free
-                    *t = Some(Box::new(Huft {
-                        v: HuftValue::T(q.clone()),  // Link to new table
-                        e: 0,                         // Extra bits
-                        b: 0,                         // Number of bits
-                    }));
-                    let mut  u_len = u.len() as i32;  // 计算长度并存储
-                    while u.len() <= (u_len + h) as usize {
-                        u.push(Box::new([]));  // 或者根据实际需要添加默认值
-                        println!("stuck");
-                    }
+                    let z = 1usize << j; // Table entries for j-bit table
+                    self.hufts += z as u32; // Track memory usage
 
-                    u[(u_len + h) as usize] = q.clone();
-                    println!("    Table at h: {}, size: {}", h, q.len());  // Debug: output table size
-
-                    u_len = u.len() as i32;
-                    // Connect to last table, if there is one
-                    if h > 0 {
-                        x[h as usize] = i;  // Save pattern for backing up
-                        let r = Huft {
-                            b: l as u8,         // Bits to dump before this table
-                            e: (16 + j) as u8,  // Bits in this table
-                            v: HuftValue::T(q.clone()), // Pointer to this table
-                        };
-                        let j = (i >> (w - l)) as usize;
-                        u[h as usize - 1][j] = r;  // Connect to last table
-                        println!("    Connected to previous table, u[{}][{}] updated", h - 1, j);  // Debug
-                    }
+                    // Remember where to connect to the last table, if there is one
+                    let index = if h > 0 {
+                        x[h as usize] = i; // Save pattern for backing up
+                        (i >> (w - l)) as usize
+                    } else {
+                        0
+                    };
+                    u.push((vec![Huft::default(); z], index, (16 + j) as u8));
                 }
 
-                let mut r = Huft::default();
-                r.b = (k - w) as u8;
-
-                if let Some(&val) = p.next() {
-                    if val < s as u32 {
-                        r.e = if val < 256 { 16 } else { 15 };
-                        r.v = HuftValue::N(val as u16);
-                    } else {
-                        r.e = e[val as usize - s] as u8;
-                        r.v = HuftValue::N(d[val as usize - s]);
-                    }
+                // Set up table entry in r
+                let mut r = Huft { v: HuftValue::N(0), e: 99, b: (k - w) as u8 };
+                if p >= n {
+                    r.e = 99; // Out of values--invalid code
+                } else if (v[p] as usize) < s {
+                    r.e = if v[p] < 256 { 16 } else { 15 }; // 256 is end-of-block code
+                    r.v = HuftValue::N(v[p] as u16); // Simple code is just the value
+                    p += 1;
                 } else {
-                    r.e = 99;
+                    r.e = e[v[p] as usize - s] as u8; // Non-simple--look up in lists
+                    r.v = HuftValue::N(d[v[p] as usize - s]);
+                    p += 1;
                 }
-//                 println!("    r: {:?}, e: {}, v: {:?}", r, r.e, r.v);  // Debug: output r
 
-                let mut f = 1 << (k - w);
-                let mut i = i >> w;
-//                 println!("    f: {}, i (after shift): {}", f, i);  // Debug: output f and shifted i
-
-                if let Some(subtable) = u.last_mut() {
-                    for j in (i as usize)..z {
-                        if j % f == 0 {
-                            subtable[j] = r.clone();
-                            println!("        Subtable[{}] = r", j);  // Debug: output subtable index and assignment
-                        }
+                // Fill code-like entries with r
+                let f = 1usize << (k - w);
+                if let Some((q, _, _)) = u.last_mut() {
+                    let mut j = (i >> w) as usize;
+                    while j < q.len() {
+                        q[j] = r.clone();
+                        j += f;
                     }
                 }
 
-                let mut j = 1 << (k - 1);
+                // Backwards increment the k-bit code i
+                let mut j: u32 = 1 << (k - 1);
                 while i & j != 0 {
-                    j >>= 1;
                     i ^= j;
+                    j >>= 1;
                 }
                 i ^= j;
-//                 println!("    i (after code increment): {}", i);  // Debug: output updated i
 
-                // Back up to the previous table if necessary
-                let mut x_index = if h >= 0 {
-                    h
-                } else {
-                    x.len() as i32 + h
-                };
-                while (i & ((1 << w) - 1)) != x[x_index as usize] {
+                // Back up over finished tables
+                while (i & ((1 << w) - 1)) != x[h as usize] {
+                    huft_link(&mut u, l);
                     h -= 1;
                     w -= l;
-                    x_index = if h >= 0 {
-                        h
-                    } else {
-                        x.len() as i32 + h
-                    };
-                    println!("    Backing up: h = {}, w = {}", h, w);  // Debug: output h and w during backup
                 }
-
-                a -= 1;
             }
         }
 
-        // Debugging: Final subtable count
-        println!("Final number of subtables: {}", u.len());
-
-        // Set result table
-        if let Some(subtable) = u.pop() {
-            *t = Some(Box::new(Huft {
-                v: HuftValue::T(subtable),
-                e: 0,
-                b: 0,
-            }));
+        // Link whatever tables are still open and return the first one
+        while u.len() > 1 {
+            huft_link(&mut u, l);
         }
+        *t = u.pop().map(|(table, _, _)| table.into_boxed_slice());
 
-        // Debugging: Final result table
-        println!("Final table: {:?}", t);
-
-        (y != 0 && g != 1) as u32
+        // Return true (1) if we were given an incomplete table; the callers
+        // decide whether that set is allowed
+        (y != 0) as u32
     }
 
 
@@ -508,8 +587,8 @@ impl Inflate {
     pub fn inflate_codes(
         &mut self,
         state: &mut GzipState,
-        tl: &Option<Box<Huft>>, // Literal/length table
-        td: &Option<Box<Huft>>, // Distance table
+        tl: &[Huft], // Literal/length table
+        td: &[Huft], // Distance table
         bl: &mut i32,                // Number of bits for literal/length table
         bd: &mut i32,                // Number of bits for distance table
     ) -> i32 {
@@ -522,18 +601,10 @@ impl Inflate {
 
         loop {
             // Get a literal/length code
-            self.need_bits(state, &mut k, &mut b, *bl as u32, w);
-            let index = (b & ml) as usize;
-
-            // Traverse the literal/length table
-            let mut t = match tl {
-                Some(t) => &**t,
-                None => return 2,
-            };
-            while let HuftValue::T(ref table) = t.v {
-                t = &table[index];
+            if !self.need_bits(state, &mut k, &mut b, *bl as u32, w) {
+                return INPUT_ERROR;
             }
-
+            let mut t = &tl[(b & ml) as usize];
             let mut e = t.e;
             while e > 16 {
                 if e == 99 {
@@ -542,7 +613,9 @@ impl Inflate {
                 self.dump_bits(&mut k, &mut b, t.b as u32);
                 e -= 16;
 
-                self.need_bits(state, &mut k, &mut b, e as u32, w);
+                if !self.need_bits(state, &mut k, &mut b, e as u32, w) {
+                    return INPUT_ERROR;
+                }
                 let index = (b & mask_bits[e as usize]) as usize;
 
                 if let HuftValue::T(ref table) = t.v {
@@ -564,7 +637,9 @@ impl Inflate {
                 self.slide[w] = n as u8;
                 w += 1;
                 if w == WSIZE {
-                    self.flush_output(state, w);
+                    if !self.flush_output(state, w) {
+                        return 4;
+                    }
                     w = 0;
                 }
             } else {
@@ -574,7 +649,9 @@ impl Inflate {
                 }
 
                 // Get length of block to copy
-                self.need_bits(state, &mut k, &mut b, e as u32, w);
+                if !self.need_bits(state, &mut k, &mut b, e as u32, w) {
+                    return INPUT_ERROR;
+                }
                 let mut n = match t.v {
                     HuftValue::N(n) => n as usize + (b & mask_bits[e as usize]) as usize,
                     _ => panic!("Expected HuftValue::N, but found HuftValue::T"),
@@ -582,18 +659,10 @@ impl Inflate {
                 self.dump_bits(&mut k, &mut b, e as u32);
 
                 // Get distance of block to copy
-                self.need_bits(state, &mut k, &mut b, *bd as u32, w);
-                let index = (b & md) as usize;
-
-                // Traverse the distance table
-                let mut t = match td {
-                    Some(t) => &**t,
-                    None => return 2,
-                };
-                while let HuftValue::T(ref table) = t.v {
-                    t = &table[index];
+                if !self.need_bits(state, &mut k, &mut b, *bd as u32, w) {
+                    return INPUT_ERROR;
                 }
-
+                let mut t = &td[(b & md) as usize];
                 let mut e = t.e;
                 while e > 16 {
                     if e == 99 {
@@ -602,7 +671,9 @@ impl Inflate {
                     self.dump_bits(&mut k, &mut b, t.b as u32);
                     e -= 16;
 
-                    self.need_bits(state, &mut k, &mut b, e as u32, w);
+                    if !self.need_bits(state, &mut k, &mut b, e as u32, w) {
+                        return INPUT_ERROR;
+                    }
                     let index = (b & mask_bits[e as usize]) as usize;
 
                     if let HuftValue::T(ref table) = t.v {
@@ -615,37 +686,41 @@ impl Inflate {
 
                 self.dump_bits(&mut k, &mut b, t.b as u32);
 
-                self.need_bits(state, &mut k, &mut b, e as u32, w);
-                let mut d = match t.v {
-                    HuftValue::N(n) => w as isize - n as isize - (b & mask_bits[e as usize]) as isize,
+                if !self.need_bits(state, &mut k, &mut b, e as u32, w) {
+                    return INPUT_ERROR;
+                }
+                let dist = match t.v {
+                    HuftValue::N(n) => n as usize + (b & mask_bits[e as usize]) as usize,
                     _ => panic!("Expected HuftValue::N, but found HuftValue::T"),
                 };
                 self.dump_bits(&mut k, &mut b, e as u32);
 
+                // The match must start inside the window and after the first byte of output
+                if dist > WSIZE || dist as u64 > self.total_out + w as u64 {
+                    return DISTANCE_TOO_FAR;
+                }
+                let mut d = w + WSIZE - dist;
+
                 // Copy block
                 while n > 0 {
-                    let e = (if d >= 0 {
-                        WSIZE - d as usize
-                    } else {
-                        w - d as usize
-                    })
-                    .min(n);
-
-                    if d >= 0 && d + e as isize <= w as isize {
-                        self.slide.copy_within(d as usize..d as usize + e, w);
-                        w += e;
-                        d += e as isize;
-                    } else {
-                        for _ in 0..e {
-                            self.slide[w] = self.slide[d as usize];
-                            w += 1;
-                            d += 1;
-                        }
-                    }
+                    d &= WSIZE - 1;
+                    let mut e = (WSIZE - d.max(w)).min(n);
                     n -= e;
 
+                    // A source less than e bytes behind the output repeats itself:
+                    // copy it a distance at a time, so each slice is already written
+                    while e > 0 {
+                        let len = if d < w { e.min(w - d) } else { e };
+                        self.slide.copy_within(d..d + len, w);
+                        w += len;
+                        d += len;
+                        e -= len;
+                    }
+
                     if w == WSIZE {
-                        self.flush_output(state, w);
+                        if !self.flush_output(state, w) {
+                            return 4;
+                        }
                         w = 0;
                     }
                 }
@@ -677,30 +752,63 @@ impl Inflate {
         self.dump_bits(&mut k, &mut b, n);
 
         // get the length and its complement
-        self.need_bits(state, &mut k, &mut b, 16, w);
+        if !self.need_bits(state, &mut k, &mut b, 16, w) {
+            return INPUT_ERROR;
+        }
         n = (b & 0xffff) as u32;
         self.dump_bits(&mut k, &mut b, 16);
-        self.need_bits(state, &mut k, &mut b, 16,w);
+        if !self.need_bits(state, &mut k, &mut b, 16, w) {
+            return INPUT_ERROR;
+        }
+        // need_bits reads no further than the header here, so padding means it was cut short
+        if self.padding > 0 {
+            self.input_error = Some(unexpected_eof());
+            return INPUT_ERROR;
+        }
 
         if n != (!b & 0xffff) as u32 {
             return 1;  // error in compressed data
         }
         self.dump_bits(&mut k, &mut b, 16);
 
-        // read and output the compressed data
-        while n > 0 {
-            self.need_bits(state, &mut k, &mut b, 8, w);
-            self.slide[w] = (b & 0xff) as u8;  // assuming slide is an array
+        // bytes already pulled into the bit buffer come first
+        while n > 0 && k > 0 {
+            self.slide[w] = (b & 0xff) as u8;
             w += 1;
-
             if w == WSIZE {
-                self.flush_output(state, w);
+                if !self.flush_output(state, w) {
+                    return 4;
+                }
                 w = 0;
             }
             self.dump_bits(&mut k, &mut b, 8);
             n -= 1;
         }
 
+        // the rest is byte aligned: copy it straight from the input buffer
+        while n > 0 {
+            if state.inptr == state.insize {
+                self.wp = w;
+                if let Err(err) = self.write_ahead(state, w).and_then(|_| self.refill(false, state)) {
+                    self.input_error = Some(err);
+                    return INPUT_ERROR;
+                }
+                state.inptr -= 1; // refill hands back the first byte; leave it for the copy
+            }
+            let len = (n as usize).min(state.insize - state.inptr).min(WSIZE - w);
+            self.slide[w..w + len].copy_from_slice(&state.inbuf[state.inptr..state.inptr + len]);
+            state.inptr += len;
+            w += len;
+            n -= len as u32;
+
+            if w == WSIZE {
+                if !self.flush_output(state, w) {
+                    return 4;
+                }
+                w = 0;
+            }
+        }
+
         // restore the globals from the locals
         self.wp = w;  // restore global window pointer
         self.bb = b;  // restore global bit buffer
@@ -711,10 +819,22 @@ impl Inflate {
 
     // Decompress an inflated type 1 (fixed Huffman codes) block
     pub fn inflate_fixed(&mut self, state: &mut GzipState) -> i32 {
-        let mut tl: Option<Box<Huft>> = None; // Literal/length table
-        let mut td: Option<Box<Huft>> = None; // Distance table
-        let mut bl: i32 = 7;                 // Lookup bits for `tl`
-        let mut bd: i32 = 5;                 // Lookup bits for `td`
+        let lbits = self.lbits.clamp(7, 9);
+        let tables = FIXED_TABLES[(lbits - 7) as usize].get_or_init(|| self.build_fixed_tables(lbits));
+        let mut bl = tables.bl; // Lookup bits for `tl`
+        let mut bd = tables.bd; // Lookup bits for `td`
+
+        // Decompress until an end-of-block code. The tables are shared, so they
+        // are not handed to huft_free afterwards
+        self.inflate_codes(state, &tables.tl, &tables.td, &mut bl, &mut bd)
+    }
+
+    // Build the fixed literal/length and distance tables for FIXED_TABLES
+    fn build_fixed_tables(&mut self, lbits: i32) -> FixedTables {
+        let mut tl: Option<Box<[Huft]>> = None; // Literal/length table
+        let mut td: Option<Box<[Huft]>> = None; // Distance table
+        let mut bl: i32 = lbits;             // Lookup bits for `tl`
+        let mut bd: i32 = self.dbits;        // Lookup bits for `td`
         let mut l = [0u32; 288];             // Length list for `huft_build`
 
         // Set up literal table
@@ -730,51 +850,27 @@ impl Inflate {
         for i in 280..288 {
             l[i] = 8;
         }
-
-        // Call huft_build for literal/length table
-        let result = self.huft_build(&l, 288, 257, &cplens, &cplext, &mut tl, &mut bl);
-        if result != 0 {
-            return result as i32;
-        }
-
-        // Set up distance table
-        let mut l = [0u32; 30]; // Length list for distance table
-        for i in 0..30 {
-            l[i] = 5;
+        self.huft_build(&l, 288, 257, &cplens, &cplext, &mut tl, &mut bl);
+
+        // Set up distance table: 30 of the 32 five-bit codes, an incomplete set
+        // huft_build reports as 1
+        let l = [5u32; 30];
+        self.huft_build(&l, 30, 0, &cpdist, &cpdext, &mut td, &mut bd);
+
+        FixedTables {
+            tl: tl.expect("fixed literal/length table"),
+            td: td.expect("fixed distance table"),
+            bl,
+            bd,
         }
-
-        // Call huft_build for distance table
-        let result = self.huft_build(&l, 30, 0, &cpdist, &cpdext, &mut td, &mut bd);
-        println!("fixed!");
-        if result > 1 {
-            if let Some(ref tl) = tl {
-                huft_free(Some(tl));
-            }
-            return result as i32;
-        }
-
-        // Decompress until an end-of-block code
-        if self.inflate_codes(state, &mut tl, &mut td, &mut bl, &mut bd) != 0 {
-            return 1;
-        }
-
-        // Free the decoding tables
-        if let Some(ref tl) = tl {
-            huft_free(Some(tl));
-        }
-        if let Some(ref td) = td {
-            huft_free(Some(td));
-        }
-
-        0
     }
 
 
 
     // Decompress an inflated type 2 (dynamic Huffman codes) block
     pub fn inflate_dynamic(&mut self, state: &mut GzipState) -> i32 {
-        let mut tl: Option<Box<Huft>> = None; // Literal/length table
-        let mut td: Option<Box<Huft>> = None; // Distance table
+        let mut tl: Option<Box<[Huft]>> = None; // Literal/length table
+        let mut td: Option<Box<[Huft]>> = None; // Distance table
         let mut bl: i32 = 7;                 // Lookup bits for `tl`
         let mut bd: i32 = 5;                 // Lookup bits for `td`
         let mut b = self.bb;                 // Bit buffer
@@ -782,13 +878,19 @@ impl Inflate {
         let mut w = self.wp as u32;          // Current window position
 
         // Read table lengths
-        self.need_bits(state, &mut k, &mut b, 5, w as usize);
+        if !self.need_bits(state, &mut k, &mut b, 5, w as usize) {
+            return INPUT_ERROR;
+        }
         let nl = 257 + (b & 0x1f); // Number of literal/length codes
         self.dump_bits(&mut k, &mut b, 5);
-        self.need_bits(state, &mut k, &mut b, 5, w as usize);
+        if !self.need_bits(state, &mut k, &mut b, 5, w as usize) {
+            return INPUT_ERROR;
+        }
         let nd = 1 + (b & 0x1f);   // Number of distance codes
         self.dump_bits(&mut k, &mut b, 5);
-        self.need_bits(state, &mut k, &mut b, 4, w as usize);
+        if !self.need_bits(state, &mut k, &mut b, 4, w as usize) {
+            return INPUT_ERROR;
+        }
         let nb = 4 + (b & 0xf);    // Number of bit length codes
         self.dump_bits(&mut k, &mut b, 4);
 
@@ -799,7 +901,9 @@ impl Inflate {
         // Build bit-length table
         let mut bit_lengths = vec![0u32; 19];
         for j in 0..nb {
-            self.need_bits(state, &mut k, &mut b, 3, w as usize);
+            if !self.need_bits(state, &mut k, &mut b, 3, w as usize) {
+                return INPUT_ERROR;
+            }
             bit_lengths[border[j as usize] as usize] = b & 7;
             self.dump_bits(&mut k, &mut b, 3);
         }
@@ -809,20 +913,15 @@ impl Inflate {
             bit_lengths[border[j as usize] as usize] = 0;
         }
 
-        // Build the Huffman table for bit-length codes
+        // Build the Huffman table for bit-length codes, which must be complete
         let mut result = self.huft_build(&bit_lengths, 19, 19, &[], &[], &mut tl, &mut bl);
         if result != 0 {
-            if result == 1 {
-                if let Some(ref tl) = tl {
-                    huft_free(Some(tl));
-                }
-            }
-            return result as i32;
+            huft_free(tl);
+            return code_set_error(result);
         }
-
-        if tl.is_none() {
+        let Some(bit_table) = tl.take() else {
             return 2; // Error in tree decoding
-        }
+        };
 
         // Decode literal/length and distance code lengths
         let n = nl + nd;
@@ -832,26 +931,14 @@ impl Inflate {
         let mask = mask_bits[bl as usize];
 
         while i < n {
-            self.need_bits(state, &mut k, &mut b, bl as u32, w as usize);
-            let index = (b & mask) as usize;
-
-            let entry = match tl.as_ref() {
-                Some(table) => {
-                    let mut t = &**table;
-                    while let HuftValue::T(ref subtable) = t.v {
-                        t = &subtable[index];
-                    }
-                    t
-                }
-                None => return 2,
-            };
-
+            if !self.need_bits(state, &mut k, &mut b, bl as u32, w as usize) {
+                return INPUT_ERROR;
+            }
+            // Bit length codes are at most 7 bits, so one table level is enough
+            let entry = &bit_table[(b & mask) as usize];
             self.dump_bits(&mut k, &mut b, entry.b as u32);
 
             if entry.e == 99 {
-                if let Some(ref tl) = tl {
-                    huft_free(Some(tl));
-                }
                 return 2; // Invalid code
             }
 
@@ -865,7 +952,9 @@ impl Inflate {
                 literal_lengths[i as usize] = l;
                 i += 1;
             } else if j == 16 {
-                self.need_bits(state, &mut k, &mut b, 2, w as usize);
+                if !self.need_bits(state, &mut k, &mut b, 2, w as usize) {
+                    return INPUT_ERROR;
+                }
                 let repeat = 3 + (b & 3);
                 self.dump_bits(&mut k, &mut b, 2);
                 if i + repeat > n {
@@ -876,7 +965,9 @@ impl Inflate {
                     i += 1;
                 }
             } else if j == 17 {
-                self.need_bits(state, &mut k, &mut b, 3, w as usize);
+                if !self.need_bits(state, &mut k, &mut b, 3, w as usize) {
+                    return INPUT_ERROR;
+                }
                 let repeat = 3 + (b & 7);
                 self.dump_bits(&mut k, &mut b, 3);
                 if i + repeat > n {
@@ -888,7 +979,9 @@ impl Inflate {
                 }
                 l = 0;
             } else if j == 18 {
-                self.need_bits(state, &mut k, &mut b, 7, w as usize);
+                if !self.need_bits(state, &mut k, &mut b, 7, w as usize) {
+                    return INPUT_ERROR;
+                }
                 let repeat = 11 + (b & 0x7f);
                 self.dump_bits(&mut k, &mut b, 7);
                 if i + repeat > n {
@@ -903,24 +996,21 @@ impl Inflate {
         }
 
         // Free the bit-length table
-        if let Some(ref tl) = tl {
-            huft_free(Some(tl));
-        }
+        huft_free(Some(bit_table));
 
         // Restore the global bit buffer
         self.bb = b;
         self.bk = k;
 
         // Build literal/length and distance Huffman tables
+        if literal_lengths[256] == 0 {
+            return 1; // No end-of-block code
+        }
         bl = self.lbits;
-        result = self.huft_build(&literal_lengths, nl as usize, 257, &cpdist, &cpdext, &mut tl, &mut bl);
-        if result != 0 {
-            if result == 1 {
-                if let Some(ref tl) = tl {
-                    huft_free(Some(tl));
-                }
-            }
-            return result as i32;
+        result = self.huft_build(&literal_lengths, nl as usize, 257, &cplens, &cplext, &mut tl, &mut bl);
+        if result != 0 && !(result == 1 && lone_code(&literal_lengths[..nl as usize])) {
+            huft_free(tl);
+            return code_set_error(result);
         }
 
         bd = self.dbits;
@@ -933,33 +1023,21 @@ impl Inflate {
             &mut td,
             &mut bd,
         );
-        if result != 0 {
-            if result == 1 {
-                if let Some(ref td) = td {
-                    huft_free(Some(td));
-                }
-            }
-            if let Some(ref tl) = tl {
-                huft_free(Some(tl));
-            }
-            return result as i32;
+        if result != 0 && !(result == 1 && lone_code(&literal_lengths[nl as usize..])) {
+            huft_free(td);
+            huft_free(tl);
+            return code_set_error(result);
         }
+        let (Some(tl), Some(td)) = (tl, td) else {
+            return 2;
+        };
 
         // Decompress until an end-of-block code
-        println!("dynamic!");
-        let err = if self.inflate_codes(state, &mut tl, &mut td, &mut bl, &mut bd) > 0 {
-            1
-        } else {
-            0
-        };
+        let err = self.inflate_codes(state, &tl, &td, &mut bl, &mut bd);
 
         // Free decoding tables
-        if let Some(ref tl) = tl {
-            huft_free(Some(tl));
-        }
-        if let Some(ref td) = td {
-            huft_free(Some(td));
-        }
+        huft_free(Some(tl));
+        huft_free(Some(td));
         err
     }
 
@@ -978,14 +1056,19 @@ impl Inflate {
         b = self.bb;
         k = self.bk;
         w = self.wp as u32;
+        let start = (self.bits_read(state), self.total_out + self.wp as u64);
 
         // Read the last block bit
-        self.need_bits(state, &mut k, &mut b, 1, w.try_into().unwrap());
+        if !self.need_bits(state, &mut k, &mut b, 1, w.try_into().unwrap()) {
+            return INPUT_ERROR;
+        }
         *e = (b & 1) as i32;
         self.dump_bits(&mut k, &mut b, 1);
 
         // Read the block type
-        self.need_bits(state, &mut k, &mut b, 2, w.try_into().unwrap());
+        if !self.need_bits(state, &mut k, &mut b, 2, w.try_into().unwrap()) {
+            return INPUT_ERROR;
+        }
         t = (b & 3) as u32;
         self.dump_bits(&mut k, &mut b, 2);
 
@@ -994,12 +1077,33 @@ impl Inflate {
         self.bk = k;
 
         // Decompress based on the block type
-        match t {
-            2 => return self.inflate_dynamic(state),
-            0 => return self.inflate_stored(state),
-            1 => return self.inflate_fixed(state),
+        let r = match t {
+            2 => self.inflate_dynamic(state),
+            0 => self.inflate_stored(state),
+            1 => self.inflate_fixed(state),
             _ => return 2, // Invalid block type
+        };
+        if r == 0 {
+            state.blocks += 1;
+        }
+
+        // -v -v traces each block: its type, whether it is the last, and its sizes
+        if r == 0 && state.opts.verbose >= TRACE_BLOCKS {
+            const BLOCK_TYPES: [&str; 3] = ["stored", "fixed", "dynamic"];
+            eprintln!(
+                "{}: {}: block type={} last={} in_bits={} out_bytes={}",
+                state.program_name, state.ifname.display(), BLOCK_TYPES[t as usize], *e,
+                self.bits_read(state) - start.0, self.total_out + self.wp as u64 - start.1
+            );
         }
+        r
+    }
+
+    // Bits of input taken so far: what has been read, less what waits in the bit buffer.
+    // Made-up bytes past the end of the input were never read
+    fn bits_read(&self, state: &GzipState) -> i64 {
+        let unused = state.insize.saturating_sub(state.inptr) as i64;
+        8 * (state.bytes_in - unused) - (self.bk as i64 - 8 * self.padding as i64)
     }
 
 
@@ -1011,8 +1115,38 @@ impl Inflate {
 
         // Initialize the window and bit buffer
         self.wp = 0; // Current window position
+        self.written = 0;
+        self.total_out = 0; // No history for distances to reach into yet
+        // A preset dictionary sits just behind the start of the window, where the
+        // wrapping window puts the output that came before it
+        if !self.dictionary.is_empty() {
+            let n = self.dictionary.len();
+            self.slide[WSIZE - n..WSIZE].copy_from_slice(&self.dictionary);
+            self.total_out = n as u64;
+        }
         self.bk = 0; // Number of bits in the bit buffer
         self.bb = 0; // Bit buffer
+        self.padding = 0;
+
+        // A start in the middle of a byte drops the bits of the block before it
+        if self.start_bits > 0 {
+            match self.get_byte(state) {
+                Ok(byte) => {
+                    self.bb = byte as u32 >> self.start_bits;
+                    self.bk = 8 - self.start_bits;
+                }
+                Err(err) => {
+                    self.input_error = Some(err);
+                    return INPUT_ERROR;
+                }
+            }
+        }
+        let history = self.total_out;
+        let mut next_checkpoint = 0;
+        if let Some(span) = self.checkpoint_span {
+            self.checkpoints.push(self.checkpoint(state, 0));
+            next_checkpoint = span;
+        }
 
         // Decompress until the last block
         h = 0;
@@ -1020,6 +1154,15 @@ impl Inflate {
             self.hufts = 0; // Initialize `hufts`
 
             r = self.inflate_block(&mut e, state);
+            // Output written ahead of a read was refused
+            if r == INPUT_ERROR && self.output_error.is_some() {
+                return 4;
+            }
+            // Data that goes wrong once the input has run out was cut short
+            if r != 0 && r != 4 && self.padding > 0 {
+                self.input_error = Some(unexpected_eof());
+                return INPUT_ERROR;
+            }
             if r != 0 {
                 return r; // Return the error code
             }
@@ -1028,24 +1171,40 @@ impl Inflate {
                 h = self.hufts; // Update the maximum `hufts`
             }
 
-            if e != 0 {
+            let produced = self.total_out + self.wp as u64 - history;
+            if e != 0 || self.output_limit.is_some_and(|limit| produced >= limit) {
                 break; // Exit the loop if this is the last block
             }
+            if let Some(span) = self.checkpoint_span.filter(|_| produced >= next_checkpoint) {
+                self.checkpoints.push(self.checkpoint(state, produced));
+                next_checkpoint = produced + span;
+            }
         }
 
         // Undo excess pre-reading. The next read will be byte-aligned,
         // so discard unused bits from the last meaningful byte.
 
+        // Made-up bytes past the end of the input sit on top and are simply dropped,
+        // unless the last code needed them
+        if self.bk < 8 * self.padding {
+            self.input_error = Some(unexpected_eof());
+            return INPUT_ERROR;
+        }
         while self.bk >= 8 {
             self.bk -= 8;
-            state.inptr -= 1; // Assume `inptr` is a global variable pointing to the input buffer
+            if self.padding > 0 {
+                self.padding -= 1;
+            } else {
+                state.inptr -= 1; // Assume `inptr` is a global variable pointing to the input buffer
+            }
         }
 
         // Flush the output window
-        self.flush_output(state, self.wp); // Assume `flush_output` is a function that writes decompressed data to the output
+        if !self.flush_output(state, self.wp) {
+            return 4;
+        }
 
         // Return success status
-        println!("{}", format!("<{}> ", h)); // Assume `trace` is a debugging output function
         0
     }
 }
@@ -0,0 +1,4666 @@
+// Print a warning unless -q was given and record WARNING for the exit status, without
+// overriding an earlier ERROR. Everything gzip only warns about goes through here, so -q
+// quiets all of it and no error
+macro_rules! warn {
+    ($state:expr, $($arg:tt)*) => {
+        $state.warn(&format!($($arg)*))
+    };
+}
+
+// An error about the input, which --verify's check keeps to itself: the file it checks
+// reports the failure
+macro_rules! report {
+    ($state:expr, $($arg:tt)*) => {
+        if !$state.checking {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+mod trees;
+mod zip;
+mod deflate;
+mod inflate;
+mod unzip;
+mod header;
+mod index;
+mod crc32;
+mod mapped;
+mod lzw;
+mod glob;
+mod stream;
+
+pub use crate::stream::{BgzfEncoder, GzipDecoder, GzipEncoder, RawDeflateDecoder, RawDeflateEncoder};
+
+use crate::zip::{finish_bgzf_block, zip, Compressor, BGZF_BLOCK_SIZE, BGZF_EOF};
+use crate::header::{GzipHeader, HeaderError};
+use crate::lzw::{lzw, unlzw};
+use crate::glob::Glob;
+use crate::index::{Index, INDEX_SPAN};
+use crate::mapped::{Input, MappedFile, MMAP_THRESHOLD};
+use crate::unzip::{unzip, LG, SH, CRPFLG, EXTFLG, LOCCRC, LOCEXT, LOCFIL, LOCFLG, LOCHDR, LOCHOW, LOCLEN, LOCSIG, LOCSIZ, LOCTIM};
+use chrono::{DateTime, Datelike, Local, NaiveDate, Timelike};
+// use crc::{Crc, Digest, CRC_16_IBM_SDLC};
+use std::any::Any;
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::fs::{File, Metadata};
+use std::io::{BufRead, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::cell::Cell;
+use std::time::{Duration, Instant, SystemTime};
+use std::{env, fmt, fs, io};
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::MetadataExt;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStringExt;
+
+// Constants (Assumed values for any not defined in the provided C code)
+const BITS: i32 = 16; // Widest LZW code
+const INIT_BITS: i32 = 9; // Narrowest LZW code, and the width the codes start at
+const DEFLATED: i32 = 8;
+const OK: i32 = 0;
+const ERROR: i32 = 1;
+const WARNING: i32 = 2;
+// -v levels past the one line per file: -v -v traces each member header and deflate
+// block, -v -v -v each symbol as well
+const TRACE_BLOCKS: i32 = 2;
+const TRACE_SYMBOLS: i32 = 3;
+// -f levels: -f overwrites outputs, takes symbolic links and files with other links or
+// the sticky bit, a name that already has the suffix, and a terminal; it also appends to
+// what is not a .gz, and passes through -d -c input that is not. -f -f also reads on past
+// reserved header flags and a wrong header CRC, and replaces an output that is a link
+const FORCE: i32 = 1;
+const FORCE_TWICE: i32 = 2;
+// How far past the current time an MTIME may be before -v remarks on it (clocks drift)
+const FUTURE_MTIME_SLACK: Duration = Duration::from_secs(24 * 60 * 60);
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250); // Redraws of --progress
+#[cfg(unix)]
+const MAX_PATH_LEN: usize = libc::PATH_MAX as usize; // Longest path the OS accepts
+#[cfg(not(unix))]
+const MAX_PATH_LEN: usize = 260; // MAX_PATH on Windows
+const NAME_MAX: usize = 255; // Maximum length of a single path component
+const MIN_PART: usize = 3; // Minimum length of a name part kept intact by shorten_name
+const Z_SUFFIX: &str = ".gz";
+const LZW_SUFFIX: &str = ".Z"; // The suffix of -Z, as compress(1) gives its files
+const MAX_SUFFIX: usize = 30; // Assuming maximum suffix length
+const WORKER_STACK_SIZE: usize = 16 << 20; // Stack for --threads workers, like a main thread
+const PARALLEL_CHUNK: usize = 1 << 20; // Default input bytes per member with --parallel
+// Suffixes recognized as compressed in addition to the configured z_suffix,
+// with the replacement used when decompressing (.tgz and .taz become .tar)
+const KNOWN_SUFFIXES: &[(&str, &str)] = &[
+    (".gz", ""), (".z", ""), (".taz", ".tar"), (".tgz", ".tar"), ("-gz", ""), ("-z", ""), ("_z", ""),
+];
+
+const VERSION: &str = "1.13"; // Assuming version 1.0, replace with actual version.
+
+// The OS byte of the gzip header: NTFS for every Windows target, Macintosh and Unix
+#[cfg(windows)]
+const OS_CODE: u8 = 0x0b;
+#[cfg(target_os = "macos")]
+const OS_CODE: u8 = 0x07;
+#[cfg(not(any(windows, target_os = "macos")))]
+const OS_CODE: u8 = 0x03;
+
+const LICENSE_MSG: &[&str] = &[
+    "Copyright (C) 2023 Free Software Foundation, Inc.",
+    "Copyright (C) 1993 Jean-loup Gailly.",
+    "This is free software.  You may redistribute copies of it under the terms of",
+    "the GNU General Public License <https://www.gnu.org/licenses/gpl.html>.",
+    "There is NO WARRANTY, to the extent permitted by law.",
+];
+
+//
+// Magic headers
+const PACK_MAGIC: &[u8] = b"\x1F\x1E"; // Magic header for packed files
+const GZIP_MAGIC: &[u8] = b"\x1F\x8B"; // Magic header for gzip files, 1F 8B
+const OLD_GZIP_MAGIC: &[u8] = b"\x1F\x9E"; // Magic header for gzip 0.5 = freeze 1.x
+const LZH_MAGIC: &[u8] = b"\x1F\xA0"; // Magic header for SCO LZH Compress files
+const LZW_MAGIC: &[u8] = b"\x1F\x9D"; // Magic header for SCO LZW Compress files
+const PKZIP_MAGIC: &[u8] = b"\x50\x4B\x03\x04"; // Magic header for pkzip files
+
+// gzip flag bytes
+const HEADER_CRC: u8 = 0x02; // bit 1 set: CRC16 for the gzip header
+const EXTRA_FIELD: u8 = 0x04; // bit 2 set: extra field present
+const ORIG_NAME: u8 = 0x08; // bit 3 set: original file name present
+const COMMENT: u8 = 0x10; // bit 4 set: file comment present
+const ENCRYPTED: u8 = 0x20; // bit 5 set: file is encrypted
+const RESERVED: u8 = 0xC0; // bits 6 and 7: reserved
+const WSIZE: usize = 0x8000;
+const INBUFSIZ: usize = 0x8000;
+const OUTBUFSIZ: usize = 16384;
+const INBUF_EXTRA: usize = 64;
+const OUTBUF_EXTRA: usize = 2048;
+const DIST_BUFSIZE: usize = 0x8000;
+const STORED: i32 = 0;
+const COMPRESSED: u8 = 1;
+const PACKED: u8 = 2;
+const LZHED: u8 = 3;
+const MAX_METHODS: usize = 9;
+const HELP_HEAD: &[&str] = &[
+    "Compress or uncompress FILEs (by default, compress FILES in-place).",
+    "",
+    "Mandatory arguments to long options are mandatory for short options too.",
+    "",
+];
+const HELP_TAIL: &[&str] = &[
+    "",
+    "With no FILE, or when FILE is -, read standard input.",
+    "",
+    "Report bugs to <bug-gzip@gnu.org>.",
+];
+
+// Whether an option takes an operand, and how it is given
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OptionArg {
+    None,
+    Required(&'static str), // -S SUF, --suffix=SUF or --suffix SUF; the name is for the help
+    Optional(&'static str), // Only as --ignore-leading=MAXBYTES
+}
+
+// One option, as parse_args_from takes it and help shows it. A long option that has a
+// short one does what the short one does. Options without help lines are taken but not
+// shown, and those not implemented are refused rather than silently ignored
+struct OptionSpec {
+    short: Option<char>,
+    long: Option<&'static str>,
+    arg: OptionArg,
+    help: &'static [&'static str],
+    implemented: bool,
+    env: bool, // Allowed in the GZIP environment variable, as gzip allows it
+}
+
+const fn opt(short: Option<char>, long: Option<&'static str>, arg: OptionArg, help: &'static [&'static str]) -> OptionSpec {
+    OptionSpec { short, long, arg, help, implemented: true, env: false }
+}
+
+// Every option, in the order help lists them
+const OPTIONS: &[OptionSpec] = &[
+    // Shown only where O_BINARY, as gzip does; elsewhere it changes nothing
+    opt(Some('a'), Some("ascii"), OptionArg::None, &[]),
+    opt(None, Some("append"), OptionArg::None, &[
+        "add a member to the end of the .gz named first, compressing",
+        "the files named after it or standard input",
+    ]),
+    opt(Some('b'), Some("bits"), OptionArg::Required("BITS"), &[
+        "with -Z, the widest LZW code, from 9 to 16 (the default)",
+    ]),
+    opt(None, Some("bgzf"), OptionArg::None, &["compress into BGZF blocks, as bgzip does"]),
+    opt(Some('c'), Some("stdout"), OptionArg::None, &["write on standard output, keep original files unchanged"]),
+    opt(Some('c'), Some("to-stdout"), OptionArg::None, &[]),
+    opt(Some('d'), Some("decompress"), OptionArg::None, &["decompress"]),
+    opt(Some('d'), Some("uncompress"), OptionArg::None, &[]),
+    opt(None, Some("dictionary"), OptionArg::Required("FILE"), &[
+        "with --format=zlib or raw, start from the dictionary in FILE",
+    ]),
+    opt(None, Some("exclude"), OptionArg::Required("GLOB"), &[
+        "with -r, pass over the files and directories that match GLOB",
+    ]),
+    opt(Some('f'), Some("force"), OptionArg::None, &["force overwrite of output file and compress links"]),
+    opt(None, Some("format"), OptionArg::Required("FMT"), &[
+        "write and read gzip (the default), zlib or raw deflate data",
+    ]),
+    opt(Some('h'), Some("help"), OptionArg::None, &["give this help"]),
+    opt(Some('H'), None, OptionArg::None, &[]),
+    opt(None, Some("ignore-leading"), OptionArg::Optional("MAXBYTES"), &[
+        "with -d -c or -t, skip what comes before the gzip data if it",
+        "starts within MAXBYTES (anywhere without)",
+    ]),
+    opt(None, Some("include"), OptionArg::Required("GLOB"), &[
+        "with -r, if given, take only the files that match a GLOB",
+    ]),
+    opt(None, Some("index"), OptionArg::Required("FILE"), &[
+        "write an index of the .gz compressed or decompressed to FILE",
+    ]),
+    opt(None, Some("index-span"), OptionArg::Required("BYTES"), &[
+        "put the checkpoints of --index BYTES of output apart",
+    ]),
+    opt(None, Some("inflate-bits"), OptionArg::Required("L,D"), &[
+        "bits of the first decoding tables for lengths and distances",
+    ]),
+    opt(Some('k'), Some("keep"), OptionArg::None, &["keep (don't delete) input files"]),
+    opt(Some('l'), Some("list"), OptionArg::None, &["list compressed file contents"]),
+    opt(Some('L'), Some("license"), OptionArg::None, &["display software license"]),
+    opt(None, Some("length"), OptionArg::Required("N"), &["with --seek, write only N bytes"]),
+    OptionSpec {
+        env: true,
+        ..opt(None, Some("level"), OptionArg::Required("N"), &[
+            "compress at level N, from 1 (fastest) to 9 (best), or 0 to",
+            "store the data as it is, in the gzip format",
+        ])
+    },
+    opt(Some('m'), Some("no-time"), OptionArg::None, &[]),
+    opt(Some('M'), Some("time"), OptionArg::None, &[]),
+    opt(None, Some("max-ratio"), OptionArg::Required("N"), &[
+        "refuse to decompress more than N bytes for each byte read",
+    ]),
+    opt(None, Some("max-size"), OptionArg::Required("BYTES"), &[
+        "refuse to decompress more than BYTES from one input",
+    ]),
+    opt(None, Some("mmap"), OptionArg::None, &["map input files into memory, however short"]),
+    opt(None, Some("mtime"), OptionArg::Required("TIME"), &[
+        "record TIME, in seconds, @FILE for the time of FILE, or none",
+        "as the time stamp of every input",
+    ]),
+    opt(None, Some("no-mmap"), OptionArg::None, &["read input files, however long, without mapping them"]),
+    OptionSpec {
+        env: true,
+        ..opt(Some('n'), Some("no-name"), OptionArg::None, &["do not save or restore the original name and timestamp"])
+    },
+    OptionSpec { env: true, ..opt(Some('N'), Some("name"), OptionArg::None, &["save or restore the original name and timestamp"]) },
+    opt(Some('o'), Some("output"), OptionArg::Required("FILE"), &["write the output of the one input to FILE"]),
+    opt(None, Some("output-dir"), OptionArg::Required("DIR"), &["write the output files into DIR"]),
+    opt(None, Some("os-code"), OptionArg::Required("N"), &["write N (0 to 255, 255 for unknown) as the header's OS"]),
+    opt(None, Some("parallel"), OptionArg::Optional("BYTES"), &[
+        "compress each BYTES of the input (1 MiB without) on a thread",
+        "of its own, as a member of its own",
+    ]),
+    // Not in the help: lets tests.sh act as if stdin were a terminal
+    opt(None, Some("presume-input-tty"), OptionArg::None, &[]),
+    opt(None, Some("progress"), OptionArg::None, &["show how far each file has got, when stderr is a terminal"]),
+    OptionSpec { env: true, ..opt(Some('q'), Some("quiet"), OptionArg::None, &["suppress all warnings"]) },
+    opt(None, Some("readahead"), OptionArg::None, &["read the input on a thread of its own, ahead of (de)compression"]),
+    opt(Some('r'), Some("recursive"), OptionArg::None, &["operate recursively on directories"]),
+    OptionSpec { implemented: false, ..opt(None, Some("rsyncable"), OptionArg::None, &["make rsync-friendly archive"]) },
+    opt(Some('S'), Some("suffix"), OptionArg::Required("SUF"), &["use suffix SUF on compressed files"]),
+    opt(None, Some("seek"), OptionArg::Required("OFFSET"), &[
+        "write the decompressed data from OFFSET on, found through",
+        "the file's --index",
+    ]),
+    opt(None, Some("strict"), OptionArg::None, &["fail on data after the last member, other than zero bytes"]),
+    opt(None, Some("sync-flush"), OptionArg::None, &["when the input pauses, send on all it gave so far"]),
+    OptionSpec {
+        implemented: false,
+        ..opt(None, Some("synchronous"), OptionArg::None, &["synchronous output (safer if system crashes, but slower)"])
+    },
+    opt(Some('t'), Some("test"), OptionArg::None, &["test compressed file integrity"]),
+    opt(None, Some("threads"), OptionArg::Required("N"), &["with -r, compress N files at a time"]),
+    opt(None, Some("totals"), OptionArg::None, &[
+        "when done, print how many files were processed, skipped and",
+        "failed, with the bytes read and written",
+    ]),
+    OptionSpec {
+        env: true,
+        ..opt(Some('v'), Some("verbose"), OptionArg::None, &[
+            "verbose mode; twice traces headers and deflate blocks,",
+            "three times each symbol too",
+        ])
+    },
+    opt(None, Some("verify"), OptionArg::None, &["decompress each new .gz and check it before removing the input"]),
+    opt(Some('V'), Some("version"), OptionArg::None, &["display version number"]),
+    // Not in the help either: tests stored names against Windows' rules
+    opt(None, Some("windows-names"), OptionArg::None, &[]),
+    opt(None, Some("zip-entries"), OptionArg::None, &["with -c, -t or -l, take every entry of a zip file"]),
+    opt(Some('Z'), Some("lzw"), OptionArg::None, &["produce output compatible with compress(1), in .Z files"]),
+    OptionSpec { env: true, ..opt(Some('1'), Some("fast"), OptionArg::None, &["compress faster"]) },
+    OptionSpec { env: true, ..opt(Some('2'), None, OptionArg::None, &[]) },
+    OptionSpec { env: true, ..opt(Some('3'), None, OptionArg::None, &[]) },
+    OptionSpec { env: true, ..opt(Some('4'), None, OptionArg::None, &[]) },
+    OptionSpec { env: true, ..opt(Some('5'), None, OptionArg::None, &[]) },
+    OptionSpec { env: true, ..opt(Some('6'), None, OptionArg::None, &[]) },
+    OptionSpec { env: true, ..opt(Some('7'), None, OptionArg::None, &[]) },
+    OptionSpec { env: true, ..opt(Some('8'), None, OptionArg::None, &[]) },
+    OptionSpec { env: true, ..opt(Some('9'), Some("best"), OptionArg::None, &["compress better"]) },
+];
+
+// The options that set the compression level
+const LEVEL_OPTIONS: &[&str] = &["-1", "-2", "-3", "-4", "-5", "-6", "-7", "-8", "-9", "--level"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum GroupKind {
+    LastWins,  // Each sets the same thing, so the last one given is what counts
+    Exclusive, // At most one of them may be given
+}
+
+// Options that set the same thing, by the names OptionSpec::name gives them. The options
+// allowed in GZIP are never exclusive, so the command line always wins over it
+const OPTION_GROUPS: &[(GroupKind, &[&str])] = &[
+    // -v counts up from the last -q, and -q takes back every -v before it
+    (GroupKind::LastWins, &["-q", "-v"]),
+    (GroupKind::LastWins, LEVEL_OPTIONS),
+    // A listing, a test, and an output other than the usual one
+    (GroupKind::Exclusive, &["-l", "-t", "-o", "--output-dir"]),
+    (GroupKind::Exclusive, &["-c", "-o", "--output-dir"]),
+    // BGZF fixes the size of the members --parallel would choose
+    (GroupKind::Exclusive, &["--bgzf", "--parallel"]),
+];
+
+impl OptionSpec {
+    // The option in diagnostics: its letter if it has one, so that aliases agree
+    fn name(&self) -> String {
+        match (self.short, self.long) {
+            (Some(short), _) => format!("-{}", short),
+            (None, long) => format!("--{}", long.unwrap_or_default()),
+        }
+    }
+
+    // How help shows the option, up to where its description starts
+    fn label(&self) -> String {
+        let mut label = match self.short {
+            Some(short) => format!("  -{}", short),
+            None => "    ".to_string(),
+        };
+        if let Some(long) = self.long {
+            label += &format!("{} --{}", if self.short.is_some() { "," } else { " " }, long);
+            match self.arg {
+                OptionArg::None => {}
+                OptionArg::Required(operand) => label += &format!("={}", operand),
+                OptionArg::Optional(operand) => label += &format!("[={}]", operand),
+            }
+        } else if let OptionArg::Required(operand) = self.arg {
+            label += &format!(" {}", operand);
+        }
+        label
+    }
+}
+
+// Container around the deflate data (--format)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Gzip,
+    Zlib, // RFC 1950: a two-byte header and an Adler-32 trailer
+    Raw,  // RFC 1951 deflate data alone
+}
+
+// A preset dictionary (--dictionary): the bytes matches may refer to before the data
+// starts, and the Adler-32 of the whole file that zlib records as its DICTID
+#[derive(Clone)]
+struct Dictionary {
+    bytes: Vec<u8>,
+    id: u32,
+}
+
+// The entry of a PKZIP file being extracted, from its local header
+#[derive(Clone, Debug)]
+struct ZipEntry {
+    name: Vec<u8>,
+    method: u16,
+    mtime: u32, // MS-DOS date in the high half, time in the low half
+    crc: u32,
+    compressed_size: u64,
+    uncompressed_size: u64,
+    has_descriptor: bool, // General purpose bit 3: the CRC and sizes follow the data
+    zip64: bool, // Has a zip64 extra field, so a data descriptor has 8-byte sizes
+    unsupported: Option<String>, // Why the entry cannot be extracted
+}
+
+// What an input that is not gzip data looks like instead, from its first bytes, and the
+// tool that reads it: formats gzip is often handed by mistake
+fn foreign_format(data: &[u8]) -> Option<(&'static str, &'static str)> {
+    const FORMATS: &[(&[u8], &str, &str)] = &[
+        (b"\x28\xB5\x2F\xFD", "zstd compressed data", "unzstd"),
+        (b"\xFD7zXZ\x00", "xz compressed data", "unxz"),
+        (b"\x04\x22\x4D\x18", "lz4 compressed data", "unlz4"),
+        (b"PK\x01\x02", "a zip central directory without its entries", "unzip"),
+        (b"PK\x05\x06", "an empty zip file", "unzip"),
+    ];
+    // bzip2 has a block size digit after its magic
+    if data.len() >= 4 && data.starts_with(b"BZh") && (b'1'..=b'9').contains(&data[3]) {
+        return Some(("bzip2 compressed data", "bunzip2"));
+    }
+    FORMATS
+        .iter()
+        .find(|(magic, _, _)| data.starts_with(magic))
+        .map(|&(_, format, tool)| (format, tool))
+}
+
+// Name a PKZIP compression method that cannot be extracted, for the error message
+fn zip_method_name(method: u16) -> String {
+    match method {
+        1 => "shrink".to_string(),
+        2..=5 => "reduce".to_string(),
+        6 => "implode".to_string(),
+        9 => "deflate64".to_string(),
+        12 => "bzip2".to_string(),
+        14 => "lzma".to_string(),
+        93 => "zstd".to_string(),
+        95 => "xz".to_string(),
+        98 => "ppmd".to_string(),
+        99 => "AES encryption".to_string(),
+        _ => format!("method {}", method),
+    }
+}
+
+// The data of the zip64 extended information record (header ID 1) in an extra field
+fn zip64_extra(mut extra: &[u8]) -> Option<&[u8]> {
+    while extra.len() >= 4 {
+        let (id, len) = (SH(extra), SH(&extra[2..]) as usize);
+        let data = extra.get(4..4 + len)?;
+        if id == 1 {
+            return Some(data);
+        }
+        extra = &extra[4 + len..];
+    }
+    None
+}
+
+// The MS-DOS date and time of a zip entry, the way -l -v shows times
+fn format_dos_time(mtime: u32) -> String {
+    let (date, time) = (mtime >> 16, mtime & 0xffff);
+    let day = NaiveDate::from_ymd_opt(1980 + (date >> 9) as i32, (date >> 5) & 15, date & 31);
+    match day {
+        Some(day) if time >> 11 < 24 && (time >> 5) & 63 < 60 => {
+            format!("{}{:3} {:02}:{:02} ", day.format("%b"), day.day(), time >> 11, (time >> 5) & 63)
+        }
+        _ => "??? ?? ??:?? ".to_string(),
+    }
+}
+
+// Whatever stdin is open on, as a File for its metadata and, when it is a regular
+// file, for seeking to its trailer
+#[cfg(unix)]
+fn stdin_file() -> io::Result<File> {
+    use std::os::fd::AsFd;
+    Ok(File::from(io::stdin().as_fd().try_clone_to_owned()?))
+}
+
+#[cfg(not(unix))]
+fn stdin_file() -> io::Result<File> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "stdin as a file"))
+}
+
+// What treat_file was asked to do with an input
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FileAction {
+    Compress,
+    Decompress,
+    Test,
+    List,
+    Recurse, // A directory walked by -r; its files are reported one by one
+}
+
+// What one run of a work function did, from the counters it leaves in GzipState. The
+// -v and -l lines are printed from these, and the library's stream types hand them back.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub bytes_in: i64,
+    pub bytes_out: i64,
+    pub header_bytes: i64, // Header and trailer bytes, which the ratio leaves out
+    pub crc32: u32,        // CRC-32 of the last member's data, as its trailer has it
+    pub blocks: u64,       // Deflate blocks written or read
+    pub method: i32,
+}
+
+impl Stats {
+    // Space saved as a percentage of the uncompressed size; gzip shows 0 when that is empty
+    fn ratio(&self, decompressed: bool) -> f64 {
+        let (saved, whole) = if decompressed {
+            (self.bytes_out - (self.bytes_in - self.header_bytes), self.bytes_out)
+        } else {
+            (self.bytes_in - (self.bytes_out - self.header_bytes), self.bytes_in)
+        };
+        if whole == 0 { 0.0 } else { 100.0 * saved as f64 / whole as f64 }
+    }
+}
+
+// The outcome of one treat_file or treat_stdin call. report_file prints it and folds it
+// into the exit code; only the lower layers flagged by `reported` print on their own.
+struct FileResult {
+    input: Option<PathBuf>,  // None for standard input
+    output: Option<PathBuf>, // Where the output went, "stdout" for -c; None for -t
+    action: FileAction,
+    stats: Stats,
+    skipped_reason: Option<String>, // Printed as a warning unless -q
+    error: Option<String>,
+    reported: bool, // Already printed where it happened, with the exit code set there
+}
+
+impl FileResult {
+    fn new(input: Option<&Path>, action: FileAction) -> Self {
+        FileResult {
+            input: input.map(Path::to_path_buf),
+            output: None,
+            action,
+            stats: Stats::default(),
+            skipped_reason: None,
+            error: None,
+            reported: false,
+        }
+    }
+
+    fn skipped(mut self, reason: String) -> Self {
+        self.skipped_reason = Some(reason);
+        self
+    }
+
+    fn failed(mut self, error: String) -> Self {
+        self.error = Some(error);
+        self
+    }
+
+    fn already_reported(mut self) -> Self {
+        self.reported = true;
+        self
+    }
+
+    fn ratio(&self) -> f64 {
+        self.stats.ratio(self.action == FileAction::Decompress)
+    }
+
+    fn exit_code(&self) -> i32 {
+        if self.reported {
+            OK
+        } else if self.error.is_some() {
+            ERROR
+        } else if self.skipped_reason.is_some() {
+            WARNING
+        } else {
+            OK
+        }
+    }
+}
+
+// What --totals prints at the end: every input that report_file saw, and the bytes of
+// those that went through. Directories walked by -r count for nothing themselves
+#[derive(Clone, Copy, Debug, Default)]
+struct Totals {
+    processed: u64,
+    skipped: u64, // Directories without -r, names with an unknown or existing suffix, ...
+    failed: u64,
+    stats: Stats, // Only bytes_in, bytes_out and header_bytes are added up
+}
+
+impl Totals {
+    fn add(&mut self, other: &Totals) {
+        self.processed += other.processed;
+        self.skipped += other.skipped;
+        self.failed += other.failed;
+        self.stats.bytes_in += other.stats.bytes_in;
+        self.stats.bytes_out += other.stats.bytes_out;
+        self.stats.header_bytes += other.stats.header_bytes;
+    }
+}
+
+// Where the work on one input stands, for a progress callback
+pub struct ProgressInfo<'a> {
+    pub name: &'a Path, // "stdin" for the library's stream types
+    pub decompress: bool,
+    pub bytes_in: i64,
+    pub bytes_out: i64,
+    pub total: Option<i64>, // Size of the input, when it is known
+    pub elapsed: Duration,
+}
+
+type ProgressCallback = Box<dyn FnMut(&ProgressInfo) + Send>;
+
+// A hook the read paths call as the input goes by, at most every PROGRESS_INTERVAL
+struct Progress {
+    callback: ProgressCallback,
+    start: Instant,
+    last: Instant,
+    terminal: bool,    // Whether callback draws a line on stderr, rather than being a caller's
+    shown: Cell<bool>, // Whether a line is up that must be cleared before other output
+}
+
+impl Progress {
+    fn new(callback: ProgressCallback, terminal: bool) -> Self {
+        let now = Instant::now();
+        Progress { callback, start: now, last: now, terminal, shown: Cell::new(false) }
+    }
+}
+
+// --progress on a terminal: one line, redrawn in place
+fn draw_progress(info: &ProgressInfo) {
+    let (saved, whole) = if info.decompress {
+        (info.bytes_out - info.bytes_in, info.bytes_out)
+    } else {
+        (info.bytes_in - info.bytes_out, info.bytes_in)
+    };
+    let percent = match info.total {
+        Some(total) if total > 0 => format!(" ({:.0}%)", 100.0 * info.bytes_in as f64 / total as f64),
+        _ => String::new(),
+    };
+    let rate = info.bytes_in as f64 / info.elapsed.as_secs_f64().max(1e-3) / 1e6;
+    eprint!(
+        "\r{}: {} bytes read{}, ratio {}, {:.1} MB/s\x1b[K",
+        info.name.display(), info.bytes_in, percent,
+        format_ratio(if whole > 0 { 100.0 * saved as f64 / whole as f64 } else { 0.0 }).trim_start(), rate
+    );
+}
+
+// --append: the gzip file new members go on the end of, and its length before the
+// current member, to cut a failed one back off
+struct AppendTarget {
+    path: PathBuf,
+    file: File,
+    start: u64,
+}
+
+impl AppendTarget {
+    // The output of the next member, which starts where the file now ends
+    fn next_member(&mut self) -> io::Result<File> {
+        self.start = self.file.metadata()?.len();
+        self.file.try_clone()
+    }
+}
+
+// The options parsed from GZIP and the command line, which a --threads worker starts
+// from a copy of
+#[derive(Clone)]
+struct Options {
+    presume_input_tty: bool,
+    ascii: bool,
+    to_stdout: bool,
+    decompress: bool,
+    force: i32, // How many times -f was given, up to FORCE_TWICE
+    keep: bool,
+    no_name: Option<bool>, // None represents -1 in C code
+    no_time: Option<bool>,
+    recursive: bool,
+    list: bool,
+    verbose: i32,
+    quiet: bool,
+    do_lzw: bool,
+    test: bool,
+    threads: usize, // Workers used for files found by -r
+    parallel_chunk: Option<usize>, // Bytes per independently compressed member (--parallel)
+    bgzf: bool,                    // --bgzf: BGZF blocks, members with their size in a BC subfield
+    verify: bool, // Decompress each new .gz and check it before removing the input
+    max_size: Option<i64>, // Most bytes one input may decompress to (--max-size)
+    max_ratio: Option<i64>, // Most output bytes per input byte when decompressing (--max-ratio)
+    inflate_bits: Option<(i32, i32)>, // First-level literal/length and distance table bits (--inflate-bits)
+    format: Format, // Container written on compression and expected on decompression (--format)
+    dictionary: Option<Dictionary>, // Preset dictionary for zlib and raw data (--dictionary)
+    zip_entries: bool, // Extract every entry of a zip file, not just the first (--zip-entries)
+    mtime: Option<u32>, // MTIME written for every input, 0 for none (--mtime)
+    os_code: Option<u8>, // OS byte written instead of OS_CODE (--os-code)
+    windows_names: bool, // Restore stored names by Windows' rules on any system (--windows-names)
+    source_date_epoch: Option<u64>, // Latest MTIME written (SOURCE_DATE_EPOCH)
+    output: Option<PathBuf>, // Output file for the one input (-o, --output)
+    output_dir: Option<PathBuf>, // Directory the output files go to (--output-dir)
+    show_progress: bool, // --progress, which only draws when stderr is a terminal
+    show_totals: bool,   // --totals: one line on stderr for the whole run
+    sync_flush: bool,    // --sync-flush: flush to a byte boundary whenever the input pauses
+    append: bool,        // --append: add a member to the end of the first file named
+    readahead: bool,     // --readahead: read the input on a thread, a buffer ahead
+    ignore_leading: Option<u64>, // --ignore-leading: how far in the first member may start
+    strict: bool,        // --strict: data after the last member is an error, not a warning
+    mmap: Option<bool>,  // --mmap or --no-mmap; by default files of MMAP_THRESHOLD bytes are mapped
+    index_path: Option<PathBuf>, // Index written by --index, or read for --seek and --length
+    index_span: u64,             // Output between its checkpoints (--index-span)
+    seek: Option<u64>,           // --seek: where the range to extract starts
+    length: Option<u64>,         // --length: how long it is, to the end if not given
+    z_suffix: String,
+    z_len: usize,
+    maxbits: Option<i32>, // -b, the widest LZW code; None without it
+    level: i32,
+    level_option: Option<String>, // The last level given on the command line, as written
+    excludes: Vec<Glob>,       // --exclude: what -r passes over, directories and all
+    includes: Vec<Glob>,       // --include: if any, the only files -r takes
+}
+
+// Every option at gzip's default, before GZIP and the command line have their say
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            presume_input_tty: false,
+            ascii: false,
+            to_stdout: false,
+            decompress: false,
+            force: 0,
+            keep: false,
+            no_name: None, // None represents -1 (undefined) in the C code
+            no_time: None, // None represents -1 (undefined) in the C code
+            recursive: false,
+            list: false,
+            verbose: 0,
+            quiet: false,
+            do_lzw: false,
+            test: false,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            parallel_chunk: None,
+            bgzf: false,
+            verify: false,
+            max_size: None,
+            max_ratio: None,
+            inflate_bits: None,
+            format: Format::Gzip,
+            dictionary: None,
+            zip_entries: false,
+            mtime: None,
+            os_code: None,
+            windows_names: false,
+            source_date_epoch: None,
+            output: None,
+            output_dir: None,
+            show_progress: false,
+            show_totals: false,
+            sync_flush: false,
+            append: false,
+            readahead: false,
+            ignore_leading: None,
+            strict: false,
+            mmap: None,
+            index_path: None,
+            index_span: INDEX_SPAN,
+            seek: None,
+            length: None,
+            z_suffix: Z_SUFFIX.to_string(),
+            z_len: Z_SUFFIX.len(),
+            maxbits: None,
+            level: 6,
+            level_option: None,
+            excludes: Vec::new(),
+            includes: Vec::new(),
+        }
+    }
+}
+
+// The main state structure encapsulating all the global variables
+struct GzipState {
+    // Options and flags
+    opts: Options,
+    // Program state
+    totals: Totals,
+    append_target: Option<AppendTarget>,
+    index: Option<Index>, // The index being built for the current file
+    checking: bool, // Whether this state is --verify's check of an output
+    stream_stats: Stats, // What standard input came to, for run_stream
+    progress: Option<Progress>,
+    _foreground: bool,
+    program_name: String,
+    env: Option<String>, // The GZIP environment variable, options taken before the command line
+    args: Vec<OsString>,
+    exit_code: i32,
+    method: i32,
+    save_orig_name: bool,
+    last_member: bool,
+    part_nb: i32,
+    time_stamp: Option<SystemTime>,
+    stored_comment: Option<Vec<u8>>, // The first member's FCOMMENT, shown by -l -v
+    ifile_size: i64,
+    _caught_signals: HashSet<i32>,
+    _exiting_signal: Option<i32>,
+    _remove_ofname_fd: Option<i32>,
+    pub bytes_in: i64,
+    bytes_out: i64,
+    total_in: i64,
+    total_out: i64,
+    ifname: PathBuf,
+    ofname: PathBuf,
+    istat: Option<Metadata>,
+    // (dev, inode) of the directories currently being walked by -r, to break cycles
+    active_dirs: HashSet<(u64, u64)>,
+    walk_root: Option<PathBuf>, // The directory -r started from, that patterns are relative to
+    ifd: Option<Input>,
+    ofd: Option<Box<dyn Write + Send>>,
+    std_streams: StdStreams, // Standard input and output, as run_with_io was given them
+    // Files collected by treat_dir for the --threads workers
+    queue: Option<Vec<PathBuf>>,
+    insize: usize,
+    inptr: usize,
+    outcnt: usize,
+    _handled_sig: Vec<i32>,
+    header_bytes: usize, // Header and trailer bytes of the first member, for ratios
+    blocks: u64,         // Deflate blocks flushed or inflated for the current file
+    in_header: bool,     // Count the bytes get_byte hands out into header_bytes
+    zip_entry: Option<ZipEntry>, // Set by check_zipfile for unzip to extract
+    // Function pointer for the current operation
+    work: Option<fn(&mut GzipState) -> io::Result<Stats>>,
+    compressor: Option<Box<Compressor>>, // What zip compressed the last file with, for the next
+    inbuf: [u8; INBUFSIZ + INBUF_EXTRA], // Input buffer
+    outbuf: [u8; OUTBUFSIZ + OUTBUF_EXTRA], // Output buffer
+    data_crc: Crc32, // CRC-32 of the uncompressed data of the current member
+    data_adler: Adler32, // Adler-32 of the same data, for --format=zlib
+    first_time: bool,
+    record_io: bool,
+    bi_buf: u16,
+    bi_valid: u8,
+}
+
+// Implementation of the GzipState struct
+impl GzipState {
+
+
+    // A state with every option at its default, for a run whose messages begin with
+    // program_name
+    fn with_program_name(program_name: &str) -> Self {
+        let program_name = program_name.to_string();
+        GzipState {
+            opts: Options::default(),
+            totals: Totals::default(),
+            append_target: None,
+            index: None,
+            checking: false,
+            stream_stats: Stats::default(),
+            progress: None,
+            _foreground: false,
+            program_name,
+            env: None,
+            args: vec![],
+            exit_code: OK,
+            method: DEFLATED,
+            save_orig_name: false,
+            last_member: false,
+            part_nb: 0,
+            time_stamp: None,
+            stored_comment: None,
+            ifile_size: -1,
+            _caught_signals: HashSet::new(),
+            _exiting_signal: None,
+            _remove_ofname_fd: None,
+            bytes_in: 0,
+            bytes_out: 0,
+            total_in: 0,
+            total_out: 0,
+            ifname: PathBuf::new(),
+            ofname: PathBuf::new(),
+            istat: None,
+            active_dirs: HashSet::new(),
+            walk_root: None,
+            ifd: None,
+            ofd: None,
+            std_streams: StdStreams::new(io::stdin(), io::stdout()),
+            queue: None,
+            insize: 0,
+            inptr: 0,
+            outcnt: 0,
+            _handled_sig: vec![],
+            header_bytes: 0,
+            blocks: 0,
+            in_header: false,
+            zip_entry: None,
+            work: None, // Function pointer will be set during runtime
+            compressor: None,
+            inbuf: [0; INBUFSIZ + INBUF_EXTRA],
+            outbuf: [0; OUTBUFSIZ + OUTBUF_EXTRA],
+            data_crc: Crc32::new(),
+            data_adler: Adler32::new(),
+            first_time: true, // -l prints its header once, before the first file
+            record_io: false,
+            bi_buf: 0,
+            bi_valid: 0,
+        }
+    }
+
+    // Example method to set the 'work' function pointer based on the operation
+    fn set_work_function(&mut self) {
+        if self.opts.decompress {
+            self.work = Some(unzip); // Assuming 'unzip' is defined elsewhere
+        } else if self.opts.do_lzw {
+            self.work = Some(lzw);
+        } else {
+            self.work = Some(zip); // Assuming 'zip' is defined elsewhere
+        }
+    }
+
+    // Other methods to manipulate the state can be added here
+    // Stop the run with exitcode, where gzip would exit(): the error goes back up
+    // through the io::Results to run_with_io, which returns exitcode
+    fn do_exit(&self, exitcode: i32) -> io::Error {
+        io::Error::other(ExitStatus(exitcode))
+    }
+
+    // Translated try_help function
+    fn try_help(&self) -> io::Error {
+        eprintln!("Try `{} --help' for more information.", self.program_name);
+        self.do_exit(ERROR)
+    }
+
+    fn help(&self) -> io::Result<()> {
+        let mut out = self.std_streams.output.clone();
+        writeln!(out, "Usage: {} [OPTION]... [FILE]...", self.program_name)?;
+        for line in HELP_HEAD {
+            writeln!(out, "{}", line)?;
+        }
+        // Descriptions start in column 20, below a label too long to leave room
+        for spec in OPTIONS.iter().filter(|spec| spec.implemented) {
+            let Some((first, rest)) = spec.help.split_first() else {
+                continue;
+            };
+            let label = spec.label();
+            if label.len() < 20 {
+                writeln!(out, "{:<19} {}", label, first)?;
+            } else {
+                writeln!(out, "{}", label)?;
+                writeln!(out, "{:20}{}", "", first)?;
+            }
+            for line in rest {
+                writeln!(out, "{:20}{}", "", line)?;
+            }
+        }
+        for line in HELP_TAIL {
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    fn license(&self) -> io::Result<()> {
+        let mut out = self.std_streams.output.clone();
+        writeln!(out, "{} {}", self.program_name, VERSION)?;
+        for line in LICENSE_MSG {
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    fn version(&self) -> io::Result<()> {
+        self.license()?;
+        let mut out = self.std_streams.output.clone();
+        writeln!(out)?;
+        writeln!(out, "Written by Jean-loup Gailly.")
+    }
+
+    // The exit status once output has gone to standard output: a failed write is an
+    // error, except that a reader that went away only gets the warning status
+    fn stdout_status(&self, written: io::Result<()>) -> i32 {
+        match written.and_then(|()| self.std_streams.output.clone().flush()) {
+            Ok(()) => OK,
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => WARNING,
+            Err(err) => {
+                eprintln!("{}: write error on standard output: {}", self.program_name, strerror(&err));
+                ERROR
+            }
+        }
+    }
+
+    fn progerror(&mut self, path: &Path, err: &io::Error) {
+        eprintln!("{}: {}: {}", self.program_name, path.display(), strerror(err));
+        self.exit_code = ERROR;
+    }
+
+    // Hand the progress callback where the current input stands, if it is time to
+    pub(crate) fn progress(&mut self) {
+        let Some(progress) = self.progress.as_mut() else {
+            return;
+        };
+        let now = Instant::now();
+        if now.duration_since(progress.last) < PROGRESS_INTERVAL {
+            return;
+        }
+        progress.last = now;
+        progress.shown.set(progress.terminal);
+        (progress.callback)(&ProgressInfo {
+            name: &self.ifname,
+            decompress: self.opts.decompress,
+            bytes_in: self.bytes_in,
+            bytes_out: self.bytes_out,
+            total: (self.ifile_size >= 0).then_some(self.ifile_size),
+            elapsed: now.duration_since(progress.start),
+        });
+    }
+
+    // Take the progress line down before anything else goes to stderr
+    pub(crate) fn clear_progress(&self) {
+        if let Some(progress) = self.progress.as_ref().filter(|progress| progress.shown.get()) {
+            eprint!("\r\x1b[K");
+            progress.shown.set(false);
+        }
+    }
+
+    // The body of warn!
+    pub(crate) fn warn(&mut self, msg: &str) {
+        self.clear_progress();
+        if !self.opts.quiet {
+            eprintln!("{}", msg);
+        }
+        if self.exit_code == OK {
+            self.exit_code = WARNING;
+        }
+    }
+
+    // Give up on a failed write of the output, as gzip does: the output is removed and
+    // the run stops. A reader that went away (gzip -dc file | head) gets no message,
+    // only the warning status
+    fn write_error(&self, err: &io::Error) -> io::Error {
+        self.clear_progress();
+        if err.kind() == io::ErrorKind::BrokenPipe {
+            return self.do_exit(WARNING);
+        }
+        eprintln!("{}: {}: write error: {}", self.program_name, self.ofname.display(), strerror(err));
+        if !self.opts.to_stdout {
+            let _ = fs::remove_file(&self.ofname);
+        }
+        self.do_exit(ERROR)
+    }
+
+    // Take in the command line: args are the arguments after the program name
+    fn parse_args_from<I: IntoIterator<Item = OsString>>(&mut self, args: I) -> io::Result<()> {
+        let args: Vec<OsString> = args.into_iter().collect();
+        // As gzip does, GZIP holds options separated by white space, taken first
+        let env_args: Vec<OsString> =
+            self.env.iter().flat_map(|env| env.split_whitespace()).map(OsString::from).collect();
+        let mut given = Vec::new();
+        self.parse_options(&env_args, true, &mut given)?;
+        self.parse_options(&args, false, &mut given)?;
+        if !env_args.is_empty() && !self.opts.quiet {
+            eprintln!("{}: warning: GZIP environment variable is deprecated; use an alias or script", self.program_name);
+        }
+        Ok(())
+    }
+
+    // Take the options among args, from GZIP if from_env, and keep the rest as the files
+    // to work on. given collects the names of the options taken, for the exclusive groups
+    fn parse_options(&mut self, args: &[OsString], from_env: bool, given: &mut Vec<String>) -> io::Result<()> {
+        let mut arg_iter = args.iter().peekable();
+
+        while let Some(os_arg) = arg_iter.next() {
+            // Options are plain ASCII; file names are kept as raw OsStrings
+            let arg = os_arg.to_string_lossy();
+            if let Some(long) = arg.strip_prefix("--").filter(|long| !long.is_empty()) {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(OsString::from(value))),
+                    None => (long, None),
+                };
+                // As with getopt_long, a long option may be cut to any prefix that names
+                // only it; two spellings of one letter (--stdout, --to-stdout) are one
+                let candidates: Vec<&OptionSpec> =
+                    OPTIONS.iter().filter(|spec| spec.long.is_some_and(|long| long.starts_with(name))).collect();
+                let spec = match OPTIONS.iter().find(|spec| spec.long == Some(name)) {
+                    Some(spec) => spec,
+                    None => match candidates.first() {
+                        None => {
+                            eprintln!("{}: unrecognized option '{}'", self.program_name, arg);
+                            return Err(self.try_help());
+                        }
+                        Some(first)
+                            if candidates.len() == 1
+                                || candidates.iter().all(|spec| spec.short.is_some() && spec.short == first.short) =>
+                        {
+                            first
+                        }
+                        Some(_) => {
+                            let possibilities: String =
+                                candidates.iter().map(|spec| format!(" '--{}'", spec.long.unwrap_or_default())).collect();
+                            eprintln!("{}: option '--{}' is ambiguous; possibilities:{}", self.program_name, name, possibilities);
+                            return Err(self.try_help());
+                        }
+                    },
+                };
+                let name = spec.long.unwrap_or(name);
+                if !spec.implemented {
+                    eprintln!("{}: option '--{}' is not supported", self.program_name, name);
+                    return Err(self.try_help());
+                }
+                let operand = match spec.arg {
+                    OptionArg::None if value.is_some() => {
+                        eprintln!("{}: option '--{}' doesn't allow an argument", self.program_name, name);
+                        return Err(self.try_help());
+                    }
+                    OptionArg::Required(_) => match value.or_else(|| arg_iter.next().cloned()) {
+                        Some(operand) => Some(operand),
+                        None => {
+                            eprintln!("{}: option '--{}' requires an argument", self.program_name, name);
+                            return Err(self.try_help());
+                        }
+                    },
+                    _ => value,
+                };
+                self.take_option(spec, &format!("--{}", name), from_env, given)?;
+                match spec.short {
+                    Some(short) => self.short_option(short, operand)?,
+                    None => self.long_option(name, operand)?,
+                }
+            } else if arg.starts_with('-') && arg.len() > 1 {
+                // A cluster such as -9v is its letters one by one, up to one that takes an
+                // operand: that takes the rest of the cluster, or else the next argument
+                let cluster = &arg[1..];
+                for (at, short) in cluster.char_indices() {
+                    let Some(spec) = OPTIONS.iter().find(|spec| spec.short == Some(short) && spec.implemented) else {
+                        eprintln!("{}: unknown option -- '{}'", self.program_name, short);
+                        return Err(self.try_help());
+                    };
+                    let rest = &cluster[at + short.len_utf8()..];
+                    let operand = match spec.arg {
+                        OptionArg::Required(_) if !rest.is_empty() => Some(OsString::from(rest)),
+                        OptionArg::Required(_) => match arg_iter.next() {
+                            Some(operand) => Some(operand.clone()),
+                            None => {
+                                eprintln!("{}: option requires an argument -- '{}'", self.program_name, short);
+                                return Err(self.try_help());
+                            }
+                        },
+                        _ => None,
+                    };
+                    self.take_option(spec, &format!("-{}", short), from_env, given)?;
+                    self.short_option(short, operand)?;
+                    if matches!(spec.arg, OptionArg::Required(_)) {
+                        break;
+                    }
+                }
+            } else if from_env {
+                eprintln!("{}: {}: non-option in GZIP environment variable", self.program_name, arg);
+                return Err(self.try_help());
+            } else {
+                self.args.push(os_arg.clone());
+            }
+        }
+        Ok(())
+    }
+
+    // Refuse an option that GZIP may not hold, or that one given before it excludes;
+    // typed is the option as it was written
+    fn take_option(&mut self, spec: &OptionSpec, typed: &str, from_env: bool, given: &mut Vec<String>) -> io::Result<()> {
+        if from_env && !spec.env {
+            eprintln!("{}: {}: option not valid in GZIP environment variable", self.program_name, typed);
+            return Err(self.try_help());
+        }
+        let name = spec.name();
+        for (_, members) in OPTION_GROUPS.iter().filter(|(kind, _)| *kind == GroupKind::Exclusive) {
+            if !members.contains(&name.as_str()) {
+                continue;
+            }
+            if let Some(other) = given.iter().find(|other| **other != name && members.contains(&other.as_str())) {
+                eprintln!("{}: options {} and {} are mutually exclusive", self.program_name, other, name);
+                return Err(self.try_help());
+            }
+        }
+        // A level from GZIP is only a default, which decompression may quietly pass over
+        if !from_env && LEVEL_OPTIONS.contains(&name.as_str()) {
+            self.opts.level_option = Some(typed.to_string());
+        }
+        given.push(name);
+        Ok(())
+    }
+
+    // An option by its letter, from -X or a long name that stands for it. The operand
+    // is there for those that take one
+    fn short_option(&mut self, short: char, operand: Option<OsString>) -> io::Result<()> {
+        let value = operand.as_ref().map(|v| v.to_string_lossy().into_owned()).unwrap_or_default();
+        match short {
+            'a' => self.opts.ascii = true,
+            'b' => {
+                let Ok(bits) = value.parse() else {
+                    eprintln!("{}: -b operand is not an integer", self.program_name);
+                    return Err(self.try_help());
+                };
+                if !(INIT_BITS..=BITS).contains(&bits) {
+                    eprintln!(
+                        "{}: -b operand must be between {} and {}",
+                        self.program_name, INIT_BITS, BITS
+                    );
+                    return Err(self.try_help());
+                }
+                self.opts.maxbits = Some(bits);
+            }
+            'c' => self.opts.to_stdout = true,
+            'd' => self.opts.decompress = true,
+            'f' => self.opts.force = (self.opts.force + 1).min(FORCE_TWICE),
+            'h' | 'H' => {
+                let written = self.help();
+                return Err(self.do_exit(self.stdout_status(written)));
+            }
+            'k' => self.opts.keep = true,
+            'l' => {
+                self.opts.list = true;
+                self.opts.decompress = true;
+                self.opts.to_stdout = true;
+            }
+            'L' => {
+                let written = self.license();
+                return Err(self.do_exit(self.stdout_status(written)));
+            }
+            'm' => self.opts.no_time = Some(true),
+            'M' => self.opts.no_time = Some(false),
+            'n' => {
+                self.opts.no_name = Some(true);
+                self.opts.no_time = Some(true);
+            }
+            'N' => {
+                self.opts.no_name = Some(false);
+                self.opts.no_time = Some(false);
+            }
+            'q' => {
+                self.opts.quiet = true;
+                self.opts.verbose = 0;
+            }
+            'o' => self.opts.output = Some(PathBuf::from(operand.unwrap_or_default())),
+            'r' => self.opts.recursive = true,
+            'S' => {
+                self.opts.z_suffix = value;
+                self.opts.z_len = self.opts.z_suffix.len();
+            }
+            't' => {
+                self.opts.test = true;
+                self.opts.decompress = true;
+                self.opts.to_stdout = true;
+            }
+            'v' => {
+                self.opts.verbose += 1;
+                self.opts.quiet = false;
+            }
+            'V' => {
+                let written = self.version();
+                return Err(self.do_exit(self.stdout_status(written)));
+            }
+            'Z' => self.opts.do_lzw = true,
+            '1'..='9' => self.opts.level = short.to_digit(10).unwrap_or_default() as i32,
+            _ => unreachable!("-{} is in OPTIONS without a meaning", short),
+        }
+        Ok(())
+    }
+
+    // A long option that has no letter. Those that take an operand have it here; for
+    // those where it is optional, text is None when it was left out
+    fn long_option(&mut self, name: &str, operand: Option<OsString>) -> io::Result<()> {
+        let text = operand.as_ref().map(|v| v.to_string_lossy().into_owned());
+        let value = text.clone().unwrap_or_default();
+        match name {
+            "threads" => {
+                self.opts.threads = match value.parse::<usize>() {
+                    Ok(n) if n > 0 => n,
+                    _ => {
+                        eprintln!("{}: invalid number of threads: '{}'", self.program_name, value);
+                        return Err(self.try_help());
+                    }
+                };
+            }
+            "verify" => self.opts.verify = true,
+            "progress" => self.opts.show_progress = true,
+            "totals" => self.opts.show_totals = true,
+            "sync-flush" => self.opts.sync_flush = true,
+            "append" => self.opts.append = true,
+            "readahead" => self.opts.readahead = true,
+            "mmap" => self.opts.mmap = Some(true),
+            "no-mmap" => self.opts.mmap = Some(false),
+            "presume-input-tty" => self.opts.presume_input_tty = true,
+            "windows-names" => self.opts.windows_names = true,
+            "strict" => self.opts.strict = true,
+            "ignore-leading" => {
+                self.opts.ignore_leading = Some(match text {
+                    None => u64::MAX,
+                    Some(value) => match value.parse() {
+                        Ok(n) => n,
+                        Err(_) => {
+                            eprintln!("{}: invalid --ignore-leading value: '{}'", self.program_name, value);
+                            return Err(self.try_help());
+                        }
+                    },
+                });
+            }
+            "exclude" | "include" => match Glob::new(&value) {
+                Ok(glob) if name == "exclude" => self.opts.excludes.push(glob),
+                Ok(glob) => self.opts.includes.push(glob),
+                Err(reason) => {
+                    eprintln!("{}: invalid --{} pattern '{}': {}", self.program_name, name, value, reason);
+                    return Err(self.try_help());
+                }
+            },
+            "index" => self.opts.index_path = Some(PathBuf::from(value)),
+            "index-span" | "seek" | "length" => {
+                let n = match value.parse::<u64>() {
+                    Ok(n) if n > 0 || name != "index-span" => n,
+                    _ => {
+                        eprintln!("{}: invalid --{} value: '{}'", self.program_name, name, value);
+                        return Err(self.try_help());
+                    }
+                };
+                match name {
+                    "index-span" => self.opts.index_span = n,
+                    "seek" => self.opts.seek = Some(n),
+                    _ => self.opts.length = Some(n),
+                }
+            }
+            "max-size" | "max-ratio" => {
+                let limit = match value.parse::<i64>() {
+                    Ok(n) if n > 0 => n,
+                    _ => {
+                        eprintln!("{}: invalid --{} value: '{}'", self.program_name, name, value);
+                        return Err(self.try_help());
+                    }
+                };
+                if name == "max-size" {
+                    self.opts.max_size = Some(limit);
+                } else {
+                    self.opts.max_ratio = Some(limit);
+                }
+            }
+            "level" => match value.parse::<i32>() {
+                Ok(level) if (0..=9).contains(&level) => self.opts.level = level,
+                _ => {
+                    eprintln!("{}: invalid --level value: '{}'", self.program_name, value);
+                    return Err(self.try_help());
+                }
+            },
+            "inflate-bits" => {
+                let bits = value.split_once(',').and_then(|(lbits, dbits)| {
+                    let lbits = lbits.parse::<i32>().ok().filter(|n| (1..=16).contains(n))?;
+                    let dbits = dbits.parse::<i32>().ok().filter(|n| (1..=16).contains(n))?;
+                    Some((lbits, dbits))
+                });
+                if bits.is_none() {
+                    eprintln!("{}: invalid --inflate-bits value: '{}'", self.program_name, value);
+                    return Err(self.try_help());
+                }
+                self.opts.inflate_bits = bits;
+            }
+            "format" => {
+                self.opts.format = match value.as_str() {
+                    "gzip" => Format::Gzip,
+                    "zlib" => Format::Zlib,
+                    "raw" => Format::Raw,
+                    _ => {
+                        eprintln!("{}: invalid --format value: '{}'", self.program_name, value);
+                        return Err(self.try_help());
+                    }
+                };
+            }
+            "dictionary" => {
+                let bytes = match fs::read(&value) {
+                    Ok(bytes) => bytes,
+                    Err(err) => {
+                        eprintln!("{}: {}: {}", self.program_name, value, strerror(&err));
+                        return Err(self.do_exit(ERROR));
+                    }
+                };
+                let mut adler = Adler32::new();
+                adler.update(&bytes);
+                self.opts.dictionary = Some(Dictionary { bytes, id: adler.finalize() });
+            }
+            "zip-entries" => self.opts.zip_entries = true,
+            "output-dir" => self.opts.output_dir = Some(PathBuf::from(operand.unwrap_or_default())),
+            "mtime" => {
+                let secs = if value == "none" {
+                    Some(0)
+                } else if let Some(file) = value.strip_prefix('@') {
+                    match fs::metadata(file).and_then(|metadata| metadata.modified()) {
+                        Ok(time) => time.duration_since(SystemTime::UNIX_EPOCH).ok().map(|d| d.as_secs()),
+                        Err(err) => {
+                            eprintln!("{}: {}: {}", self.program_name, file, strerror(&err));
+                            return Err(self.do_exit(ERROR));
+                        }
+                    }
+                } else {
+                    value.parse::<u64>().ok()
+                };
+                match secs.and_then(|secs| u32::try_from(secs).ok()) {
+                    Some(secs) => self.opts.mtime = Some(secs),
+                    None => {
+                        eprintln!("{}: invalid --mtime value: '{}'", self.program_name, value);
+                        return Err(self.try_help());
+                    }
+                }
+            }
+            "os-code" => match value.parse::<u8>() {
+                Ok(code) => self.opts.os_code = Some(code),
+                Err(_) => {
+                    eprintln!("{}: invalid --os-code value: '{}'", self.program_name, value);
+                    return Err(self.try_help());
+                }
+            },
+            "bgzf" => self.opts.bgzf = true,
+            "parallel" => {
+                self.opts.parallel_chunk = match text {
+                    None => Some(PARALLEL_CHUNK),
+                    Some(value) => match value.parse::<usize>() {
+                        Ok(n) if n > 0 => Some(n),
+                        _ => {
+                            eprintln!("{}: invalid chunk size: '{}'", self.program_name, value);
+                            return Err(self.try_help());
+                        }
+                    },
+                };
+            }
+            _ => unreachable!("--{} is in OPTIONS without a meaning", name),
+        }
+        Ok(())
+    }
+
+    // Implement other methods like help, try_help, do_exit, license, version...
+    // For brevity, let's assume they are already implemented as in previous translations
+
+    // Entry point to start processing files or stdin
+    // Take in args, the arguments after the program name, and process the files they
+    // name or standard input, reading input in place of standard input and writing
+    // output in place of standard output. The exit status is returned rather than
+    // exited with
+    fn run_with_io<I, R, W>(&mut self, args: I, input: R, output: W) -> i32
+    where
+        I: IntoIterator<Item = OsString>,
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        self.std_streams = StdStreams::new(input, output);
+        match self.parse_args_from(args).and_then(|()| self.run()) {
+            Ok(status) => status,
+            Err(err) => match exit_status(&err) {
+                Some(status) => status,
+                // As for a write error, a reader that went away only gets the warning status
+                None if err.kind() == io::ErrorKind::BrokenPipe => WARNING,
+                None => {
+                    eprintln!("{}: {}", self.program_name, strerror(&err));
+                    ERROR
+                }
+            },
+        }
+    }
+
+    // The way in for the library's stream types: one stream, with the options already
+    // set, input and output standing in for stdin and stdout. What goes wrong comes back
+    // in the error rather than on stderr
+    fn run_stream<R, W>(&mut self, input: R, output: W) -> io::Result<Stats>
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        self.std_streams = StdStreams::new(input, output);
+        self.checking = true;
+        self.opts.quiet = true;
+        let status = match self.run() {
+            Ok(status) => status,
+            Err(err) => exit_status(&err).ok_or(err)?,
+        };
+        match status {
+            OK | WARNING => Ok(self.stream_stats),
+            _ if self.opts.decompress => {
+                Err(io::Error::new(io::ErrorKind::InvalidData, "invalid or damaged compressed data"))
+            }
+            _ => Err(io::Error::other("compression failed")),
+        }
+    }
+
+    fn run(&mut self) -> io::Result<i32> {
+        self.check_index_options()?;
+
+        // By default, save name and timestamp on compression but do not restore them on decompression.
+        if self.opts.no_time.is_none() {
+            self.opts.no_time = Some(self.opts.decompress);
+        }
+        if self.opts.no_name.is_none() {
+            self.opts.no_name = Some(self.opts.decompress);
+        }
+
+        if self.opts.verify && self.opts.to_stdout && !self.opts.decompress {
+            warn!(self, "{}: --verify has no effect with --stdout", self.program_name);
+        }
+
+        // zlib and raw deflate data record neither the sizes nor the name that -l shows
+        if self.opts.format != Format::Gzip && self.opts.list {
+            eprintln!("{}: --list is only supported with --format=gzip", self.program_name);
+            return Err(self.do_exit(ERROR));
+        }
+
+        // The gzip format has no way to say that a dictionary is needed
+        if self.opts.format == Format::Gzip && self.opts.dictionary.is_some() {
+            eprintln!("{}: --dictionary needs --format=zlib or --format=raw", self.program_name);
+            return Err(self.do_exit(ERROR));
+        }
+
+        // The entries of a zip file have one output only when it is stdout
+        if self.opts.zip_entries && !self.opts.to_stdout && !self.opts.list {
+            eprintln!("{}: --zip-entries needs --stdout, --test or --list", self.program_name);
+            return Err(self.do_exit(ERROR));
+        }
+
+        // BGZF is a series of --parallel members of a fixed size, in gzip format only
+        if self.opts.bgzf && !self.opts.decompress {
+            if self.opts.format != Format::Gzip {
+                eprintln!("{}: --bgzf cannot be used with --format", self.program_name);
+                return Err(self.do_exit(ERROR));
+            }
+            self.opts.parallel_chunk = Some(BGZF_BLOCK_SIZE);
+        }
+
+        // Nothing is removed with -c anyway; -t and -l write to stdout too, but keep quiet
+        if self.opts.keep && self.opts.to_stdout && !self.opts.test && !self.opts.list && self.opts.verbose != 0 {
+            eprintln!("{}: --keep is redundant with --stdout; input files are kept", self.program_name);
+        }
+
+        // -Z writes .Z files unless -S says otherwise
+        if self.opts.do_lzw && !self.opts.decompress && self.opts.z_suffix == Z_SUFFIX {
+            self.opts.z_suffix = LZW_SUFFIX.to_string();
+            self.opts.z_len = LZW_SUFFIX.len();
+        }
+
+        // Leading garbage is only passed over on the way to stdout, or to nowhere with -t
+        if self.opts.ignore_leading.is_some() {
+            if !self.opts.decompress || self.opts.list || !(self.opts.to_stdout || self.opts.test) {
+                eprintln!("{}: --ignore-leading needs --decompress with --stdout or --test", self.program_name);
+                return Err(self.do_exit(ERROR));
+            }
+            if self.opts.format != Format::Gzip {
+                eprintln!("{}: --ignore-leading cannot be used with --format", self.program_name);
+                return Err(self.do_exit(ERROR));
+            }
+        }
+
+        // Decompression has no level to use; one from GZIP goes by without a word
+        if let Some(option) = self.opts.level_option.as_ref().filter(|_| self.opts.decompress) {
+            warn!(self, "{}: {} has no effect when decompressing", self.program_name, option);
+        }
+
+        // -b bounds the LZW codes of .Z files, and deflate has no use for it
+        if self.opts.maxbits.is_some() && !self.opts.decompress && !self.opts.do_lzw {
+            warn!(self, "{}: -b has no effect without -Z", self.program_name);
+        }
+
+        // -S '' takes any name on decompression, but a compressed name needs a suffix
+        if (self.opts.z_len == 0 && !self.opts.decompress) || self.opts.z_len > MAX_SUFFIX {
+            eprintln!("{}: invalid suffix '{}'", self.program_name, self.opts.z_suffix);
+            return Err(self.do_exit(ERROR));
+        }
+
+        // -o names the output of a single input, a file or stdin; --output-dir only moves
+        // the outputs. OPTION_GROUPS keeps them from -c, -t, -l and each other
+        if self.opts.output.is_some() && (self.args.len() > 1 || self.opts.recursive) {
+            eprintln!("{}: --output needs exactly one input", self.program_name);
+            return Err(self.do_exit(ERROR));
+        }
+        if self.opts.append {
+            self.open_append_target()?;
+        }
+        if let Some(dir) = self.opts.output_dir.clone().filter(|_| !self.opts.list) {
+            // Only the directory itself is made, not its parents
+            if let Err(err) = fs::create_dir(&dir) {
+                if err.kind() != io::ErrorKind::AlreadyExists || !dir.is_dir() {
+                    eprintln!("{}: {}: {}", self.program_name, dir.display(), strerror(&err));
+                    return Err(self.do_exit(ERROR));
+                }
+            }
+        }
+
+        // Reproducible builds set SOURCE_DATE_EPOCH to the latest time stamp to record
+        if let Ok(value) = env::var("SOURCE_DATE_EPOCH") {
+            match value.parse::<u64>() {
+                Ok(epoch) => self.opts.source_date_epoch = Some(epoch),
+                Err(_) => {
+                    eprintln!("{}: invalid SOURCE_DATE_EPOCH: '{}'", self.program_name, value);
+                    return Err(self.do_exit(ERROR));
+                }
+            }
+        }
+
+        // A progress line would only get in the way of traces, or of a file on stderr
+        if self.opts.show_progress && self.opts.verbose < TRACE_BLOCKS && atty::is(atty::Stream::Stderr) {
+            self.progress = Some(Progress::new(Box::new(draw_progress), true));
+        }
+
+        // Set work function based on options
+        self.set_work_function();
+
+        // Install signal handlers (if necessary)
+        self.install_signal_handlers();
+
+        // Process files
+        if let Some(index_path) = self.opts.index_path.clone().filter(|_| self.opts.seek.is_some() || self.opts.length.is_some()) {
+            let path = PathBuf::from(&self.args[0]);
+            index::extract(self, &path, &index_path, self.opts.seek.unwrap_or(0), self.opts.length.unwrap_or(u64::MAX))?;
+        } else if !self.args.is_empty() {
+            if self.opts.to_stdout && !self.opts.test && !self.opts.list && (!self.opts.decompress || !self.opts.ascii) {
+                // Set stdout to binary mode if necessary
+                // In Rust, stdout is typically in binary mode
+            }
+            for filename in self.args.clone() {
+                let result = self.treat_file(Path::new(&filename))?;
+                self.report_file(&result);
+            }
+        } else {
+            // Process standard input
+            let result = self.treat_stdin()?;
+            self.stream_stats = result.stats;
+            self.report_file(&result);
+        }
+
+        if self.opts.list && !self.opts.quiet && self.args.len() > 1 {
+            self.do_list(None, -1)?; // Print totals
+        }
+
+        if self.opts.show_totals {
+            let totals = self.totals;
+            eprintln!(
+                "{}: {} processed, {} skipped, {} failed, {} bytes in, {} bytes out, ratio {}",
+                self.program_name, totals.processed, totals.skipped, totals.failed,
+                totals.stats.bytes_in, totals.stats.bytes_out,
+                format_ratio(totals.stats.ratio(self.opts.decompress)).trim_start()
+            );
+        }
+
+        // What is still buffered for the output would be flushed by exit() with any error
+        // ignored, leaving a truncated output behind a zero status
+        let flushed = match self.ofd.take() {
+            Some(mut ofd) => ofd.flush(),
+            None => Ok(()),
+        };
+        match self.stdout_status(flushed) {
+            ERROR => self.exit_code = ERROR,
+            WARNING if self.exit_code == OK => self.exit_code = WARNING,
+            _ => {}
+        }
+
+        Ok(self.exit_code)
+    }
+
+    // Compress, decompress, test or list one named input, or walk it with -r
+    fn treat_file(&mut self, iname: &Path) -> io::Result<FileResult> {
+        if iname.as_os_str() == "-" {
+            let cflag = self.opts.to_stdout;
+            let result = self.treat_stdin();
+            self.opts.to_stdout = cflag;
+            return result;
+        }
+
+        self.reset_for_next_file();
+        let path = iname;
+        self.ifname = iname.to_path_buf();
+        let action = if self.opts.list {
+            FileAction::List
+        } else if self.opts.test {
+            FileAction::Test
+        } else if self.opts.decompress {
+            FileAction::Decompress
+        } else {
+            FileAction::Compress
+        };
+        let mut result = FileResult::new(Some(iname), action);
+
+        // Like gzip's O_NOFOLLOW open, symbolic links are refused unless -f or -c is given
+        if self.opts.force < FORCE && !self.opts.to_stdout
+            && fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink())
+        {
+            return Ok(result.failed(format!("{}: Too many levels of symbolic links", iname.display())));
+        }
+
+        let metadata = match fs::metadata(path) {
+            Ok(meta) => meta,
+            Err(err) => return Ok(result.failed(format!("{}: {}", iname.display(), strerror(&err)))),
+        };
+        self.istat = Some(metadata.clone());
+
+        if metadata.is_dir() {
+            if self.opts.recursive {
+                let result = FileResult::new(Some(iname), FileAction::Recurse);
+                // A directory reached again through a followed link would recurse forever
+                let id = file_id(&metadata);
+                if id.is_some_and(|id| !self.active_dirs.insert(id)) {
+                    return Ok(result.failed(format!("{}: Too many levels of symbolic links", iname.display())));
+                }
+                let top = self.walk_root.is_none();
+                if top {
+                    self.walk_root = Some(path.to_path_buf());
+                }
+                let walked = if self.opts.threads > 1 && !self.opts.to_stdout && !self.opts.list && self.queue.is_none() {
+                    // Walk the whole tree first, then compress what it found on the workers.
+                    // -l lists on this state, so its header and totals come once
+                    self.queue = Some(Vec::new());
+                    let walked = self.treat_dir(path);
+                    let files = self.queue.take().unwrap_or_default();
+                    walked.and_then(|()| self.treat_files_in_parallel(&files))
+                } else {
+                    self.treat_dir(path)
+                };
+                if let Some(id) = id {
+                    self.active_dirs.remove(&id);
+                }
+                if top {
+                    self.walk_root = None;
+                }
+                walked?;
+                // Warning: ifname is now invalid
+                return Ok(result);
+            } else {
+                return Ok(result.skipped(format!("{} is a directory -- ignored", self.ifname.display())));
+            }
+        }
+
+        if !self.opts.to_stdout {
+            if !metadata.is_file() {
+                return Ok(result.skipped(format!(
+                    "{} is not a directory or a regular file -- ignored",
+                    self.ifname.display()
+                )));
+            }
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = metadata.permissions().mode();
+
+                if (mode & 0o4000) != 0 {
+                    return Ok(result.skipped(format!(
+                        "{} is set-user-ID on execution -- ignored",
+                        self.ifname.display()
+                    )));
+                }
+                if (mode & 0o2000) != 0 {
+                    return Ok(result.skipped(format!(
+                        "{} is set-group-ID on execution -- ignored",
+                        self.ifname.display()
+                    )));
+                }
+
+                if self.opts.force < FORCE {
+                    if (mode & 0o1000) != 0 {
+                        return Ok(result.skipped(format!(
+                            "{} has the sticky bit set -- file ignored",
+                            self.ifname.display()
+                        )));
+                    }
+                    if metadata.nlink() >= 2 {
+                        let other_links = metadata.nlink() - 1;
+                        return Ok(result.skipped(format!(
+                            "{} has {} other link{} -- file ignored",
+                            self.ifname.display(),
+                            other_links,
+                            if other_links == 1 { "" } else { "s" }
+                        )));
+                    }
+                }
+            }
+        }
+
+        self.ifile_size = if metadata.is_file() {
+            metadata.len() as i64
+        } else {
+            -1
+        };
+
+        if !self.opts.no_time.unwrap_or(false) || self.opts.list {
+            self.time_stamp = metadata.modified().ok();
+        }
+        self.check_time_stamp();
+
+        if self.opts.to_stdout && !self.opts.list && !self.opts.test {
+            self.ofname = PathBuf::from("stdout");
+        } else if let Err(err) = self.make_ofname() {
+            // make_ofname explains itself, and an input it skips is not always a warning
+            return Ok(result.skipped(err.to_string()).already_reported());
+        }
+
+        self.clear_bufs();
+        self.part_nb = 0;
+
+        let mut ifd = match File::open(path) {
+            Ok(file) => file,
+            Err(err) => return Ok(result.failed(format!("{}: {}", path.display(), strerror(&err)))),
+        };
+        // -l takes the sizes from the trailer; a file too short to have one has no header either
+        let trailer = if self.opts.list { read_trailer(&mut ifd).ok() } else { None };
+        self.ifd = Some(self.file_input(ifd));
+
+        if self.opts.format != Format::Gzip && self.opts.decompress {
+            self.method = self.start_deflate_stream();
+        } else if self.opts.decompress {
+            self.method = match self.get_method()? {
+                Some(method) => method,
+                None => return Ok(result.failed("not in a known format".to_string()).already_reported()),
+            };
+        }
+
+        if self.opts.list && self.zip_entry.is_some() {
+            self.list_zip_entries()?;
+            return Ok(result);
+        } else if self.opts.list {
+            self.do_list(trailer, self.method)?;
+            return Ok(result);
+        }
+
+        if self.opts.test {
+            // -t checks the data and CRC but keeps nothing
+            self.ofd = Some(Box::new(io::sink()));
+        } else if self.opts.to_stdout {
+            self.ofd = Some(Box::new(self.std_streams.output.clone()));
+        } else {
+            if self.is_same_file(&self.ofname) {
+                return Ok(result.failed(if self.ifname == self.ofname {
+                    format!(
+                        "{}: cannot {}compress onto itself",
+                        self.ifname.display(), if self.opts.decompress { "de" } else { "" }
+                    )
+                } else {
+                    format!("{} and {} are the same file", self.ifname.display(), self.ofname.display())
+                }));
+            }
+            let opened = match self.append_target.as_mut() {
+                Some(target) => target.next_member(),
+                None => self.create_outfile(),
+            };
+            match opened {
+                Ok(file) => self.ofd = Some(Box::new(file)),
+                // An existing output that was not overwritten has already been reported
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    return Ok(result.skipped(format!("{} not overwritten", self.ofname.display())).already_reported());
+                }
+                Err(err) => return Ok(result.failed(format!("{}: {}", self.ofname.display(), err))),
+            }
+        }
+
+        if !self.save_orig_name {
+            self.save_orig_name = !self.opts.no_name.unwrap_or(false);
+        }
+        if self.opts.decompress && self.opts.index_path.is_some() {
+            self.index = Some(Index::new(self.opts.index_span));
+        }
+
+        loop {
+            if let Some(work_fn) = self.work {
+                // The work functions print their own diagnostics
+                match self.do_work(work_fn) {
+                    Ok(stats) => result.stats = stats,
+                    Err(err) if exit_status(&err).is_some() => return Err(err),
+                    Err(err) => {
+                        self.method = -1;
+                        result = result.failed(err.to_string()).already_reported();
+                        break;
+                    }
+                }
+            } else {
+                return Ok(result.failed("work function not set".to_string()));
+            }
+
+            if self.next_zip_entry()? {
+                self.bytes_out = 0;
+                continue;
+            }
+
+            // zlib and raw input is a single stream, with no next member to look for
+            if self.opts.format != Format::Gzip || self.input_eof()? {
+                break;
+            }
+
+            self.method = match self.get_method()? {
+                Some(method) => method,
+                None => break,
+            };
+            self.bytes_out = 0;
+        }
+
+        self.ifd = None;
+
+        // Only data that decompressed without error gets its index
+        if let (Some(mut index), Some(index_path)) = (self.index.take(), self.opts.index_path.as_ref()) {
+            if self.method != -1 {
+                if let Err(err) = index.save(index_path, self.ifile_size as u64) {
+                    self.method = -1;
+                    result = result.failed(format!("{}: {}", index_path.display(), strerror(&err)));
+                }
+            }
+        }
+
+        if !self.opts.to_stdout {
+            // The file added to keeps its own time stamp
+            if self.append_target.is_none() {
+                self.copy_stat()?;
+            }
+
+            if let Some(Err(err)) = self.ofd.take().map(|mut ofd| ofd.flush()) {
+                return Err(self.write_error(&err));
+            }
+
+            if self.opts.verify && !self.opts.decompress && self.method != -1 {
+                if let Err(reason) = self.verify_output() {
+                    // Keep the input and drop the output, as for any failed file
+                    self.method = -1;
+                    result = result.failed(format!("{}: {}", self.ofname.display(), reason));
+                }
+            }
+
+            if let Some(index_path) = self.opts.index_path.as_ref().filter(|_| !self.opts.decompress && self.method != -1) {
+                if let Err(reason) = self.index_output() {
+                    self.method = -1;
+                    result = result.failed(format!("{}: {}", index_path.display(), reason));
+                }
+            }
+
+            // A failed input stays; its partial output is removed below
+            if !self.opts.keep && self.method != -1 {
+                if let Err(err) = fs::remove_file(path) {
+                    warn!(self, "{}: {}: {}", self.program_name, path.display(), strerror(&err));
+                }
+            }
+        }
+
+        if self.method == -1 {
+            if !self.opts.to_stdout {
+                self.remove_output_file()?;
+            }
+            if result.error.is_none() {
+                result = result.failed("invalid compressed data".to_string()).already_reported();
+            }
+            return Ok(result);
+        }
+
+        if !self.opts.test {
+            result.output = Some(self.ofname.clone());
+        }
+        Ok(result)
+    }
+
+    fn treat_dir(&mut self, dir: &Path) -> io::Result<()> {
+        // Attempt to read the directory entries
+        let dir_entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.progerror(dir, &err);
+                return Ok(());
+            }
+        };
+
+        // Sort the entries so the processing order does not depend on the filesystem
+        let mut file_names = Vec::new();
+        for entry_result in dir_entries {
+            match entry_result {
+                Ok(entry) => file_names.push(entry.file_name()),
+                Err(err) => self.progerror(dir, &err),
+            }
+        }
+        file_names.sort();
+
+        // A failing entry is reported and the walk carries on with the next one
+        for file_name in file_names {
+            // Skip "." and ".." entries
+            if file_name == "." || file_name == ".." {
+                continue;
+            }
+
+            let len = dir.as_os_str().len();
+            let entrylen = file_name.len();
+
+            // Check if the combined path length is within limits
+            if len + entrylen < MAX_PATH_LEN - 2 {
+                let mut nbuf = PathBuf::from(dir);
+
+                // On some systems, an empty `dir` means the current directory
+                if !dir.as_os_str().is_empty() {
+                    nbuf.push(&file_name);
+                } else {
+                    nbuf = PathBuf::from(&file_name);
+                }
+
+                let is_dir = fs::metadata(&nbuf).is_ok_and(|meta| meta.is_dir());
+                if self.filtered_out(&nbuf, is_dir) {
+                    continue;
+                }
+                if !is_dir && self.passed_over_in_walk(&nbuf) {
+                    self.totals.skipped += 1;
+                    continue;
+                }
+
+                // Leave anything but directories to the --threads workers, if there are any
+                if let Some(queue) = self.queue.as_mut() {
+                    if !is_dir {
+                        queue.push(nbuf);
+                        continue;
+                    }
+                }
+
+                // Call treat_file with the new path
+                match self.treat_file(&nbuf) {
+                    Ok(result) => self.report_file(&result),
+                    Err(err) if exit_status(&err).is_some() => return Err(err),
+                    Err(err) => {
+                        self.totals.failed += 1;
+                        self.progerror(&nbuf, &err);
+                    }
+                }
+            } else {
+                eprintln!(
+                    "{}: {}/{}: pathname too long",
+                    self.program_name,
+                    dir.display(),
+                    file_name.to_string_lossy()
+                );
+                self.totals.failed += 1;
+                self.exit_code = ERROR;
+            }
+        }
+
+        Ok(())
+    }
+
+    // --exclude and --include, on the path below the directory -r started from. An
+    // excluded directory is not walked at all; --include only picks among files
+    fn filtered_out(&self, path: &Path, is_dir: bool) -> bool {
+        let relative = self.walk_root.as_ref().and_then(|root| path.strip_prefix(root).ok()).unwrap_or(path);
+        let name = name_bytes(relative.as_os_str());
+        if self.opts.excludes.iter().any(|glob| glob.matches(name)) {
+            return true;
+        }
+        !is_dir && !self.opts.includes.is_empty() && !self.opts.includes.iter().any(|glob| glob.matches(name))
+    }
+
+    // Whether -r leaves a file alone on its name, before any other check could warn about
+    // it: without a known suffix when decompressing, testing or listing, and with one when
+    // compressing without -f. As in make_ofname, only -v says so, and -c takes any name
+    fn passed_over_in_walk(&mut self, path: &Path) -> bool {
+        if self.opts.to_stdout && !self.opts.list && !self.opts.test {
+            return false;
+        }
+        let suffix = self.get_suffix(name_bytes(path.as_os_str())).map(|(suff, _)| suff.to_vec());
+        if self.opts.decompress && self.opts.z_len != 0 && suffix.is_none() {
+            if self.opts.verbose != 0 {
+                self.unknown_suffix(path);
+            }
+            return true;
+        }
+        if let Some(suffix) = suffix.filter(|_| !self.opts.decompress && self.opts.force < FORCE) {
+            if self.opts.verbose != 0 {
+                self.already_suffixed(path, &suffix);
+            }
+            return true;
+        }
+        false
+    }
+
+    fn unknown_suffix(&mut self, path: &Path) {
+        warn!(self, "{}: {}: unknown suffix -- ignored", self.program_name, path.display());
+    }
+
+    // Not a warning for the exit status
+    fn already_suffixed(&self, path: &Path, suffix: &[u8]) {
+        eprintln!(
+            "{}: {} already has {} suffix -- unchanged",
+            self.program_name, path.display(), String::from_utf8_lossy(suffix)
+        );
+    }
+
+    // Compress files on self.opts.threads workers, each with its own state. Only stderr and the
+    // exit status are shared: a verbose report is written under the stderr lock so lines
+    // from different files do not interleave. A worker that stops the run (a write error)
+    // takes the rest of the files from the others, and its error is returned
+    fn treat_files_in_parallel(&mut self, files: &[PathBuf]) -> io::Result<()> {
+        let next = AtomicUsize::new(0);
+        let workers = self.opts.threads.min(files.len());
+        let outcomes: Vec<(i32, Totals, Option<io::Error>)> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    let mut worker = self.worker_state();
+                    let next = &next;
+                    // The compressor keeps its tables on the stack, as main does
+                    let builder = thread::Builder::new().stack_size(WORKER_STACK_SIZE);
+                    builder.spawn_scoped(scope, move || {
+                        while let Some(file) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                            let _stderr = (worker.opts.verbose != 0).then(|| io::stderr().lock());
+                            match worker.treat_file(file) {
+                                Ok(result) => worker.report_file(&result),
+                                Err(err) if exit_status(&err).is_some() => {
+                                    next.store(files.len(), Ordering::Relaxed);
+                                    return (worker.exit_code, worker.totals, Some(err));
+                                }
+                                Err(err) => {
+                                    worker.totals.failed += 1;
+                                    worker.progerror(file, &err);
+                                }
+                            }
+                        }
+                        (worker.exit_code, worker.totals, None)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    // A worker that could not start or panicked still failed its share
+                    let failed = Totals { failed: 1, ..Totals::default() };
+                    handle.map_or((ERROR, failed, None), |handle| handle.join().unwrap_or((ERROR, failed, None)))
+                })
+                .collect()
+        });
+        let mut stopped = None;
+        for (code, totals, exit) in outcomes {
+            self.totals.add(&totals);
+            if code == ERROR || (code == WARNING && self.exit_code == OK) {
+                self.exit_code = code;
+            }
+            stopped = stopped.or(exit);
+        }
+        stopped.map_or(Ok(()), Err)
+    }
+
+    // Run the work function, splitting compression across workers when --parallel is given
+    fn do_work(&mut self, work_fn: fn(&mut GzipState) -> io::Result<Stats>) -> io::Result<Stats> {
+        match self.opts.parallel_chunk {
+            // zlib and raw streams cannot be joined end to end the way gzip members can
+            Some(chunk_size) if !self.opts.decompress && !self.opts.do_lzw && self.opts.format == Format::Gzip => {
+                self.zip_parallel(chunk_size)
+            }
+            _ => work_fn(self),
+        }
+    }
+
+    // Compress the input as independent gzip members of chunk_size bytes, one per worker and
+    // up to self.opts.threads at a time. Concatenated members form a valid gzip stream, so they are
+    // simply written out in input order. Only the first member records the original name.
+    fn zip_parallel(&mut self, chunk_size: usize) -> io::Result<Stats> {
+        let Some(mut input) = self.ifd.take() else {
+            return Ok(self.stats());
+        };
+        self.bytes_in = 0;
+        self.bytes_out = 0;
+        self.header_bytes = 0;
+        let mut first = true;
+        let mut crc32 = 0;
+        loop {
+            let mut batch = Vec::new();
+            let mut at_eof = false;
+            while batch.len() < self.opts.threads && !at_eof {
+                let mut chunk = Vec::with_capacity(chunk_size);
+                input.by_ref().take(chunk_size as u64).read_to_end(&mut chunk)?;
+                at_eof = chunk.len() < chunk_size;
+                // Even an empty input gets one (empty) member, which for BGZF is the EOF block
+                if !chunk.is_empty() || (first && batch.is_empty() && !self.opts.bgzf) {
+                    batch.push(chunk);
+                }
+            }
+            if batch.is_empty() {
+                break;
+            }
+
+            let members = thread::scope(|scope| -> io::Result<Vec<(Vec<u8>, Stats)>> {
+                let handles = batch
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, chunk)| {
+                        let mut worker = self.worker_state();
+                        worker.ifname = self.ifname.clone();
+                        worker.time_stamp = self.time_stamp;
+                        worker.save_orig_name = self.save_orig_name && first && i == 0;
+                        worker.opts.to_stdout = false; // The member goes to memory, not to stdout
+                        worker.ifd = Some(Input::Reader(Box::new(io::Cursor::new(chunk))));
+                        let member = MemberBuf::default();
+                        worker.ofd = Some(Box::new(member.clone()));
+                        thread::Builder::new().stack_size(WORKER_STACK_SIZE).spawn_scoped(scope, move || {
+                            let stats = zip(&mut worker)?;
+                            let mut member = member.take();
+                            if worker.opts.bgzf {
+                                finish_bgzf_block(&mut member)?;
+                            }
+                            Ok((member, stats))
+                        })
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+                handles
+                    .into_iter()
+                    .map(|handle| {
+                        handle.join().unwrap_or_else(|_| {
+                            Err(io::Error::other("compression worker panicked"))
+                        })
+                    })
+                    .collect()
+            })?;
+            first = false;
+
+            for (member, stats) in members {
+                if let Some(Err(err)) = self.ofd.as_mut().map(|ofd| ofd.write_all(&member)) {
+                    return Err(self.write_error(&err));
+                }
+                self.bytes_in += stats.bytes_in;
+                self.bytes_out += member.len() as i64;
+                self.header_bytes += stats.header_bytes as usize;
+                self.blocks += stats.blocks;
+                crc32 = stats.crc32;
+                // The members each have their own CRC; --verify checks the whole input
+                self.data_crc.combine(stats.crc32, stats.bytes_in as u64);
+            }
+            self.progress();
+            if at_eof {
+                break;
+            }
+        }
+        if self.opts.bgzf {
+            if let Some(Err(err)) = self.ofd.as_mut().map(|ofd| ofd.write_all(&BGZF_EOF)) {
+                return Err(self.write_error(&err));
+            }
+            self.bytes_out += BGZF_EOF.len() as i64;
+            self.header_bytes += BGZF_EOF.len();
+        }
+        Ok(Stats { crc32, ..self.stats() })
+    }
+
+    // The most output the current input may decompress to. The ratio is taken against
+    // the file size, or for a pipe against the compressed bytes read so far.
+    fn expansion_limit(&self) -> Option<i64> {
+        let compressed = if self.ifile_size > 0 { self.ifile_size } else { self.bytes_in.max(1) };
+        let by_ratio = self.opts.max_ratio.map(|ratio| ratio.saturating_mul(compressed));
+        match (self.opts.max_size, by_ratio) {
+            (Some(size), Some(ratio)) => Some(size.min(ratio)),
+            (size, ratio) => size.or(ratio),
+        }
+    }
+
+    // A state of its own for a worker, with every option this one was given
+    fn worker_state(&self) -> GzipState {
+        let mut state = GzipState::with_program_name(&self.program_name);
+        state.opts = self.opts.clone();
+        state.opts.threads = 1;
+        state.work = self.work;
+        state
+    }
+
+    // --verify: decompress the .gz just written and check that it gives back the CRC and
+    // length of what was compressed. The check runs on a state of its own, so the
+    // current file's counters are left alone.
+    fn verify_output(&self) -> Result<(), String> {
+        // Lets tests.sh damage the output, to show that a failed check keeps the input
+        #[cfg(debug_assertions)]
+        if let Some(offset) = env::var("GZIP_TEST_CORRUPT_OUTPUT").ok().and_then(|v| v.parse::<u64>().ok()) {
+            corrupt_byte(&self.ofname, offset).map_err(|err| strerror(&err))?;
+        }
+
+        let mut checker = self.worker_state();
+        checker.checking = true;
+        checker.opts.quiet = true;
+        checker.opts.verbose = 0;
+        checker.opts.decompress = true;
+        checker.opts.test = true;
+        checker.opts.to_stdout = true;
+        checker.ifname = self.ofname.clone();
+        let sink = VerifySink::default();
+        checker.ifd = Some(Input::Reader(Box::new(File::open(&self.ofname).map_err(|err| strerror(&err))?)));
+        loop {
+            checker.method = if checker.opts.format != Format::Gzip {
+                checker.start_deflate_stream()
+            } else {
+                match checker.get_method() {
+                    Ok(Some(method)) => method,
+                    Ok(None) => return Err("verification failed: not in gzip format".to_string()),
+                    Err(err) => return Err(format!("verification failed: {}", strerror(&err))),
+                }
+            };
+            checker.ofd = Some(Box::new(sink.clone()));
+            // Each member's length is checked against its own trailer
+            checker.bytes_out = 0;
+            // get_method picks unlzw for the output of -Z
+            let work = checker.work.filter(|_| checker.opts.format == Format::Gzip).unwrap_or(unzip);
+            work(&mut checker).map_err(|err| format!("verification failed: {}", err))?;
+            if checker.opts.format != Format::Gzip || checker.input_eof().map_err(|err| strerror(&err))? {
+                break;
+            }
+        }
+
+        let (crc, len) = sink.finish();
+        if crc != self.data_crc.finalize() || len != self.bytes_in as u64 {
+            return Err("verification failed: decompressed data does not match the input".to_string());
+        }
+        Ok(())
+    }
+
+    // --index when compressing: decompress the .gz just written, as --verify does, for
+    // the checkpoints, and write the index of it
+    fn index_output(&self) -> Result<(), String> {
+        let mut indexer = self.worker_state();
+        indexer.opts.decompress = true;
+        indexer.opts.test = true;
+        indexer.opts.to_stdout = true;
+        indexer.ifname = self.ofname.clone();
+        indexer.index = Some(Index::new(self.opts.index_span));
+        indexer.ifd = Some(Input::Reader(Box::new(File::open(&self.ofname).map_err(|err| strerror(&err))?)));
+        loop {
+            indexer.method = match indexer.get_method() {
+                Ok(Some(method)) => method,
+                Ok(None) => return Err("not in gzip format".to_string()),
+                Err(err) => return Err(strerror(&err)),
+            };
+            indexer.ofd = Some(Box::new(io::sink()));
+            indexer.bytes_out = 0;
+            unzip(&mut indexer).map_err(|err| err.to_string())?;
+            if indexer.input_eof().map_err(|err| strerror(&err))? {
+                break;
+            }
+        }
+
+        let size = fs::metadata(&self.ofname).map_err(|err| strerror(&err))?.len();
+        let (Some(mut index), Some(index_path)) = (indexer.index.take(), self.opts.index_path.as_ref()) else {
+            return Ok(());
+        };
+        index.save(index_path, size).map_err(|err| strerror(&err))
+    }
+
+    // -l of a pipe, which cannot be seeked to its trailer: as gzip does, decompress the
+    // members to nowhere. The last one's CRC and length make the trailer, which ends all
+    // that was read. None if something other than a member follows the last one
+    fn stream_trailer(&mut self) -> io::Result<Option<(u64, [u8; 8])>> {
+        self.ofd = Some(Box::new(io::sink()));
+        loop {
+            let work_fn = self.work.unwrap_or(unzip);
+            self.bytes_out = 0;
+            let stats = work_fn(self)?;
+            if self.input_eof()? {
+                let mut buf = [0u8; 8];
+                buf[..4].copy_from_slice(&stats.crc32.to_le_bytes());
+                buf[4..].copy_from_slice(&(stats.bytes_out as u32).to_le_bytes());
+                self.ofd = None;
+                // As in gzip, the ratio of several members leaves no header bytes out
+                if self.part_nb > 1 {
+                    self.header_bytes = 0;
+                }
+                return Ok(Some((self.bytes_in as u64 - 8, buf)));
+            }
+            if self.get_method()?.is_none() {
+                return Ok(None);
+            }
+        }
+    }
+
+    // The input as the work functions read it: as it is, or with --readahead through
+    // a thread that reads the next buffer while this one is worked on
+    fn input_reader(&self, input: impl Read + Send + 'static) -> Box<dyn Read + Send> {
+        if self.opts.readahead {
+            Box::new(ReadAhead::new(Box::new(input)))
+        } else {
+            Box::new(input)
+        }
+    }
+
+    // An input file as the work functions read it: mapped into memory with --mmap, or
+    // once it is MMAP_THRESHOLD bytes long unless --no-mmap or --readahead say otherwise,
+    // and as input_reader has it when not. Pipes and special files are never mapped
+    fn file_input(&self, file: File) -> Input {
+        let map = self.opts.mmap.unwrap_or(!self.opts.readahead && self.ifile_size >= MMAP_THRESHOLD as i64);
+        if !map {
+            return Input::Reader(self.input_reader(file));
+        }
+        match MappedFile::new(file) {
+            Ok(mapping) => Input::Mapped(mapping),
+            Err(file) => Input::Reader(self.input_reader(file)),
+        }
+    }
+
+    fn treat_stdin(&mut self) -> io::Result<FileResult> {
+        self.reset_for_next_file();
+        // Compressed data is not read from a terminal, nor written to one, unless -f says so.
+        // -l and -t write nothing, and with --append or -o the data goes to a file
+        let to_file = self.append_target.is_some() || self.opts.output.is_some();
+        let terminal = if self.opts.decompress {
+            self.opts.presume_input_tty || self.std_streams.is_terminal(atty::Stream::Stdin)
+        } else {
+            !to_file && self.std_streams.is_terminal(atty::Stream::Stdout)
+        };
+        if self.opts.force < FORCE && !self.opts.list && !self.opts.test && terminal {
+            if !self.opts.quiet {
+                eprintln!(
+                    "{}: compressed data not {} a terminal. Use -f to force {}compression.\nFor help, type: {} -h",
+                    self.program_name,
+                    if self.opts.decompress { "read from" } else { "written to" },
+                    if self.opts.decompress { "de" } else { "" },
+                    self.program_name
+                );
+            }
+            return Err(self.do_exit(ERROR));
+        }
+
+        self.ifname = PathBuf::from("stdin");
+        self.ofname = PathBuf::from("stdout");
+
+        // As for a file, but only a regular file has a size and a time stamp worth
+        // keeping: a pipe's would make the output differ from run to run. Nor can a
+        // pipe be seeked to its trailer, so -l reads it through instead
+        let mut trailer = None;
+        let stdin = if self.std_streams.process_input { stdin_file().ok() } else { None };
+        match (stdin.as_ref().and_then(|file| file.metadata().ok()), stdin) {
+            (Some(metadata), Some(mut file)) if metadata.is_file() => {
+                self.ifile_size = metadata.len() as i64;
+                self.istat = Some(metadata.clone());
+                if !self.opts.no_time.unwrap_or(false) || self.opts.list {
+                    self.time_stamp = metadata.modified().ok();
+                }
+                self.check_time_stamp();
+                if self.opts.list {
+                    trailer = read_trailer(&mut file).ok();
+                }
+            }
+            _ => self.ifile_size = -1,
+        }
+
+        self.clear_bufs();
+        self.opts.to_stdout = self.opts.output.is_none();
+        self.part_nb = 0;
+
+        self.ifd = Some(Input::Reader(self.input_reader(self.std_streams.input.clone())));
+
+        if self.opts.format != Format::Gzip && self.opts.decompress {
+            self.method = self.start_deflate_stream();
+        } else if self.opts.decompress {
+            self.method = match self.get_method()? {
+                Some(method) => method,
+                None => {
+                    return Err(self.do_exit(self.exit_code));
+                }
+            };
+        }
+
+        let action = if self.opts.list {
+            FileAction::List
+        } else if self.opts.test {
+            FileAction::Test
+        } else if self.opts.decompress {
+            FileAction::Decompress
+        } else {
+            FileAction::Compress
+        };
+        let mut result = FileResult::new(None, action);
+
+        if self.opts.list && self.zip_entry.is_some() {
+            self.list_zip_entries()?;
+            return Ok(result);
+        } else if self.opts.list {
+            if trailer.is_none() && self.method == DEFLATED && !self.last_member {
+                match self.stream_trailer() {
+                    Ok(Some(found)) => trailer = Some(found),
+                    // Data after the last member has been warned about, and gzip lists nothing
+                    Ok(None) => return Ok(result),
+                    Err(err) if exit_status(&err).is_some() => return Err(err),
+                    Err(err) => return Ok(result.failed(err.to_string()).already_reported()),
+                }
+            }
+            self.do_list(trailer, self.method)?;
+            return Ok(result);
+        }
+
+        if let Some(target) = self.append_target.as_mut() {
+            self.ofname = target.path.clone();
+            match target.next_member() {
+                Ok(file) => self.ofd = Some(Box::new(file)),
+                Err(err) => return Ok(result.failed(format!("{}: {}", self.ofname.display(), err))),
+            }
+        } else if let Some(output) = self.opts.output.clone() {
+            // -o takes the output off stdout and makes it a file like any other: its time
+            // stamp is set, and it is removed if the input turns out to be bad
+            self.ofname = output;
+            if self.is_same_file(&self.ofname) {
+                return Ok(result.failed(format!("stdin and {} are the same file", self.ofname.display())));
+            }
+            match self.create_outfile() {
+                Ok(file) => self.ofd = Some(Box::new(file)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    return Ok(result.skipped(format!("{} not overwritten", self.ofname.display())).already_reported());
+                }
+                Err(err) => return Ok(result.failed(format!("{}: {}", self.ofname.display(), err))),
+            }
+        }
+
+        loop {
+            if let Some(work_fn) = self.work {
+                if !to_file {
+                    self.ofd = Some(if self.opts.test { Box::new(io::sink()) } else { Box::new(self.std_streams.output.clone()) });
+                }
+                // The work functions print their own diagnostics
+                match self.do_work(work_fn) {
+                    Ok(stats) => result.stats = stats,
+                    Err(err) if exit_status(&err).is_some() => return Err(err),
+                    Err(err) => {
+                        if to_file {
+                            self.remove_output_file()?;
+                        }
+                        return Ok(result.failed(err.to_string()).already_reported());
+                    }
+                }
+            } else {
+                return Ok(result.failed("work function not set".to_string()));
+            }
+
+            if self.next_zip_entry()? {
+                self.bytes_out = 0;
+                continue;
+            }
+
+            // zlib and raw input is a single stream, with no next member to look for
+            if self.opts.format != Format::Gzip || self.input_eof()? {
+                break;
+            }
+
+            self.method = match self.get_method()? {
+                Some(method) => method,
+                None if self.method != -1 => break,
+                None => {
+                    if to_file {
+                        self.remove_output_file()?;
+                    }
+                    return Ok(result.failed("not in a known format".to_string()).already_reported());
+                }
+            };
+            self.bytes_out = 0;
+        }
+
+        if self.opts.output.is_some() {
+            self.copy_stat()?;
+            if let Some(Err(err)) = self.ofd.take().map(|mut ofd| ofd.flush()) {
+                return Err(self.write_error(&err));
+            }
+            if self.opts.verify && !self.opts.decompress {
+                if let Err(reason) = self.verify_output() {
+                    self.remove_output_file()?;
+                    return Ok(result.failed(format!("{}: {}", self.ofname.display(), reason)));
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    // zlib and raw input is always deflate data; unzip reads and checks the zlib header
+    fn start_deflate_stream(&mut self) -> i32 {
+        self.part_nb += 1;
+        self.header_bytes = 0;
+        self.last_member = true;
+        self.work = Some(unzip);
+        DEFLATED
+    }
+
+    // Read the header of the next member and set up the work function for it. As in gzip,
+    // only the first member's header is counted: its length, found by counting the bytes
+    // read, not from where inbuf happens to be, goes into the ratios of -v and -l
+    fn get_method(&mut self) -> io::Result<Option<i32>> {
+        self.in_header = self.part_nb == 0;
+        if self.in_header {
+            self.header_bytes = 0;
+            if let Some(max) = self.opts.ignore_leading {
+                self.skip_leading_garbage(max)?;
+            }
+        }
+        let method = self.read_header();
+        self.in_header = false;
+        method
+    }
+
+    // --ignore-leading: pass over whatever comes before the first gzip signature (the
+    // magic and the deflate method) that starts within max bytes, leaving inbuf at it.
+    // The last bytes of a read that did not have it are kept, in case it starts there
+    fn skip_leading_garbage(&mut self, max: u64) -> io::Result<()> {
+        const SIGNATURE: [u8; 3] = [GZIP_MAGIC[0], GZIP_MAGIC[1], DEFLATED as u8];
+        let mut skipped = 0u64;
+        loop {
+            let window = &self.inbuf[self.inptr..self.insize];
+            if let Some(at) = window.windows(SIGNATURE.len()).position(|bytes| bytes == SIGNATURE) {
+                if skipped + at as u64 <= max {
+                    self.inptr += at;
+                    skipped += at as u64;
+                    if skipped > 0 {
+                        warn!(
+                            self,
+                            "{}: {}: skipped {} bytes of leading garbage",
+                            self.program_name, self.ifname.display(), skipped
+                        );
+                    }
+                }
+                return Ok(());
+            }
+            let keep = window.len().min(SIGNATURE.len() - 1);
+            skipped += (window.len() - keep) as u64;
+            if skipped > max {
+                return Ok(());
+            }
+            self.inbuf.copy_within(self.insize - keep..self.insize, 0);
+            let read = match self.ifd.as_mut() {
+                Some(input) => read_full(input, &mut self.inbuf[keep..INBUFSIZ])?,
+                None => 0,
+            };
+            self.bytes_in += read as i64;
+            self.inptr = 0;
+            self.insize = keep + read;
+            if read == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    // The input ended before its magic number did
+    fn truncated_magic(&mut self) -> Option<i32> {
+        self.clear_progress();
+        report!(self, "\n{}: {}: unexpected end of file", self.program_name, self.ifname.display());
+        self.exit_code = ERROR;
+        None
+    }
+
+    fn read_header(&mut self) -> io::Result<Option<i32>> {
+        let mut magic = [0u8; 2];
+        let method_before = self.method;
+
+        // -d -c -f may be handed something too short for a magic number, to pass through,
+        // and so may anything after a member, to be taken as trailing data. Otherwise only
+        // a file of one zero byte gets as far as "not in gzip format"
+        let short_ok = self.opts.force >= FORCE && self.opts.to_stdout || self.part_nb > 0;
+        let imagic0 = self.try_byte()?;
+        match imagic0 {
+            Some(byte) => magic[0] = byte,
+            None if short_ok => {}
+            None => return Ok(self.truncated_magic()),
+        }
+        let imagic1 = self.try_byte()?;
+        match imagic1 {
+            Some(byte) => magic[1] = byte,
+            None if short_ok || imagic0 == Some(0) => {}
+            None => return Ok(self.truncated_magic()),
+        }
+        self.method = -1;
+        self.part_nb += 1;
+        self.zip_entry = None;
+        self.last_member = self.record_io;
+
+        if magic[0..2] == GZIP_MAGIC[..] || magic[0..2] == OLD_GZIP_MAGIC[..] {
+            // gzip -f -f reads on past reserved flags and a wrong FHCRC, with an error
+            let lenient = self.opts.force >= FORCE_TWICE;
+            let parsed = {
+                let mut input = (&magic[..2]).chain(HeaderInput(self));
+                if lenient { GzipHeader::read(&mut input, true) } else { GzipHeader::parse(&mut input) }
+            };
+            let header = match parsed {
+                Ok((header, _)) => header,
+                Err(err) => {
+                    let Some(refused) = err.get_ref().and_then(|inner| inner.downcast_ref::<HeaderError>()) else {
+                        return Err(err);
+                    };
+                    match refused {
+                        HeaderError::UnknownMethod(method) => {
+                            self.method = *method as i32;
+                            report!(self, "{}: {}: {}", self.program_name, self.ifname.display(), refused);
+                        }
+                        // As gzip words these: "file is encrypted", "file has flags 0x40"
+                        HeaderError::Encrypted => {
+                            report!(self, "{}: {} is {}", self.program_name, self.ifname.display(), refused);
+                        }
+                        HeaderError::ReservedFlags(_) => {
+                            report!(self, "{}: {} has {}", self.program_name, self.ifname.display(), refused);
+                        }
+                        HeaderError::Checksum { .. } | HeaderError::NotGzip | HeaderError::TooLong => {
+                            report!(self, "{}: {}: {}", self.program_name, self.ifname.display(), refused);
+                        }
+                    }
+                    self.exit_code = ERROR;
+                    return Ok(None);
+                }
+            };
+            self.method = header.method as i32;
+            self.work = Some(unzip);
+            if lenient && header.flags & RESERVED != 0 {
+                report!(
+                    self,
+                    "{}: {} has {}",
+                    self.program_name, self.ifname.display(), HeaderError::ReservedFlags(header.flags)
+                );
+                self.exit_code = ERROR;
+            }
+            if let Some(stored) = header.header_crc.filter(|_| lenient) {
+                let computed = header.computed_crc();
+                if stored != computed {
+                    report!(
+                        self,
+                        "{}: {}: {}",
+                        self.program_name, self.ifname.display(), HeaderError::Checksum { stored, computed }
+                    );
+                    self.exit_code = ERROR;
+                }
+            }
+
+            if header.mtime != 0 && !self.opts.no_time.unwrap_or(false) {
+                let time_stamp = SystemTime::UNIX_EPOCH + Duration::from_secs(header.mtime as u64);
+                // Still restored, as gzip does, but likely a damaged or made-up header
+                if self.opts.verbose != 0 && time_stamp > SystemTime::now() + FUTURE_MTIME_SLACK {
+                    eprintln!(
+                        "{}: {}: MTIME {} is in the future",
+                        self.program_name, self.ifname.display(), header.mtime
+                    );
+                }
+                self.time_stamp = Some(time_stamp);
+            }
+
+            if let Some(extra) = header.extra.as_ref().filter(|_| self.opts.verbose != 0) {
+                eprintln!(
+                    "{}: {}: extra field of {} bytes ignored",
+                    self.program_name, self.ifname.display(), extra.len()
+                );
+            }
+
+            // A name too long for any path is dropped
+            if let Some(name) = &header.name {
+                let keep_name = (self.opts.list || !self.opts.to_stdout) && !self.opts.no_name.unwrap_or(false) && self.part_nb == 1;
+                if keep_name && name.len() < MAX_PATH_LEN {
+                    self.restore_name(name);
+                } else if keep_name {
+                    warn!(
+                        self,
+                        "{}: {}: stored name of {} bytes too long -- ignored",
+                        self.program_name, self.ifname.display(), name.len()
+                    );
+                }
+            }
+
+            if self.opts.list && self.opts.verbose != 0 && self.part_nb == 1 {
+                self.stored_comment = header.comment.clone();
+            }
+
+            // -v -v traces each member's header on one line, for looking into damaged files
+            if self.opts.verbose >= TRACE_BLOCKS {
+                eprintln!(
+                    "{}: {}: member {}: method={} flags=0x{:02x} mtime={} xfl={} os={} name={}",
+                    self.program_name, self.ifname.display(), self.part_nb, header.method,
+                    header.flag_byte(), header.mtime, header.xfl, header.os,
+                    header.name.as_deref().map_or("-".into(), |name| format!("{:?}", String::from_utf8_lossy(name)))
+                );
+            }
+
+            if self.in_header {
+                self.header_bytes += 2 * 4; // the trailer
+            }
+            return Ok(Some(self.method));
+        } else if magic[0..2] == PKZIP_MAGIC[..2] && self.inptr == 2 && self.inbuf[0..4] == PKZIP_MAGIC[..] {
+            // The signature is read again as part of the local header
+            self.inptr = 0;
+            if self.in_header {
+                self.header_bytes = 0;
+            }
+            self.work = Some(unzip);
+            if !self.check_zipfile().unwrap_or(false) {
+                return Ok(None);
+            }
+            self.last_member = true;
+            return Ok(Some(self.method));
+        } else if magic[0..2] == PACK_MAGIC[..] {
+            self.work = Some(unpack);
+            self.method = PACKED as i32;
+            return Ok(Some(self.method));
+        } else if magic[0..2] == LZW_MAGIC[..] {
+            self.work = Some(unlzw);
+            self.method = COMPRESSED as i32;
+            self.last_member = true;
+            return Ok(Some(self.method));
+        } else if magic[0..2] == LZH_MAGIC[..] {
+            self.work = Some(unlzh);
+            self.method = LZHED as i32;
+            self.last_member = true;
+            return Ok(Some(self.method));
+        } else if self.opts.force >= FORCE && self.opts.to_stdout && !self.opts.list {
+            self.method = STORED as i32;
+            self.work = Some(copy);
+            if imagic1.is_some() {
+                self.inptr -= 1;
+            }
+            self.last_member = true;
+            if let Some(byte) = imagic0 {
+                self.write_buf(&mut self.std_streams.output.clone(), &[byte], 1)?;
+                self.bytes_out += 1;
+            }
+            return Ok(Some(self.method));
+        }
+
+        if self.part_nb == 1 {
+            // The first read of the input is still all in inbuf, nothing taken from it
+            // but the magic
+            let found = if self.inptr == 2 { foreign_format(&self.inbuf[..self.insize]) } else { None };
+            match found {
+                Some((format, tool)) => report!(
+                    self,
+                    "\n{}: {}: not in gzip format ({}; use {})",
+                    self.program_name, self.ifname.display(), format, tool
+                ),
+                None => report!(self, "\n{}: {}: not in gzip format", self.program_name, self.ifname.display()),
+            }
+            self.exit_code = ERROR;
+            return Ok(None);
+        } else {
+            // Let go, the data leaves the input with the method of its last member
+            if self.trailing_data(imagic0, imagic1)? {
+                self.method = method_before;
+            }
+            return Ok(None);
+        }
+    }
+
+    // After the last member: zero bytes, as tape blocks pad it with, are let go. Anything
+    // else is warned about, or with --strict an error, and false. Either way the input is
+    // done with
+    fn trailing_data(&mut self, imagic0: Option<u8>, imagic1: Option<u8>) -> io::Result<bool> {
+        let mut inbyte = imagic1;
+        if imagic0 == Some(0) {
+            while inbyte == Some(0) {
+                inbyte = self.try_byte()?;
+            }
+            if inbyte.is_none() {
+                if self.opts.verbose != 0 {
+                    warn!(
+                        self,
+                        "\n{}: {}: decompression OK, trailing zero bytes ignored",
+                        self.program_name, self.ifname.display()
+                    );
+                }
+                return Ok(true);
+            }
+        }
+        if self.opts.strict {
+            self.clear_progress();
+            report!(self, "\n{}: {}: trailing garbage after the last member", self.program_name, self.ifname.display());
+            self.exit_code = ERROR;
+            return Ok(false);
+        }
+        warn!(
+            self,
+            "\n{}: {}: decompression OK, trailing garbage ignored",
+            self.program_name, self.ifname.display()
+        );
+        Ok(true)
+    }
+
+    // What the work function that just returned did: zip and unzip hand this back
+    pub(crate) fn stats(&self) -> Stats {
+        Stats {
+            bytes_in: self.bytes_in,
+            bytes_out: self.bytes_out,
+            header_bytes: self.header_bytes as i64,
+            crc32: self.data_crc.finalize(),
+            blocks: self.blocks,
+            method: self.method,
+        }
+    }
+
+    // Refill inbuf from ifd and return its first byte, or None at the end of the input.
+    // inbuf is the only buffer over ifd, so the header readers and the work functions
+    // all see one stream
+    pub(crate) fn fill_inbuf(&mut self) -> io::Result<Option<u8>> {
+        self.insize = match self.ifd.as_mut() {
+            // A mapped file is copied straight out of the mapping
+            Some(Input::Mapped(mapping)) => {
+                let taken = mapping.next_slice(INBUFSIZ)?;
+                self.inbuf[..taken.len()].copy_from_slice(taken);
+                taken.len()
+            }
+            // With --sync-flush one read is taken as it comes, so that what came before a
+            // pause in the input is decoded without waiting for the rest
+            Some(input) if self.opts.sync_flush => loop {
+                match input.read(&mut self.inbuf[..INBUFSIZ]) {
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    read => break read?,
+                }
+            },
+            Some(input) => read_full(input, &mut self.inbuf[..INBUFSIZ])?,
+            None => 0,
+        };
+        self.bytes_in += self.insize as i64;
+        self.progress();
+        self.inptr = 0;
+        if self.insize == 0 {
+            return Ok(None);
+        }
+        self.inptr = 1;
+        Ok(Some(self.inbuf[0]))
+    }
+
+    fn get_byte(&mut self) -> io::Result<u8> {
+        self.try_byte()?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Unexpected EOF"))
+    }
+
+    fn try_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = if self.inptr >= self.insize {
+            self.fill_inbuf()?
+        } else {
+            self.inptr += 1;
+            Some(self.inbuf[self.inptr - 1])
+        };
+        if self.in_header && byte.is_some() {
+            self.header_bytes += 1;
+        }
+        Ok(byte)
+    }
+
+    fn discard_input_bytes(&mut self, nbytes: usize) -> io::Result<()> {
+        for _ in 0..nbytes {
+            self.get_byte()?;
+        }
+        Ok(())
+    }
+
+    // Raw bytes of the last component of fname
+    fn gzip_base_name<'a>(&self, fname: &'a Path) -> &'a [u8] {
+        let bytes = name_bytes(fname.as_os_str());
+        &bytes[base_name_start(bytes)..]
+    }
+
+    fn gzip_error(&self, msg: &str) -> io::Error {
+        self.clear_progress();
+        if !self.ifname.as_os_str().is_empty() {
+            report!(self, "{}: {}: {}", self.program_name, self.ifname.display(), msg);
+        } else {
+            report!(self, "{}: {}", self.program_name, msg);
+        }
+        self.do_exit(ERROR)
+    }
+
+    // Replace characters that are invalid in file names in the last component of ofname
+    // Make the base of ofname a name the file system takes. Windows also refuses control
+    // characters, a trailing dot or space, and device names such as NUL, whatever the
+    // extension; that is checked on Windows, or anywhere with --windows-names. Returns
+    // false if nothing is left of the name
+    fn make_legal_name(&mut self) -> bool {
+        let mut name = name_bytes(self.ofname.as_os_str()).to_vec();
+        let dir_len = base_name_start(&name);
+
+        let invalid_chars = b"/\\:*?\"<>|";
+        for c in &mut name[dir_len..] {
+            if invalid_chars.contains(c) {
+                *c = b'_';
+            }
+        }
+        if cfg!(windows) || self.opts.windows_names {
+            let mut base: Vec<u8> = name[dir_len..].iter().copied().filter(|c| !c.is_ascii_control()).collect();
+            while matches!(base.last(), Some(b'.' | b' ')) {
+                base.pop();
+            }
+            let stem = base.split(|&c| c == b'.').next().unwrap_or_default();
+            if is_device_name(stem.trim_ascii_end()) {
+                base.insert(0, b'_');
+            }
+            name.truncate(dir_len);
+            name.extend_from_slice(&base);
+        }
+        let legal = name.len() > dir_len;
+        self.ofname = name_from_bytes(name);
+        legal
+    }
+
+    // Adopt a name stored in a gzip or zip header for the output file. Only the final
+    // component is kept, so the output always lands next to the name derived from the input.
+    // The stored name is taken byte for byte, so names that are not UTF-8 survive a round trip.
+    fn restore_name(&mut self, stored: &[u8]) {
+        // An output named with -o wins over the stored name
+        if self.opts.output.is_some() {
+            return;
+        }
+        let base = stored.rsplit(|&c| c == b'/' || c == b'\\').next().unwrap_or(&[]);
+        if matches!(base, b"" | b"." | b"..") {
+            warn!(
+                self,
+                "{}: {}: unsafe stored name {} ignored",
+                self.program_name, self.ifname.display(), quote_name(stored)
+            );
+            return;
+        }
+        if base != stored {
+            warn!(
+                self,
+                "{}: {}: stored name {} contains directories -- using {}",
+                self.program_name,
+                self.ifname.display(),
+                quote_name(stored),
+                quote_name(base)
+            );
+        }
+
+        let derived = self.ofname.clone();
+        let mut name = name_bytes(self.ofname.as_os_str()).to_vec();
+        name.truncate(base_name_start(&name));
+        name.extend_from_slice(base);
+        self.ofname = name_from_bytes(name);
+        let stored_path = self.ofname.clone();
+        if !self.make_legal_name() {
+            // Nothing usable was stored, so the name made from the input's stays
+            self.ofname = derived;
+            warn!(
+                self,
+                "{}: {}: stored name {} is not a legal file name -- ignored",
+                self.program_name, self.ifname.display(), quote_name(base)
+            );
+        } else if self.ofname != stored_path {
+            warn!(
+                self,
+                "{}: {}: stored name {} is not a legal file name -- using {}",
+                self.program_name,
+                self.ifname.display(),
+                quote_name(base),
+                quote_name(self.gzip_base_name(&self.ofname))
+            );
+        }
+    }
+
+    fn write_buf<W: Write>(&mut self, output: &mut W, buf: &[u8], count: usize) -> io::Result<()> {
+        if let Err(err) = output.write_all(&buf[..count]) {
+            return Err(self.write_error(&err));
+        }
+        Ok(())
+    }
+
+    // Read a local header of a PKZIP file from inbuf
+    fn read_zip_entry(&mut self) -> io::Result<ZipEntry> {
+        let mut h = [0u8; LOCHDR];
+        for byte in h.iter_mut() {
+            *byte = self.get_byte()?;
+        }
+        if LG(&h) != LOCSIG {
+            eprintln!("{}: {}: not a valid zip file", self.program_name, self.ifname.display());
+            self.exit_code = ERROR;
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid ZIP file"));
+        }
+
+        // Read the file name and the extra field
+        let mut name = vec![0u8; SH(&h[LOCFIL..]) as usize];
+        for byte in name.iter_mut() {
+            *byte = self.get_byte()?;
+        }
+        let mut extra = vec![0u8; SH(&h[LOCEXT..]) as usize];
+        for byte in extra.iter_mut() {
+            *byte = self.get_byte()?;
+        }
+
+        let flags = SH(&h[LOCFLG..]);
+        let mut entry = ZipEntry {
+            name,
+            method: SH(&h[LOCHOW..]),
+            mtime: LG(&h[LOCTIM..]),
+            crc: LG(&h[LOCCRC..]),
+            compressed_size: LG(&h[LOCSIZ..]) as u64,
+            uncompressed_size: LG(&h[LOCLEN..]) as u64,
+            has_descriptor: flags & EXTFLG != 0,
+            zip64: false,
+            unsupported: None,
+        };
+
+        // A size of 0xffffffff stands for the 8-byte one in the zip64 extra field, which
+        // has the uncompressed size first and the compressed size next, each only if needed
+        let mut zip64_sizes = zip64_extra(&extra);
+        entry.zip64 = zip64_sizes.is_some();
+        let mut zip64_size = |size: &mut u64| -> bool {
+            if *size != u32::MAX as u64 {
+                return true;
+            }
+            match zip64_sizes.as_mut().filter(|sizes| sizes.len() >= 8) {
+                Some(sizes) => {
+                    *size = LG(sizes) as u64 | (LG(&sizes[4..]) as u64) << 32;
+                    *sizes = &sizes[8..];
+                    true
+                }
+                None => false,
+            }
+        };
+        let sizes_known = zip64_size(&mut entry.uncompressed_size) && zip64_size(&mut entry.compressed_size);
+
+        entry.unsupported = if flags & CRPFLG != 0 {
+            Some("encrypted".to_string())
+        } else if entry.method as i32 != STORED && entry.method as i32 != DEFLATED {
+            Some(format!("compressed with {}", zip_method_name(entry.method)))
+        } else if !sizes_known {
+            Some("without its zip64 sizes".to_string())
+        } else if entry.method as i32 == STORED && entry.has_descriptor && entry.compressed_size == 0 {
+            // Stored data has no end of its own to find the descriptor by
+            Some("stored with its size after the data".to_string())
+        } else {
+            None
+        };
+        Ok(entry)
+    }
+
+    // Read local headers of a PKZIP file until one whose entry can be extracted, and set
+    // it up for unzip, which checks the data against the CRC and sizes here, or against
+    // the data descriptor after the data when bit 3 is set. Only --zip-entries looks past
+    // an entry that cannot be extracted; false means no entry is left to extract.
+    // -l takes any entry, to list it
+    fn check_zipfile(&mut self) -> io::Result<bool> {
+        loop {
+            let entry = self.read_zip_entry()?;
+            let Some(reason) = entry.unsupported.clone().filter(|_| !self.opts.list) else {
+                // Set the output file name if necessary
+                if self.part_nb == 1 && !self.opts.no_name.unwrap_or(false) {
+                    self.restore_name(&entry.name);
+                }
+
+                self.method = entry.method as i32;
+                self.zip_entry = Some(entry);
+                self.work = Some(unzip);
+                self.last_member = true; // Only the first entry is extracted
+                return Ok(true);
+            };
+
+            if !self.opts.zip_entries {
+                eprintln!(
+                    "{}: {} has its first entry {} -- not supported",
+                    self.program_name, self.ifname.display(), reason
+                );
+                self.exit_code = ERROR;
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Unsupported zip entry"));
+            }
+
+            // Without the sizes in the local header there is no finding the next one
+            let name = String::from_utf8_lossy(&entry.name).into_owned();
+            if entry.has_descriptor {
+                warn!(
+                    self,
+                    "{}: {}: entry {} {} -- rest ignored",
+                    self.program_name, self.ifname.display(), name, reason
+                );
+                return Ok(false);
+            }
+            warn!(
+                self,
+                "{}: {}: entry {} {} -- skipped",
+                self.program_name, self.ifname.display(), name, reason
+            );
+            self.discard_input_bytes(entry.compressed_size as usize)?;
+            if !self.at_zip_entry()? {
+                return Ok(false);
+            }
+        }
+    }
+
+    // Whether the input goes on with another local header, looked at without using it up:
+    // what is left of inbuf moves to its start and is topped up to a signature's length
+    fn at_zip_entry(&mut self) -> io::Result<bool> {
+        // inflate leaves insize at 0 once the input has run out
+        self.inptr = self.inptr.min(self.insize);
+        if self.insize - self.inptr < 4 {
+            self.inbuf.copy_within(self.inptr..self.insize, 0);
+            self.insize -= self.inptr;
+            self.inptr = 0;
+            if let (true, Some(input)) = (self.insize < 4, self.ifd.as_mut()) {
+                let n = read_full(input, &mut self.inbuf[self.insize..4])?;
+                self.insize += n;
+                self.bytes_in += n as i64;
+            }
+        }
+        Ok(self.insize - self.inptr >= 4 && LG(&self.inbuf[self.inptr..]) == LOCSIG)
+    }
+
+    // With --zip-entries, go on to the next entry of a zip file that can be extracted
+    fn next_zip_entry(&mut self) -> io::Result<bool> {
+        if !self.opts.zip_entries || self.zip_entry.is_none() || !self.at_zip_entry()? {
+            return Ok(false);
+        }
+        self.part_nb += 1;
+        self.zip_entry = None;
+        self.check_zipfile()
+    }
+
+    // -l on a zip file: a line for each entry, from one local header after another. Sizes
+    // kept in a data descriptor after the data are only known once the entry is decoded
+    fn list_zip_entries(&mut self) -> io::Result<()> {
+        let mut out = self.std_streams.output.clone();
+        let width = i64::MAX.to_string().len();
+        if self.first_time {
+            self.first_time = false;
+            if self.opts.verbose != 0 {
+                write!(out, "method  crc     date  time  ")?;
+            }
+            if !self.opts.quiet {
+                writeln!(out, "{:>width$} {:>width$}  ratio uncompressed_name", "compressed", "uncompressed", width = width)?;
+            }
+        }
+
+        while let Some(mut entry) = self.zip_entry.take() {
+            if !entry.has_descriptor {
+                self.discard_input_bytes(entry.compressed_size as usize)?;
+            } else if entry.unsupported.is_none() {
+                self.ofd = Some(Box::new(io::sink()));
+                self.bytes_out = 0;
+                self.zip_entry = Some(entry);
+                // unzip has reported bad data, which leaves the next header unknown
+                let decoded = unzip(self);
+                match self.zip_entry.take() {
+                    Some(decoded_entry) if decoded.is_ok() => entry = decoded_entry,
+                    _ => break,
+                }
+            } else {
+                warn!(
+                    self,
+                    "{}: {}: entry {} {} -- rest ignored",
+                    self.program_name, self.ifname.display(), String::from_utf8_lossy(&entry.name),
+                    entry.unsupported.as_deref().unwrap_or_default()
+                );
+                break;
+            }
+
+            if self.opts.verbose != 0 {
+                let method = match entry.method as i32 {
+                    STORED => "store".to_string(),
+                    DEFLATED => "defla".to_string(),
+                    method => format!("{:<5}", method),
+                };
+                write!(out, "{} {:08x} {}", method, entry.crc, format_dos_time(entry.mtime))?;
+            }
+            let (compressed, uncompressed) = (entry.compressed_size as i64, entry.uncompressed_size as i64);
+            self.fprint_off(&mut out, compressed, width)?;
+            write!(out, " ")?;
+            self.fprint_off(&mut out, uncompressed, width)?;
+            write!(out, " ")?;
+            self.display_ratio(&mut out, uncompressed - compressed, uncompressed)?;
+            write!(out, " ")?;
+            out.write_all(&entry.name)?;
+            writeln!(out)?;
+            if self.total_in >= 0 {
+                self.total_in += compressed;
+            }
+            if self.total_out >= 0 {
+                self.total_out += uncompressed;
+            }
+
+            if self.at_zip_entry()? {
+                self.zip_entry = Some(self.read_zip_entry()?);
+            }
+        }
+        Ok(())
+    }
+
+    // trailer is where a file's last 8 bytes start and what they are, from read_trailer
+    fn do_list(&mut self, trailer: Option<(u64, [u8; 8])>, method: i32) -> io::Result<()> {
+        let mut out = self.std_streams.output.clone();
+        const METHODS: [&str; MAX_METHODS] = [
+            "store",  /* 0 */
+            "compr",  /* 1 */
+            "pack ",  /* 2 */
+            "lzh  ",  /* 3 */
+            "", "", "", "", /* 4 to 7 reserved */
+            "defla",  /* 8 */
+        ];
+
+        let mut positive_off_t_width = 1;
+        let mut o = i64::MAX;
+
+        while o > 9 {
+            positive_off_t_width += 1;
+            o /= 10;
+        }
+
+        if self.first_time && method >= 0 {
+            self.first_time = false;
+            if self.opts.verbose != 0 {
+                write!(out, "method  crc     date  time  ")?;
+            }
+            if !self.opts.quiet {
+                writeln!(out, 
+                    "{:>width$} {:>width$}  ratio uncompressed_name",
+                    "compressed",
+                    "uncompressed",
+                    width = positive_off_t_width as usize
+                )?;
+            }
+        } else if method < 0 {
+            if self.total_in <= 0 || self.total_out <= 0 {
+                return Ok(());
+            }
+            if self.opts.verbose != 0 {
+                write!(out, "                            ")?;
+            }
+            if self.opts.verbose != 0 || !self.opts.quiet {
+                self.fprint_off(&mut out, self.total_in, positive_off_t_width)?;
+                write!(out, " ")?;
+                self.fprint_off(&mut out, self.total_out, positive_off_t_width)?;
+                write!(out, " ")?;
+            }
+            self.display_ratio(
+                &mut out,
+                self.total_out - (self.total_in - self.header_bytes as i64),
+                self.total_out,
+            )?;
+            writeln!(out, " (totals)")?;
+            return Ok(());
+        }
+
+        let mut crc: u32 = !0; // unknown
+        self.bytes_out = -1;
+        self.bytes_in = self.ifile_size; // -1 when unknown, as for a pipe
+
+        if !self.record_io && method == DEFLATED && !self.last_member {
+            // Get the crc and uncompressed size for gzip'ed (not zip'ed) files.
+            // If the seek fails, we could use read() to get to the end, but
+            // --list is used to get quick results.
+            // Use "gunzip < foo.gz | wc -c" to get the uncompressed size if
+            // you are not concerned about speed.
+            if let Some((offset, buf)) = trailer {
+                self.bytes_in = offset as i64 + 8;
+                crc = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+                // ISIZE is the length modulo 2^32, and as in gzip only the last member's
+                // is seen. The compressed length cannot tell a wrapped length from the
+                // other members of a multi-member file, so it is shown as stored
+                self.bytes_out = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]) as i64;
+            }
+        }
+
+        let mut stats = Stats { crc32: crc, method, ..self.stats() };
+        if self.opts.verbose != 0 {
+            write!(out, "{:5} {:08x} ", METHODS[stats.method as usize], stats.crc32)?;
+            if let Some(time_stamp) = self.time_stamp {
+                let datetime: DateTime<Local> = DateTime::from(time_stamp);
+                write!(out, 
+                    "{}{:3} {:02}:{:02} ",
+                    datetime.format("%b"),
+                    datetime.day(),
+                    datetime.hour(),
+                    datetime.minute()
+                )?;
+            } else {
+                write!(out, "??? ?? ??:?? ")?;
+            }
+        }
+
+        self.fprint_off(&mut out, stats.bytes_in, positive_off_t_width)?;
+        write!(out, " ")?;
+        self.fprint_off(&mut out, stats.bytes_out, positive_off_t_width)?;
+        write!(out, " ")?;
+
+        if stats.bytes_in == -1 {
+            self.total_in = -1;
+        } else if self.total_in >= 0 {
+            self.total_in += stats.bytes_in;
+        }
+        if stats.bytes_out == -1 {
+            self.total_out = -1;
+        } else if self.total_out >= 0 {
+            self.total_out += stats.bytes_out;
+        }
+        // An unknown size leaves nothing to take a ratio of
+        if stats.bytes_in == -1 || stats.bytes_out == -1 {
+            stats = Stats::default();
+            self.header_bytes = 0;
+        }
+
+        write!(out, "{} ", format_ratio(stats.ratio(true)))?;
+        // The name goes out as its bytes are, as gzip prints it, whatever their encoding
+        out.write_all(name_bytes(self.ofname.as_os_str()))?;
+        writeln!(out)?;
+        // gzip leaves the comment out; -v gives it a line of its own under the file's
+        if let Some(comment) = self.stored_comment.take() {
+            writeln!(out, "    comment: {}", String::from_utf8_lossy(&comment))?;
+        }
+
+        Ok(())
+    }
+
+    fn fprint_off<W: Write>(&self, file: &mut W, mut offset: i64, width: usize) -> io::Result<()> {
+        // Buffer to hold the string representation of the offset
+        let mut buf = [0u8; 65]; // 64 digits max for i64 plus sign
+        let mut p = buf.len();
+
+        // Don't negate offset here; it might overflow.
+        if offset < 0 {
+            // Build the digits in reverse order
+            loop {
+                p -= 1;
+                buf[p] = (b'0' as i64 - offset % 10) as u8;
+                offset /= 10;
+                if offset == 0 {
+                    break;
+                }
+            }
+            p -= 1;
+            buf[p] = b'-';
+        } else {
+            // Positive offset
+            loop {
+                p -= 1;
+                buf[p] = b'0' + (offset % 10) as u8;
+                offset /= 10;
+                if offset == 0 {
+                    break;
+                }
+            }
+        }
+
+        // Calculate the number of digits
+        let num_digits = buf.len() - p;
+
+        // Adjust the width by subtracting the number of digits
+        let mut width = if width > num_digits {
+            width - num_digits
+        } else {
+            0
+        };
+
+        // Write leading spaces to align the number to the right
+        while width > 0 {
+            file.write_all(b" ")?;
+            width -= 1;
+        }
+
+        // Write the number to the file
+        file.write_all(&buf[p..])?;
+        Ok(())
+    }
+
+    // Function to install signal handlers
+    fn install_signal_handlers(&self) {
+        // Implement signal handling if necessary
+    }
+
+    // Return the compression suffix of name, if any, and its replacement on decompression.
+    // The configured suffix takes precedence; the name must not consist solely of the suffix.
+    // Matching ignores case unless a custom suffix was given with -S.
+    fn get_suffix<'a>(&self, name: &'a [u8]) -> Option<(&'a [u8], &'static str)> {
+        let base_len = name.len() - base_name_start(name);
+        let default_suffix = self.opts.z_suffix == Z_SUFFIX;
+        std::iter::once((self.opts.z_suffix.as_str(), ""))
+            .chain(KNOWN_SUFFIXES.iter().copied())
+            .enumerate()
+            .find(|&(i, (suf, _))| {
+                if suf.is_empty() || base_len <= suf.len() {
+                    return false;
+                }
+                let tail = &name[name.len() - suf.len()..];
+                if i == 0 && !default_suffix {
+                    tail == suf.as_bytes()
+                } else {
+                    tail.eq_ignore_ascii_case(suf.as_bytes())
+                }
+            })
+            .map(|(_, (suf, replacement))| (&name[name.len() - suf.len()..], replacement))
+    }
+
+    // Shorten ofname (which ends with a compression suffix) until its last component fits
+    // in NAME_MAX bytes: name.tar becomes name.tgz, otherwise the last part longer than
+    // MIN_PART bytes loses its final byte, keeping short extensions intact.
+    fn shorten_name(&mut self) -> io::Result<()> {
+        let mut name = name_bytes(self.ofname.as_os_str()).to_vec();
+        let base_start = base_name_start(&name);
+        while name.len() - base_start > NAME_MAX {
+            let Some(suffix_len) = self.get_suffix(&name).map(|(suff, _)| suff.len()) else {
+                eprintln!("{}: {}: can't recover suffix", self.program_name, self.ofname.display());
+                self.exit_code = ERROR;
+                return Err(io::Error::other("can't recover suffix"));
+            };
+            let stem_len = name.len() - suffix_len;
+            name.truncate(stem_len);
+
+            if name.len() - base_start > 4 && name.ends_with(b".tar") {
+                name.truncate(stem_len - 4);
+                name.extend_from_slice(b".tgz");
+                continue;
+            }
+
+            let mut trunc = None;
+            let mut min_part = MIN_PART;
+            while trunc.is_none() && min_part != 0 {
+                let mut start = base_start;
+                for part in name[base_start..].split(|&c| c == b'.') {
+                    let end = start + part.len();
+                    if part.len() > min_part {
+                        trunc = Some(end);
+                    }
+                    start = end + 1;
+                }
+                min_part -= 1;
+            }
+            match trunc {
+                Some(end) => {
+                    name.remove(end - 1);
+                }
+                None => match name[base_start..].iter().rposition(|&c| c == b'.') {
+                    Some(dot) => name.truncate(base_start + dot),
+                    None => {
+                        eprintln!("{}: {}: internal error in shorten_name", self.program_name, self.ofname.display());
+                        self.exit_code = ERROR;
+                        return Err(io::Error::other("cannot shorten name"));
+                    }
+                },
+            }
+            name.extend_from_slice(self.opts.z_suffix.as_bytes());
+        }
+        self.ofname = name_from_bytes(name);
+
+        // The original name no longer follows from the output name, so always store it
+        self.save_orig_name = true;
+        if self.opts.verbose == 0 && !self.opts.quiet {
+            eprintln!("{}: {} compressed to {}", self.program_name, self.ifname.display(), self.ofname.display());
+        }
+        Ok(())
+    }
+
+    // --append: take the first file named as the gzip file to add members to. It is made
+    // if it is not there; one that is must look like gzip data unless -f is given
+    // --index builds the index of one .gz as it is written or decompressed. With --seek
+    // or --length it is read instead, to decompress just that range of the .gz to stdout
+    fn check_index_options(&mut self) -> io::Result<()> {
+        let extracting = self.opts.seek.is_some() || self.opts.length.is_some();
+        if self.opts.index_path.is_none() {
+            if extracting {
+                eprintln!("{}: --seek and --length need --index", self.program_name);
+                return Err(self.do_exit(ERROR));
+            }
+            return Ok(());
+        }
+        let option = if extracting { "--seek" } else { "--index" };
+        let conflicts = [
+            (self.opts.list, "--list"),
+            (extracting && self.opts.test, "--test"),
+            // Compressed data on stdout cannot be read back to find the checkpoints
+            (!extracting && !self.opts.decompress && self.opts.to_stdout, "--stdout"),
+            (self.opts.recursive, "--recursive"),
+            (self.opts.append, "--append"),
+            (self.opts.format != Format::Gzip, "--format"),
+        ];
+        if let Some((_, conflict)) = conflicts.iter().find(|(set, _)| *set) {
+            eprintln!("{}: {} cannot be used with {}", self.program_name, option, conflict);
+            return Err(self.do_exit(ERROR));
+        }
+        if self.args.len() != 1 || self.args[0] == "-" {
+            eprintln!("{}: {} needs exactly one input file", self.program_name, option);
+            return Err(self.do_exit(ERROR));
+        }
+        if extracting {
+            self.opts.decompress = true;
+            self.opts.to_stdout = true;
+        }
+        Ok(())
+    }
+
+    fn open_append_target(&mut self) -> io::Result<()> {
+        let conflicts = [
+            (self.opts.decompress, "--decompress"),
+            (self.opts.list, "--list"),
+            (self.opts.test, "--test"),
+            (self.opts.to_stdout, "--stdout"),
+            (self.opts.output.is_some(), "--output"),
+            (self.opts.output_dir.is_some(), "--output-dir"),
+            (self.opts.recursive, "--recursive"),
+            (self.opts.verify, "--verify"),
+            (self.opts.threads > 1, "--threads"),
+            (self.opts.format != Format::Gzip, "--format"),
+        ];
+        if let Some((_, option)) = conflicts.iter().find(|(set, _)| *set) {
+            eprintln!("{}: --append cannot be used with {}", self.program_name, option);
+            return Err(self.do_exit(ERROR));
+        }
+        if self.args.is_empty() || self.args[0] == "-" {
+            eprintln!("{}: --append needs the gzip file to add to", self.program_name);
+            return Err(self.do_exit(ERROR));
+        }
+
+        let path = PathBuf::from(self.args.remove(0));
+        let opened = fs::OpenOptions::new().read(true).append(true).create(true).open(&path);
+        let mut file = match opened {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("{}: {}: {}", self.program_name, path.display(), strerror(&err));
+                return Err(self.do_exit(ERROR));
+            }
+        };
+        let start = file.metadata().map_or(0, |metadata| metadata.len());
+        // The shortest gzip member is 20 bytes: a 10-byte header, an empty block and the trailer
+        let mut magic = [0u8; 2];
+        let looks_gzip = start >= 20 && file.read_exact(&mut magic).is_ok() && magic[..] == GZIP_MAGIC[..];
+        if start != 0 && !looks_gzip && self.opts.force < FORCE {
+            eprintln!("{}: {}: not in gzip format -- use -f to append anyway", self.program_name, path.display());
+            return Err(self.do_exit(ERROR));
+        }
+        self.append_target = Some(AppendTarget { path, file, start });
+        Ok(())
+    }
+
+    fn make_ofname(&mut self) -> io::Result<()> {
+        // An output named with -o is used as it is, as is the file --append adds to
+        if let Some(output) = self.opts.output.clone() {
+            self.ofname = output;
+            return Ok(());
+        }
+        if let Some(target) = self.append_target.as_ref() {
+            self.ofname = target.path.clone();
+            return Ok(());
+        }
+
+        let iname = name_bytes(self.ifname.as_os_str());
+        self.ofname = self.ifname.clone();
+
+        if self.opts.decompress {
+            // Decompressing: remove the suffix. With -S '' a name without a known suffix
+            // is kept, for the stored name (-N) to replace; if nothing does, writing the
+            // output fails as decompressing onto the input
+            if let Some((suff, replacement)) = self.get_suffix(iname) {
+                // Remove the suffix, turning .tgz and .taz into .tar
+                let mut name = iname[..iname.len() - suff.len()].to_vec();
+                name.extend_from_slice(replacement.as_bytes());
+                self.ofname = name_from_bytes(name);
+            } else if self.opts.z_len != 0 && (self.opts.recursive || !(self.opts.list || self.opts.test)) {
+                // Input file does not have the expected suffix. -f makes no difference, and
+                // only -t and -l take any name, unless they walk directories with -r where
+                // they pass over what -d would. Avoid annoying messages with -r
+                if self.opts.verbose != 0 || (!self.opts.recursive && !self.opts.quiet) {
+                    self.unknown_suffix(&self.ifname.clone());
+                }
+                return Err(io::Error::other("unknown suffix"));
+            }
+        } else {
+            // Compressing: refuse to compress a file that already has a compression suffix
+            if self.opts.force < FORCE {
+                if let Some((suff, _)) = self.get_suffix(iname) {
+                    // Avoid annoying messages with -r; this is not a warning for the exit status
+                    if self.opts.verbose != 0 || (!self.opts.recursive && !self.opts.quiet) {
+                        self.already_suffixed(&self.ifname, suff);
+                    }
+                    return Err(io::Error::other("already has suffix"));
+                }
+            }
+            if self.opts.z_suffix.is_empty() {
+                eprintln!("{}: invalid suffix ''", self.program_name);
+                self.exit_code = ERROR;
+                return Err(io::Error::other("empty suffix"));
+            }
+            self.ofname.as_mut_os_string().push(&self.opts.z_suffix);
+            if self.gzip_base_name(&self.ofname).len() > NAME_MAX {
+                self.shorten_name()?;
+            }
+        }
+
+        // --output-dir keeps the derived name but not the input's directory
+        if let Some(dir) = self.opts.output_dir.as_ref() {
+            if let Some(name) = self.ofname.file_name() {
+                self.ofname = dir.join(name);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Check whether `other` refers to the input file (e.g. through a hard link or case folding)
+    fn is_same_file(&self, other: &Path) -> bool {
+        let (Some(istat), Ok(ostat)) = (self.istat.as_ref(), fs::metadata(other)) else {
+            return false;
+        };
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            istat.dev() == ostat.dev() && istat.ino() == ostat.ino()
+        }
+        #[cfg(not(unix))]
+        {
+            istat.len() == ostat.len()
+                && match (fs::canonicalize(&self.ifname), fs::canonicalize(other)) {
+                    (Ok(a), Ok(b)) => a == b,
+                    _ => false,
+                }
+        }
+    }
+
+    // Open ofname for the output, never through a symbolic link: one in a shared directory
+    // could point anywhere. -f replaces an existing file but not a link, which takes -f -f
+    // and is removed rather than followed
+    fn create_outfile(&mut self) -> io::Result<File> {
+        use std::fs::OpenOptions;
+        let mut options = OpenOptions::new();
+        options.write(true);
+
+        let is_link = fs::symlink_metadata(&self.ofname).is_ok_and(|meta| meta.file_type().is_symlink());
+        if is_link && self.opts.force >= FORCE_TWICE {
+            fs::remove_file(&self.ofname)?;
+        } else if is_link && self.opts.force >= FORCE {
+            return Err(io::Error::other("output is a symbolic link; use -f -f to replace it"));
+        }
+        if self.opts.force >= FORCE {
+            options.create(true).truncate(true);
+            // A link made since the check above is refused by the open itself
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.custom_flags(libc::O_NOFOLLOW);
+            }
+        } else {
+            options.create_new(true);
+        }
+
+        match options.open(&self.ofname) {
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                let interactive = !self.opts.quiet && (self.opts.presume_input_tty || atty::is(atty::Stream::Stdin));
+                if !self.check_ofname(interactive, &mut io::stdin().lock()) {
+                    return Err(err);
+                }
+                fs::remove_file(&self.ofname)?;
+                options.open(&self.ofname)
+            }
+            result => result,
+        }
+    }
+
+    // Decide whether an existing output file may be replaced, asking on `answer` when interactive.
+    // Returns false (and records WARNING) if the file must be left alone.
+    fn check_ofname<R: BufRead>(&mut self, interactive: bool, answer: &mut R) -> bool {
+        eprint!("{}: {} already exists;", self.program_name, self.ofname.display());
+        let mut ok = false;
+        if interactive {
+            eprint!(" do you wish to overwrite (y or n)? ");
+            let _ = io::stderr().flush();
+            let mut line = String::new();
+            ok = answer.read_line(&mut line).is_ok()
+                && line.trim_start().starts_with(['y', 'Y']);
+        }
+        if !ok {
+            eprintln!("\tnot overwritten");
+            if self.exit_code == OK {
+                self.exit_code = WARNING;
+            }
+        }
+        ok
+    }
+
+    // The gzip header has 32 bits of seconds since 1970 for MTIME, and 0 means that there
+    // is none. --mtime replaces the input's time stamp, SOURCE_DATE_EPOCH caps it, and a
+    // time stamp the header cannot hold is left out with a warning
+    fn check_time_stamp(&mut self) {
+        if self.opts.decompress || self.opts.list {
+            return;
+        }
+        if let Some(mtime) = self.opts.mtime {
+            self.time_stamp = (mtime != 0).then(|| SystemTime::UNIX_EPOCH + Duration::from_secs(mtime as u64));
+            return;
+        }
+        if let Some(epoch) = self.opts.source_date_epoch {
+            let epoch = SystemTime::UNIX_EPOCH + Duration::from_secs(epoch);
+            self.time_stamp = self.time_stamp.map(|time_stamp| time_stamp.min(epoch));
+        }
+        let Some(time_stamp) = self.time_stamp else {
+            return;
+        };
+        let secs = time_stamp.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+        if secs == 0 || secs > u32::MAX as u64 {
+            warn!(
+                self,
+                "{}: {}: warning: file timestamp out of range for gzip format",
+                self.program_name, self.ifname.display()
+            );
+            self.time_stamp = None;
+        }
+    }
+
+    fn copy_stat(&mut self) -> io::Result<()> {
+        // Copy file metadata from input to output
+        // For simplicity, we'll set the modified time
+        if let Some(ref time_stamp) = self.time_stamp {
+            let result = filetime::set_file_mtime(&self.ofname, filetime::FileTime::from_system_time(*time_stamp));
+            if let Err(err) = result {
+                warn!(self, "{}: {}: {}", self.program_name, self.ofname.display(), strerror(&err));
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_output_file(&self) -> io::Result<()> {
+        // Only the member that failed comes off the file --append adds to
+        if let Some(target) = self.append_target.as_ref() {
+            return target.file.set_len(target.start);
+        }
+        fs::remove_file(&self.ofname)?;
+        Ok(())
+    }
+
+    // Whether the input has ended after a member; any byte looked at is left in inbuf
+    // for get_method. Compression and members that must come last end the input
+    fn input_eof(&mut self) -> io::Result<bool> {
+        if !self.opts.decompress || self.last_member {
+            return Ok(true);
+        }
+        if self.inptr >= self.insize {
+            if self.fill_inbuf()?.is_none() {
+                return Ok(true);
+            }
+            self.inptr = 0;
+        }
+        Ok(false)
+    }
+
+    fn display_ratio<W: Write>(&self, out: &mut W, num: i64, den: i64) -> io::Result<()> {
+        write!(out, "{}", format_ratio(if den == 0 { 0.0 } else { 100.0 * num as f64 / den as f64 }))
+    }
+
+    // Print what happened to one input and fold it into the exit code. With -v, a
+    // processed input gets one line on stderr, as gzip does.
+    fn report_file(&mut self, result: &FileResult) {
+        self.clear_progress();
+        if !result.reported {
+            if let Some(error) = &result.error {
+                eprintln!("{}: {}", self.program_name, error);
+            } else if let Some(reason) = &result.skipped_reason {
+                if !self.opts.quiet {
+                    eprintln!("{}: {}", self.program_name, reason);
+                }
+            }
+        }
+        let code = result.exit_code();
+        if code == ERROR || (code == WARNING && self.exit_code == OK) {
+            self.exit_code = code;
+        }
+        if result.action != FileAction::Recurse {
+            let counted = if result.error.is_some() {
+                Totals { failed: 1, ..Totals::default() }
+            } else if result.skipped_reason.is_some() {
+                Totals { skipped: 1, ..Totals::default() }
+            } else {
+                Totals { processed: 1, stats: result.stats, ..Totals::default() }
+            };
+            self.totals.add(&counted);
+        }
+
+        if self.opts.verbose == 0 || result.error.is_some() || result.skipped_reason.is_some() {
+            return;
+        }
+        let outcome = match result.action {
+            FileAction::List | FileAction::Recurse => return,
+            FileAction::Test => " OK".to_string(),
+            // Standard input gets no line of its own when decompressed
+            FileAction::Decompress if result.input.is_none() => return,
+            FileAction::Compress | FileAction::Decompress => format_ratio(result.ratio()),
+        };
+        let done = if self.append_target.is_some() {
+            "appended to"
+        } else if self.opts.keep {
+            "created"
+        } else {
+            "replaced with"
+        };
+        match (&result.input, &result.output) {
+            (Some(input), Some(output)) => eprintln!("{}:\t{} -- {} {}", input.display(), outcome, done, output.display()),
+            (Some(input), None) => eprintln!("{}:\t{}", input.display(), outcome),
+            (None, _) => eprintln!("{}", outcome),
+        }
+    }
+
+    // Forget everything the previous file left behind. Options, the exit code and
+    // per-run state such as the -l header and totals are kept.
+    fn reset_for_next_file(&mut self) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress.start = Instant::now();
+            progress.last = progress.start;
+        }
+        self.save_orig_name = false;
+        self.last_member = false;
+        self.part_nb = 0;
+        self.time_stamp = None;
+        self.stored_comment = None;
+        self.ifile_size = -1;
+        self.istat = None;
+        self.ifd = None;
+        self.ofd = None;
+        self.header_bytes = 0;
+        self.blocks = 0;
+        self.data_crc.reset();
+        self.data_adler.reset();
+        self.bi_buf = 0;
+        self.bi_valid = 0;
+        self.clear_bufs();
+    }
+
+    fn clear_bufs(&mut self) {
+        // Clear any buffers if needed
+        self.bytes_in = 0;
+        self.bytes_out = 0;
+        self.insize = 0;
+        self.inptr = 0;
+        self.outcnt = 0;
+    }
+
+    // Append a byte to the output buffer, writing the buffer out once it is full
+    fn put_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.outbuf[self.outcnt] = byte;
+        self.outcnt += 1;
+        if self.outcnt == OUTBUFSIZ {
+            self.flush_outbuf()?;
+        }
+        Ok(())
+    }
+
+    // Check the expansion limits, then CRC and write decoded bytes straight from the
+    // buffer a decoder put them in
+    pub(crate) fn write_decoded(&mut self, buf: &[u8]) -> io::Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+
+        // --max-size and --max-ratio stop a small bomb before it fills the disk
+        if let Some(limit) = self.expansion_limit() {
+            if self.bytes_out + buf.len() as i64 > limit {
+                return Err(io::Error::other("expansion limit exceeded"));
+            }
+        }
+
+        self.update_check(buf);
+
+        // With -t, ofd is a sink
+        let Some(ofd) = self.ofd.as_mut() else {
+            return Err(io::Error::other("no output file"));
+        };
+        if let Err(err) = ofd.write_all(buf) {
+            return Err(self.write_error(&err));
+        }
+        self.bytes_out += buf.len() as i64;
+        Ok(())
+    }
+
+    // Add uncompressed data to the member's check value: the CRC always, and the
+    // Adler-32 as well when it is what the zlib trailer will hold
+    fn update_check(&mut self, data: &[u8]) {
+        self.data_crc.update(data);
+        if self.opts.format == Format::Zlib {
+            self.data_adler.update(data);
+        }
+    }
+
+    // Write the buffered output to ofd and account for it in bytes_out
+    fn flush_outbuf(&mut self) -> io::Result<()> {
+        if self.outcnt == 0 {
+            return Ok(());
+        }
+        let Some(ofd) = self.ofd.as_mut() else {
+            return Err(io::Error::other("no output file"));
+        };
+        if let Err(err) = ofd.write_all(&self.outbuf[..self.outcnt]) {
+            return Err(self.write_error(&err));
+        }
+        self.bytes_out += self.outcnt as i64;
+        self.outcnt = 0;
+        Ok(())
+    }
+
+    /// Send a value on a given number of bits.
+    /// IN assertion: length <= 16 and value fits in length bits.
+    fn send_bits(&mut self, mut value: u16, length: u8) -> io::Result<()> {
+        // If not enough room in bi_buf, use (valid) bits from bi_buf and
+        // (16 - bi_valid) bits from value, leaving (width - (16 - bi_valid))
+        // unused bits in value.
+
+        const BUF_SIZE: u8 = 16; // Size of bi_buf in bits
+
+        if self.bi_valid + length >= BUF_SIZE {
+            // bi_buf has less room than the number of bits we need to add
+            self.bi_buf |= value << self.bi_valid;
+//             println!("Current bi_buf (before put_short): {:04x}", self.bi_buf);
+            self.put_short(self.bi_buf)?;
+
+            // Shift the value right by (BUF_SIZE - bi_valid) bits
+            self.bi_buf = ((value as u32) >> (BUF_SIZE - self.bi_valid)) as u16;
+            self.bi_valid = self.bi_valid + length - BUF_SIZE;
+        } else {
+            // There is enough room in bi_buf
+            self.bi_buf |= value << self.bi_valid;
+            self.bi_valid += length;
+        }
+        Ok(())
+    }
+
+    fn put_short(&mut self, value: u16) -> io::Result<()> {
+        self.put_byte((value & 0xFF) as u8)?;        // Lower byte
+        self.put_byte(((value >> 8) & 0xFF) as u8) // Upper byte
+    }
+
+    // Write out any remaining bits and the buffered output, so -c streams block by block
+    fn bi_windup(&mut self) -> io::Result<()> {
+        if self.bi_valid > 8 {
+            self.put_short(self.bi_buf)?;
+        } else if self.bi_valid > 0 {
+            self.put_byte(self.bi_buf as u8)?;
+        }
+        self.bi_buf = 0;
+        self.bi_valid = 0;
+        self.flush_outbuf()
+    }
+
+    // Function to write a 4-byte little-endian unsigned long
+    fn put_long(&mut self, value: u32) -> io::Result<()> {
+        for byte in value.to_le_bytes() {
+            self.put_byte(byte)?;
+        }
+        Ok(())
+    }
+}
+
+// CRC-32 as stored in the gzip trailer
+#[derive(Clone)]
+struct Crc32 {
+    crc: u32, // Of the data so far
+}
+
+impl Crc32 {
+    fn new() -> Self {
+        Crc32 { crc: 0 }
+    }
+
+    fn reset(&mut self) {
+        self.crc = 0;
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.crc = crc32::update(self.crc, data);
+    }
+
+    // Carry on past len bytes of data whose CRC is crc, without the data
+    fn combine(&mut self, crc: u32, len: u64) {
+        self.crc = crc32::combine(self.crc, crc, len);
+    }
+
+    fn finalize(&self) -> u32 {
+        self.crc
+    }
+}
+
+// Adler-32 (RFC 1950), the check value of the zlib format
+struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    // Largest n such that 255 * n * (n + 1) / 2 + (n + 1) * (BASE - 1) fits in a u32,
+    // so the sums only need reducing once per n bytes
+    const NMAX: usize = 5552;
+    const BASE: u32 = 65521;
+
+    fn new() -> Self {
+        Adler32 { a: 1, b: 0 }
+    }
+
+    fn reset(&mut self) {
+        *self = Adler32::new();
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for chunk in data.chunks(Self::NMAX) {
+            for &byte in chunk {
+                self.a += byte as u32;
+                self.b += self.a;
+            }
+            self.a %= Self::BASE;
+            self.b %= Self::BASE;
+        }
+    }
+
+    fn finalize(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+// The input as a Read for GzipHeader, a byte at a time through try_byte, which keeps
+// header_bytes
+struct HeaderInput<'a>(&'a mut GzipState);
+
+impl Read for HeaderInput<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(slot) = buf.first_mut() else {
+            return Ok(0);
+        };
+        match self.0.try_byte()? {
+            Some(byte) => {
+                *slot = byte;
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+// Output of one --parallel member, shared between the worker writing it and the caller
+#[derive(Clone, Default)]
+struct MemberBuf(Arc<Mutex<Vec<u8>>>);
+
+impl MemberBuf {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl Write for MemberBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Decompressed output of --verify: only its CRC-32 and length are kept
+#[derive(Clone)]
+struct VerifySink(Arc<Mutex<(Crc32, u64)>>);
+
+impl Default for VerifySink {
+    fn default() -> Self {
+        VerifySink(Arc::new(Mutex::new((Crc32::new(), 0))))
+    }
+}
+
+impl VerifySink {
+    fn finish(&self) -> (u32, u64) {
+        let seen = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        (seen.0.finalize(), seen.1)
+    }
+}
+
+impl Write for VerifySink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut seen = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        seen.0.update(buf);
+        seen.1 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The error do_exit makes, that stops the run with this status rather than failing
+// one file
+#[derive(Debug)]
+struct ExitStatus(i32);
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "exit status {}", self.0)
+    }
+}
+
+impl std::error::Error for ExitStatus {}
+
+// The status err stops the run with, if do_exit made it
+fn exit_status(err: &io::Error) -> Option<i32> {
+    err.get_ref().and_then(|inner| inner.downcast_ref::<ExitStatus>()).map(|status| status.0)
+}
+
+// Standard input and output of a run, shared by the files that use them in turn. Only
+// the process's own are asked whether they are a terminal, or for the file that stdin
+// is open on
+struct StdStreams {
+    input: SharedInput,
+    output: SharedOutput,
+    process_input: bool,
+    process_output: bool,
+}
+
+impl StdStreams {
+    fn new<R, W>(input: R, output: W) -> Self
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        StdStreams {
+            process_input: (&input as &dyn Any).is::<io::Stdin>(),
+            process_output: (&output as &dyn Any).is::<io::Stdout>(),
+            input: SharedInput(Arc::new(Mutex::new(Box::new(input)))),
+            output: SharedOutput(Arc::new(Mutex::new(Box::new(output)))),
+        }
+    }
+
+    fn is_terminal(&self, stream: atty::Stream) -> bool {
+        match stream {
+            atty::Stream::Stdin => self.process_input && atty::is(stream),
+            atty::Stream::Stdout => self.process_output && atty::is(stream),
+            atty::Stream::Stderr => atty::is(stream),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct SharedInput(Arc<Mutex<Box<dyn Read + Send>>>);
+
+impl Read for SharedInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).read(buf)
+    }
+}
+
+#[derive(Clone)]
+struct SharedOutput(Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).flush()
+    }
+}
+
+// The input of --readahead. A thread of its own reads it into one buffer while the
+// other is taken from; the two go back and forth through a pair of channels. Each
+// buffer holds what one read gave, so a short read still shows as one (--sync-flush),
+// and the end of the input or a read error arrives in its place
+struct ReadAhead {
+    full: mpsc::Receiver<io::Result<Vec<u8>>>,
+    empty: mpsc::SyncSender<Vec<u8>>,
+    current: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+const READAHEAD_BUFFERS: usize = 2;
+
+impl ReadAhead {
+    fn new(mut input: Box<dyn Read + Send>) -> Self {
+        let (full_sender, full) = mpsc::sync_channel(READAHEAD_BUFFERS);
+        let (empty, empty_receiver) = mpsc::sync_channel::<Vec<u8>>(READAHEAD_BUFFERS);
+        for _ in 0..READAHEAD_BUFFERS {
+            let _ = empty.send(vec![0; INBUFSIZ]);
+        }
+        // The thread stops at the end of the input, or once the reader has gone
+        thread::spawn(move || {
+            while let Ok(mut buf) = empty_receiver.recv() {
+                buf.resize(INBUFSIZ, 0);
+                let read = loop {
+                    match input.read(&mut buf) {
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                        read => break read,
+                    }
+                };
+                let end = !matches!(read, Ok(n) if n > 0);
+                let sent = full_sender.send(read.map(|n| {
+                    buf.truncate(n);
+                    buf
+                }));
+                if end || sent.is_err() {
+                    break;
+                }
+            }
+        });
+        ReadAhead { full, empty, current: Vec::new(), pos: 0, done: false }
+    }
+}
+
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.pos == self.current.len() {
+            if self.done {
+                return Ok(0);
+            }
+            // Hand the spent buffer back to be filled again
+            let spent = std::mem::take(&mut self.current);
+            self.pos = 0;
+            if spent.capacity() > 0 {
+                let _ = self.empty.send(spent);
+            }
+            match self.full.recv() {
+                Ok(Ok(chunk)) if !chunk.is_empty() => self.current = chunk,
+                Ok(Err(err)) => {
+                    self.done = true;
+                    return Err(err);
+                }
+                _ => {
+                    self.done = true;
+                    return Ok(0);
+                }
+            }
+        }
+        let n = buf.len().min(self.current.len() - self.pos);
+        buf[..n].copy_from_slice(&self.current[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// Flip every bit of one byte of a file (GZIP_TEST_CORRUPT_OUTPUT)
+#[cfg(debug_assertions)]
+fn corrupt_byte(path: &Path, offset: u64) -> io::Result<()> {
+    use std::io::{Seek, SeekFrom};
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+    let mut byte = [0u8; 1];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut byte)?;
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(&[!byte[0]])
+}
+
+fn unpack(_state: &mut GzipState) -> io::Result<Stats> {
+    unimplemented!()
+}
+
+fn unlzh(_state: &mut GzipState) -> io::Result<Stats> {
+    unimplemented!()
+}
+
+// fn unzip(_state: &mut GzipState) -> io::Result<()> {
+//     unimplemented!()
+// }
+
+// Pass the input through as it is, for -d -c -f on data in no known format. read_header
+// wrote the first byte itself and left the rest of the first read in inbuf
+fn copy(state: &mut GzipState) -> io::Result<Stats> {
+    while state.inptr < state.insize {
+        let unwritten = state.inbuf[state.inptr..state.insize].to_vec();
+        state.write_decoded(&unwritten)?;
+        if state.fill_inbuf()?.is_none() {
+            break;
+        }
+        state.inptr = 0;
+    }
+    Ok(state.stats())
+}
+
+// A percentage as -v and -l print it: right-aligned to one decimal, as gzip's "%5.1f%%"
+fn format_ratio(ratio: f64) -> String {
+    format!("{:5.1}%", ratio)
+}
+
+// The last 8 bytes of a file and how far past the current position they start, leaving
+// the file where it was. For gzip they are the trailer of the last member, which -l reports
+fn read_trailer(file: &mut File) -> io::Result<(u64, [u8; 8])> {
+    let start = file.stream_position()?;
+    let offset = file.seek(SeekFrom::End(-8))?;
+    let mut buf = [0u8; 8];
+    file.read_exact(&mut buf)?;
+    file.seek(SeekFrom::Start(start))?;
+    Ok((offset.saturating_sub(start), buf))
+}
+
+// Read until buf is full or the input ends, retrying interrupted reads. A pipe or
+// socket may hand back less than was asked for, so one short read is not EOF.
+pub(crate) fn read_full<R: Read + ?Sized>(input: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+// Message for an I/O error without the "(os error N)" suffix, as strerror would print it
+fn strerror(err: &io::Error) -> String {
+    let msg = err.to_string();
+    match msg.find(" (os error ") {
+        Some(end) => msg[..end].to_string(),
+        None => msg,
+    }
+}
+
+// Identity of a file for cycle detection; not available on every platform
+#[cfg(unix)]
+fn file_id(meta: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_meta: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+// Raw bytes of a file name; on Unix these are exactly the bytes the kernel sees
+fn name_bytes(name: &OsStr) -> &[u8] {
+    name.as_encoded_bytes()
+}
+
+// Build a file name from raw bytes, such as a name stored in a gzip or zip header
+#[cfg(unix)]
+fn name_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn name_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// The name to go by in messages: the base name gzip was run as, up to any extension
+// such as .exe
+fn program_name_from(arg0: &OsStr) -> String {
+    let name = name_bytes(arg0);
+    let base = &name[base_name_start(name)..];
+    let stem = base.split(|&c| c == b'.').next().unwrap_or_default();
+    if stem.is_empty() {
+        "gzip".to_string()
+    } else {
+        String::from_utf8_lossy(stem).into_owned()
+    }
+}
+
+// Offset of the last component of a file name given as raw bytes
+// Names Windows keeps for devices, in any case: CON, PRN, AUX, NUL, COM1 to COM9 and
+// LPT1 to LPT9
+fn is_device_name(stem: &[u8]) -> bool {
+    let upper = stem.to_ascii_uppercase();
+    match upper.as_slice() {
+        b"CON" | b"PRN" | b"AUX" | b"NUL" => true,
+        [b'C', b'O', b'M', digit] | [b'L', b'P', b'T', digit] => (b'1'..=b'9').contains(digit),
+        _ => false,
+    }
+}
+
+// A stored name for a message, quoted the way GNU tools quote a name that may hold
+// anything ('a'$'\001''b'): control characters and bytes that are not UTF-8 are escaped,
+// so a name cannot garble the terminal or pass for more than one line
+fn quote_name(name: &[u8]) -> String {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Part {
+        Closed,
+        Plain,   // Inside '...'
+        Escaped, // Inside $'...'
+    }
+    // Close the part that is open, if it is not the one wanted, and open that one
+    fn enter(quoted: &mut String, part: &mut Part, to: Part) {
+        if *part == to {
+            return;
+        }
+        if *part != Part::Closed {
+            quoted.push('\'');
+        }
+        match to {
+            Part::Plain => quoted.push('\''),
+            Part::Escaped => quoted.push_str("$'"),
+            Part::Closed => {}
+        }
+        *part = to;
+    }
+
+    let mut quoted = String::new();
+    let mut part = Part::Closed;
+    for chunk in name.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\'' => {
+                    enter(&mut quoted, &mut part, Part::Closed);
+                    quoted.push_str("\\'");
+                }
+                '\n' | '\t' | '\r' => {
+                    enter(&mut quoted, &mut part, Part::Escaped);
+                    quoted.push_str(c.escape_default().to_string().as_str());
+                }
+                c if c.is_control() => {
+                    enter(&mut quoted, &mut part, Part::Escaped);
+                    let mut bytes = [0; 4];
+                    for byte in c.encode_utf8(&mut bytes).bytes() {
+                        quoted.push_str(&format!("\\{:03o}", byte));
+                    }
+                }
+                c => {
+                    enter(&mut quoted, &mut part, Part::Plain);
+                    quoted.push(c);
+                }
+            }
+        }
+        for byte in chunk.invalid() {
+            enter(&mut quoted, &mut part, Part::Escaped);
+            quoted.push_str(&format!("\\{:03o}", byte));
+        }
+    }
+    if quoted.is_empty() {
+        return "''".to_string();
+    }
+    enter(&mut quoted, &mut part, Part::Closed);
+    quoted
+}
+
+fn base_name_start(name: &[u8]) -> usize {
+    name.iter()
+        .rposition(|&c| c == b'/' || (cfg!(windows) && c == b'\\'))
+        .map_or(0, |i| i + 1)
+}
+
+// CRC calculation functions
+fn crc32d(c: u32, data: u64) -> u32 {
+    // Implement CRC32D logic (using the equivalent algorithm)
+    let mut crc = c;
+    for i in 0..8 {
+        crc = crc32b(crc, (data >> (i * 8)) as u8);
+    }
+    crc
+}
+
+fn crc32w(c: u32, data: u32) -> u32 {
+    // Implement CRC32W logic (using the equivalent algorithm)
+    crc32d(c, data as u64)
+}
+
+fn crc32h(c: u32, data: u16) -> u32 {
+    // Implement CRC32H logic (using the equivalent algorithm)
+    crc32d(c, data as u64)
+}
+
+fn crc32b(c: u32, data: u8) -> u32 {
+    // Implement CRC32B logic (using the equivalent algorithm)
+    let poly: u32 = 0xedb88320;
+    let mut crc = c ^ 0xff; // Initial value for CRC
+    crc ^= data as u32;
+
+    for _ in 0..8 {
+        if (crc & 1) != 0 {
+            crc = (crc >> 1) ^ poly;
+        } else {
+            crc >>= 1;
+        }
+    }
+    crc
+}
+
+// The gzip command, as src/main.rs runs it: the arguments, GZIP and the standard
+// streams are the process's own. Gives the exit status
+pub fn run_process() -> i32 {
+    let mut args = env::args_os();
+    let program_name = args.next().map_or_else(|| "gzip".to_string(), |arg0| program_name_from(&arg0));
+    let mut state = GzipState::with_program_name(&program_name);
+    state.env = env::var("GZIP").ok();
+
+    // Parse the command line and run the main processing loop
+    state.run_with_io(args, io::stdin(), io::stdout())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An output that refuses every write with kind
+    struct FailingOutput(io::ErrorKind);
+
+    impl Write for FailingOutput {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(self.0))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::from(self.0))
+        }
+    }
+
+    // An input that gives one byte at a time, with an Interrupted error before each
+    struct Interrupting {
+        data: Vec<u8>,
+        pos: usize,
+        interrupt: bool,
+    }
+
+    impl Read for Interrupting {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::Error::from(io::ErrorKind::Interrupted));
+            }
+            if buf.is_empty() || self.pos == self.data.len() {
+                return Ok(0);
+            }
+            buf[0] = self.data[self.pos];
+            self.pos += 1;
+            Ok(1)
+        }
+    }
+
+    // Run gzip with args on input, with a stack as large as main's for the compressor
+    fn run<W: Write + Send + 'static>(args: &[&str], input: &[u8], output: W) -> i32 {
+        run_on(args, io::Cursor::new(input.to_vec()), output)
+    }
+
+    fn run_on<R, W>(args: &[&str], input: R, output: W) -> i32
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        thread::Builder::new()
+            .stack_size(WORKER_STACK_SIZE)
+            .spawn(move || GzipState::with_program_name("gzip").run_with_io(args, input, output))
+            .and_then(|handle| handle.join().map_err(|_| io::Error::other("gzip panicked")))
+            .unwrap()
+    }
+
+    // The exit status and what was written to stdout
+    pub(crate) fn gzip(args: &[&str], input: &[u8]) -> (i32, Vec<u8>) {
+        let output = MemberBuf::default();
+        let status = run(args, input, output.clone());
+        (status, output.take())
+    }
+
+    #[test]
+    fn compress_and_decompress_stdin() {
+        let text = b"hello, hello, hello world\n".repeat(100);
+        let (status, compressed) = gzip(&["-1"], &text);
+        assert_eq!(status, OK);
+        assert_eq!(compressed[..2], GZIP_MAGIC[..]);
+        assert!(compressed.len() < text.len());
+
+        let (status, decompressed) = gzip(&["-d"], &compressed);
+        assert_eq!(status, OK);
+        assert_eq!(decompressed, text);
+    }
+
+    #[test]
+    fn bad_data_fails_the_input() {
+        let (status, _) = gzip(&["-t"], b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03\xff\xff");
+        assert_eq!(status, ERROR);
+    }
+
+    #[test]
+    fn usage_error_returns_status() {
+        let (status, output) = gzip(&["--no-such-option"], b"");
+        assert_eq!(status, ERROR);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn help_returns_status() {
+        let (status, output) = gzip(&["-h"], b"");
+        assert_eq!(status, OK);
+        assert!(output.starts_with(b"Usage: gzip"));
+    }
+
+    // Interrupted reads are tried again, and reads one byte long go on to the next, so
+    // the output is the same as from an input read whole
+    #[test]
+    fn interrupted_one_byte_reads() {
+        let text = b"hello, hello, hello world\n".repeat(1000);
+        let compressed = gzip(&["-6"], &text).1;
+        for (args, input, expected) in [
+            (&["-1"][..], &text, gzip(&["-1"], &text).1),
+            (&["-6"][..], &text, compressed.clone()),
+            (&["-d"][..], &compressed, text.clone()),
+            (&["-d", "--sync-flush"][..], &compressed, text.clone()),
+        ] {
+            let output = MemberBuf::default();
+            let input = Interrupting { data: input.clone(), pos: 0, interrupt: false };
+            assert_eq!(run_on(args, input, output.clone()), OK, "{:?}", args);
+            assert_eq!(output.take(), expected, "{:?}", args);
+        }
+    }
+
+    // Which inputs are mapped: long regular files by default, any regular file with
+    // --mmap, none with --no-mmap, and never a special file
+    #[cfg(unix)]
+    #[test]
+    fn files_mapped_by_length_and_option() {
+        let path = std::env::temp_dir().join(format!("gzip-file-input-{}", std::process::id()));
+        fs::write(&path, b"a short regular file").unwrap();
+        let mut state = GzipState::with_program_name("gzip");
+        for (mmap, size, mapped) in [
+            (None, 20, false),
+            (None, MMAP_THRESHOLD as i64, true),
+            (Some(false), MMAP_THRESHOLD as i64, false),
+            (Some(true), 20, true),
+        ] {
+            state.opts.mmap = mmap;
+            state.ifile_size = size;
+            let input = state.file_input(File::open(&path).unwrap());
+            assert_eq!(matches!(input, Input::Mapped(_)), mapped, "{:?} at {} bytes", mmap, size);
+        }
+        // --readahead reads even a long file, unless --mmap asks for the mapping
+        state.opts.readahead = true;
+        state.opts.mmap = None;
+        assert!(matches!(state.file_input(File::open(&path).unwrap()), Input::Reader(_)));
+        state.opts.mmap = Some(true);
+        assert!(matches!(state.file_input(File::open(&path).unwrap()), Input::Mapped(_)));
+        state.opts.readahead = false;
+        assert!(matches!(state.file_input(File::open("/dev/null").unwrap()), Input::Reader(_)));
+        fs::remove_file(path).unwrap();
+    }
+
+    // A write error stops the run where gzip would exit, and the status comes back
+    #[test]
+    fn write_error_returns_status() {
+        assert_eq!(run(&["-1"], b"data", FailingOutput(io::ErrorKind::Other)), ERROR);
+        assert_eq!(run(&["-1"], b"data", FailingOutput(io::ErrorKind::BrokenPipe)), WARNING);
+    }
+}
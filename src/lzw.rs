@@ -0,0 +1,354 @@
+use std::io;
+use crate::unzip::data_error;
+use crate::{read_full, GzipState, Stats, BITS, COMPRESSED, INBUFSIZ, INIT_BITS, LZW_MAGIC, OUTBUFSIZ};
+
+// The third byte of a .Z file, after the magic: the largest code width in the low bits,
+// and whether code 256 clears the table
+const BIT_MASK: u8 = 0x1f;
+const BLOCK_MODE: u8 = 0x80;
+const LZW_RESERVED: u8 = 0x60;
+
+const CLEAR: usize = 256; // Code that empties the table in block mode
+const FIRST: usize = 257; // First free entry in block mode
+
+// The string table of the compressor, open addressed as compress has it: a prime size
+// big enough for 16-bit codes, and the first probe at (byte << 8) ^ prefix
+const HSIZE: usize = 69001;
+const HSHIFT: u32 = 8;
+
+// Once the table is full, the compression ratio is looked at every this many input
+// bytes, and the table cleared when it has got worse
+const CHECK_GAP: i64 = 10000;
+
+// Codes are read in groups of eight, one group taking as many bytes as the codes have
+// bits, and a change of width leaves the rest of the group unused (compress writes it
+// out whole). Reading a byte at a time, the place in the group is a count of codes
+struct CodeReader {
+    bits: u32,
+    nbits: u32,
+    width: u32,
+    in_group: u32,
+}
+
+impl CodeReader {
+    fn next(&mut self, state: &mut GzipState) -> io::Result<Option<usize>> {
+        while self.nbits < self.width {
+            match state.try_byte()? {
+                Some(byte) => {
+                    self.bits |= (byte as u32) << self.nbits;
+                    self.nbits += 8;
+                }
+                // What is left at the end is the padding of the last byte
+                None => return Ok(None),
+            }
+        }
+        let code = self.bits & ((1 << self.width) - 1);
+        self.bits >>= self.width;
+        self.nbits -= self.width;
+        self.in_group = (self.in_group + 1) % 8;
+        Ok(Some(code as usize))
+    }
+
+    // Pass over the rest of the group, and read codes of width bits from the next one.
+    // Between codes less than a byte is held, and groups start on a byte
+    fn set_width(&mut self, state: &mut GzipState, width: u32) -> io::Result<()> {
+        if self.in_group != 0 {
+            let skip = ((8 - self.in_group) * self.width - self.nbits) / 8;
+            for _ in 0..skip {
+                if state.try_byte()?.is_none() {
+                    break;
+                }
+            }
+        }
+        self.bits = 0;
+        self.nbits = 0;
+        self.in_group = 0;
+        self.width = width;
+        Ok(())
+    }
+}
+
+// Decompress the data of compress(1) (.Z), as gzip's unlzw does. The magic has been read;
+// the code width given in the header must not be beyond -b, or BITS without it
+pub(crate) fn unlzw(state: &mut GzipState) -> io::Result<Stats> {
+    let flags = match state.try_byte()? {
+        Some(flags) => flags,
+        None => return Err(data_error(state, "unexpected end of file")),
+    };
+    let maxbits = (flags & BIT_MASK) as u32;
+    let block_mode = flags & BLOCK_MODE != 0;
+    if flags & LZW_RESERVED != 0 {
+        warn!(
+            state,
+            "{}: {}: warning, unknown flags 0x{:x}",
+            state.program_name, state.ifname.display(), flags & LZW_RESERVED
+        );
+    }
+    let limit = state.opts.maxbits.unwrap_or(BITS) as u32;
+    if maxbits > limit {
+        let msg = format!("compressed with {} bits, can only handle {} bits", maxbits, limit);
+        return Err(data_error(state, &msg));
+    }
+    let maxmaxcode = 1usize << maxbits;
+
+    // Sized for BITS, as a header may claim fewer bits than the first codes have
+    let mut prefix = vec![0u16; 1 << BITS];
+    let mut suffix: Vec<u8> = (0..1usize << BITS).map(|code| code as u8).collect();
+    let mut stack = Vec::with_capacity(1 << BITS);
+    let mut out = Vec::with_capacity(OUTBUFSIZ + (1 << BITS));
+
+    let mut reader = CodeReader { bits: 0, nbits: 0, width: INIT_BITS as u32, in_group: 0 };
+    let mut maxcode = (1usize << INIT_BITS) - 1;
+    let mut free_ent = if block_mode { FIRST } else { 256 };
+    let mut oldcode: Option<usize> = None;
+    let mut finchar = 0u8;
+
+    loop {
+        if free_ent > maxcode {
+            let width = reader.width + 1;
+            reader.set_width(state, width)?;
+            maxcode = if width == maxbits { maxmaxcode } else { (1 << width) - 1 };
+        }
+        let Some(code) = reader.next(state)? else {
+            break;
+        };
+
+        let Some(old) = oldcode else {
+            if code >= 256 {
+                return Err(data_error(state, "corrupt input."));
+            }
+            finchar = code as u8;
+            out.push(finchar);
+            oldcode = Some(code);
+            continue;
+        };
+
+        if code == CLEAR && block_mode {
+            // The entry made after a clear goes where CLEAR is, and is never read
+            free_ent = FIRST - 1;
+            reader.set_width(state, INIT_BITS as u32)?;
+            maxcode = (1 << INIT_BITS) - 1;
+            continue;
+        }
+
+        // A code not yet in the table is the one about to be made (KwKwK): the previous
+        // string and its own first byte
+        let mut entry = code;
+        if code >= free_ent {
+            if code > free_ent {
+                return Err(data_error(state, "corrupt input."));
+            }
+            stack.push(finchar);
+            entry = old;
+        }
+        while entry >= 256 {
+            stack.push(suffix[entry]);
+            entry = prefix[entry] as usize;
+        }
+        finchar = suffix[entry];
+        stack.push(finchar);
+        out.extend(stack.drain(..).rev());
+
+        if free_ent < maxmaxcode {
+            prefix[free_ent] = old as u16;
+            suffix[free_ent] = finchar;
+            free_ent += 1;
+        }
+        oldcode = Some(code);
+
+        if out.len() >= OUTBUFSIZ {
+            state.write_decoded(&out)?;
+            out.clear();
+        }
+    }
+    state.write_decoded(&out)?;
+    Ok(state.stats())
+}
+
+// The other side of CodeReader: codes go out in groups of eight, and a change of width
+// pads the group to its full size with zero bits
+struct CodeWriter {
+    bits: u32,
+    nbits: u32,
+    width: u32,
+    in_group: u32,
+    written: i64, // Bytes out as compress counts them, a group at a time, for the ratio
+}
+
+impl CodeWriter {
+    fn put(&mut self, state: &mut GzipState, code: usize) -> io::Result<()> {
+        self.bits |= (code as u32) << self.nbits;
+        self.nbits += self.width;
+        self.flush_bytes(state)?;
+        self.in_group = (self.in_group + 1) % 8;
+        if self.in_group == 0 {
+            self.written += self.width as i64;
+        }
+        Ok(())
+    }
+
+    fn set_width(&mut self, state: &mut GzipState, width: u32) -> io::Result<()> {
+        if self.in_group != 0 {
+            self.nbits += (8 - self.in_group) * self.width;
+            self.flush_bytes(state)?;
+            self.written += self.width as i64;
+        }
+        self.in_group = 0;
+        self.width = width;
+        Ok(())
+    }
+
+    fn flush_bytes(&mut self, state: &mut GzipState) -> io::Result<()> {
+        while self.nbits >= 8 {
+            state.put_byte(self.bits as u8)?;
+            self.bits >>= 8;
+            self.nbits -= 8;
+        }
+        Ok(())
+    }
+
+    // The last group is only as long as its codes
+    fn finish(&mut self, state: &mut GzipState) -> io::Result<()> {
+        if self.nbits > 0 {
+            state.put_byte(self.bits as u8)?;
+            self.bits = 0;
+            self.nbits = 0;
+        }
+        Ok(())
+    }
+}
+
+// Where the compressor's table stands, and the width the codes have to be for it
+struct Table {
+    writer: CodeWriter,
+    maxbits: u32,
+    maxcode: usize,
+    free_ent: usize,
+}
+
+impl Table {
+    // Write a code, and widen the codes that follow once the next entry would not fit.
+    // unlzw looks at the same free_ent before reading the next code
+    fn output(&mut self, state: &mut GzipState, code: usize) -> io::Result<()> {
+        self.writer.put(state, code)?;
+        if self.free_ent > self.maxcode {
+            let width = self.writer.width + 1;
+            self.writer.set_width(state, width)?;
+            self.maxcode = if width == self.maxbits { 1 << self.maxbits } else { (1 << width) - 1 };
+        }
+        Ok(())
+    }
+
+    // Start the table afresh, telling the decoder with CLEAR
+    fn clear(&mut self, state: &mut GzipState) -> io::Result<()> {
+        self.free_ent = FIRST;
+        self.writer.put(state, CLEAR)?;
+        self.writer.set_width(state, INIT_BITS as u32)?;
+        self.maxcode = (1 << INIT_BITS) - 1;
+        Ok(())
+    }
+}
+
+// Compress to the .Z format of compress(1) in block mode, with codes of up to -b bits
+// (BITS without it), for -Z
+pub(crate) fn lzw(state: &mut GzipState) -> io::Result<Stats> {
+    state.outcnt = 0;
+    // .Z has no check value, but --verify compares the CRC of what it decompresses
+    state.data_crc.reset();
+    state.method = COMPRESSED as i32;
+    let maxbits = state.opts.maxbits.unwrap_or(BITS) as u32;
+    let maxmaxcode = 1usize << maxbits;
+    for byte in [LZW_MAGIC[0], LZW_MAGIC[1], BLOCK_MODE | maxbits as u8] {
+        state.put_byte(byte)?;
+    }
+    state.header_bytes = 3;
+
+    let mut table = Table {
+        writer: CodeWriter { bits: 0, nbits: 0, width: INIT_BITS as u32, in_group: 0, written: 3 },
+        maxbits,
+        maxcode: (1 << INIT_BITS) - 1,
+        free_ent: FIRST,
+    };
+    let mut htab = vec![-1i64; HSIZE]; // prefix + (byte << maxbits) of each entry, -1 for none
+    let mut codetab = vec![0u16; HSIZE];
+    let mut ratio = 0i64;
+    let mut checkpoint = CHECK_GAP;
+    let mut in_count = 0i64;
+    let mut ent: Option<usize> = None;
+
+    let mut buf = vec![0u8; INBUFSIZ];
+    loop {
+        let read = match state.ifd.as_mut() {
+            Some(input) => read_full(input, &mut buf),
+            None => Ok(0),
+        };
+        let n = match read {
+            Ok(n) => n,
+            Err(err) => return Err(state.gzip_error(&format!("Error reading input: {}", err))),
+        };
+        if n == 0 {
+            break;
+        }
+        state.update_check(&buf[..n]);
+        state.bytes_in += n as i64;
+        state.progress();
+
+        for &c in &buf[..n] {
+            in_count += 1;
+            let Some(prefix) = ent else {
+                ent = Some(c as usize);
+                continue;
+            };
+
+            // Look for prefix followed by c
+            let fcode = ((c as i64) << maxbits) + prefix as i64;
+            let mut i = ((c as usize) << HSHIFT) ^ prefix;
+            if htab[i] >= 0 && htab[i] != fcode {
+                let disp = if i == 0 { 1 } else { HSIZE - i };
+                loop {
+                    i = if i >= disp { i - disp } else { i + HSIZE - disp };
+                    if htab[i] < 0 || htab[i] == fcode {
+                        break;
+                    }
+                }
+            }
+            if htab[i] == fcode {
+                ent = Some(codetab[i] as usize);
+                continue;
+            }
+
+            table.output(state, prefix)?;
+            ent = Some(c as usize);
+            if table.free_ent < maxmaxcode {
+                codetab[i] = table.free_ent as u16;
+                htab[i] = fcode;
+                table.free_ent += 1;
+            } else if in_count >= checkpoint {
+                // The ratio, in 1/256ths while that cannot overflow
+                checkpoint = in_count + CHECK_GAP;
+                let rat = if in_count > 0x007f_ffff {
+                    match table.writer.written >> 8 {
+                        0 => 0x7fff_ffff,
+                        out => in_count / out,
+                    }
+                } else {
+                    (in_count << 8) / table.writer.written
+                };
+                if rat > ratio {
+                    ratio = rat;
+                } else {
+                    ratio = 0;
+                    htab.fill(-1);
+                    table.clear(state)?;
+                }
+            }
+        }
+    }
+    if let Some(prefix) = ent {
+        table.output(state, prefix)?;
+    }
+    table.writer.finish(state)?;
+    state.flush_outbuf()?;
+
+    Ok(state.stats())
+}
@@ -0,0 +1,150 @@
+use std::fs::File;
+use std::io::{self, Read};
+
+pub(crate) const MMAP_THRESHOLD: u64 = 16 << 20; // Regular files this long are mapped unless --no-mmap
+
+// The input the work functions read: any reader, or a regular file mapped into memory.
+// Deflate's window and inbuf are filled from a mapping by copying straight out of it,
+// with no read(2); everything else reads either as a Read
+pub(crate) enum Input {
+    Reader(Box<dyn Read + Send>),
+    Mapped(MappedFile),
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Input::Reader(reader) => reader.read(buf),
+            Input::Mapped(mapping) => mapping.read(buf),
+        }
+    }
+}
+
+// A regular file mapped read-only, handed out a slice at a time. A file cut short while
+// it is mapped faults on the pages past its new end (SIGBUS) instead of reading short,
+// so before each slice the file's length is looked at again, one fstat in place of a
+// read, and a file found shorter is a read error, "file shrank while being read". Only
+// a truncation between that look and the copy still faults
+pub(crate) struct MappedFile {
+    file: File,
+    ptr: *const u8,
+    len: usize,
+    pos: usize,
+}
+
+// The mapping is private to this reader and only ever read
+unsafe impl Send for MappedFile {}
+
+impl MappedFile {
+    // Map file from its current position on; a file that cannot be mapped (not a
+    // regular file, empty, or refused by the system) is given back to be read as usual
+    #[cfg(unix)]
+    pub(crate) fn new(mut file: File) -> Result<Self, File> {
+        use std::io::Seek;
+        use std::os::unix::io::AsRawFd;
+        let (len, pos) = match (file.metadata(), file.stream_position()) {
+            (Ok(meta), Ok(pos)) if meta.is_file() && meta.len() > pos && meta.len() <= usize::MAX as u64 => {
+                (meta.len() as usize, pos as usize)
+            }
+            _ => return Err(file),
+        };
+        // SAFETY: a fresh read-only private mapping of the whole file, unmapped on drop
+        let ptr = unsafe {
+            libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0)
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(file);
+        }
+        // SAFETY: only advice about the mapping just made
+        unsafe { libc::madvise(ptr, len, libc::MADV_SEQUENTIAL) };
+        Ok(MappedFile { file, ptr: ptr as *const u8, len, pos })
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn new(file: File) -> Result<Self, File> {
+        Err(file)
+    }
+
+    // The next bytes of the file, at most max of them and none at its end
+    pub(crate) fn next_slice(&mut self, max: usize) -> io::Result<&[u8]> {
+        let n = max.min(self.len - self.pos);
+        if n == 0 {
+            return Ok(&[]);
+        }
+        if self.file.metadata()?.len() < self.len as u64 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file shrank while being read"));
+        }
+        // SAFETY: the mapping is len bytes long and lives as long as self
+        let mapped = unsafe { std::slice::from_raw_parts(self.ptr, self.len) };
+        let taken = &mapped[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(taken)
+    }
+}
+
+impl Read for MappedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let taken = self.next_slice(buf.len())?;
+        buf[..taken.len()].copy_from_slice(taken);
+        Ok(taken.len())
+    }
+}
+
+#[cfg(unix)]
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        // SAFETY: ptr and len are the mapping made in new, and nothing borrows it past self
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::path::PathBuf;
+
+    // A file of its own in the temporary directory, holding data
+    fn temp_file(name: &str, data: &[u8]) -> (PathBuf, File) {
+        let path = std::env::temp_dir().join(format!("gzip-mapped-{}-{}", std::process::id(), name));
+        File::create(&path).and_then(|mut file| file.write_all(data)).unwrap();
+        let file = File::options().read(true).write(true).open(&path).unwrap();
+        (path, file)
+    }
+
+    #[test]
+    fn a_regular_file_is_handed_out_from_its_position() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let (path, mut file) = temp_file("regular", &data);
+        file.seek(SeekFrom::Start(10)).unwrap();
+        let mut mapping = MappedFile::new(file).map_err(|_| "not mapped").unwrap();
+        assert_eq!(mapping.next_slice(1000).unwrap(), &data[10..1010]);
+        let mut rest = Vec::new();
+        mapping.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[1010..]);
+        assert!(mapping.next_slice(1000).unwrap().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // Special files and empty ones are given back to be read
+    #[test]
+    fn other_files_are_not_mapped() {
+        assert!(MappedFile::new(File::open("/dev/null").unwrap()).is_err());
+        let (path, file) = temp_file("empty", b"");
+        assert!(MappedFile::new(file).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // A file cut short under the mapping is an error, not a fault on its missing pages
+    #[test]
+    fn a_file_that_shrinks_is_an_error() {
+        let (path, file) = temp_file("shrinks", &vec![7; 1 << 20]);
+        let mut mapping = MappedFile::new(file.try_clone().unwrap()).map_err(|_| "not mapped").unwrap();
+        assert_eq!(mapping.next_slice(4096).unwrap().len(), 4096);
+        file.set_len(1000).unwrap();
+        let err = mapping.next_slice(4096).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(err.to_string(), "file shrank while being read");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,694 @@
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use crate::{Format, GzipState, Options, Progress, ProgressCallback, ProgressInfo, Stats, WORKER_STACK_SIZE};
+
+const PENDING_WRITES: usize = 4; // Writes that may wait for the compressor before write blocks
+const PENDING_OUTPUT: usize = 16; // Buffers a worker may write before it waits for them to be taken
+const SEND_RETRY: Duration = Duration::from_millis(1); // How long a full input waits on output between tries
+
+type WorkerHandle = JoinHandle<io::Result<Stats>>; // Gives the Stats of a worker's run
+
+// Compresses what is written to it into a gzip stream on output, as gzip does its
+// standard input. The compressor runs on a thread of its own, started by the first
+// write; what it gives back is passed on to output from write, flush and finish. The
+// level is gzip's default, 6, unless level sets another
+pub struct GzipEncoder<W: Write> {
+    output: W,
+    opts: Options, // Fixed once the first write starts the worker
+    progress: Option<ProgressCallback>, // Handed to the worker with opts
+    worker: Option<Worker>,
+    unsynced: bool, // Written to since the last flush_sync
+}
+
+// A GzipState at work on a thread of its own
+struct Worker {
+    input: SyncSender<Vec<u8>>,
+    output: Receiver<Vec<u8>>,
+    handle: WorkerHandle,
+}
+
+impl<W: Write> GzipEncoder<W> {
+    pub fn new(output: W) -> Self {
+        GzipEncoder { output, opts: Options::default(), progress: None, worker: None, unsynced: false }
+    }
+
+    // Compression level, as -0 to -9 give it
+    pub fn level(mut self, level: u32) -> Self {
+        self.opts.level = level as i32;
+        self
+    }
+
+    // Compress chunk_size bytes at a time as independent members, on up to threads
+    // workers at once, as --threads and --parallel do. The members are written in input
+    // order, so the output is still one gzip stream
+    pub fn parallel(mut self, threads: usize, chunk_size: usize) -> Self {
+        self.opts.threads = threads.max(1);
+        self.opts.parallel_chunk = Some(chunk_size.max(1));
+        self
+    }
+
+    // Call callback with how far the input has got as it is compressed, at most four
+    // times a second, as --progress redraws its line
+    pub fn progress_callback<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&ProgressInfo) + Send + 'static,
+    {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    // Send on everything written so far, ending on a byte boundary with an empty stored
+    // block (00 00 ff ff) as --sync-flush does at a pause in its input, so that a decoder
+    // can give it all back before any more is written. The stream goes on after it. Not
+    // for parallel or BGZF output, whose members are only compressed once they are full
+    pub fn flush_sync(&mut self) -> io::Result<()> {
+        if self.opts.parallel_chunk.is_some() || self.opts.bgzf {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "flush_sync cannot be used with parallel output"));
+        }
+        // Nothing new to send; the worker would take a pause before any input for its end
+        if !self.unsynced {
+            return self.flush();
+        }
+        if let Some(worker) = &self.worker {
+            // An empty buffer is the pause, and the worker flushing at it the empty buffer back
+            let mut synced = worker.send(Vec::new(), &mut self.output)?;
+            while synced {
+                match worker.output.recv() {
+                    Ok(buf) if buf.is_empty() => break,
+                    Ok(buf) => self.output.write_all(&buf)?,
+                    Err(_) => synced = false,
+                }
+            }
+            if !synced {
+                // The worker has stopped short, and its error is the one to give
+                if let Some(worker) = self.worker.take() {
+                    join(worker.handle)?;
+                }
+                return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+            }
+        }
+        self.unsynced = false;
+        self.output.flush()
+    }
+
+    // Compress what is left and end the stream, then hand back output and what the
+    // stream came to
+    pub fn finish(mut self) -> io::Result<(W, Stats)> {
+        let worker = match self.worker.take() {
+            Some(worker) => worker,
+            None => Worker::spawn(self.opts.clone(), self.progress.take())?,
+        };
+        let Worker { input, output, handle } = worker;
+        drop(input);
+        // The worker's end of the channel goes once it has written everything
+        for buf in output {
+            self.output.write_all(&buf)?;
+        }
+        let stats = join(handle)?;
+        self.output.flush()?;
+        Ok((self.output, stats))
+    }
+
+    // Write on whatever the worker has compressed so far
+    fn pass_on(&mut self) -> io::Result<()> {
+        if let Some(worker) = &self.worker {
+            for buf in worker.output.try_iter() {
+                self.output.write_all(&buf)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for GzipEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // An empty buffer would read as the end of the input
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let worker = match self.worker.take() {
+            Some(worker) => worker,
+            None => Worker::spawn(self.opts.clone(), self.progress.take())?,
+        };
+        if !worker.send(buf.to_vec(), &mut self.output)? {
+            // The worker has stopped short, and its error is the one to give
+            join(worker.handle)?;
+            return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+        }
+        self.worker = Some(worker);
+        self.unsynced = true;
+        self.pass_on()?;
+        Ok(buf.len())
+    }
+
+    // Only what the compressor has already given back goes out; the rest waits for
+    // more input, flush_sync or finish
+    fn flush(&mut self) -> io::Result<()> {
+        self.pass_on()?;
+        self.output.flush()
+    }
+}
+
+impl Worker {
+    // A compressor with opts, reading what is sent to input
+    fn spawn(opts: Options, progress: Option<ProgressCallback>) -> io::Result<Worker> {
+        let (input, received) = mpsc::sync_channel(PENDING_WRITES);
+        let (output, handle) = spawn_state(opts, progress, ChannelReader::new(received, true))?;
+        Ok(Worker { input, output, handle })
+    }
+
+    // Send buf to the compressor, writing on what it gives back to output while its input
+    // is full: a compressor waiting for room in its own output would never take more
+    // input. False if the worker has stopped short
+    fn send<W: Write>(&self, mut buf: Vec<u8>, output: &mut W) -> io::Result<bool> {
+        loop {
+            match self.input.try_send(buf) {
+                Ok(()) => return Ok(true),
+                Err(TrySendError::Full(back)) => buf = back,
+                Err(TrySendError::Disconnected(_)) => return Ok(false),
+            }
+            match self.output.recv_timeout(SEND_RETRY) {
+                Ok(compressed) => output.write_all(&compressed)?,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(false),
+            }
+        }
+    }
+}
+
+// Decompresses what is read from input, as gzip -d does its standard input: every
+// member in turn, or a .Z file or a zip file's first entry. The decompressor runs on a
+// thread of its own, started by the first read, and it is that thread that reads input.
+// As with -d --sync-flush, what it has decoded is passed on before it waits for more
+// input, so a stream from GzipEncoder::flush_sync is read back as far as it goes
+pub struct GzipDecoder<R: Read + Send + 'static> {
+    input: Option<R>, // Until the first read hands it to the worker
+    opts: Options,
+    progress: Option<ProgressCallback>,
+    output: Option<ChannelReader>,
+    handle: Option<WorkerHandle>, // Joined at the end of the output, for its error
+    stats: Option<Stats>,
+}
+
+impl<R: Read + Send + 'static> GzipDecoder<R> {
+    pub fn new(input: R) -> Self {
+        let opts = Options { decompress: true, sync_flush: true, ..Options::default() };
+        GzipDecoder { input: Some(input), opts, progress: None, output: None, handle: None, stats: None }
+    }
+
+    // Call callback with how far the input has got as it is decompressed, as
+    // GzipEncoder::progress_callback does
+    pub fn progress_callback<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&ProgressInfo) + Send + 'static,
+    {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    // What the stream came to, once a read has reached its end
+    pub fn stats(&self) -> Option<Stats> {
+        self.stats
+    }
+}
+
+impl<R: Read + Send + 'static> Read for GzipDecoder<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if out.is_empty() {
+            return Ok(0);
+        }
+        if let Some(input) = self.input.take() {
+            let (output, handle) = spawn_state(self.opts.clone(), self.progress.take(), input)?;
+            self.output = Some(ChannelReader::new(output, false));
+            self.handle = Some(handle);
+        }
+        let n = match self.output.as_mut() {
+            Some(output) => output.read(out)?,
+            None => 0,
+        };
+        if n == 0 {
+            if let Some(handle) = self.handle.take() {
+                self.stats = Some(join(handle)?);
+            }
+        }
+        Ok(n)
+    }
+}
+
+// GzipEncoder with nothing around the deflate data: a bare RFC 1951 stream, as
+// --format=raw writes. Such streams cannot be joined end to end, so there is no parallel
+pub struct RawDeflateEncoder<W: Write>(GzipEncoder<W>);
+
+impl<W: Write> RawDeflateEncoder<W> {
+    pub fn new(output: W) -> Self {
+        let mut encoder = GzipEncoder::new(output);
+        encoder.opts.format = Format::Raw;
+        RawDeflateEncoder(encoder)
+    }
+
+    pub fn level(self, level: u32) -> Self {
+        RawDeflateEncoder(self.0.level(level))
+    }
+
+    pub fn progress_callback<F>(self, callback: F) -> Self
+    where
+        F: FnMut(&ProgressInfo) + Send + 'static,
+    {
+        RawDeflateEncoder(self.0.progress_callback(callback))
+    }
+
+    pub fn finish(self) -> io::Result<(W, Stats)> {
+        self.0.finish()
+    }
+}
+
+impl<W: Write> Write for RawDeflateEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// GzipEncoder writing BGZF, as --bgzf does: members of at most 65280 bytes of input,
+// each with its compressed size in a BC subfield, and the 28-byte EOF block at the end.
+// The blocks are compressed on up to threads workers at once
+pub struct BgzfEncoder<W: Write>(GzipEncoder<W>);
+
+impl<W: Write> BgzfEncoder<W> {
+    pub fn new(output: W) -> Self {
+        let mut encoder = GzipEncoder::new(output);
+        encoder.opts.bgzf = true;
+        BgzfEncoder(encoder)
+    }
+
+    pub fn level(self, level: u32) -> Self {
+        BgzfEncoder(self.0.level(level))
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.0.opts.threads = threads.max(1);
+        self
+    }
+
+    pub fn progress_callback<F>(self, callback: F) -> Self
+    where
+        F: FnMut(&ProgressInfo) + Send + 'static,
+    {
+        BgzfEncoder(self.0.progress_callback(callback))
+    }
+
+    pub fn finish(self) -> io::Result<(W, Stats)> {
+        self.0.finish()
+    }
+}
+
+impl<W: Write> Write for BgzfEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// GzipDecoder for a bare RFC 1951 stream, as --format=raw -d reads. There is no CRC or
+// length to check it against, and it ends with its last block
+pub struct RawDeflateDecoder<R: Read + Send + 'static>(GzipDecoder<R>);
+
+impl<R: Read + Send + 'static> RawDeflateDecoder<R> {
+    pub fn new(input: R) -> Self {
+        let mut decoder = GzipDecoder::new(input);
+        decoder.opts.format = Format::Raw;
+        RawDeflateDecoder(decoder)
+    }
+
+    pub fn progress_callback<F>(self, callback: F) -> Self
+    where
+        F: FnMut(&ProgressInfo) + Send + 'static,
+    {
+        RawDeflateDecoder(self.0.progress_callback(callback))
+    }
+
+    pub fn stats(&self) -> Option<Stats> {
+        self.0.stats()
+    }
+}
+
+impl<R: Read + Send + 'static> Read for RawDeflateDecoder<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        self.0.read(out)
+    }
+}
+
+// Run a GzipState with opts and any progress callback over input, on a thread with
+// room for the compressor. What it writes comes back through the receiver a buffer at a
+// time, at most PENDING_OUTPUT of them waiting, and its Stats from the handle once it
+// has done
+fn spawn_state<R>(
+    opts: Options,
+    progress: Option<ProgressCallback>,
+    input: R,
+) -> io::Result<(Receiver<Vec<u8>>, WorkerHandle)>
+where
+    R: Read + Send + 'static,
+{
+    let (sender, output) = mpsc::sync_channel(PENDING_OUTPUT);
+    let handle = thread::Builder::new().stack_size(WORKER_STACK_SIZE).spawn(move || {
+        let mut state = GzipState::with_program_name("gzip");
+        state.opts = opts;
+        state.progress = progress.map(|callback| Progress::new(callback, false));
+        state.run_stream(input, ChannelWriter(sender))
+    })?;
+    Ok((output, handle))
+}
+
+// What the worker came to, or a panic in it as an error
+fn join(handle: WorkerHandle) -> io::Result<Stats> {
+    handle.join().unwrap_or_else(|_| Err(io::Error::other("gzip worker panicked")))
+}
+
+// The buffers sent through a channel, read one after another until the sender is
+// dropped: an encoder's input to its worker, or a worker's output to a decoder. An empty
+// buffer is a flush_sync in an encoder's input, which a read gives as WouldBlock, the
+// pause the compressor flushes at; in a worker's output it is a flush, and skipped
+struct ChannelReader {
+    input: Receiver<Vec<u8>>,
+    buf: Vec<u8>,
+    pos: usize,
+    pauses: bool,
+}
+
+impl ChannelReader {
+    fn new(input: Receiver<Vec<u8>>, pauses: bool) -> Self {
+        ChannelReader { input, buf: Vec::new(), pos: 0, pauses }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            match self.input.recv() {
+                Ok(buf) if buf.is_empty() && self.pauses => return Err(io::Error::from(io::ErrorKind::WouldBlock)),
+                Ok(buf) => (self.buf, self.pos) = (buf, 0),
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// The worker's standard output, sent back a buffer at a time, and each flush as an
+// empty one, which tells flush_sync it has everything. Once the other end has gone it is
+// a broken pipe, as a reader that went away would be
+struct ChannelWriter(SyncSender<Vec<u8>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.0.send(buf.to_vec()).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.send(Vec::new()).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::gzip;
+    use crate::zip::{BGZF_BLOCK_SIZE, BGZF_EOF};
+    use crate::{Crc32, DEFLATED, OK, PROGRESS_INTERVAL};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    fn text() -> Vec<u8> {
+        (0..20_000).flat_map(|i: u32| format!("line {} of the stream\n", i % 700).into_bytes()).collect()
+    }
+
+    #[test]
+    fn encoder_output_decompresses() {
+        let text = text();
+        let mut encoder = GzipEncoder::new(Vec::new()).level(1);
+        for piece in text.chunks(1000) {
+            encoder.write_all(piece).unwrap();
+        }
+        let compressed = encoder.finish().unwrap().0;
+        assert!(compressed.len() < text.len() / 4);
+        assert_eq!(gzip(&["-d"], &compressed), (OK, text));
+    }
+
+    // Without level the encoder compresses as plain gzip does, at -6
+    #[test]
+    fn default_level_matches_gzip() {
+        let text = text();
+        let mut encoder = GzipEncoder::new(Vec::new());
+        encoder.write_all(&text).unwrap();
+        let compressed = encoder.finish().unwrap().0;
+        assert_eq!(gzip(&["-d"], &compressed), (OK, text.clone()));
+        assert_eq!(compressed, gzip(&[], &text).1);
+    }
+
+    // Stored output is as big as the input, so the worker fills its output channel
+    // while write is still waiting for room in its input
+    #[test]
+    fn stored_output_outruns_the_channels() {
+        let mut seed = 1u32;
+        let data: Vec<u8> = (0..4 << 20)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (seed >> 24) as u8
+            })
+            .collect();
+        let mut encoder = GzipEncoder::new(Vec::new()).level(0);
+        for piece in data.chunks(1000) {
+            encoder.write_all(piece).unwrap();
+        }
+        let compressed = encoder.finish().unwrap().0;
+        assert!(compressed.len() > data.len());
+        assert_eq!(gzip(&["-d"], &compressed), (OK, data));
+    }
+
+    #[test]
+    fn empty_input_is_one_empty_member() {
+        let compressed = GzipEncoder::new(Vec::new()).level(1).finish().unwrap().0;
+        assert_eq!(gzip(&["-d"], &compressed), (OK, Vec::new()));
+    }
+
+    // Chunks compressed on several workers come out as members in input order, the
+    // same as --parallel writes
+    #[test]
+    fn parallel_members_are_in_order() {
+        let text = text();
+        let mut encoder = GzipEncoder::new(Vec::new()).level(1).parallel(3, 64 * 1024);
+        encoder.write_all(&text).unwrap();
+        let compressed = encoder.finish().unwrap().0;
+        assert_eq!(gzip(&["-d"], &compressed), (OK, text.clone()));
+        assert_eq!(compressed, gzip(&["-1", "--threads=3", "--parallel=65536"], &text).1);
+    }
+
+    fn decode<R: Read>(mut decoder: R) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        decoder.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn decoder_reads_every_member() {
+        let text = text();
+        let (_, mut compressed) = gzip(&["-1"], &text[..1000]);
+        compressed.extend(gzip(&["-1"], &text[1000..]).1);
+        assert_eq!(decode(GzipDecoder::new(io::Cursor::new(compressed))).unwrap(), text);
+    }
+
+    #[test]
+    fn decoder_fails_on_damaged_data() {
+        let (_, mut compressed) = gzip(&["-1"], &text());
+        let middle = compressed.len() / 2;
+        compressed[middle] ^= 0x55;
+        assert!(decode(GzipDecoder::new(io::Cursor::new(compressed))).is_err());
+        assert!(decode(GzipDecoder::new(io::Cursor::new(b"not gzip".to_vec()))).is_err());
+    }
+
+    #[test]
+    fn raw_deflate_round_trip() {
+        let text = text();
+        let mut encoder = RawDeflateEncoder::new(Vec::new()).level(1);
+        encoder.write_all(&text).unwrap();
+        let compressed = encoder.finish().unwrap().0;
+        assert_eq!(compressed, gzip(&["-1", "--format=raw"], &text).1);
+        assert_eq!(decode(RawDeflateDecoder::new(io::Cursor::new(compressed))).unwrap(), text);
+    }
+
+    // A stream from zlib (Python's, at level 9 with wbits -15), not from this encoder
+    #[test]
+    fn raw_deflate_decoder_reads_zlib_output() {
+        let compressed = [
+            0x4b, 0x54, 0x28, 0x4a, 0x2c, 0x57, 0x48, 0x49, 0x4d, 0xcb, 0x49, 0x2c, 0x49, 0x55, 0x28, 0x2e,
+            0x29, 0x4a, 0x4d, 0xcc, 0xd5, 0x51, 0x48, 0xc4, 0x22, 0xca, 0x95, 0x48, 0x13, 0xa5, 0x00,
+        ];
+        let text = b"a raw deflate stream, a raw deflate stream\n".repeat(3);
+        assert_eq!(decode(RawDeflateDecoder::new(io::Cursor::new(compressed.to_vec()))).unwrap(), text);
+    }
+
+    // The callback hears of the input once a read has waited past the interval, and
+    // nothing is drawn on stderr for it
+    #[test]
+    fn progress_callback_sees_the_input() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let record = Arc::clone(&seen);
+        let text = text();
+        let mut encoder = GzipEncoder::new(Vec::new())
+            .level(1)
+            .progress_callback(move |info| record.lock().unwrap().push((info.bytes_in, info.decompress)));
+        let (first, rest) = text.split_at(100_000);
+        encoder.write_all(first).unwrap();
+        thread::sleep(PROGRESS_INTERVAL + Duration::from_millis(100));
+        encoder.write_all(rest).unwrap();
+        let compressed = encoder.finish().unwrap().0;
+        let seen = seen.lock().unwrap().clone();
+        assert!(!seen.is_empty());
+        assert!(seen.iter().all(|&(bytes_in, decompress)| bytes_in > 0 && bytes_in <= text.len() as i64 && !decompress));
+
+        let called = Arc::new(Mutex::new(false));
+        let record = Arc::clone(&called);
+        let slow = SlowReader(io::Cursor::new(compressed));
+        let decoder = GzipDecoder::new(slow).progress_callback(move |info| *record.lock().unwrap() = info.decompress);
+        assert_eq!(decode(decoder).unwrap(), text);
+        assert!(*called.lock().unwrap());
+    }
+
+    // A reader that takes longer than PROGRESS_INTERVAL over its first read
+    struct SlowReader(io::Cursor<Vec<u8>>);
+
+    impl Read for SlowReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.position() == 0 {
+                thread::sleep(PROGRESS_INTERVAL + Duration::from_millis(100));
+            }
+            self.0.read(buf)
+        }
+    }
+
+    // The figures are those of the streams themselves, as -v and -l print them
+    #[test]
+    fn stats_match_the_streams() {
+        let text = text();
+        let mut encoder = GzipEncoder::new(Vec::new()).level(1);
+        encoder.write_all(&text).unwrap();
+        let (compressed, stats) = encoder.finish().unwrap();
+        let mut crc = Crc32::new();
+        crc.update(&text);
+        assert_eq!(stats.bytes_in, text.len() as i64);
+        assert_eq!(stats.bytes_out, compressed.len() as i64);
+        assert_eq!(stats.header_bytes, 10 + 8);
+        assert_eq!(stats.crc32, crc.finalize());
+        assert_eq!(stats.method, DEFLATED);
+        assert!(stats.blocks > 0);
+
+        let mut decoder = GzipDecoder::new(io::Cursor::new(compressed.clone()));
+        assert_eq!(decoder.stats(), None);
+        let mut out = Vec::new();
+        decoder.read_to_end(&mut out).unwrap();
+        let decoded = decoder.stats().unwrap();
+        assert_eq!(decoded.bytes_in, compressed.len() as i64);
+        assert_eq!(decoded.bytes_out, text.len() as i64);
+        assert_eq!((decoded.header_bytes, decoded.crc32, decoded.method), (10 + 8, stats.crc32, DEFLATED));
+        assert_eq!(decoded.blocks, stats.blocks);
+    }
+
+    // Each block is a member whose BC subfield gives its size less one, and the last is
+    // the EOF block; the output is what --bgzf writes
+    #[test]
+    fn bgzf_blocks() {
+        let text: Vec<u8> = text().repeat(3);
+        let mut encoder = BgzfEncoder::new(Vec::new()).level(1).threads(2);
+        encoder.write_all(&text).unwrap();
+        let compressed = encoder.finish().unwrap().0;
+        assert_eq!(compressed, gzip(&["-1", "--bgzf", "--threads=2"], &text).1);
+        assert!(compressed.ends_with(&BGZF_EOF));
+
+        let mut blocks = Vec::new();
+        let mut at = 0;
+        while at < compressed.len() {
+            let block = &compressed[at..];
+            assert_eq!(block[..4], [0x1f, 0x8b, 0x08, 0x04]);
+            assert_eq!(block[12..16], *b"BC\x02\x00");
+            let bsize = u16::from_le_bytes([block[16], block[17]]) as usize + 1;
+            let isize = u32::from_le_bytes(block[bsize - 4..bsize].try_into().unwrap()) as usize;
+            assert!(isize <= BGZF_BLOCK_SIZE);
+            blocks.push(isize);
+            at += bsize;
+        }
+        assert_eq!(at, compressed.len());
+        assert_eq!(blocks.iter().sum::<usize>(), text.len());
+        assert_eq!(blocks.last(), Some(&0));
+        assert_eq!(decode(GzipDecoder::new(io::Cursor::new(compressed))).unwrap(), text);
+
+        let empty = BgzfEncoder::new(Vec::new()).level(1).finish().unwrap().0;
+        assert_eq!(empty, BGZF_EOF);
+    }
+
+    // What was written before flush_sync can be read back before any more is written,
+    // and the stream goes on after it
+    #[test]
+    fn flush_sync_lets_a_decoder_read_what_was_written() {
+        for level in [0, 1, 6] {
+            let (sender, received) = mpsc::sync_channel(PENDING_OUTPUT);
+            let mut encoder = GzipEncoder::new(ChannelWriter(sender)).level(level);
+            let mut decoder = GzipDecoder::new(ChannelReader::new(received, false));
+            encoder.write_all(b"hello").unwrap();
+            encoder.flush_sync().unwrap();
+            // Nothing was written since, so this one has nothing to add
+            encoder.flush_sync().unwrap();
+
+            let (read_sender, read) = mpsc::channel();
+            // The pipe is bounded, so the reader goes on reading while the rest is written
+            let reader = thread::spawn(move || {
+                let mut hello = [0; 5];
+                decoder.read_exact(&mut hello).unwrap();
+                read_sender.send(hello).unwrap();
+                let mut rest = Vec::new();
+                decoder.read_to_end(&mut rest).unwrap();
+                rest
+            });
+            assert_eq!(&read.recv_timeout(Duration::from_secs(10)).unwrap(), b"hello", "level {}", level);
+
+            let text = text();
+            encoder.write_all(&text).unwrap();
+            encoder.flush_sync().unwrap();
+            encoder.write_all(b"bye").unwrap();
+            encoder.finish().unwrap();
+            assert_eq!(reader.join().unwrap(), [&text[..], b"bye"].concat(), "level {}", level);
+        }
+    }
+
+    #[test]
+    fn flush_sync_refuses_parallel_output() {
+        let mut encoder = GzipEncoder::new(Vec::new()).level(1).parallel(2, 64 * 1024);
+        encoder.write_all(b"data").unwrap();
+        assert_eq!(encoder.flush_sync().unwrap_err().kind(), io::ErrorKind::Unsupported);
+        let mut encoder = BgzfEncoder::new(Vec::new()).level(1);
+        encoder.write_all(b"data").unwrap();
+        assert_eq!(encoder.0.flush_sync().unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn bad_level_fails_finish() {
+        let mut encoder = GzipEncoder::new(Vec::new()).level(10);
+        let _ = encoder.write_all(b"data");
+        assert!(encoder.finish().is_err());
+    }
+}
@@ -1,8 +1,7 @@
 use crate::deflate::{Deflate, MAX_DIST, MAX_MATCH, MIN_MATCH};
-use crate::{GzipState, STORED};
-use std::cell::RefCell;
-use std::ops::{Deref, DerefMut};
-use std::rc::Rc;
+use crate::{GzipState, STORED, TRACE_BLOCKS, TRACE_SYMBOLS};
+use std::io;
+use std::sync::OnceLock;
 
 const MAX_BITS: usize = 15;
 const MAX_BL_BITS: usize = 7;
@@ -63,22 +62,132 @@ pub struct CtData {
     dad: u16
 }
 
-pub struct Trees<'a> {
-    pub file_type: Option<&'a mut u16>,
+// Code maps, base tables and static trees: the same for every stream, so they
+// are built on first use and shared
+struct StaticTables {
+    base_length: [i32; LENGTH_CODES],
+    base_dist: [i32; D_CODES],
+    length_code: [u8; 256],
+    dist_code: [u8; 512],
+    static_ltree: [CtData; L_CODES + 2],
+    static_dtree: [CtData; D_CODES],
+}
+
+static STATIC_TABLES: OnceLock<StaticTables> = OnceLock::new();
+
+impl StaticTables {
+    fn get() -> &'static StaticTables {
+        STATIC_TABLES.get_or_init(Self::build)
+    }
+
+    fn build() -> StaticTables {
+        let mut n: i32;
+        let mut length: i32;
+        let mut code: i32;
+        let mut dist: i32;
+        let mut t = StaticTables {
+            base_length: [0; LENGTH_CODES],
+            base_dist: [0; D_CODES],
+            length_code: [0; 256],
+            dist_code: [0; 512],
+            static_ltree: [CtData::default(); L_CODES + 2],
+            static_dtree: [CtData::default(); D_CODES],
+        };
+
+        // Initialize the mapping length (0..255) -> length code (0..28)
+        length = 0;
+        code = 0;
+        while code < (LENGTH_CODES - 1) as i32 {
+            t.base_length[code as usize] = length;
+            n = 0;
+            while n < (1 << EXTRA_LBITS[code as usize]) {
+                t.length_code[length as usize] = code as u8;
+                length += 1;
+                n += 1;
+            }
+            code += 1;
+        }
+        assert!(length == 256, "ct_init: length != 256");
+
+        // Overwrite length_code[255] to use the best encoding
+        t.length_code[(length - 1) as usize] = code as u8;
+
+        // Initialize the mapping dist (0..32K) -> dist code (0..29)
+        dist = 0;
+        code = 0;
+        while code < 16 {
+            t.base_dist[code as usize] = dist;
+            n = 0;
+            while n < (1 << EXTRA_DBITS[code as usize]) {
+                t.dist_code[dist as usize] = code as u8;
+                dist += 1;
+                n += 1;
+            }
+            code += 1;
+        }
+        assert!(dist == 256, "ct_init: dist != 256");
+
+        dist >>= 7; // From now on, all distances are divided by 128
+        while code < D_CODES as i32 {
+            t.base_dist[code as usize] = dist << 7;
+            n = 0;
+            while n < (1 << (EXTRA_DBITS[code as usize] - 7)) {
+                t.dist_code[(256 + dist) as usize] = code as u8;
+                dist += 1;
+                n += 1;
+            }
+            code += 1;
+        }
+        assert!(dist == 256, "ct_init: 256+dist != 512");
+
+        // Construct the codes of the static literal tree
+        let mut bl_count = [0i32; MAX_BITS + 1];
+
+        n = 0;
+        while n <= 143 {
+            t.static_ltree[n as usize].len = 8;
+            bl_count[8] += 1;
+            n += 1;
+        }
+        while n <= 255 {
+            t.static_ltree[n as usize].len = 9;
+            bl_count[9] += 1;
+            n += 1;
+        }
+        while n <= 279 {
+            t.static_ltree[n as usize].len = 7;
+            bl_count[7] += 1;
+            n += 1;
+        }
+        while n <= 287 {
+            t.static_ltree[n as usize].len = 8;
+            bl_count[8] += 1;
+            n += 1;
+        }
+
+        // Generate the codes
+        Trees::gen_codes(&bl_count, &mut t.static_ltree, (L_CODES + 1) as i32);
+
+        // The static distance tree is trivial
+        for n in 0..D_CODES as i32 {
+            t.static_dtree[n as usize].len = 5;
+            t.static_dtree[n as usize].code = Trees::bi_reverse(n as u16, 5);
+        }
+
+        t
+    }
+}
+
+pub struct Trees {
+    pub file_type: Option<u16>, // ASCII or BINARY, guessed from the literals of the first block
     pub file_method: i32,
     pub compressed_len: u64,
     pub input_len: u64,
-    pub base_length: [i32; LENGTH_CODES],
-    pub base_dist: [i32; D_CODES],
-    pub length_code: [u8; 256],
-    pub dist_code: [u8; 512],
+    tables: &'static StaticTables,
     pub bl_count: [i32; MAX_BITS + 1],
-    pub static_ltree: Rc<RefCell<Vec<CtData>>>,
-    pub static_dtree: Rc<RefCell<Vec<CtData>>>,
-    pub bltree: Rc<RefCell<Vec<CtData>>>,
-    pub dyn_ltree: Rc<RefCell<Vec<CtData>>>,
-    pub dyn_dtree: Rc<RefCell<Vec<CtData>>>,
-    pub bl_tree: Box<[CtData; 2 * BL_CODES + 1]>,
+    pub dyn_ltree: [CtData; HEAP_SIZE],
+    pub dyn_dtree: [CtData; 2 * D_CODES + 1],
+    pub bl_tree: [CtData; 2 * BL_CODES + 1],
     pub opt_len: u64,
     pub static_len: u64,
     pub last_lit: i32,
@@ -86,52 +195,51 @@ pub struct Trees<'a> {
     pub last_flags: i32,
     pub flags: i32,
     pub flag_bit: i32,
-    pub l_buf: Box<[usize; LIT_BUFSIZE]>,
-    pub d_buf: Box<[usize; DIST_BUFSIZE]>,
-    pub flag_buf: Box<[usize; LIT_BUFSIZE/8]>,
-    pub l_desc: TreeDesc<'a>,
-    pub d_desc: TreeDesc<'a>,
-    pub bl_desc: TreeDesc<'a>,
+    pub l_buf: Box<[u8; LIT_BUFSIZE]>,     // Literals or match lengths - MIN_MATCH
+    pub d_buf: Box<[u16; DIST_BUFSIZE]>,   // Match distances - 1
+    pub flag_buf: Box<[u8; LIT_BUFSIZE/8]>, // One bit per l_buf entry: set for a match
+    pub l_desc: TreeDesc,
+    pub d_desc: TreeDesc,
+    pub bl_desc: TreeDesc,
     pub heap: [i32; 2*L_CODES+1],
     pub depth: [i32; 2*L_CODES+1],
     pub heap_len: usize,
     pub heap_max: usize
 }
 
-#[derive(Clone)]
-struct TreeDesc<'a> {
-    dyn_tree: Rc<RefCell<Vec<CtData>>>,      // The dynamic tree
-    static_tree: Option<Rc<RefCell<Vec<CtData>>>>, // The corresponding static tree or None
-    extra_bits: Option<&'a [i32]>,    // Extra bits for each code or None
+// Which of the trees owned by `Trees` a descriptor or block refers to
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum TreeId {
+    DynLit,
+    DynDist,
+    BitLen,
+    StaticLit,
+    StaticDist,
+}
+
+#[derive(Copy, Clone)]
+struct TreeDesc {
+    dyn_tree: TreeId,                // The dynamic tree
+    static_tree: Option<TreeId>,     // The corresponding static tree or None
+    extra_bits: Option<&'static [i32]>, // Extra bits for each code or None
     extra_base: usize,
     elems: usize,                    // Number of elements in the tree
     max_length: usize,               // Maximum bit length for the codes
     max_code: i32,                   // Largest code with non-zero frequency
 }
 
-impl<'a> Trees<'a> {
+impl Trees {
     pub fn new() -> Self {
-        let static_ltree = Rc::new(RefCell::new(vec![CtData::default(); L_CODES + 2]));
-        let static_dtree = Rc::new(RefCell::new(vec![CtData::default(); D_CODES]));
-        let bltree = Rc::new(RefCell::new(vec![CtData::default(); 2 * BL_CODES + 1]));
-        let dyn_ltree = Rc::new(RefCell::new(vec![CtData::default(); HEAP_SIZE]));
-        let dyn_dtree = Rc::new(RefCell::new(vec![CtData::default(); 2 * D_CODES + 1]));
         Self {
             file_type: None,
             file_method: 0,
             compressed_len: 0,
             input_len: 0,
-            base_length: [0; LENGTH_CODES],
-            base_dist: [0; D_CODES],
-            length_code: [0; 256],
-            dist_code: [0; 512],
+            tables: StaticTables::get(),
             bl_count: [0; MAX_BITS + 1],
-            static_ltree: static_ltree.clone(),
-            static_dtree: static_dtree.clone(),
-            bltree: bltree.clone(),
-            dyn_ltree: dyn_ltree.clone(),
-            dyn_dtree: dyn_dtree.clone(),
-            bl_tree: Box::new([CtData::default(); 2 * BL_CODES + 1]),
+            dyn_ltree: [CtData::default(); HEAP_SIZE],
+            dyn_dtree: [CtData::default(); 2 * D_CODES + 1],
+            bl_tree: [CtData::default(); 2 * BL_CODES + 1],
             opt_len: 0,
             static_len: 0,
             last_lit: 0,
@@ -143,8 +251,8 @@ impl<'a> Trees<'a> {
             d_buf: Box::new([0; DIST_BUFSIZE]),
             flag_buf: Box::new([0; LIT_BUFSIZE/8]),
             l_desc: TreeDesc {
-                dyn_tree: dyn_ltree,
-                static_tree: Some(static_ltree),
+                dyn_tree: TreeId::DynLit,
+                static_tree: Some(TreeId::StaticLit),
                 extra_bits: Some(&EXTRA_LBITS),
                 extra_base: LITERALS+1,
                 elems: L_CODES,
@@ -152,8 +260,8 @@ impl<'a> Trees<'a> {
                 max_code: 0,
             },
             d_desc: TreeDesc {
-                dyn_tree: dyn_dtree,
-                static_tree: Some(static_dtree),
+                dyn_tree: TreeId::DynDist,
+                static_tree: Some(TreeId::StaticDist),
                 extra_bits: Some(&EXTRA_DBITS),
                 extra_base: 0,
                 elems: D_CODES,
@@ -161,7 +269,7 @@ impl<'a> Trees<'a> {
                 max_code: 0,
             },
             bl_desc: TreeDesc {
-                dyn_tree: bltree,
+                dyn_tree: TreeId::BitLen,
                 static_tree: None,
                 extra_bits: Some(&EXTRA_BLBITS),
                 extra_base: 0,
@@ -176,102 +284,40 @@ impl<'a> Trees<'a> {
         }
     }
 
-    pub(crate) fn ct_init(&mut self, attr: &'a mut u16, methodp: i32) {
-        let mut n: i32;
-        let mut length: i32;
-        let mut code: i32;
-        let mut dist: i32;
-
-        self.file_type = Some(attr);
-        self.file_method = methodp;
-        self.compressed_len = 0;
-        self.input_len = 0;
-
-        if self.static_dtree.borrow()[0].len != 0 {
-            return; // ct_init already called
-        }
-
-        // Initialize the mapping length (0..255) -> length code (0..28)
-        length = 0;
-        code = 0;
-        while code < (LENGTH_CODES - 1) as i32 {
-            self.base_length[code as usize] = length;
-            n = 0;
-            while n < (1 << EXTRA_LBITS[code as usize]) {
-                self.length_code[length as usize] = code as u8;
-                length += 1;
-                n += 1;
-            }
-            code += 1;
-        }
-        assert!(length == 256, "ct_init: length != 256");
-
-        // Overwrite length_code[255] to use the best encoding
-        self.length_code[(length - 1) as usize] = code as u8;
-
-        // Initialize the mapping dist (0..32K) -> dist code (0..29)
-        dist = 0;
-        code = 0;
-        while code < 16 {
-            self.base_dist[code as usize] = dist;
-            n = 0;
-            while n < (1 << EXTRA_DBITS[code as usize]) {
-                self.dist_code[dist as usize] = code as u8;
-                dist += 1;
-                n += 1;
-            }
-            code += 1;
-        }
-        assert!(dist == 256, "ct_init: dist != 256");
-
-        dist >>= 7; // From now on, all distances are divided by 128
-        while code < D_CODES as i32 {
-            self.base_dist[code as usize] = dist << 7;
-            n = 0;
-            while n < (1 << (EXTRA_DBITS[code as usize] - 7)) {
-                self.dist_code[(256 + dist) as usize] = code as u8;
-                dist += 1;
-                n += 1;
-            }
-            code += 1;
+    fn tree(&self, id: TreeId) -> &[CtData] {
+        match id {
+            TreeId::DynLit => &self.dyn_ltree,
+            TreeId::DynDist => &self.dyn_dtree,
+            TreeId::BitLen => &self.bl_tree,
+            TreeId::StaticLit => &self.tables.static_ltree,
+            TreeId::StaticDist => &self.tables.static_dtree,
         }
-        assert!(dist == 256, "ct_init: 256+dist != 512");
+    }
 
-        // Construct the codes of the static literal tree
-        for bits in 0..=MAX_BITS as i32 {
-            self.bl_count[bits as usize] = 0;
+    fn tree_mut(&mut self, id: TreeId) -> &mut [CtData] {
+        match id {
+            TreeId::DynLit => &mut self.dyn_ltree,
+            TreeId::DynDist => &mut self.dyn_dtree,
+            TreeId::BitLen => &mut self.bl_tree,
+            TreeId::StaticLit | TreeId::StaticDist => unreachable!("static trees are shared and never rebuilt"),
         }
+    }
 
-        n = 0;
-        while n <= 143 {
-            self.static_ltree.borrow_mut()[n as usize].len = 8;
-            self.bl_count[8] += 1;
-            n += 1;
-        }
-        while n <= 255 {
-            self.static_ltree.borrow_mut()[n as usize].len = 9;
-            self.bl_count[9] += 1;
-            n += 1;
-        }
-        while n <= 279 {
-            self.static_ltree.borrow_mut()[n as usize].len = 7;
-            self.bl_count[7] += 1;
-            n += 1;
-        }
-        while n <= 287 {
-            self.static_ltree.borrow_mut()[n as usize].len = 8;
-            self.bl_count[8] += 1;
-            n += 1;
+    // The descriptor whose dynamic tree is `id`
+    fn desc_mut(&mut self, id: TreeId) -> &mut TreeDesc {
+        match id {
+            TreeId::DynLit => &mut self.l_desc,
+            TreeId::DynDist => &mut self.d_desc,
+            TreeId::BitLen => &mut self.bl_desc,
+            TreeId::StaticLit | TreeId::StaticDist => unreachable!("static trees have no descriptor"),
         }
+    }
 
-        // Generate the codes
-        Self::gen_codes(&self.bl_count, self.static_ltree.borrow_mut().deref_mut(), (L_CODES + 1) as i32);
-
-        // The static distance tree is trivial
-        for n in 0..D_CODES as i32 {
-            self.static_dtree.borrow_mut()[n as usize].len = 5;
-            self.static_dtree.borrow_mut()[n as usize].code = Self::bi_reverse(n as u16, 5);
-        }
+    pub(crate) fn ct_init(&mut self, methodp: i32) {
+        self.file_type = None;
+        self.file_method = methodp;
+        self.compressed_len = 0;
+        self.input_len = 0;
 
         // Initialize the first block of the first file
         self.init_block();
@@ -280,12 +326,12 @@ impl<'a> Trees<'a> {
     fn init_block(&mut self) {
         // Initialize the dynamic literal tree frequencies
         for n in 0..L_CODES {
-            self.dyn_ltree.borrow_mut()[n].freq = 0;
+            self.dyn_ltree[n].freq = 0;
         }
 
         // Initialize the dynamic distance tree frequencies
         for n in 0..D_CODES {
-            self.dyn_dtree.borrow_mut()[n].freq = 0;
+            self.dyn_dtree[n].freq = 0;
         }
 
         // Initialize the bit length tree frequencies
@@ -294,7 +340,16 @@ impl<'a> Trees<'a> {
         }
 
         // Set the frequency of the END_BLOCK symbol to 1
-        self.dyn_ltree.borrow_mut()[END_BLOCK].freq = 1;
+        self.dyn_ltree[END_BLOCK].freq = 1;
+
+        // Start the tally buffers of the next block empty
+        self.opt_len = 0;
+        self.static_len = 0;
+        self.last_lit = 0;
+        self.last_dist = 0;
+        self.last_flags = 0;
+        self.flags = 0;
+        self.flag_bit = 1;
     }
 
     fn gen_codes(bl_count: &[i32; MAX_BITS + 1], tree: &mut [CtData], max_code: i32) {
@@ -308,7 +363,7 @@ impl<'a> Trees<'a> {
         }
 
         // Assign codes to tree nodes
-        for n in 0..max_code as usize {
+        for n in 0..=max_code as usize {
             let len = tree[n].len as usize;
             if len != 0 {
                 tree[n].code = Self::bi_reverse(next_code[len], len);
@@ -328,13 +383,18 @@ impl<'a> Trees<'a> {
     }
 
     pub fn ct_tally(&mut self, deflate: &mut Deflate, state: &mut GzipState, dist: usize, lc: usize) -> bool {
+        // The caller flushes as soon as the previous call returned true
+        debug_assert!((self.last_lit as usize) < LIT_BUFSIZE - 1, "ct_tally: l_buf overflow");
+        debug_assert!((self.last_dist as usize) < DIST_BUFSIZE, "ct_tally: d_buf overflow");
+        debug_assert!((self.last_flags as usize) < LIT_BUFSIZE / 8, "ct_tally: flag_buf overflow");
+
         // Add the character or match length to the literal buffer
-        self.l_buf[self.last_lit as usize] = lc as u8 as usize;
+        self.l_buf[self.last_lit as usize] = lc as u8;
         self.last_lit += 1;
 
         if dist == 0 {
             // lc is the unmatched character (literal)
-            self.dyn_ltree.borrow_mut()[lc].freq += 1;
+            self.dyn_ltree[lc].freq += 1;
         } else {
             // lc is the match length - MIN_MATCH
             let dist = dist - 1; // Adjust distance
@@ -345,10 +405,10 @@ impl<'a> Trees<'a> {
                 "ct_tally: bad match"
             );
 
-            self.dyn_ltree.borrow_mut()[self.length_code[lc] as usize + LITERALS + 1].freq += 1;
-            self.dyn_dtree.borrow_mut()[self.d_code(dist)].freq += 1;
+            self.dyn_ltree[self.tables.length_code[lc] as usize + LITERALS + 1].freq += 1;
+            self.dyn_dtree[self.d_code(dist)].freq += 1;
 
-            self.d_buf[self.last_dist as usize] = dist as u16 as usize;
+            self.d_buf[self.last_dist as usize] = dist as u16;
             self.last_dist += 1;
             self.flags |= self.flag_bit;
         }
@@ -357,26 +417,27 @@ impl<'a> Trees<'a> {
 
         // Output the flags if they fill a byte
         if (self.last_lit & 7) == 0 {
-            self.flag_buf[self.last_flags as usize] = self.flags as usize;
+            self.flag_buf[self.last_flags as usize] = self.flags as u8;
             self.last_flags += 1;
             self.flags = 0;
             self.flag_bit = 1;
         }
 
         // Try to guess if it is profitable to stop the current block here
-        if state.level > 2 && (self.last_lit & 0xfff) == 0 {
+        if state.opts.level > 2 && (self.last_lit & 0xfff) == 0 {
             // Compute an upper bound for the compressed length
             let mut out_length = self.last_lit as u64 * 8;
-            let in_length = deflate.strstart - deflate.block_start as usize;
+            // block_start goes negative once the window slides past it
+            let in_length = (deflate.strstart as i64 - deflate.block_start) as usize;
 
             for dcode in 0..D_CODES {
-                out_length += self.dyn_dtree.borrow()[dcode].freq as u64
+                out_length += self.dyn_dtree[dcode].freq as u64
                     * (5 + EXTRA_DBITS[dcode] as u64);
             }
 
             out_length >>= 3; // Divide by 8
 
-            if state.verbose > 0 {
+            if state.opts.verbose >= TRACE_BLOCKS {
                 eprintln!(
                     "\nlast_lit {}, last_dist {}, in {}, out ~{}({}%)",
                     self.last_lit,
@@ -392,15 +453,16 @@ impl<'a> Trees<'a> {
             }
         }
 
-        // Return true if the buffer is full
+        // Return true if the buffer is full. As in trees.c l_buf keeps one slot
+        // spare, so flag_buf still has room for the partial byte flush_block saves.
         self.last_lit == (LIT_BUFSIZE - 1) as i32 || self.last_dist == DIST_BUFSIZE as i32
     }
 
     fn d_code(&self, dist: usize) -> usize {
         if dist < 256 {
-            self.dist_code[dist] as usize
+            self.tables.dist_code[dist] as usize
         } else {
-            self.dist_code[256 + (dist >> 7)] as usize
+            self.tables.dist_code[256 + (dist >> 7)] as usize
         }
     }
 
@@ -410,13 +472,14 @@ impl<'a> Trees<'a> {
         buf: Option<&[u8]>,
         stored_len: u64,
         eof: bool,
-    ) -> i64 {
+    ) -> io::Result<i64> {
+        state.blocks += 1;
         let mut opt_lenb: u64;
         let static_lenb: u64;
         let max_blindex: i32;
 
         // Save the flags for the last 8 items
-        self.flag_buf[self.last_flags as usize] = self.flags as usize;
+        self.flag_buf[self.last_flags as usize] = self.flags as u8;
 
         // Check if the file is ASCII or binary
         if self.file_type == None {
@@ -424,16 +487,16 @@ impl<'a> Trees<'a> {
         }
 
         // Construct the literal and distance trees
-        self.build_tree(state, &mut self.l_desc.clone());
-        if state.verbose > 1 {
+        self.build_tree(state, TreeId::DynLit);
+        if state.opts.verbose >= TRACE_BLOCKS {
             eprintln!(
                 "\nlit data: dyn {}, stat {}",
                 self.opt_len, self.static_len
             );
         }
 
-        self.build_tree(state, &mut self.d_desc.clone());
-        if state.verbose > 1 {
+        self.build_tree(state, TreeId::DynDist);
+        if state.opts.verbose >= TRACE_BLOCKS {
             eprintln!(
                 "\ndist data: dyn {}, stat {}",
                 self.opt_len, self.static_len
@@ -448,7 +511,7 @@ impl<'a> Trees<'a> {
         static_lenb = (self.static_len.wrapping_add(3 + 7)) >> 3;
         self.input_len += stored_len; // For debugging only
 
-        if state.verbose > 0 {
+        if state.opts.verbose >= TRACE_BLOCKS {
             eprintln!(
                 "\nopt {}({}) stat {}({}) stored {} lit {} dist {}",
                 opt_lenb,
@@ -472,53 +535,75 @@ impl<'a> Trees<'a> {
         if stored_len <= opt_lenb && eof && self.compressed_len == 0 && seekable() {
             // Since LIT_BUFSIZE <= 2*WSIZE, the input data must be there
             if buf.is_none() {
-                state.gzip_error("block vanished");
+                return Err(state.gzip_error("block vanished"));
             }
 
-            self.copy_block(state, buf.unwrap(), stored_len as usize, false); // Without header
+            self.copy_block(state, buf.unwrap(), stored_len as usize, false)?; // Without header
             self.compressed_len = stored_len << 3;
             self.file_method = STORED as i32;
         } else if stored_len + 4 <= opt_lenb && buf.is_some() {
             // 4: two words for the lengths
-            let eof_flag = if eof { 1 } else { 0 };
-            state.send_bits(((STORED_BLOCK << 1) + eof_flag) as u16, 3); // Send block type
-            self.compressed_len = (self.compressed_len + 3 + 7) & !7u64;
-            self.compressed_len += (stored_len + 4) << 3;
-
-            self.copy_block(state, buf.unwrap(), stored_len as usize, true); // With header
+            self.send_stored_block(state, &buf.unwrap()[..stored_len as usize], eof)?;
         } else if static_lenb == opt_lenb {
             let eof_flag = if eof { 1 } else { 0 };
-            state.send_bits(((STATIC_TREES << 1) + eof_flag) as u16, 3);
-            self.compress_block(state, self.static_ltree.clone().borrow().deref(), self.static_dtree.clone().borrow().deref());
-            self.compressed_len += 3 + self.static_len;
+            state.send_bits(((STATIC_TREES << 1) + eof_flag) as u16, 3)?;
+            self.compress_block(state, TreeId::StaticLit, TreeId::StaticDist)?;
+            self.compressed_len = self.compressed_len.wrapping_add(3).wrapping_add(self.static_len);
         } else {
             let eof_flag = if eof { 1 } else { 0 };
-            state.send_bits(((DYN_TREES << 1) + eof_flag) as u16, 3);
+            state.send_bits(((DYN_TREES << 1) + eof_flag) as u16, 3)?;
             self.send_all_trees(
                 state,
                 (self.l_desc.max_code + 1) as usize,
                 (self.d_desc.max_code + 1) as usize,
                 (max_blindex + 1) as usize,
-            );
-            self.compress_block(state, self.dyn_ltree.clone().borrow().deref(), self.dyn_dtree.clone().borrow().deref());
-            self.compressed_len += 3 + self.opt_len;
+            )?;
+            self.compress_block(state, TreeId::DynLit, TreeId::DynDist)?;
+            self.compressed_len = self.compressed_len.wrapping_add(3).wrapping_add(self.opt_len);
+        }
+
+        self.end_block(state, eof)
+    }
+
+    // Send buf as a stored block, whatever was tallied: level 0 sends all its input this
+    // way, without building any trees
+    pub(crate) fn flush_stored_block(&mut self, state: &mut GzipState, buf: &[u8], eof: bool) -> io::Result<i64> {
+        state.blocks += 1;
+        self.input_len += buf.len() as u64; // For debugging only
+        if state.opts.verbose >= TRACE_BLOCKS {
+            eprintln!("\nstored {}", buf.len());
         }
+        self.send_stored_block(state, buf, eof)?;
+        self.end_block(state, eof)
+    }
+
+    // The block type, then on a byte boundary the length, its complement and the data
+    fn send_stored_block(&mut self, state: &mut GzipState, buf: &[u8], eof: bool) -> io::Result<()> {
+        let eof_flag = if eof { 1 } else { 0 };
+        state.send_bits(((STORED_BLOCK << 1) + eof_flag) as u16, 3)?; // Send block type
+        self.compressed_len = (self.compressed_len + 3 + 7) & !7u64;
+        self.compressed_len += (buf.len() as u64 + 4) << 3;
+
+        self.copy_block(state, buf, buf.len(), true) // With header
+    }
 
+    // Start the next block afresh; after the last one, finish the output on a byte
+    fn end_block(&mut self, state: &mut GzipState, eof: bool) -> io::Result<i64> {
         self.init_block();
 
         if eof {
             //assert!(self.input_len as i64 == state.bytes_in, "bad input size");
-            state.bi_windup();
+            state.bi_windup()?;
             self.compressed_len = self.compressed_len.wrapping_add(7); // Align on byte boundary
         }
 
-        (self.compressed_len >> 3) as i64
+        Ok((self.compressed_len >> 3) as i64)
     }
 
     /// Send the header for a block using dynamic Huffman trees:
     /// the counts, the lengths of the bit length codes, the literal tree, and the distance tree.
     /// IN assertion: lcodes >= 257, dcodes >= 1, blcodes >= 4.
-    fn send_all_trees(&mut self, state: &mut GzipState, lcodes: usize, dcodes: usize, blcodes: usize) {
+    fn send_all_trees(&mut self, state: &mut GzipState, lcodes: usize, dcodes: usize, blcodes: usize) -> io::Result<()> {
         // Assertions to ensure we have the correct number of codes
         assert!(
             lcodes >= 257 && dcodes >= 1 && blcodes >= 4,
@@ -530,39 +615,40 @@ impl<'a> Trees<'a> {
         );
 
         // Optional debugging output
-        if state.verbose > 1 {
+        if state.opts.verbose >= TRACE_BLOCKS {
             eprintln!("\nbl counts:");
         }
 
         // Send the number of literal codes, distance codes, and bit length codes
-        state.send_bits((lcodes - 257) as u16, 5); // lcodes - 257 in 5 bits
-        state.send_bits((dcodes - 1) as u16, 5);   // dcodes - 1 in 5 bits
-        state.send_bits((blcodes - 4) as u16, 4);  // blcodes - 4 in 4 bits
+        state.send_bits((lcodes - 257) as u16, 5)?; // lcodes - 257 in 5 bits
+        state.send_bits((dcodes - 1) as u16, 5)?;   // dcodes - 1 in 5 bits
+        state.send_bits((blcodes - 4) as u16, 4)?;  // blcodes - 4 in 4 bits
 
         // Send the bit length codes in the order specified by bl_order
         for rank in 0..blcodes {
             let bl_code = BL_ORDER[rank];
 
-            if state.verbose > 1 {
+            if state.opts.verbose >= TRACE_SYMBOLS {
                 eprintln!("\nbl code {:2}", bl_code);
             }
 
             // Send the bit length for the current code in 3 bits
-            state.send_bits(self.bl_tree[bl_code].len as u16, 3);
+            state.send_bits(self.bl_tree[bl_code].len as u16, 3)?;
         }
 
         // Send the literal tree
-        self.send_tree(state, &self.dyn_ltree.clone().borrow().deref(), lcodes - 1);
+        self.send_tree(state, TreeId::DynLit, lcodes - 1)?;
 
         // Send the distance tree
-        self.send_tree(state, self.dyn_dtree.clone().borrow().deref(), dcodes - 1);
+        self.send_tree(state, TreeId::DynDist, dcodes - 1)?;
+        Ok(())
     }
 
     /// Send a literal or distance tree in compressed form, using the codes in bl_tree.
-    fn send_tree(&mut self, state: &mut GzipState, tree: &[CtData], max_code: usize) {
+    fn send_tree(&mut self, state: &mut GzipState, tree: TreeId, max_code: usize) -> io::Result<()> {
         let mut prevlen: i32 = -1; // Last emitted length
         let mut curlen: i32; // Length of current code
-        let mut nextlen: i32 = tree[0].len as i32; // Length of next code
+        let mut nextlen: i32 = self.tree(tree)[0].len as i32; // Length of next code
         let mut count: i32 = 0; // Repeat count of the current code length
         let mut max_count: i32 = 7; // Max repeat count
         let mut min_count: i32 = 4; // Min repeat count
@@ -576,7 +662,7 @@ impl<'a> Trees<'a> {
         for n in 0..=max_code {
             curlen = nextlen;
             if n + 1 <= max_code {
-                nextlen = tree[n + 1].len as i32;
+                nextlen = self.tree(tree)[n + 1].len as i32;
             } else {
                 nextlen = -1;
             }
@@ -589,11 +675,11 @@ impl<'a> Trees<'a> {
                 if count < min_count {
                     // Send the code 'count' times
                     for _ in 0..count {
-                        self.send_code(state, curlen as usize, self.bl_tree.deref());
+                        self.send_code(state, curlen as usize, TreeId::BitLen)?;
                     }
                 } else if curlen != 0 {
                     if curlen != prevlen {
-                        self.send_code(state, curlen as usize, self.bl_tree.deref());
+                        self.send_code(state, curlen as usize, TreeId::BitLen)?;
                         count -= 1;
                     }
                     assert!(
@@ -601,14 +687,14 @@ impl<'a> Trees<'a> {
                         "Invalid count for REP_3_6: count = {}",
                         count
                     );
-                    self.send_code(state, REP_3_6, self.bl_tree.deref());
-                    state.send_bits((count - 3) as u16, 2);
+                    self.send_code(state, REP_3_6, TreeId::BitLen)?;
+                    state.send_bits((count - 3) as u16, 2)?;
                 } else if count <= 10 {
-                    self.send_code(state, REPZ_3_10, self.bl_tree.deref());
-                    state.send_bits((count - 3) as u16, 3);
+                    self.send_code(state, REPZ_3_10, TreeId::BitLen)?;
+                    state.send_bits((count - 3) as u16, 3)?;
                 } else {
-                    self.send_code(state, REPZ_11_138, self.bl_tree.deref());
-                    state.send_bits((count - 11) as u16, 7);
+                    self.send_code(state, REPZ_11_138, TreeId::BitLen)?;
+                    state.send_bits((count - 11) as u16, 7)?;
                 }
 
                 count = 0;
@@ -626,6 +712,7 @@ impl<'a> Trees<'a> {
                 }
             }
         }
+        Ok(())
     }
 
     fn set_file_type(&mut self) {
@@ -634,23 +721,23 @@ impl<'a> Trees<'a> {
         let mut bin_freq: u32 = 0;
 
         while n < 7 {
-            bin_freq += self.dyn_ltree.borrow()[n].freq as u32;
+            bin_freq += self.dyn_ltree[n].freq as u32;
             n += 1;
         }
         while n < 128 {
-            ascii_freq += self.dyn_ltree.borrow()[n].freq as u32;
+            ascii_freq += self.dyn_ltree[n].freq as u32;
             n += 1;
         }
         while n < LITERALS {
-            bin_freq += self.dyn_ltree.borrow()[n].freq as u32;
+            bin_freq += self.dyn_ltree[n].freq as u32;
             n += 1;
         }
 
-        **self.file_type.as_mut().unwrap() = if bin_freq > (ascii_freq >> 2) {
+        self.file_type = Some(if bin_freq > (ascii_freq >> 2) {
             BINARY
         } else {
             ASCII
-        };
+        });
     }
 
     fn warning(&self, msg: &str) {
@@ -659,7 +746,7 @@ impl<'a> Trees<'a> {
 
 
     /// Send the block data compressed using the given Huffman trees
-    fn compress_block(&mut self, state: &mut GzipState, ltree: &[CtData], dtree: &[CtData]) {
+    fn compress_block(&mut self, state: &mut GzipState, ltree: TreeId, dtree: TreeId) -> io::Result<()> {
         let mut dist: u32;      // Distance of matched string
         let mut lc: i32;        // Match length or unmatched char (if dist == 0)
         let mut lx: usize = 0;  // Running index in l_buf
@@ -674,7 +761,7 @@ impl<'a> Trees<'a> {
             while lx < self.last_lit as usize {
                 // Load a new flag byte every 8 literals
                 if (lx & 7) == 0 {
-                    flag = self.flag_buf[fx] as u8;
+                    flag = self.flag_buf[fx];
                     fx += 1;
                 }
 
@@ -683,21 +770,21 @@ impl<'a> Trees<'a> {
 
                 if (flag & 1) == 0 {
                     // It's a literal byte
-                    self.send_code(state, lc as usize, ltree); // Send a literal byte
+                    self.send_code(state, lc as usize, ltree)?; // Send a literal byte
                     // Optionally trace the literal character
                     // if lc is printable, you can log it for debugging
                 } else {
                     // It's a match
                     // Here, lc is the match length minus MIN_MATCH
                     let lc_usize = lc as usize;
-                    code = self.length_code[lc_usize] as usize;
-                    self.send_code(state, code + LITERALS + 1, ltree); // Send the length code
+                    code = self.tables.length_code[lc_usize] as usize;
+                    self.send_code(state, code + LITERALS + 1, ltree)?; // Send the length code
                     extra = EXTRA_LBITS[code] as u8;
 
                     if extra != 0 {
-                        let base_len = self.base_length[code] as i32;
+                        let base_len = self.tables.base_length[code] as i32;
                         let lc_adjusted = lc - base_len;
-                        state.send_bits(lc_adjusted as u16, extra); // Send the extra length bits
+                        state.send_bits(lc_adjusted as u16, extra)?; // Send the extra length bits
                     }
 
                     dist = self.d_buf[dx] as u32;
@@ -707,13 +794,13 @@ impl<'a> Trees<'a> {
                     code = self.d_code(dist as usize);
                     assert!(code < D_CODES, "bad d_code");
 
-                    self.send_code(state, code, dtree); // Send the distance code
+                    self.send_code(state, code, dtree)?; // Send the distance code
                     extra = EXTRA_DBITS[code] as u8;
 
                     if extra != 0 {
-                        let base_dist = self.base_dist[code] as u32;
+                        let base_dist = self.tables.base_dist[code] as u32;
                         let dist_adjusted = dist - base_dist;
-                        state.send_bits(dist_adjusted as u16, extra); // Send the extra distance bits
+                        state.send_bits(dist_adjusted as u16, extra)?; // Send the extra distance bits
                     }
                 }
 
@@ -722,33 +809,37 @@ impl<'a> Trees<'a> {
         }
 
         // Send the end of block code
-        self.send_code(state, END_BLOCK, ltree);
+        self.send_code(state, END_BLOCK, ltree)
     }
 
-    fn send_code(&self, state: &mut GzipState, c: usize, tree: &[CtData]) {
-        // Debugging output if verbose > 1
-        if state.verbose > 1 {
+    fn send_code(&self, state: &mut GzipState, c: usize, tree: TreeId) -> io::Result<()> {
+        if state.opts.verbose >= TRACE_SYMBOLS {
             eprintln!("\ncd {:3}", c);
         }
 
          // Output the code and its length in hexadecimal
-        let code = tree[c].code;
-        let length = tree[c].len;
+        let code = self.tree(tree)[c].code;
+        let length = self.tree(tree)[c].len;
 
 //         eprintln!("Code: {:X}, Length: {}", code, length);
 
         // Send the code and its length using the send_bits function
-        state.send_bits(code, length as u8);
+        state.send_bits(code, length as u8)
+    }
 
+    // An empty, non-final stored block: the marker of a sync flush
+    pub(crate) fn send_empty_stored_block(&mut self, state: &mut GzipState) -> io::Result<()> {
+        state.send_bits((STORED_BLOCK << 1) as u16, 3)?;
+        self.copy_block(state, &[], 0, true)
     }
 
-    fn copy_block(&mut self, state: &mut GzipState, buf: &[u8], len: usize, header: bool) {
+    fn copy_block(&mut self, state: &mut GzipState, buf: &[u8], len: usize, header: bool) -> io::Result<()> {
         // Align on byte boundary
-        state.bi_windup();
+        state.bi_windup()?;
 
         if header {
-            state.put_short(len as u16);
-            state.put_short(!len as u16);
+            state.put_short(len as u16)?;
+            state.put_short(!len as u16)?;
         }
 
         // Iterate over the buffer and output each byte
@@ -766,14 +857,16 @@ impl<'a> Trees<'a> {
             }
             #[cfg(not(feature = "encryption"))]
             {
-                state.put_byte(byte).expect("Failed");
+                state.put_byte(byte)?;
             }
         }
+        Ok(())
     }
 
-    fn build_tree(&mut self, state: &GzipState, desc: &mut TreeDesc) {
-        let tree = desc.dyn_tree.clone();
-        let stree = desc.static_tree.as_ref();
+    fn build_tree(&mut self, state: &GzipState, id: TreeId) {
+        let desc = *self.desc_mut(id);
+        let tree = desc.dyn_tree;
+        let stree = desc.static_tree;
         let elems = desc.elems;
         let mut n: usize;
         let mut m: usize;
@@ -786,13 +879,13 @@ impl<'a> Trees<'a> {
         self.heap_max = HEAP_SIZE;
 
         for n in 0..elems {
-            if tree.borrow()[n].freq != 0 {
+            if self.tree(tree)[n].freq != 0 {
                 self.heap_len += 1;
                 self.heap[self.heap_len] = n as i32;
                 max_code = n as i32;
                 self.depth[n] = 0;
             } else {
-                tree.borrow_mut()[n].len = 0;
+                self.tree_mut(tree)[n].len = 0;
             }
         }
 
@@ -801,29 +894,33 @@ impl<'a> Trees<'a> {
         // possible code. So to avoid special checks later on, we force at least
         // two codes of non-zero frequency.
         while self.heap_len < 2 {
-            max_code += 1;
-            let new_node = if max_code < 2 { max_code } else { 0 } as usize;
+            let new_node = if max_code < 2 {
+                max_code += 1;
+                max_code as usize
+            } else {
+                0
+            };
             self.heap_len += 1;
             self.heap[self.heap_len] = new_node as i32;
-            tree.borrow_mut()[new_node].freq = 1;
+            self.tree_mut(tree)[new_node].freq = 1;
             self.depth[new_node] = 0;
             self.opt_len = self.opt_len.wrapping_sub(1);
             if let Some(stree) = stree {
-                self.static_len = self.static_len.wrapping_sub(stree.borrow()[new_node].len as u64);
+                self.static_len = self.static_len.wrapping_sub(self.tree(stree)[new_node].len as u64);
             }
             // new_node is 0 or 1, so it does not have extra bits
         }
-        desc.max_code = max_code as i32;
+        self.desc_mut(id).max_code = max_code as i32;
 
         // The elements heap[heap_len/2+1 .. heap_len] are leaves of the tree,
         // establish sub-heaps of increasing lengths:
         for n in (1..=(self.heap_len / 2)).rev() {
-            self.pq_down_heap(tree.borrow().deref(), n);
+            self.pq_down_heap(tree, n);
         }
 
         // Construct the Huffman tree by repeatedly combining the two least frequent nodes.
         loop {
-            n = self.pq_remove(tree.borrow().deref()) as usize; // Node of least frequency
+            n = self.pq_remove(tree) as usize; // Node of least frequency
             m = self.heap[SMALLEST] as usize;  // Node of next least frequency
 
             self.heap_max -= 1;
@@ -832,15 +929,15 @@ impl<'a> Trees<'a> {
             self.heap[self.heap_max] = m as i32;
 
             // Create a new node as the parent of n and m
-            let freq = tree.borrow()[n].freq + tree.borrow()[m].freq;
-            tree.borrow_mut()[node].freq = freq;
+            let freq = self.tree(tree)[n].freq + self.tree(tree)[m].freq;
+            self.tree_mut(tree)[node].freq = freq;
             self.depth[node] = self.depth[n].max(self.depth[m]) + 1;
-            tree.borrow_mut()[n].dad = node as u16;
-            tree.borrow_mut()[m].dad = node as u16;
+            self.tree_mut(tree)[n].dad = node as u16;
+            self.tree_mut(tree)[m].dad = node as u16;
 
             // Insert the new node into the heap
             self.heap[SMALLEST] = node as i32;
-            self.pq_down_heap(tree.borrow_mut().deref_mut(), SMALLEST);
+            self.pq_down_heap(tree, SMALLEST);
 
             node += 1;
             if self.heap_len < 2 {
@@ -852,20 +949,22 @@ impl<'a> Trees<'a> {
         self.heap[self.heap_max] = self.heap[SMALLEST];
 
         // At this point, the fields freq and dad are set. We can now generate the bit lengths.
-        self.gen_bitlen(state, desc);
+        self.gen_bitlen(state, id);
 
         // The field len is now set; we can generate the bit codes
-        Self::gen_codes(&self.bl_count, tree.borrow_mut().deref_mut(), desc.max_code);
+        let bl_count = self.bl_count;
+        let max_code = self.desc_mut(id).max_code;
+        Self::gen_codes(&bl_count, self.tree_mut(tree), max_code);
     }
 
     /// Remove the smallest element from the heap and adjust the heap.
     /// Returns the index of the smallest node.
-    fn pq_remove(&mut self, tree: &[CtData]) -> usize {
-        // The smallest item is at the root of the heap (index 0 in zero-based indexing)
+    fn pq_remove(&mut self, tree: TreeId) -> usize {
+        // The smallest item is at the root of the heap, heap[SMALLEST]
         let top = self.heap[SMALLEST]; // Remove the smallest item
 
         // Move the last item to the root and reduce the heap size
-        self.heap[SMALLEST] = self.heap[self.heap_len - 1];
+        self.heap[SMALLEST] = self.heap[self.heap_len];
         self.heap_len -= 1;
 
         // Restore the heap property by moving down from the root
@@ -882,13 +981,14 @@ impl<'a> Trees<'a> {
     /// array bl_count contains the frequencies for each bit length.
     /// The length opt_len is updated; static_len is also updated if stree is
     /// not null.
-    fn gen_bitlen(&mut self, state: &GzipState, desc: &mut TreeDesc) {
-        let tree = &mut desc.dyn_tree; // Dynamic tree
+    fn gen_bitlen(&mut self, state: &GzipState, id: TreeId) {
+        let desc = *self.desc_mut(id);
+        let tree = desc.dyn_tree;      // Dynamic tree
         let extra = desc.extra_bits;   // Extra bits array
         let base = desc.extra_base;    // Base index for extra bits
         let max_code = desc.max_code;  // Maximum code with non-zero frequency
         let max_length = desc.max_length; // Maximum allowed bit length
-        let stree = &desc.static_tree;  // Static tree (if any)
+        let stree = desc.static_tree;  // Static tree (if any)
 
         let mut overflow = 0; // Number of elements with bit length too large
 
@@ -898,18 +998,21 @@ impl<'a> Trees<'a> {
         }
 
         // In a first pass, compute the optimal bit lengths (which may overflow)
-        tree.borrow_mut()[self.heap[self.heap_max as usize] as usize].len = 0; // Root of the heap
+        let root = self.heap[self.heap_max] as usize;
+        self.tree_mut(tree)[root].len = 0; // Root of the heap
 
-        for h in (self.heap_max + 1)..self.heap_len {
-            let n = self.heap[h as usize] as usize;
-            let mut bits = tree.borrow()[tree.borrow()[n].dad as usize].len + 1;
+        // heap[heap_max + 1..HEAP_SIZE] holds every other node, sorted by frequency
+        for h in (self.heap_max + 1)..HEAP_SIZE {
+            let n = self.heap[h] as usize;
+            let dad = self.tree(tree)[n].dad as usize;
+            let mut bits = self.tree(tree)[dad].len + 1;
 
             if bits > max_length as u16 {
                 bits = max_length as u16;
                 overflow += 1;
             }
 
-            tree.borrow_mut()[n].len = bits;
+            self.tree_mut(tree)[n].len = bits;
 
             // If it's not a leaf node, continue
             if n > max_code as usize {
@@ -924,10 +1027,10 @@ impl<'a> Trees<'a> {
                 xbits = extra.as_ref().unwrap()[n - base];
             }
 
-            let f = tree.borrow()[n].freq as u64;
-            self.opt_len += f * (bits as u64 + xbits as u64);
+            let f = self.tree(tree)[n].freq as u64;
+            self.opt_len = self.opt_len.wrapping_add(f * (bits as u64 + xbits as u64));
             if let Some(stree) = stree {
-                self.static_len += f * (stree.borrow()[n].len as u64 + xbits as u64);
+                self.static_len = self.static_len.wrapping_add(f * (self.tree(stree)[n].len as u64 + xbits as u64));
             }
         }
 
@@ -939,13 +1042,13 @@ impl<'a> Trees<'a> {
         self.adjust_bit_lengths(state, overflow, max_length as i32);
 
         // Now recompute all bit lengths, scanning in increasing frequency
-        self.recompute_bit_lengths(state, tree.borrow_mut().deref_mut(), max_code, max_length as i32);
+        self.recompute_bit_lengths(state, tree, max_code, max_length as i32);
     }
 
     /// Adjust bit lengths to eliminate overflow
     fn adjust_bit_lengths(&mut self, state: &GzipState, mut overflow: i32, max_length: i32) {
         // This happens for example on obj2 and pic of the Calgary corpus
-        if state.verbose > 0 {
+        if state.opts.verbose >= TRACE_BLOCKS {
             eprintln!("\nbit length overflow");
         }
 
@@ -973,33 +1076,37 @@ impl<'a> Trees<'a> {
         }
     }
 
-    /// Recompute all bit lengths, scanning in increasing frequency
-    fn recompute_bit_lengths(&mut self, state: &GzipState, tree: &mut [CtData], max_code: i32, max_length: i32) {
-        let mut h = self.heap_len as usize;
-        // Start from the largest bit length
+    /// Recompute all bit lengths, scanning in increasing frequency.
+    /// heap[heap_max + 1..HEAP_SIZE] is sorted by frequency, so walking it down from
+    /// the top hands the longest of the corrected lengths to the least frequent leaves.
+    fn recompute_bit_lengths(&mut self, state: &GzipState, tree: TreeId, max_code: i32, max_length: i32) {
+        let mut h = HEAP_SIZE;
         for bits in (1..=max_length).rev() {
-            let n = self.bl_count[bits as usize];
-            for _ in 0..n {
+            let mut n = self.bl_count[bits as usize];
+            while n != 0 {
                 h -= 1;
                 let m = self.heap[h] as usize;
 
+                // Internal nodes do not count towards bl_count
                 if m > max_code as usize {
                     continue;
                 }
 
-                if tree[m].len != bits as u16 {
-                    if state.verbose > 1 {
+                let node = self.tree(tree)[m];
+                if node.len != bits as u16 {
+                    if state.opts.verbose >= TRACE_SYMBOLS {
                         eprintln!(
                             "code {} bits {}->{}",
                             m,
-                            tree[m].len,
+                            node.len,
                             bits
                         );
                     }
-                    let freq = tree[m].freq as u64;
-                    self.opt_len += (bits as u64 - tree[m].len as u64) * freq;
-                    tree[m].len = bits as u16;
+                    let delta = (bits as i64 - node.len as i64) * node.freq as i64;
+                    self.opt_len = self.opt_len.wrapping_add(delta as u64);
+                    self.tree_mut(tree)[m].len = bits as u16;
                 }
+                n -= 1;
             }
         }
     }
@@ -1007,20 +1114,21 @@ impl<'a> Trees<'a> {
     /// Restore the heap property by moving down the tree starting at node `k`,
     /// exchanging a node with the smallest of its two children if necessary,
     /// stopping when the heap property is re-established (each parent smaller than its two children).
-    fn pq_down_heap(&mut self, tree: &[CtData], mut k: usize) {
+    fn pq_down_heap(&mut self, tree: TreeId, mut k: usize) {
         let heap_len = self.heap_len;
 
         let v = self.heap[k];
 
+        // The heap is 1-based: the sons of heap[k] are heap[2*k] and heap[2*k+1]
         loop {
-            let mut j = 2 * k + 1; // Left child index in zero-based array
+            let mut j = 2 * k; // Left son
 
-            if j >= heap_len {
+            if j > heap_len {
                 break;
             }
 
             // If right child exists and is smaller than left child, use right child
-            if j + 1 < heap_len && self.smaller(tree, self.heap[j + 1] as usize, self.heap[j] as usize) {
+            if j < heap_len && self.smaller(tree, self.heap[j + 1] as usize, self.heap[j] as usize) {
                 j += 1; // Move to right child
             }
 
@@ -1041,7 +1149,8 @@ impl<'a> Trees<'a> {
 
     /// Compare two nodes in the heap based on frequencies and depths.
     /// Returns true if node `n` is "smaller" than node `m`.
-    fn smaller(&self, tree: &[CtData], n: usize, m: usize) -> bool {
+    fn smaller(&self, tree: TreeId, n: usize, m: usize) -> bool {
+        let tree = self.tree(tree);
         tree[n].freq < tree[m].freq
             || (tree[n].freq == tree[m].freq && self.depth[n] <= self.depth[m])
     }
@@ -1050,11 +1159,11 @@ impl<'a> Trees<'a> {
         let mut max_blindex: i32;
 
         // Determine the bit length frequencies for literal and distance trees
-        Self::scan_tree(&mut self.bl_tree, self.dyn_ltree.borrow_mut().deref_mut(), self.l_desc.max_code);
-        Self::scan_tree(&mut self.bl_tree, self.dyn_dtree.borrow_mut().deref_mut(), self.d_desc.max_code);
+        self.scan_tree(TreeId::DynLit, self.l_desc.max_code);
+        self.scan_tree(TreeId::DynDist, self.d_desc.max_code);
 
         // Build the bit length tree
-        self.build_tree(state, &mut self.bl_desc.clone());
+        self.build_tree(state, TreeId::BitLen);
 
         // At this point, opt_len includes the length of the tree representations,
         // except the lengths of the bit lengths codes and the 5+5+4 bits for the counts.
@@ -1073,17 +1182,17 @@ impl<'a> Trees<'a> {
         // Update opt_len to include the bit length tree and counts
         self.opt_len = self.opt_len.wrapping_add(3 * ((max_blindex as u64) + 1) + 5 + 5 + 4);
 
-        if state.verbose > 1 {
+        if state.opts.verbose >= TRACE_BLOCKS {
             eprintln!("\ndyn trees: dyn {}, stat {}", self.opt_len, self.static_len);
         }
 
         max_blindex
     }
 
-    fn scan_tree(bl_tree: &mut [CtData; 2 * BL_CODES + 1], tree: &mut [CtData], max_code: i32) {
+    fn scan_tree(&mut self, tree: TreeId, max_code: i32) {
         let mut prevlen: i32 = -1;           // Last emitted length
         let mut curlen: i32;                 // Length of current code
-        let mut nextlen: i32 = tree[0].len as i32; // Length of next code
+        let mut nextlen: i32 = self.tree(tree)[0].len as i32; // Length of next code
         let mut count: i32 = 0;              // Repeat count of the current code
         let mut max_count: i32;              // Max repeat count
         let mut min_count: i32;              // Min repeat count
@@ -1096,16 +1205,15 @@ impl<'a> Trees<'a> {
             min_count = 4;
         }
 
-        // Set a guard value to prevent out-of-bounds access
-        if (max_code + 1) as usize >= tree.len() {
-            panic!("Tree array is too small");
-        }
-        tree[(max_code + 1) as usize].len = 0xFFFF;
-
-        for n in 0..=max_code {
-            let n = n as usize;
+        // Past max_code the run ends; the slots there may hold internal heap nodes,
+        // so they are never read (trees.c writes a guard length there instead)
+        for n in 0..=max_code as usize {
             curlen = nextlen;
-            nextlen = tree[n + 1].len as i32;
+            nextlen = if n < max_code as usize {
+                self.tree(tree)[n + 1].len as i32
+            } else {
+                -1
+            };
 
             count += 1;
 
@@ -1114,16 +1222,16 @@ impl<'a> Trees<'a> {
             } else {
                 if count < min_count {
                     // Update the frequency for the current code length
-                    bl_tree[curlen as usize].freq += count as u16;
+                    self.bl_tree[curlen as usize].freq += count as u16;
                 } else if curlen != 0 {
                     if curlen != prevlen {
-                        bl_tree[curlen as usize].freq += 1;
+                        self.bl_tree[curlen as usize].freq += 1;
                     }
-                    bl_tree[REP_3_6].freq += 1;
+                    self.bl_tree[REP_3_6].freq += 1;
                 } else if count <= 10 {
-                    bl_tree[REPZ_3_10].freq += 1;
+                    self.bl_tree[REPZ_3_10].freq += 1;
                 } else {
-                    bl_tree[REPZ_11_138].freq += 1;
+                    self.bl_tree[REPZ_11_138].freq += 1;
                 }
 
                 count = 0;
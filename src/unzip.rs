@@ -1,152 +1,298 @@
 use std::io;
 use std::time::SystemTime;
 use std::io::{stdout, Read, Write};
-use crate::{OK, ERROR, GzipState, STORED, DEFLATED, GZIP_MAGIC, ORIG_NAME, OS_CODE, INBUFSIZ, INBUF_EXTRA, OUTBUFSIZ, OUTBUF_EXTRA, DIST_BUFSIZE, WSIZE};
+use crate::{exit_status, OK, ERROR, Format, GzipState, Stats, STORED, DEFLATED, GZIP_MAGIC, ORIG_NAME, OS_CODE, INBUFSIZ, INBUF_EXTRA, OUTBUFSIZ, OUTBUF_EXTRA, DIST_BUFSIZE, WSIZE};
 use crate::deflate::Deflate;
-use crate::inflate::Inflate;
+use crate::inflate::{Inflate, DISTANCE_TOO_FAR, INCOMPLETE_CODE_SET, INPUT_ERROR, OVERSUBSCRIBED_CODE_SET};
 use crate::trees::Trees;
 
 // Macros for getting two-byte and four-byte header values
 /// 提取两字节无符号整数
-fn SH(p: &[u8]) -> u16 {
+pub(crate) fn SH(p: &[u8]) -> u16 {
     (p[0] as u16) | ((p[1] as u16) << 8)
 }
 
 /// 提取四字节无符号整数
-fn LG(p: &[u8]) -> u32 {
+pub(crate) fn LG(p: &[u8]) -> u32 {
     (SH(&p[0..2]) as u32) | ((SH(&p[2..4]) as u32) << 16)
 }
 
 
 /* PKZIP header definitions */
-const LOCSIG: u32 = 0x04034b50; // four-byte lead-in (lsb first)
-const LOCFLG: usize = 6;        // offset of bit flag
-const CRPFLG: u32 = 1;          // bit for encrypted entry
-const EXTFLG: u32 = 8;          // bit for extended local header
-const LOCHOW: usize = 8;        // offset of compression method
-// const LOCTIM: usize = 10;    // UNUSED file mod time (for decryption)
-const LOCCRC: usize = 14;       // offset of crc
-const LOCSIZ: usize = 18;       // offset of compressed size
-const LOCLEN: usize = 22;       // offset of uncompressed length
-const LOCFIL: usize = 26;       // offset of file name field length
-const LOCEXT: usize = 28;       // offset of extra field length
-const LOCHDR: usize = 30;       // size of local header, including sig
+pub(crate) const LOCSIG: u32 = 0x04034b50; // four-byte lead-in (lsb first)
+const EXTSIG: u32 = 0x08074b50; // optional lead-in of the extended local header
+pub(crate) const LOCFLG: usize = 6;        // offset of bit flag
+pub(crate) const CRPFLG: u16 = 1;          // bit for encrypted entry
+pub(crate) const EXTFLG: u16 = 8;          // bit for extended local header
+pub(crate) const LOCHOW: usize = 8;        // offset of compression method
+pub(crate) const LOCTIM: usize = 10;       // offset of file mod time
+pub(crate) const LOCCRC: usize = 14;       // offset of crc
+pub(crate) const LOCSIZ: usize = 18;       // offset of compressed size
+pub(crate) const LOCLEN: usize = 22;       // offset of uncompressed length
+pub(crate) const LOCFIL: usize = 26;       // offset of file name field length
+pub(crate) const LOCEXT: usize = 28;       // offset of extra field length
+pub(crate) const LOCHDR: usize = 30;       // size of local header, including sig
 const EXTHDR: usize = 16;       // size of extended local header, inc sig
-const RAND_HEAD_LEN: u32 = 12; // length of encryption random header
 
 /* Globals */
 
 
-pub fn unzip (state: &mut GzipState) -> io::Result<()> {
-    let mut decrypt: i32 = 0;        // flag to turn on decryption
-    let mut pkzip: i32 = 0;          // set for a pkzip file
-    let mut ext_header: i32 = 0;     // set if extended local header
+// Report bad compressed data for this file only, so -t and a list of files carry on
+// with the next input instead of exiting
+pub(crate) fn data_error(state: &mut GzipState, msg: &str) -> io::Error {
+    state.clear_progress();
+    report!(state, "{}: {}: {}", state.program_name, state.ifname.display(), msg);
+    state.exit_code = ERROR;
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// An output that was refused: a write error has stopped the run already, and anything
+// else (an expansion limit) is reported as for bad data
+fn output_error(state: &mut GzipState, err: io::Error) -> io::Error {
+    if exit_status(&err).is_some() {
+        return err;
+    }
+    data_error(state, &err.to_string())
+}
+
+// Read the bytes of a trailer or data descriptor that follows the compressed data
+pub(crate) fn read_trailer(inflate: &mut Inflate, state: &mut GzipState, buf: &mut [u8]) -> io::Result<()> {
+    for byte in buf.iter_mut() {
+        *byte = match inflate.get_byte(state) {
+            Ok(byte) => byte,
+            Err(err) => return Err(data_error(state, &err.to_string())),
+        };
+    }
+    Ok(())
+}
+
+// Read and check the two-byte zlib header (RFC 1950): deflate with at most a 32K
+// window and a valid check. Returns whether the data was compressed with a preset
+// dictionary, after checking that its DICTID is that of --dictionary
+fn read_zlib_header(inflate: &mut Inflate, state: &mut GzipState) -> io::Result<bool> {
+    let mut header = [0u8; 2];
+    for byte in header.iter_mut() {
+        *byte = match inflate.get_byte(state) {
+            Ok(byte) => byte,
+            Err(err) => return Err(data_error(state, &err.to_string())),
+        };
+    }
+    let (cmf, flg) = (header[0], header[1]);
+
+    if !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+        return Err(data_error(state, "invalid compressed data--incorrect header check"));
+    }
+    if (cmf & 0x0f) as i32 != DEFLATED {
+        return Err(data_error(state, "invalid compressed data--unknown compression method"));
+    }
+    if cmf >> 4 > 7 {
+        return Err(data_error(state, "invalid compressed data--invalid window size"));
+    }
+    if flg & 0x20 == 0 {
+        return Ok(false);
+    }
+
+    let Some(expected) = state.opts.dictionary.as_ref().map(|dictionary| dictionary.id) else {
+        return Err(data_error(state, "invalid compressed data--needs a preset dictionary"));
+    };
+    let mut id = 0u32;
+    for _ in 0..4 {
+        id = (id << 8) | match inflate.get_byte(state) {
+            Ok(byte) => byte as u32,
+            Err(err) => return Err(data_error(state, &err.to_string())),
+        };
+    }
+    if id != expected {
+        return Err(data_error(state, "invalid compressed data--wrong preset dictionary"));
+    }
+    Ok(true)
+}
+
+// Compare the big-endian Adler-32 that ends zlib data with that of the output
+fn check_zlib_trailer(inflate: &mut Inflate, state: &mut GzipState) -> io::Result<()> {
+    let mut adler = 0u32;
+    for _ in 0..4 {
+        adler = (adler << 8) | match inflate.get_byte(state) {
+            Ok(byte) => byte as u32,
+            Err(err) => return Err(data_error(state, &err.to_string())),
+        };
+    }
+    if adler != state.data_adler.finalize() {
+        return Err(data_error(state, "invalid compressed data--adler-32 error"));
+    }
+    Ok(())
+}
+
+// Turn what inflate() returned into the message gzip gives for it
+pub(crate) fn check_inflate(inflate: &mut Inflate, state: &mut GzipState, res: i32) -> io::Result<()> {
+    if res == 3 {
+        Err(data_error(state, "memory exhausted"))
+    } else if res == 4 {
+        // The output was refused: a write error or an expansion limit
+        let err = inflate.take_output_error();
+        Err(output_error(state, err))
+    } else if res == DISTANCE_TOO_FAR {
+        Err(data_error(state, "invalid compressed data--distance too far back"))
+    } else if res == INPUT_ERROR {
+        let err = inflate.take_input_error();
+        Err(data_error(state, &err.to_string()))
+    } else if res == INCOMPLETE_CODE_SET {
+        Err(data_error(state, "invalid compressed data--incomplete code set"))
+    } else if res == OVERSUBSCRIBED_CODE_SET {
+        Err(data_error(state, "invalid compressed data--oversubscribed code set"))
+    } else if res != 0 {
+        Err(data_error(state, "invalid compressed data--format violated"))
+    } else {
+        Ok(())
+    }
+}
+
+pub fn unzip (state: &mut GzipState) -> io::Result<Stats> {
+    let entry = state.zip_entry.clone(); // the pkzip entry being extracted, if any
     let mut orig_crc: u32 = 0;        // original crc
-    let mut orig_len: u32 = 0;        // original uncompressed length
+    let mut orig_len: u64 = 0;        // original uncompressed length, modulo 2^32 for gzip
     let mut n: i32;
-    let mut buf: [u8; EXTHDR] = [0; EXTHDR]; // extended local header
+    let mut buf: [u8; EXTHDR] = [0; EXTHDR]; // trailer or extended local header
     let mut err = OK;
 //     let mut inbuf: [u8; INBUFSIZ + INBUF_EXTRA] = [0; INBUFSIZ + INBUF_EXTRA];
 //     let mut outbuf: [u8; OUTBUFSIZ + OUTBUF_EXTRA] = [0; OUTBUFSIZ + OUTBUF_EXTRA];
 //     let mut d_buf: [u8; DIST_BUFSIZE] = [0; DIST_BUFSIZE];
 //     let mut window: [u8; 2 * WSIZE] = [0; 2 * WSIZE];
 
-    let mut inflate = Inflate::new();
+    let mut inflate = match state.opts.inflate_bits {
+        Some((lbits, dbits)) => Inflate::new().with_table_bits(lbits, dbits),
+        None => Inflate::new(),
+    };
 
-    state.updcrc(None, 0); // initialize crc
+    state.data_crc.reset(); // initialize crc
+    state.data_adler.reset();
 
-    if pkzip>0 && ext_header == 0 {  // crc and length at the end otherwise
-        orig_crc = LG(&state.inbuf[LOCCRC..]);
-        orig_len = LG(&state.inbuf[LOCLEN..]);
+    if let Some(entry) = entry.as_ref().filter(|entry| !entry.has_descriptor) {  // crc and length at the end otherwise
+        orig_crc = entry.crc;
+        orig_len = entry.uncompressed_size;
+    }
+
+    // Raw data gives no sign of needing a dictionary, so --dictionary is simply used
+    let use_dictionary = match state.opts.format {
+        Format::Gzip => false,
+        Format::Zlib => read_zlib_header(&mut inflate, state)?,
+        Format::Raw => true,
+    };
+    if let Some(dictionary) = state.opts.dictionary.as_ref().filter(|_| use_dictionary) {
+        inflate = inflate.with_dictionary(&dictionary.bytes);
+    }
+    // --index keeps checkpoints through each gzip member
+    if let Some(index) = state.index.as_ref().filter(|_| entry.is_none()) {
+        inflate = inflate.with_checkpoints(index.span());
     }
 
     // Decompress
     if state.method == DEFLATED {
         let res = inflate.inflate(state);
+        check_inflate(&mut inflate, state, res)?;
+    } else if let Some(entry) = entry.as_ref().filter(|entry| entry.method as i32 == STORED) {
+        let mut n = entry.uncompressed_size;
 
-        if res == 3 {
-            state.gzip_error("memory exhausted");
-        } else if res != 0 {
-            eprintln!("Debugging res: {}", res);
-            state.gzip_error("invalid compressed data--format violated");
-        }
-    } else if pkzip>0 && state.method == STORED {
-        let mut n = LG(&state.inbuf[LOCLEN..]);
-
-        if n != LG(&state.inbuf[LOCSIZ..]) - (decrypt != 0) as u32 * RAND_HEAD_LEN {
-            eprintln!("len {}, siz {}", n, LG(&state.inbuf[LOCSIZ..]));
-            state.gzip_error("invalid compressed data--length mismatch");
+        if n != entry.compressed_size {
+            return Err(data_error(state, "invalid compressed data--length mismatch"));
         }
         while n > 0 {
             let c: u8 = inflate.get_byte(state)?;
-            state.put_byte(c);
+            if let Err(err) = inflate.put_byte(c, state) {
+                return Err(output_error(state, err));
+            }
             n -= 1;
         }
-        inflate.flush_window(state);
+        if let Err(err) = inflate.flush_window(state) {
+            return Err(output_error(state, err));
+        }
     } else {
-        state.gzip_error("internal error, invalid method");
+        return Err(data_error(state, "internal error, invalid method"));
+    }
+
+    // --format=raw data ends with its last deflate block, and --format=zlib data
+    // with an Adler-32 instead of the gzip CRC and length
+    match state.opts.format {
+        Format::Raw => return Ok(state.stats()),
+        Format::Zlib => return check_zlib_trailer(&mut inflate, state).map(|()| state.stats()),
+        Format::Gzip => {}
     }
 
     // Get the crc and original length
-    if pkzip == 0 {
+    if entry.is_none() {
         // crc32 (see algorithm.doc)
         // uncompressed input size modulo 2^32
-        for n in 0..8 {
-            buf[n] = inflate.get_byte(state)?; // may cause an error if EOF
-        }
+        read_trailer(&mut inflate, state, &mut buf[..8])?;
         orig_crc = LG(&buf);
-        orig_len = LG(&buf[4..]);
-    } else if ext_header>0 {
-        // If extended header, check it
-        // signature - 4bytes: 0x50 0x4b 0x07 0x08
+        orig_len = LG(&buf[4..]) as u64;
+        if let Some(index) = state.index.as_mut() {
+            index.add_member(inflate.take_checkpoints(), state.bytes_out as u64);
+        }
+    } else if let Some(entry) = entry.as_ref().filter(|entry| entry.has_descriptor) {
+        // The data descriptor after the data:
+        // signature - 4bytes: 0x50 0x4b 0x07 0x08, which not every writer puts in
         // CRC-32 value
-        // compressed size 4-bytes
-        // uncompressed size 4-bytes
-        for n in 0..EXTHDR {
-            buf[n] = inflate.get_byte(state)?; // may cause an error if EOF
+        // compressed size 4-bytes, 8 for a zip64 entry
+        // uncompressed size 4-bytes, 8 for a zip64 entry
+        let size_len = if entry.zip64 { 8 } else { 4 };
+        read_trailer(&mut inflate, state, &mut buf[..4])?;
+        if LG(&buf) == EXTSIG {
+            read_trailer(&mut inflate, state, &mut buf[..4])?;
+        }
+        orig_crc = LG(&buf);
+        read_trailer(&mut inflate, state, &mut buf[..2 * size_len])?;
+        let size = |p: &[u8]| if size_len == 8 { LG(p) as u64 | (LG(&p[4..]) as u64) << 32 } else { LG(p) as u64 };
+        orig_len = size(&buf[size_len..]);
+
+        // -l lists the entry with what the descriptor says
+        if let Some(listed) = state.zip_entry.as_mut() {
+            listed.crc = orig_crc;
+            listed.compressed_size = size(&buf);
+            listed.uncompressed_size = size(&buf[size_len..]);
         }
-        orig_crc = LG(&buf[4..]);
-        orig_len = LG(&buf[12..]);
     }
 
 
     // Validate decompression
-    if  u32::from(orig_crc) != state.updcrc(Some(&state.outbuf.clone()), 0) {
-        eprintln!(
+    if  u32::from(orig_crc) != state.data_crc.finalize() {
+        report!(
+            state,
             "\n{}: {}: invalid compressed data--crc error",
-            state.program_name, state.ifname
+            state.program_name, state.ifname.display()
         );
         err = ERROR;
     }
-    if  u32::from(orig_len) != (state.bytes_out & 0xffffffff) as u32 {
-        eprintln!(
+    // The gzip trailer only keeps the low 32 bits of the length; zip entries keep all 64
+    let out_len = if entry.is_some() { state.bytes_out as u64 } else { state.bytes_out as u64 & 0xffffffff };
+    if orig_len != out_len {
+        report!(
+            state,
             "\n{}: {}: invalid compressed data--length error",
-            state.program_name, state.ifname
+            state.program_name, state.ifname.display()
         );
         err = ERROR;
     }
 
-    // Check if there are more entries in a pkzip file
-    if pkzip>0 && state.inptr + 4 < state.insize && LG(&state.inbuf[state.inptr..] ) == LOCSIG {
-        if state.to_stdout {
-            eprintln!(
+    // Check if there are more entries in a pkzip file, unless they are wanted
+    if entry.is_some() && !state.opts.zip_entries && !state.opts.list && state.at_zip_entry()? {
+        if state.opts.to_stdout {
+            warn!(
+                state,
                 "{}: {} has more than one entry--rest ignored",
-                state.program_name, state.ifname
+                state.program_name, state.ifname.display()
             );
         } else {
             // Don't destroy the input zip file
             eprintln!(
                 "{}: {} has more than one entry -- unchanged",
-                state.program_name, state.ifname
+                state.program_name, state.ifname.display()
             );
             err = ERROR;
         }
     }
-    ext_header = 0; // for next file
-    pkzip = 0;
 
     if err == OK {
-        return Ok(());
+        return Ok(state.stats());
     }
 
     state.exit_code = ERROR;
@@ -154,5 +300,5 @@ pub fn unzip (state: &mut GzipState) -> io::Result<()> {
 //         abort_gzip();
 //     }
 
-    return Err(io::Error::new(io::ErrorKind::Other, "Decompression error"));
+    return Err(io::Error::other("Decompression error"));
 }
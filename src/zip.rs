@@ -1,89 +1,175 @@
 use std::io;
 use std::time::SystemTime;
-use crate::{GzipState, DEFLATED, GZIP_MAGIC, ORIG_NAME, OS_CODE};
+use crate::{Format, GzipState, Stats, DEFLATED, OS_CODE};
+use crate::header::GzipHeader;
 use crate::deflate::Deflate;
 use crate::trees::Trees;
 
-pub fn zip (state: &mut GzipState) -> io::Result<()> {
-    // Initialize output count
-    state.outcnt = 0;
+// BGZF (--bgzf) as htslib writes it: input blocks of at most this many bytes, so that
+// a compressed block always fits the 16-bit BSIZE
+pub(crate) const BGZF_BLOCK_SIZE: usize = 0xff00;
+// An empty BGZF block, which ends every BGZF file
+pub(crate) const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 0x06, 0, b'B', b'C', 0x02, 0,
+    0x1b, 0, 0x03, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+// Where BSIZE sits in a BGZF header: after the 10 fixed bytes, XLEN, and the BC subfield's
+// ID and length
+const BGZF_BSIZE_OFFSET: usize = 16;
 
-    // Write the gzip header
-    state.method = DEFLATED;
-    state.put_byte(GZIP_MAGIC[0])?;
-    state.put_byte(GZIP_MAGIC[1])?;
-    state.put_byte(DEFLATED as u8)?;
-    let mut flags = 0;
+// The window, hash table and trees zip works in. GzipState keeps one from each file to
+// the next, since for small files (gzip -r over a source tree) making them afresh would
+// take longer than the compression
+pub(crate) struct Compressor {
+    trees: Trees,
+    deflate: Deflate,
+}
 
-    if state.save_orig_name {
-        flags |= ORIG_NAME;
+impl Compressor {
+    fn new(state: &GzipState) -> Box<Self> {
+        let deflate = match &state.opts.dictionary {
+            Some(dictionary) => Deflate::new().with_dictionary(&dictionary.bytes),
+            None => Deflate::new(),
+        };
+        Box::new(Compressor { trees: Trees::new(), deflate })
     }
-    state.put_byte(flags)?;         // general flags
-
-    let stamp = if let Some(time_stamp) = state.time_stamp {
-        match time_stamp.duration_since(SystemTime::UNIX_EPOCH) {
-            Ok(duration) => {
-                let secs = duration.as_secs();
-                if secs <= u32::MAX as u64 {
-                    secs as u32
-                } else {
-                    0
-                }
-            }
-            Err(_) => 0,
-        }
-    } else {
-        0
-    };
 
-    state.put_long(stamp)?;
+    // Ready for the next input: ct_init and lm_init only clear what the last one left
+    // behind that the next could see
+    fn reset(&mut self, state: &mut GzipState, deflate_flags: &mut u16) -> io::Result<()> {
+        self.trees.ct_init(state.method);
+        self.deflate.lm_init(state, state.opts.level, deflate_flags)
+    }
+}
+
+pub fn zip (state: &mut GzipState) -> io::Result<Stats> {
+    // Initialize output count
+    state.outcnt = 0;
+    // Start this member's data CRC afresh; a state may compress many files (--threads)
+    state.data_crc.reset();
+    state.data_adler.reset();
 
     // Initialize compression (bi_init, ct_init, lm_init)
-    let mut trees = Trees::new();
-    let mut deflate = Deflate::new();
-    let mut attr = 0;
+    state.method = DEFLATED;
+    let mut compressor = state.compressor.take().unwrap_or_else(|| Compressor::new(state));
     let mut deflate_flags = 0;
-    trees.ct_init(&mut attr, state.method);
-    deflate.lm_init(state, state.level, &mut deflate_flags);
-
-    // Write deflate flags and OS identifier
-    state.put_byte(deflate_flags as u8)?; // Assuming `deflate_flags` fits in u8
-    state.put_byte(OS_CODE)?;
+    compressor.reset(state, &mut deflate_flags)?;
 
-    // Write original filename if `save_orig_name` is set
-    if state.save_orig_name {
-        let basename = state.gzip_base_name(&state.ifname).to_string();
-        for byte in basename.bytes() {
-            state.put_byte(byte)?;
-        }
-        state.put_byte(0)?; // Null-terminate the filename
+    // --format=raw writes the deflate data alone
+    match state.opts.format {
+        Format::Gzip => write_header(state, deflate_flags)?,
+        Format::Zlib => write_zlib_header(state)?,
+        Format::Raw => {}
     }
 
     // Record header bytes
-    state.header_bytes = state.outcnt;
+    state.header_bytes = state.bytes_out as usize + state.outcnt;
 
     // Perform deflation (compression)
-    deflate.deflate(&mut trees, state)?;
+    let Compressor { trees, deflate } = &mut *compressor;
+    deflate.deflate(trees, state)?;
+    state.compressor = Some(compressor);
 
     // Optionally check input size (similar to C code)
     #[cfg(not(any(target_os = "windows", target_os = "vms")))]
     {
         if state.ifile_size != -1 && state.bytes_in != state.ifile_size {
-            eprintln!(
+            warn!(
+                state,
                 "{}: {}: file size changed while zipping",
-                state.program_name, state.ifname
+                state.program_name, state.ifname.display()
             );
         }
     }
 
-    // Write the CRC and uncompressed size
-    let crc_value = state.crc16_digest;
-    let uncompressed_size = state.bytes_in.try_into().unwrap();
+    // Write the CRC and uncompressed size, or for zlib the big-endian Adler-32
+    match state.opts.format {
+        Format::Gzip => {
+            let crc_value = state.data_crc.finalize();
+            // ISIZE is the input size modulo 2^32
+            let uncompressed_size = (state.bytes_in & 0xffffffff) as u32;
+
+            state.put_long(crc_value)?;
+            state.put_long(uncompressed_size)?;
+
+            state.header_bytes += 8; // 2 * 4 bytes
+        }
+        Format::Zlib => {
+            for byte in state.data_adler.finalize().to_be_bytes() {
+                state.put_byte(byte)?;
+            }
+            state.header_bytes += 4;
+        }
+        Format::Raw => {}
+    }
+    state.flush_outbuf()?;
+
+    Ok(state.stats())
+}
 
-    state.put_long(crc_value)?;
-    state.put_long(uncompressed_size)?;
+// Write the zlib header: deflate with a 32K window, the FLEVEL zlib would give this
+// level, and the check bits that make the pair a multiple of 31. With a dictionary,
+// FDICT is set and the dictionary's Adler-32 follows
+fn write_zlib_header(state: &mut GzipState) -> io::Result<()> {
+    let cmf: u16 = 0x78;
+    let flevel: u16 = match state.opts.level {
+        ..=1 => 0,
+        2..=5 => 1,
+        6 => 2,
+        _ => 3,
+    };
+    let fdict: u16 = if state.opts.dictionary.is_some() { 0x20 } else { 0 };
+    let header = (cmf << 8) | (flevel << 6) | fdict;
+    let header = header + (31 - header % 31) % 31;
+    state.put_byte((header >> 8) as u8)?;
+    state.put_byte(header as u8)?;
+    if let Some(id) = state.opts.dictionary.as_ref().map(|dictionary| dictionary.id) {
+        for byte in id.to_be_bytes() {
+            state.put_byte(byte)?;
+        }
+    }
+    Ok(())
+}
+
+// Fill in BSIZE, the size of the whole block less one, once a BGZF block is complete
+pub(crate) fn finish_bgzf_block(block: &mut [u8]) -> io::Result<()> {
+    let bsize = u16::try_from(block.len() - 1)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "BGZF block larger than 64K"))?;
+    block[BGZF_BSIZE_OFFSET..BGZF_BSIZE_OFFSET + 2].copy_from_slice(&bsize.to_le_bytes());
+    Ok(())
+}
 
-    state.header_bytes += 8; // 2 * 4 bytes
+// Write the gzip header: magic, method, flags, time stamp, deflate flags, OS and
+// the original name if it is saved. A BGZF block has neither name nor time, and a BC
+// subfield whose BSIZE finish_bgzf_block fills in. --os-code replaces the OS of either
+fn write_header(state: &mut GzipState, deflate_flags: u16) -> io::Result<()> {
+    let header = if state.opts.bgzf {
+        GzipHeader {
+            method: DEFLATED as u8,
+            os: state.opts.os_code.unwrap_or(0xff),
+            extra: Some(vec![b'B', b'C', 2, 0, 0, 0]),
+            ..GzipHeader::default()
+        }
+    } else {
+        // A time stamp outside what MTIME can hold is left out, as it is when there is none
+        let mtime = state.time_stamp
+            .and_then(|time_stamp| time_stamp.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .and_then(|duration| u32::try_from(duration.as_secs()).ok())
+            .unwrap_or(0);
+        GzipHeader {
+            method: DEFLATED as u8,
+            mtime,
+            xfl: deflate_flags as u8,
+            os: state.opts.os_code.unwrap_or(OS_CODE),
+            name: state.save_orig_name.then(|| state.gzip_base_name(&state.ifname).to_vec()),
+            ..GzipHeader::default()
+        }
+    };
 
+    let mut bytes = Vec::new();
+    header.write(&mut bytes)?;
+    for byte in bytes {
+        state.put_byte(byte)?;
+    }
     Ok(())
-}
\ No newline at end of file
+}
@@ -4,6 +4,7 @@ passed=0
 decom=0
 total=0
 decomtotal=0
+failed=""
 
 compare_gzip_outputs() {
     # Capture all arguments passed to the function
@@ -42,6 +43,26 @@ compare_gzip_outputs() {
     ((total++))
 }
 
+compare_exit_codes() {
+    # Run both implementations with the same arguments and compare exit statuses
+    local args=("$@")
+
+    gzip "${args[@]}" > /dev/null 2>&1
+    local gzip_status=$?
+
+    cargo build > /dev/null 2>&1
+    ./target/debug/gzip "${args[@]}" > /dev/null 2>&1
+    local cargo_status=$?
+
+    if [ "$gzip_status" -eq "$cargo_status" ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. Expected exit code $gzip_status, got $cargo_status"
+    fi
+    ((total++))
+}
+
 compare_gzip_outputs_no_file() {
     # Capture all arguments passed to the function
     local args=("$@")
@@ -73,6 +94,18 @@ compare_gzip_outputs_no_file() {
     ((total++))
 }
 
+report_cases() {
+    # Pass a test of several cases if none of them was added to $failed, then clear it
+    if [ -z "$failed" ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. $1$failed"
+    fi
+    failed=""
+    ((total++))
+}
+
 echo "Testing no-arg output"
 compare_gzip_outputs_no_file " "
 
@@ -98,8 +131,158 @@ else
 fi
 ((total++))
 
-echo "Testing help menu"
-compare_gzip_outputs_no_file -h
+echo "Testing --help and the options it lists"
+# --help goes to stdout with status 0, and every long option it names is taken, with 1
+# as its operand if it needs one (or as a file name otherwise, which may then be missing)
+rm -rf target/test-help
+mkdir -p target/test-help
+./target/debug/gzip --help > target/test-help/help 2> target/test-help/err
+if [ $? -ne 0 ] || [ -s target/test-help/err ] || ! grep -q "^  -h, --help" target/test-help/help; then
+    failed="$failed --help"
+fi
+for option in $(grep -o -- '--[a-z][a-z-]*' target/test-help/help | sort -u); do
+    (cd target/test-help && ../debug/gzip "$option" 1 < /dev/null > /dev/null 2> err)
+    if grep -q "unrecognized option\|not supported\|requires an argument" target/test-help/err; then
+        failed="$failed $option"
+    fi
+done
+report_cases "Not taken:"
+rm -rf target/test-help
+
+echo "Testing abbreviated long options"
+# A long option may be cut to any prefix that names only it, and one that could be
+# several is refused with them all listed
+compare_gzip_outputs_no_file --stdo --fas tests/test-word.txt
+gzip -c tests/test-sentence.txt > target/test-abbreviated.gz
+compare_gzip_outputs_no_file --decomp --stdo target/test-abbreviated.gz
+rm -f target/test-abbreviated.gz
+compare_exit_codes --he
+compare_exit_codes --ver
+if ./target/debug/gzip --ver 2>&1 | grep -qx "gzip: option '--ver' is ambiguous; possibilities: '--verbose' '--verify' '--version'"; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. --ver was not refused as ambiguous"
+fi
+((total++))
+
+echo "Testing option conflicts and precedence"
+# Of each exclusive group at most one option may be given, whatever the order and
+# however it is spelled; within a last-one-wins group, and over GZIP, the last one counts
+rm -rf target/test-options
+mkdir -p target/test-options
+seq 1 20000 > target/test-options/input
+for case in "-l -o x:-l and -o" "-o x -l:-o and -l" "-t --output-dir x:-t and --output-dir" \
+    "--output x -c:-o and -c" "--to-stdout --output-dir=x:-c and --output-dir" "-o x --output-dir x:-o and --output-dir" \
+    "--bgzf --parallel:--bgzf and --parallel" "--list --test:-l and -t"; do
+    ./target/debug/gzip ${case%%:*} target/test-options/input > /dev/null 2> target/test-options/err
+    status=$?
+    if [ $status -ne 1 ] || [ "$(head -n 1 target/test-options/err)" != "gzip: options ${case#*:} are mutually exclusive" ] \
+        || [ ! -f target/test-options/input ]; then
+        failed="$failed '${case%%:*}'"
+    fi
+done
+for args in "-c -t" "-l -l" "-c -c" "-1 -3 -c" "-q -v -c" "-v -q -c"; do
+    if ! ./target/debug/gzip $args target/test-options/input > /dev/null 2> target/test-options/err \
+        && grep -q "mutually exclusive" target/test-options/err; then
+        failed="$failed '$args'"
+    fi
+done
+for case in "::-q -v:1" "::-v -q:0" "-q::-v:2" "-v::-q:0" "-v -q::-v:2" "-1::-3:=3" "-3::-1:=1" "-3 -1::-c:=1"; do
+    env_options=${case%%::*}
+    rest=${case#*::}
+    expected=${rest#*:}
+    GZIP="$env_options" ./target/debug/gzip -c ${rest%%:*} $( [ "${expected#=}" = "$expected" ] && echo -1) \
+        target/test-options/input > target/test-options/out 2> target/test-options/err
+    case "$expected" in
+        =*) ./target/debug/gzip -c -${expected#=} target/test-options/input | cmp -s - target/test-options/out ;;
+        *) [ "$(wc -l < target/test-options/err)" -eq "$expected" ] ;;
+    esac || failed="$failed 'GZIP=$env_options ${rest%%:*}'"
+done
+for env_options in "-c" "--stdout" "-t" "-S x" "input"; do
+    GZIP="$env_options" ./target/debug/gzip -1 -c target/test-options/input > /dev/null 2> target/test-options/err
+    if [ $? -ne 1 ] || ! grep -q "in GZIP environment variable" target/test-options/err; then
+        failed="$failed 'GZIP=$env_options'"
+    fi
+done
+report_cases "Wrong for"
+rm -rf target/test-options
+
+echo "Testing levels in clusters and --fast/--best"
+# A digit anywhere in a cluster sets the level, the last one winning; -d says it ignores them
+rm -rf target/test-clusters
+mkdir -p target/test-clusters
+seq 1 20000 > target/test-clusters/input
+for level in 1 3 9; do
+    ./target/debug/gzip -$level -c target/test-clusters/input > target/test-clusters/expected-$level
+done
+for case in "-v3c:3" "-3vc:3" "-c31:1" "-13 -c:3" "--fast -c:1" "-3 --fast -c:1" "::-3:--fast -c:1" "::--fast:-3 -c:3" \
+    "-c9:9" "-19c:9" "--best -c:9" "-1 --best -c:9" "::--best:-c:9"; do
+    env_options=""
+    if [ "${case#::}" != "$case" ]; then
+        case=${case#::}
+        env_options=${case%%:*}
+        case=${case#*:}
+    fi
+    GZIP="$env_options" ./target/debug/gzip ${case%:*} target/test-clusters/input > target/test-clusters/out 2> /dev/null
+    if ! cmp -s target/test-clusters/out target/test-clusters/expected-${case##*:}; then
+        failed="$failed '$env_options ${case%:*}'"
+    fi
+done
+./target/debug/gzip -1 target/test-clusters/input
+for case in "-9v:-9" "-d19v:-9" "--best -v:--best" "-v --best:--best"; do
+    ./target/debug/gzip -d -k -f ${case%:*} target/test-clusters/input.gz 2> target/test-clusters/err
+    status=$?
+    if [ $status -ne 2 ] || [ "$(head -n 1 target/test-clusters/err)" != "gzip: ${case#*:} has no effect when decompressing" ] \
+        || ! grep -q "^target/test-clusters/input.gz:.* created target/test-clusters/input$" target/test-clusters/err \
+        || ! cmp -s target/test-clusters/input <(seq 1 20000); then
+        failed="$failed '-d ${case%:*}'"
+    fi
+done
+GZIP=-9 ./target/debug/gzip -d -k -f target/test-clusters/input.gz 2> target/test-clusters/err
+if [ $? -ne 0 ] || grep -q "no effect" target/test-clusters/err; then
+    failed="$failed 'GZIP=-9 -d'"
+fi
+./target/debug/gzip -1x target/test-clusters/input 2> target/test-clusters/err
+if [ $? -ne 1 ] || [ "$(head -n 1 target/test-clusters/err)" != "gzip: unknown option -- 'x'" ]; then
+    failed="$failed '-1x'"
+fi
+report_cases "Wrong for"
+rm -rf target/test-clusters
+
+echo "Testing --level 0"
+# Stored blocks only: the size is the input's plus the header and trailer and 5 bytes
+# for each block (at least one per 32K window), and gzip reads it back
+rm -rf target/test-level0
+mkdir -p target/test-level0
+: > target/test-level0/empty
+echo "hello" > target/test-level0/small
+seq 1 100000 > target/test-level0/text
+head -c 300000 /dev/urandom > target/test-level0/random
+for file in empty small text random; do
+    input=target/test-level0/$file
+    for args in "--level 0" "--level=0" "-9 --level 0"; do
+        ./target/debug/gzip $args -n -c $input > $input.gz 2> /dev/null
+        actual_status=$?
+        size=$(wc -c < $input)
+        limit=$((size + 18 + 5 * (size / 32768 + 2)))
+        if [ "$actual_status" -ne 0 ] || [ "$(wc -c < $input.gz)" -lt $((size + 23)) ] || [ "$(wc -c < $input.gz)" -gt $limit ] \
+            || ! gzip -dc $input.gz | cmp -s - $input; then
+            failed="$failed '$args $file'"
+        fi
+    done
+done
+GZIP="--level 0" ./target/debug/gzip -1 -n -c target/test-level0/text 2> /dev/null | gzip -dc | cmp -s - target/test-level0/text \
+    && [ "$(GZIP="--level 0" ./target/debug/gzip -1 -c target/test-level0/text 2> /dev/null | wc -c)" -lt 300000 ] \
+    || failed="$failed 'GZIP=--level 0 with -1'"
+for value in 10 -1 x ""; do
+    ./target/debug/gzip --level "$value" -c target/test-level0/small > /dev/null 2> target/test-level0/err
+    if [ $? -ne 1 ] || ! grep -q "invalid --level value" target/test-level0/err; then
+        failed="$failed '--level $value'"
+    fi
+done
+report_cases "Wrong for"
+rm -rf target/test-level0
 
 echo "Testing empty bits operand"
 compare_gzip_outputs_no_file -b
@@ -108,7 +291,16 @@ echo "Testing incorrect bits operand"
 compare_gzip_outputs_no_file -b test
 
 echo "Testing bits operand"
-compare_gzip_outputs -k -1 -b 3 tests/test-word.txt
+# LZW codes are 9 to 16 bits wide, where gzip itself takes any number
+actual=$(./target/debug/gzip -b 3 -c tests/test-word.txt 2>&1 > /dev/null)
+if [ $? -eq 1 ] && [ "$actual" = "gzip: -b operand must be between 9 and 16
+Try \`gzip --help' for more information." ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Got '$actual'"
+fi
+((total++))
 
 echo "Testing compression level 2"
 compare_gzip_outputs -k -2 tests/test-word.txt
@@ -116,6 +308,39 @@ compare_gzip_outputs -k -2 tests/test-word.txt
 echo "Testing compression level 3"
 compare_gzip_outputs -k -3 tests/test-word.txt
 
+echo "Testing levels 2 and 3 match gzip byte for byte over many blocks"
+# Each level takes its own max_lazy, which limits the strings deflate_fast
+# inserts after a match
+seq 1 200000 > target/test-levels.txt
+for level in 2 3; do
+    if ! cmp -s <(gzip -c -$level target/test-levels.txt) <(./target/debug/gzip -c -$level target/test-levels.txt 2> /dev/null); then
+        failed="$failed -$level"
+    fi
+done
+report_cases "Output differs from gzip at"
+rm -f target/test-levels.txt
+
+echo "Testing the lazy matcher of levels 4 to 9"
+# Levels 4 and 5 hash as gzip does and match it byte for byte; from level 6, gzip's
+# default among them, strings are hashed on four bytes, so gzip only has to read it back
+seq 1 200000 > target/test-lazy.txt
+head -c 100000 /dev/urandom > target/test-lazy.bin
+(cat target/test-lazy.bin; seq 1 5000; cat target/test-lazy.bin) > target/test-lazy-far.bin
+for file in target/test-lazy.txt target/test-lazy-far.bin tests/test-paragraphs.txt; do
+    for level in 4 5; do
+        if ! cmp -s <(gzip -c -$level "$file") <(./target/debug/gzip -c -$level "$file" 2> /dev/null); then
+            failed="$failed -$level:$file"
+        fi
+    done
+    for level in 6 7 8 9 ""; do
+        if ! ./target/debug/gzip -c ${level:+-$level} "$file" 2> /dev/null | gzip -dc 2> /dev/null | cmp -s - "$file"; then
+            failed="$failed '-$level':$file"
+        fi
+    done
+done
+report_cases "Wrong for"
+rm -f target/test-lazy.txt target/test-lazy.bin target/test-lazy-far.bin
+
 echo "Testing ascii mode"
 compare_gzip_outputs -k -a -1 tests/test-word.txt
 
@@ -129,7 +354,7 @@ echo "Testing no name mode"
 compare_gzip_outputs -k -n -1 tests/test-word.txt
 
 echo "Testing large arg combinations"
-compare_gzip_outputs -k -a -b 3 -q -n -1 tests/test-word.txt
+compare_gzip_outputs -k -a -b 12 -q -n -1 tests/test-word.txt
 
 echo "Testing recursive"
 compare_gzip_outputs -r -k -1 tests/testing
@@ -149,6 +374,3173 @@ fi
 rm tests/testing/*.gz
 rm tests/*.gz
 
+echo "Testing trailing garbage exit code"
+echo "test" | gzip > tests/test-garbage.gz
+echo "garbage" >> tests/test-garbage.gz
+compare_exit_codes -dc tests/test-garbage.gz
+compare_exit_codes -q -dc tests/test-garbage.gz
+rm tests/test-garbage.gz
+
+echo "Testing multi-link exit code"
+ln tests/test-word.txt tests/test-link.txt
+compare_exit_codes -1 tests/test-link.txt
+compare_exit_codes -q -1 tests/test-link.txt
+rm tests/test-link.txt
+
+echo "Testing existing output is not overwritten"
+touch tests/test-word.txt.gz
+gzip -k -1 tests/test-word.txt < /dev/null > target/gzip_console_output.txt 2>&1
+gzip_status=$?
+./target/debug/gzip -k -1 tests/test-word.txt < /dev/null > target/cargo_console_output.txt 2>&1
+cargo_status=$?
+if [ "$gzip_status" -eq "$cargo_status" ] && diff -u target/gzip_console_output.txt target/cargo_console_output.txt \
+    && [ ! -s tests/test-word.txt.gz ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed."
+fi
+((total++))
+rm tests/test-word.txt.gz
+
+echo "Testing output hard-linked to the input"
+echo "test" > tests/test-same.txt
+ln tests/test-same.txt tests/test-same.txt.gz
+./target/debug/gzip -f -1 tests/test-same.txt > /dev/null 2>&1
+if [ $? -eq 1 ] && [ "$(cat tests/test-same.txt)" = "test" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Input was overwritten"
+fi
+((total++))
+rm -f tests/test-same.txt tests/test-same.txt.gz
+
+echo "Testing already suffixed input"
+echo "test" > tests/test-suffix.gz
+compare_gzip_outputs -k -1 tests/test-suffix.gz
+rm tests/test-suffix.gz
+
+echo "Testing recursive run twice does not double-compress"
+mkdir -p target/test-recursive
+echo "test" > target/test-recursive/a.txt
+./target/debug/gzip -r -1 target/test-recursive > /dev/null 2>&1
+./target/debug/gzip -r -1 target/test-recursive > /dev/null 2>&1
+if [ -f target/test-recursive/a.txt.gz ] && [ ! -f target/test-recursive/a.txt.gz.gz ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. File compressed twice"
+fi
+((total++))
+rm -rf target/test-recursive
+
+echo "Testing historical suffixes"
+gzip -c tests/test-word.txt > tests/test-tar.tgz
+compare_gzip_outputs_no_file -l tests/test-tar.tgz
+# One header for the run, however many files are listed
+gzip -c tests/test-sentence.txt > tests/test-sentence.txt.gz
+compare_gzip_outputs_no_file -l tests/test-tar.tgz tests/test-sentence.txt.gz
+rm -f tests/test-sentence.txt.gz
+./target/debug/gzip -d tests/test-tar.tgz > /dev/null 2>&1
+if [ -f tests/test-tar.tar ] && diff -u tests/test-word.txt tests/test-tar.tar; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. .tgz was not decompressed to .tar"
+fi
+((total++))
+rm -f tests/test-tar.tgz tests/test-tar.tar
+
+echo "Testing long names are shortened like gzip"
+mkdir -p target/test-long-gzip target/test-long-cargo
+for name in "$(printf 'a%.0s' $(seq 1 251)).txt" "$(printf 'b%.0s' $(seq 1 249)).tar"; do
+    echo "test" > "target/test-long-gzip/$name"
+    echo "test" > "target/test-long-cargo/$name"
+    gzip -k -1 "target/test-long-gzip/$name" > /dev/null 2>&1
+    ./target/debug/gzip -k -1 "target/test-long-cargo/$name" > /dev/null 2>&1
+done
+if diff -u <(ls target/test-long-gzip) <(ls target/test-long-cargo); then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed."
+fi
+((total++))
+rm -rf target/test-long-gzip target/test-long-cargo
+
+echo "Testing upper-case suffix"
+echo "test" > tests/test-upper.GZ
+compare_gzip_outputs -k -1 tests/test-upper.GZ
+rm tests/test-upper.GZ
+
+echo "Testing stored names cannot escape the output directory"
+{ printf '\x1f\x8b\x08\x08\0\0\0\0\0\x03../test-evil\0'; gzip -c -n tests/test-word.txt | tail -c +11; } > tests/test-evil.gz
+./target/debug/gzip -d -N tests/test-evil.gz > /dev/null 2>&1
+if [ ! -e test-evil ] && [ -f tests/test-evil ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Stored name escaped the output directory"
+fi
+((total++))
+rm -f test-evil tests/test-evil tests/test-evil.gz
+
+echo "Testing file names that are not UTF-8"
+name="tests/test-$(printf 'caf\xe9').txt"
+echo "test" > "$name"
+compare_gzip_outputs -k -1 "$name"
+./target/debug/gzip -k -1 "$name" > /dev/null 2>&1
+# -l prints the name's bytes as they are
+compare_gzip_outputs_no_file -l "$name.gz"
+mv "$name" target/test-latin1.txt
+./target/debug/gzip -d -N "$name.gz" > /dev/null 2>&1
+if [ -f "$name" ] && diff -u target/test-latin1.txt "$name"; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Name was not restored byte for byte"
+fi
+((total++))
+rm -f "$name" "$name.gz" target/test-latin1.txt
+
+echo "Testing recursive mode skips symbolic links and survives cycles"
+mkdir -p target/test-cycle/sub target/test-cycle-outside
+echo "test" > target/test-cycle/a.txt
+echo "test" > target/test-cycle-outside/b.txt
+ln -s .. target/test-cycle/sub/loop
+ln -s ../test-cycle-outside target/test-cycle/outside
+ln -s a.txt target/test-cycle/link.txt
+timeout 10 ./target/debug/gzip -r -1 target/test-cycle > /dev/null 2>&1
+status=$?
+if [ $status -eq 1 ] && [ -f target/test-cycle/a.txt.gz ] && [ -L target/test-cycle/link.txt ] \
+    && [ -f target/test-cycle-outside/b.txt ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Exit code $status"
+fi
+((total++))
+timeout 10 ./target/debug/gzip -r -f -1 target/test-cycle > /dev/null 2>&1
+if [ $? -ne 124 ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Cycle was followed forever"
+fi
+((total++))
+rm -rf target/test-cycle target/test-cycle-outside
+
+echo "Testing recursive mode is ordered and continues past unreadable files"
+mkdir -p target/test-order
+for f in c a b; do echo "test $f" > "target/test-order/$f.txt"; done
+expected_status=0
+expected_order="target/test-order/a.txt: target/test-order/b.txt: target/test-order/c.txt:"
+if [ "$(id -u)" -ne 0 ]; then
+    # Permissions are not enforced for root, so only check the error path as a regular user
+    chmod 000 target/test-order/b.txt
+    expected_status=1
+    expected_order="target/test-order/a.txt: target/test-order/c.txt:"
+fi
+order=$(./target/debug/gzip -r -v -1 --threads 1 target/test-order 2>&1 | grep -o "^target/test-order/[a-z]*\.txt:" | tr '\n' ' ')
+status=${PIPESTATUS[0]}
+if [ "$status" -eq "$expected_status" ] && [ "$order" = "$expected_order " ] \
+    && [ -f target/test-order/a.txt.gz ] && [ -f target/test-order/c.txt.gz ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Got order '$order' and exit code $status"
+fi
+((total++))
+chmod -R u+rw target/test-order
+rm -rf target/test-order
+
+echo "Testing recursive compression of 1000 files with --threads"
+mkdir -p target/test-threads-src
+for i in $(seq 1 1000); do seq 1 "$i" > "target/test-threads-src/$i.txt"; done
+for threads in 1 4; do
+    rm -rf target/test-threads
+    cp -r target/test-threads-src target/test-threads
+    start=$EPOCHREALTIME
+    ./target/debug/gzip -r -1 --threads "$threads" target/test-threads > /dev/null 2>&1
+    status=$?
+    awk -v start="$start" -v end="$EPOCHREALTIME" -v n="$threads" 'BEGIN { printf "  %d thread(s): %.2fs\n", n, end - start }'
+    ok=1
+    for i in $(seq 1 1000); do
+        if ! gzip -dc "target/test-threads/$i.txt.gz" 2> /dev/null | cmp -s - "target/test-threads-src/$i.txt"; then
+            ok=0
+            break
+        fi
+    done
+    if [ "$status" -eq 0 ] && [ "$ok" -eq 1 ] && [ -z "$(find target/test-threads -name '*.txt')" ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. Bad output with $threads thread(s)"
+    fi
+    ((total++))
+done
+rm -rf target/test-threads target/test-threads-src
+
+echo "Testing --verify and --bgzf on --threads workers"
+# Each worker starts from every option given, not just the ones a file needs to be found
+mkdir -p target/test-worker-options
+for threads in 1 3; do
+    for i in 1 2 3 4 5 6; do seq 1 "$((i * 100))" > "target/test-worker-options/$i.txt"; done
+    GZIP_TEST_CORRUPT_OUTPUT=30 ./target/debug/gzip -1 -r --verify --threads "$threads" target/test-worker-options 2> /dev/null
+    status=$?
+    if [ "$status" -eq 1 ] && [ "$(find target/test-worker-options -name '*.txt' | wc -l)" -eq 6 ] \
+        && [ -z "$(find target/test-worker-options -name '*.gz')" ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. --verify with $threads thread(s): status $status, inputs or outputs left wrong"
+    fi
+    ((total++))
+    ./target/debug/gzip -1 -r --bgzf --threads "$threads" target/test-worker-options 2> /dev/null
+    ok=1
+    for i in 1 2 3 4 5 6; do
+        file="target/test-worker-options/$i.txt.gz"
+        if [ "$(head -c 4 "$file" | od -An -tx1 | tr -d ' \n')" != 1f8b0804 ] \
+            || [ "$(tail -c 28 "$file" | od -An -tx1 | tr -d ' \n')" != 1f8b08040000000000ff0600424302001b0003000000000000000000 ] \
+            || ! gzip -dc "$file" | cmp -s - <(seq 1 "$((i * 100))"); then
+            ok=0
+        fi
+    done
+    if [ "$ok" -eq 1 ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. --bgzf with $threads thread(s) did not write BGZF"
+    fi
+    ((total++))
+    rm -f target/test-worker-options/*
+done
+rm -rf target/test-worker-options
+
+echo "Testing --parallel compresses a file as independent members"
+seq 1 200000 > target/test-parallel.txt
+for threads in 1 4; do
+    start=$EPOCHREALTIME
+    ./target/debug/gzip -c -1 --parallel=16384 --threads "$threads" target/test-parallel.txt > target/test-parallel.txt.gz 2> /dev/null
+    awk -v start="$start" -v end="$EPOCHREALTIME" -v n="$threads" 'BEGIN { printf "  %d thread(s): %.2fs\n", n, end - start }'
+    if gzip -dc target/test-parallel.txt.gz 2> /dev/null | cmp -s - target/test-parallel.txt; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. System gzip could not read the members back"
+    fi
+    ((total++))
+done
+if ./target/debug/gzip -1 --parallel=16384 < target/test-parallel.txt 2> /dev/null | gzip -dc 2> /dev/null \
+    | cmp -s - target/test-parallel.txt; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. --parallel from stdin"
+fi
+((total++))
+rm -f target/test-parallel.txt target/test-parallel.txt.gz
+
+echo "Testing buffered output matches gzip byte for byte"
+for file in tests/test-word.txt tests/test-sentence.txt; do
+    if cmp -s <(gzip -c -1 "$file") <(./target/debug/gzip -c -1 "$file" 2> /dev/null); then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. $file differs on stdout"
+    fi
+    ((total++))
+done
+
+echo "Testing buffered output throughput"
+seq 1 400000 > target/test-throughput.txt
+start=$EPOCHREALTIME
+./target/debug/gzip -c -1 --parallel=16384 --threads 1 target/test-throughput.txt > target/test-throughput.txt.gz 2> /dev/null
+awk -v start="$start" -v end="$EPOCHREALTIME" -v size="$(stat -c %s target/test-throughput.txt)" \
+    'BEGIN { printf "  %.1f MB/s\n", size / (end - start) / 1000000 }'
+if gzip -dc target/test-throughput.txt.gz 2> /dev/null | cmp -s - target/test-throughput.txt; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed."
+fi
+((total++))
+rm -f target/test-throughput.txt target/test-throughput.txt.gz
+
+echo "Testing the trailer CRC-32"
+printf 123456789 > target/test-crc.txt
+crc=$(./target/debug/gzip -c -1 target/test-crc.txt 2> /dev/null | tail -c 8 | head -c 4 | od -An -tx4 | tr -d ' ')
+if [ "$crc" = "cbf43926" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Expected cbf43926, got $crc"
+fi
+((total++))
+head -c 1048576 /dev/urandom > target/test-crc.txt
+if ./target/debug/gzip -c -1 --parallel=16384 target/test-crc.txt 2> /dev/null | gzip -dc 2> /dev/null \
+    | cmp -s - target/test-crc.txt; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. System gzip rejected the CRC of random data"
+fi
+((total++))
+rm -f target/test-crc.txt
+
+echo "Testing per-member CRCs of concatenated output"
+printf 'first member\n' > target/test-member1.txt
+seq 1 5000 > target/test-member2.txt
+if ./target/debug/gzip -c -1 target/test-member1.txt target/test-member2.txt 2> /dev/null | gzip -t 2> /dev/null; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. A member CRC carried state from the previous member"
+fi
+((total++))
+rm -f target/test-member1.txt target/test-member2.txt
+
+echo "Testing Huffman tree output round-trips through gzip"
+seq 1 3000 > target/test-trees-seq.txt
+head -c 20000 /dev/urandom > target/test-trees-rand.bin
+for level in 1 2; do
+    for file in tests/test-paragraphs.txt target/test-trees-seq.txt target/test-trees-rand.bin; do
+        if ./target/debug/gzip -c -$level "$file" 2> /dev/null | gzip -dc 2> /dev/null | cmp -s - "$file"; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. -$level $file did not round-trip"
+        fi
+        ((total++))
+    done
+done
+rm -f target/test-trees-seq.txt target/test-trees-rand.bin
+
+echo "Testing blocks flushed when the tally buffers fill"
+for size in 32766 32767 32768 100000; do
+    head -c $size /dev/urandom > target/test-tally.bin
+    if ./target/debug/gzip -c -1 target/test-tally.bin 2> /dev/null | gzip -dc 2> /dev/null | cmp -s - target/test-tally.bin; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. $size random bytes did not round-trip"
+    fi
+    ((total++))
+done
+seq 1 100000 > target/test-tally.bin
+for level in 1 2 3; do
+    if ./target/debug/gzip -c -$level target/test-tally.bin 2> /dev/null | gzip -dc 2> /dev/null | cmp -s - target/test-tally.bin; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. -$level on a match-heavy input did not round-trip"
+    fi
+    ((total++))
+done
+rm -f target/test-tally.bin
+
+echo "Testing longest_match on crafted windows"
+head -c 8000 /dev/urandom > target/test-match-block.bin
+cat target/test-match-block.bin target/test-match-block.bin > target/test-match.bin
+size=$(./target/debug/gzip -c -1 target/test-match.bin 2> /dev/null | wc -c)
+if [ "$size" -lt 9000 ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. A repeated block compressed to $size bytes"
+fi
+((total++))
+head -c 30000 /dev/urandom > target/test-match-block.bin
+cat target/test-match-block.bin target/test-match-block.bin target/test-match-block.bin > target/test-match.bin
+yes abc | head -c 100000 > target/test-match-abc.txt
+for level in 1 2 3; do
+    for file in target/test-match.bin target/test-match-abc.txt; do
+        if ./target/debug/gzip -c -$level "$file" 2> /dev/null | gzip -dc 2> /dev/null | cmp -s - "$file"; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. -$level $file did not round-trip"
+        fi
+        ((total++))
+    done
+done
+rm -f target/test-match-block.bin target/test-match.bin target/test-match-abc.txt
+
+echo "Testing inputs around the window boundaries"
+for size in 32767 32768 32769 65273 65274 65275 65536; do
+    head -c $size /dev/urandom > target/test-window.bin
+    seq 1 20000 | head -c $size > target/test-window.txt
+    for file in target/test-window.bin target/test-window.txt; do
+        if ./target/debug/gzip -c -1 "$file" 2> /dev/null | gzip -dc 2> /dev/null | cmp -s - "$file" \
+            && cat "$file" | ./target/debug/gzip -1 2> /dev/null | gzip -dc 2> /dev/null | cmp -s - "$file"; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. $size bytes of $file did not round-trip"
+        fi
+        ((total++))
+    done
+done
+head -c 20000 /dev/urandom > target/test-window.bin
+cat target/test-window.bin target/test-window.bin > target/test-window.txt
+size=$(./target/debug/gzip -c -1 target/test-window.txt 2> /dev/null | wc -c)
+if [ "$size" -lt 22000 ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. A block repeated 20000 bytes later compressed to $size bytes"
+fi
+((total++))
+rm -f target/test-window.bin target/test-window.txt
+
+echo "Testing hash insertion after short and long matches"
+head -c 5000 /dev/urandom > target/test-insert.bin
+(seq 1 20000; cat target/test-insert.bin target/test-insert.bin; seq 1 20000) > target/test-insert.txt
+for level in 1 2 3; do
+    if ./target/debug/gzip -c -$level target/test-insert.txt 2> /dev/null | gzip -dc 2> /dev/null | cmp -s - target/test-insert.txt; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. -$level did not round-trip"
+    fi
+    ((total++))
+done
+rm -f target/test-insert.bin target/test-insert.txt
+
+echo "Testing files shorter than a minimum match"
+for size in 0 1 2 3 4; do
+    yes a | tr -d '\n' | head -c $size > target/test-tiny.txt
+    for level in 1 2 3; do
+        if ./target/debug/gzip -c -$level target/test-tiny.txt 2> /dev/null | gzip -dc 2> /dev/null | cmp -s - target/test-tiny.txt; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. A $size byte file at -$level did not round-trip"
+        fi
+        ((total++))
+    done
+done
+rm -f target/test-tiny.txt
+
+echo "Testing incompressible data is stored"
+head -c 1048576 /dev/urandom > target/test-stored.bin
+./target/debug/gzip -c -1 target/test-stored.bin > target/test-stored.bin.gz 2> /dev/null
+size=$(stat -c %s target/test-stored.bin.gz)
+if [ "$size" -le $((1048576 + 1048576 / 1000 + 18)) ] \
+    && gzip -dc target/test-stored.bin.gz 2> /dev/null | cmp -s - target/test-stored.bin; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. 1 MiB of random data compressed to $size bytes"
+fi
+((total++))
+rm -f target/test-stored.bin target/test-stored.bin.gz
+
+echo "Testing blocks whose Huffman codes overflow MAX_BITS"
+for sample in 1 2 3; do
+    head -c 5000 /dev/urandom > target/test-overflow.bin
+    (seq 1 20000; cat target/test-overflow.bin target/test-overflow.bin; seq 1 20000) > target/test-overflow.txt
+    for level in 1 2 3; do
+        if ./target/debug/gzip -c -$level target/test-overflow.txt 2> /dev/null | gzip -dc 2> /dev/null \
+            | cmp -s - target/test-overflow.txt; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. -$level on sample $sample did not round-trip"
+        fi
+        ((total++))
+    done
+done
+rm -f target/test-overflow.bin target/test-overflow.txt
+
+echo "Testing a Fibonacci literal tree that overflows MAX_BITS matches gzip"
+# Literals with counts 1, 2, 3, 5, ... 610 nest one inside the next in the Huffman
+# tree, 18 deep with the end of block. They are spread through a de Bruijn sequence
+# of 30 other bytes so that no string of 3 repeats, every byte is a literal, and the
+# whole block is one tree whose lengths must be cut back to 15 as trees.c cuts them
+if command -v python3 > /dev/null; then
+    python3 - > target/test-fibonacci.bin <<'PYTHON'
+import heapq, random, sys
+k, a, filler = 30, [0] * 90, []
+def de_bruijn(t, p):
+    if t > 3:
+        if 3 % p == 0:
+            filler.extend(0x40 + s for s in a[1:p + 1])
+    else:
+        a[t] = a[t - p]
+        de_bruijn(t + 1, p)
+        for j in range(a[t - p] + 1, k):
+            a[t] = j
+            de_bruijn(t + 1, t)
+de_bruijn(1, 1)
+fib = [1, 2]
+while len(fib) < 14:
+    fib.append(fib[-1] + fib[-2])
+# Each literal goes between filler bytes where it makes no string of 3 seen before
+rng, taken, seen, at = random.Random(574), set(), set(), {}
+for c, count in zip(range(0x61, 0x61 + len(fib)), fib):
+    while count:
+        i = rng.randrange(2, len(filler) - 2)
+        grams = {(c, j, *filler[i + j - 2:i + j]) for j in range(3)}
+        if taken & {i - 1, i, i + 1} or seen & grams:
+            continue
+        taken.add(i)
+        seen |= grams
+        at[i] = c
+        count -= 1
+data = bytearray()
+for i, b in enumerate(filler):
+    data.extend([at[i], b] if i in at else [b])
+grams = [bytes(data[i:i + 3]) for i in range(len(data) - 2)]
+# Huffman depth with gzip's tie break, the shallower node first
+heap = [(data.count(b), 0) for b in set(data)] + [(1, 0)]
+heapq.heapify(heap)
+while len(heap) > 1:
+    (f1, d1), (f2, d2) = heapq.heappop(heap), heapq.heappop(heap)
+    heapq.heappush(heap, (f1 + f2, max(d1, d2) + 1))
+if len(set(grams)) != len(grams) or heap[0][1] <= 15:
+    sys.exit(1)
+sys.stdout.buffer.write(data)
+PYTHON
+    if [ $? -ne 0 ]; then
+        failed="$failed generating-the-input"
+    fi
+    for level in 1 4; do
+        if ! cmp -s <(gzip -c -n -$level target/test-fibonacci.bin) <(./target/debug/gzip -c -n -$level target/test-fibonacci.bin 2> /dev/null); then
+            failed="$failed -$level"
+        fi
+    done
+    report_cases "Output differed from gzip at:"
+    rm -f target/test-fibonacci.bin
+fi
+
+echo "Testing dynamic tree headers match gzip byte for byte"
+# Longest match code in use, farthest distance code in use, and a small alphabet
+# whose literal tree ends in a long run of zero lengths
+head -c 100000 /dev/zero > target/test-trees-zero.bin
+head -c 30000 /dev/urandom > target/test-trees-far.bin
+(cat target/test-trees-far.bin; seq 1 1000; cat target/test-trees-far.bin) > target/test-trees-far.txt
+yes ab | head -c 5000 > target/test-trees-ab.txt
+for file in target/test-trees-zero.bin target/test-trees-far.txt target/test-trees-ab.txt tests/test-paragraphs.txt; do
+    if cmp -s <(gzip -c -1 "$file") <(./target/debug/gzip -c -1 "$file" 2> /dev/null); then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. $file differs from gzip -1"
+    fi
+    ((total++))
+done
+rm -f target/test-trees-zero.bin target/test-trees-far.bin target/test-trees-far.txt target/test-trees-ab.txt
+
+echo "Testing static trees are shared across files in one run"
+# Short inputs are sent with the static trees; every stream after the first
+# reuses the tables built for the first
+for i in 1 2 3 4 5; do
+    echo "file $i" > target/test-static-$i.txt
+done
+if cmp -s <(gzip -c -1 target/test-static-*.txt) <(./target/debug/gzip -c -1 target/test-static-*.txt 2> /dev/null); then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Output for several small files differs from gzip -1"
+fi
+((total++))
+rm -f target/test-static-*.txt
+
+echo "Testing files and stdin mixed in one run are compressed independently"
+# Each member must match compressing that input alone: stdin after a named file
+# gets no FNAME, and the file after stdin gets its name back. The stdin member's
+# MTIME (4 bytes after its magic, method and flags) is skipped.
+printf 'alpha\n' > target/test-mixed-a.txt
+printf 'bravo\n' > target/test-mixed-b.txt
+printf 'charlie\n' > target/test-mixed-c.txt
+(gzip -c -1 target/test-mixed-a.txt; gzip -c -1 < target/test-mixed-b.txt; gzip -c -1 target/test-mixed-c.txt) > target/test-mixed-expected.gz
+./target/debug/gzip -c -1 target/test-mixed-a.txt - target/test-mixed-c.txt < target/test-mixed-b.txt > target/test-mixed-actual.gz 2> /dev/null
+first=$(gzip -c -1 target/test-mixed-a.txt | wc -c)
+if cmp -s <(head -c $((first + 4)) target/test-mixed-expected.gz) <(head -c $((first + 4)) target/test-mixed-actual.gz) \
+    && cmp -s <(tail -c +$((first + 9)) target/test-mixed-expected.gz) <(tail -c +$((first + 9)) target/test-mixed-actual.gz); then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Members of a mixed run differ from compressing each input alone"
+fi
+((total++))
+rm -f target/test-mixed-*.txt target/test-mixed-expected.gz target/test-mixed-actual.gz
+
+echo "Testing per-file results decide the exit status"
+# A missing file is an error and a directory without -r a warning; the good file is
+# still compressed, and the error wins over the warning
+mkdir -p target/test-results-dir
+printf 'results\n' > target/test-results.txt
+./target/debug/gzip -1 -c target/test-results-dir target/test-results-missing target/test-results.txt > target/test-results-actual.gz 2> /dev/null
+actual_status=$?
+gzip -1 -c target/test-results-dir target/test-results-missing target/test-results.txt > target/test-results-expected.gz 2> /dev/null
+expected_status=$?
+if [ "$actual_status" -eq "$expected_status" ] && cmp -s target/test-results-actual.gz target/test-results-expected.gz; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Exit status $actual_status, expected $expected_status"
+fi
+((total++))
+./target/debug/gzip -1 -c target/test-results-dir > /dev/null 2>&1
+actual_status=$?
+if [ "$actual_status" -eq 2 ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. A skipped directory exited with $actual_status, expected 2"
+fi
+((total++))
+
+echo "Testing -v reports on stderr only"
+if cmp -s <(./target/debug/gzip -v -1 -c target/test-results.txt 2> /dev/null) <(gzip -1 -c target/test-results.txt); then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. -v -c wrote more than the compressed data to stdout"
+fi
+((total++))
+rm -rf target/test-results-dir target/test-results.txt target/test-results-actual.gz target/test-results-expected.gz
+
+echo "Testing -t gives a verdict per file and keeps going"
+# Members that fail before any data is decoded: each one is reported, the run carries on,
+# nothing reaches stdout, and the exit status is 1 as with gzip
+mkdir -p target/test-verdicts
+printf 'not gzip data\n' > target/test-verdicts/plain.gz
+printf '\037\213\011\000\000\000\000\000\000\003' > target/test-verdicts/method.gz
+: > target/test-verdicts/empty.gz
+for option in -t --test; do
+    stdout=$(./target/debug/gzip $option target/test-verdicts/plain.gz target/test-verdicts/method.gz target/test-verdicts/empty.gz 2> target/test-verdicts/stderr.txt)
+    actual_status=$?
+    gzip $option target/test-verdicts/plain.gz target/test-verdicts/method.gz target/test-verdicts/empty.gz > /dev/null 2>&1
+    expected_status=$?
+    reported=$(grep -c "target/test-verdicts/" target/test-verdicts/stderr.txt)
+    if [ "$actual_status" -eq "$expected_status" ] && [ -z "$stdout" ] && [ "$reported" -eq 3 ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. $option: status $actual_status (expected $expected_status), $reported of 3 files reported"
+    fi
+    ((total++))
+done
+rm -rf target/test-verdicts
+
+echo "Testing --verify keeps the input when the output is damaged"
+# GZIP_TEST_CORRUPT_OUTPUT (debug builds only) flips a byte of the .gz before it is checked.
+# The failure is reported once, by the file, and not again by the check
+for keep in "" -k; do
+    printf 'verify me\n' > target/test-verify.txt
+    GZIP_TEST_CORRUPT_OUTPUT=0 ./target/debug/gzip -1 $keep --verify target/test-verify.txt 2> target/test-verify.err
+    actual_status=$?
+    if [ "$actual_status" -eq 1 ] && [ "$(cat target/test-verify.txt 2> /dev/null)" = "verify me" ] && [ ! -e target/test-verify.txt.gz ] \
+        && [ "$(cat target/test-verify.err)" = "gzip: target/test-verify.txt.gz: verification failed: not in gzip format" ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. --verify $keep: status $actual_status, input or output left wrong"
+    fi
+    ((total++))
+    rm -f target/test-verify.txt target/test-verify.txt.gz target/test-verify.err
+done
+
+echo "Testing --verify is ignored with -c"
+printf 'verify me\n' > target/test-verify.txt
+if cmp -s <(./target/debug/gzip -1 -c --verify target/test-verify.txt 2> /dev/null) <(gzip -1 -c target/test-verify.txt); then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. --verify changed the -c output"
+fi
+((total++))
+rm -f target/test-verify.txt
+
+echo "Testing --max-size and --max-ratio reject bad values"
+for option in "--max-size=0" "--max-size=lots" "--max-ratio=-3" "--max-ratio"; do
+    ./target/debug/gzip -d $option < /dev/null > /dev/null 2>&1
+    actual_status=$?
+    if [ "$actual_status" -eq 1 ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. $option exited with $actual_status, expected 1"
+    fi
+    ((total++))
+done
+
+echo "Testing expansion limits stop a gzip bomb"
+# 1 MiB of zeros compresses to about 1 KiB: a ratio of about 1000
+head -c 1048576 /dev/zero | gzip -9 > target/test-bomb.gz
+for option in "--max-size=100000" "--max-ratio=10"; do
+    cp target/test-bomb.gz target/test-bomb-copy.gz
+    ./target/debug/gzip -d $option target/test-bomb-copy.gz > /dev/null 2> target/test-bomb-stderr.txt
+    actual_status=$?
+    if [ "$actual_status" -eq 1 ] && grep -q "expansion limit exceeded" target/test-bomb-stderr.txt \
+        && [ -e target/test-bomb-copy.gz ] && [ ! -e target/test-bomb-copy ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. $option did not stop the bomb cleanly (status $actual_status)"
+    fi
+    ((total++))
+    rm -f target/test-bomb-copy.gz target/test-bomb-copy target/test-bomb-stderr.txt
+done
+rm -f target/test-bomb.gz
+
+echo "Testing deeply nested decoding tables"
+# Fibonacci symbol counts give the longest codes deflate allows, so the
+# literal table needs sub-tables that a recursive teardown would walk
+awk 'BEGIN { a = 1; b = 1; for (i = 0; i < 24; i++) { for (j = 0; j < a; j++) printf "%c", 65 + i; t = a + b; a = b; b = t } }' > target/test-deep-orig
+gzip -9 -c target/test-deep-orig > target/test-deep.gz
+timeout 10 ./target/debug/gzip -d target/test-deep.gz > /dev/null 2>&1
+actual_status=$?
+if [ "$actual_status" -eq 0 ] && cmp -s target/test-deep-orig target/test-deep; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Deep codes did not decode (status $actual_status)"
+fi
+((total++))
+rm -f target/test-deep-orig target/test-deep target/test-deep.gz
+
+echo "Testing matches that reach back before the start of the data"
+# Fixed-code deflate streams: a match as the very first symbol, and a
+# distance of 3 after only two literals
+for stream in '\x03\x02\x00' '\x4b\x4c\x02\x22\x00'; do
+    { printf '\x1f\x8b\x08\0\0\0\0\0\0\x03'; printf "$stream"; printf '\0\0\0\0\0\0\0\0'; } > target/test-far.gz
+    timeout 10 ./target/debug/gzip -d target/test-far.gz > /dev/null 2> target/test-far-stderr.txt
+    actual_status=$?
+    if [ "$actual_status" -eq 1 ] && grep -q "invalid compressed data--distance too far back" target/test-far-stderr.txt \
+        && [ -e target/test-far.gz ] && [ ! -e target/test-far ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. A match before the start of the data was not rejected (status $actual_status)"
+    fi
+    ((total++))
+    rm -f target/test-far.gz target/test-far target/test-far-stderr.txt
+done
+# Repeats 20000 bytes apart straddle the 32 KiB window wrap and must still decode
+head -c 20000 /dev/urandom > target/test-wrap-part
+cat target/test-wrap-part target/test-wrap-part target/test-wrap-part > target/test-wrap-orig
+gzip -9 -c target/test-wrap-orig > target/test-wrap.gz
+timeout 10 ./target/debug/gzip -d target/test-wrap.gz > /dev/null 2>&1
+actual_status=$?
+if [ "$actual_status" -eq 0 ] && cmp -s target/test-wrap-orig target/test-wrap; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Matches across the window wrap did not decode (status $actual_status)"
+fi
+((total++))
+rm -f target/test-wrap-part target/test-wrap-orig target/test-wrap target/test-wrap.gz
+
+echo "Testing incomplete and oversubscribed code sets"
+# Raw deflate data and trailer, with zlib's verdict and the reason for a
+# rejection. A single one-bit code is the only incomplete set RFC 1951 allows;
+# the last three streams are from zlib's infcover.c (invalid code lengths,
+# literal/length and distance sets)
+for vector in \
+    "accept \x0d\xe0\x01\x04\x00\x00\x00\x80\x20\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x08\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\xf8\x2c\x45\xe5\x98\xad\x04\x00\x00\x00" \
+    "accept \x05\xe0\x01\x04\x00\x00\x00\x00\x10\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x0c\x00\x00\x00\x00\x00\x00\x00\x00" \
+    "incomplete \x0d\xe1\x01\x04\x00\x00\x00\x80\x20\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x08\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\xf8\x37\x01\x00\x00\x00\x00\x00\x00\x00\x00" \
+    "incomplete \x04\x00\xfe\xff\x00\x00\x00\x00\x00\x00\x00\x00" \
+    "oversubscribed \x04\x80\x49\x92\x24\x49\x92\x24\x71\xff\xff\x93\x11\x00\x00\x00\x00\x00\x00\x00\x00\x00" \
+    "incomplete \x04\x80\x49\x92\x24\x49\x92\x24\x0f\xb4\xff\xff\xc3\x84\x00\x00\x00\x00\x00\x00\x00\x00"; do
+    verdict=${vector%% *}
+    { printf '\x1f\x8b\x08\0\0\0\0\0\0\x03'; printf "${vector#* }"; } > target/test-codes.gz
+    timeout 10 ./target/debug/gzip -d target/test-codes.gz > /dev/null 2> target/test-codes-stderr.txt
+    actual_status=$?
+    if [ "$verdict" = accept ] && [ "$actual_status" -eq 0 ]; then
+        echo "Test passed."
+        ((passed++))
+    elif [ "$verdict" != accept ] && [ "$actual_status" -eq 1 ] \
+        && grep -q "invalid compressed data--$verdict code set" target/test-codes-stderr.txt; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. Expected zlib's verdict ($verdict), got status $actual_status"
+    fi
+    ((total++))
+    rm -f target/test-codes.gz target/test-codes target/test-codes-stderr.txt
+done
+
+echo "Testing --inflate-bits"
+for option in "--inflate-bits=0,6" "--inflate-bits=9,17" "--inflate-bits=9" "--inflate-bits"; do
+    ./target/debug/gzip -d $option < /dev/null > /dev/null 2>&1
+    actual_status=$?
+    if [ "$actual_status" -eq 1 ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. $option exited with $actual_status, expected 1"
+    fi
+    ((total++))
+done
+# The smallest tables walk a sub-table for nearly every bit, the largest never do;
+# both must decode the same data, and the timings show the speed difference
+for i in 1 2 3 4 5 6 7 8; do cat src/*.rs; done > target/test-bits-orig
+gzip -9 -c target/test-bits-orig > target/test-bits.gz
+for bits in 1,1 16,16; do
+    start=$(date +%s%N)
+    timeout 10 ./target/debug/gzip -d -k -f --inflate-bits=$bits target/test-bits.gz > /dev/null 2>&1
+    actual_status=$?
+    echo "--inflate-bits=$bits took $(( ($(date +%s%N) - start) / 1000000 )) ms"
+    if [ "$actual_status" -eq 0 ] && cmp -s target/test-bits-orig target/test-bits; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. --inflate-bits=$bits did not decode the file (status $actual_status)"
+    fi
+    ((total++))
+done
+rm -f target/test-bits-orig target/test-bits target/test-bits.gz
+
+echo "Testing stored blocks at the end of the input"
+# gzip stores random data as it is; cut the file after the last stored byte
+# (losing the trailer) and in the middle of the data
+head -c 100000 /dev/urandom > target/test-stored-orig
+gzip -1 -c target/test-stored-orig > target/test-stored.gz
+size=$(stat -c %s target/test-stored.gz)
+timeout 10 ./target/debug/gzip -d -k target/test-stored.gz > /dev/null 2>&1
+actual_status=$?
+if [ "$actual_status" -eq 0 ] && cmp -s target/test-stored-orig target/test-stored; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Stored blocks did not decode (status $actual_status)"
+fi
+((total++))
+for cut in $((size - 8)) 50000; do
+    head -c $cut target/test-stored.gz > target/test-stored-cut.gz
+    timeout 10 ./target/debug/gzip -d target/test-stored-cut.gz > /dev/null 2> target/test-stored-stderr.txt
+    actual_status=$?
+    if [ "$actual_status" -eq 1 ] && grep -q "unexpected end of file" target/test-stored-stderr.txt; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. Input cut at $cut of $size bytes was not reported (status $actual_status)"
+    fi
+    ((total++))
+    rm -f target/test-stored-cut.gz target/test-stored-cut target/test-stored-stderr.txt
+done
+# A stored length whose complement does not match
+{ printf '\x1f\x8b\x08\0\0\0\0\0\0\x03\x01\x05\x00\xfa\xfehello'; printf '\x86\xa6\x10\x36\x05\0\0\0'; } > target/test-stored-cut.gz
+timeout 10 ./target/debug/gzip -d target/test-stored-cut.gz > /dev/null 2>&1
+actual_status=$?
+if [ "$actual_status" -eq 1 ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. A bad stored length complement was accepted (status $actual_status)"
+fi
+((total++))
+rm -f target/test-stored-orig target/test-stored target/test-stored.gz target/test-stored-cut.gz target/test-stored-cut
+
+echo "Testing many fixed Huffman blocks"
+# Each 8-byte block is a non-final fixed block holding six 9-bit literals, so
+# the blocks stay byte aligned and can be repeated; an empty final block ends
+# the stream, and gzip's own output supplies the trailer
+for i in $(seq 2000); do printf '\xe6\xe9\xf8\xe5\xe4\xa1'; done > target/test-fixed-orig
+{
+    printf '\x1f\x8b\x08\0\0\0\0\0\0\x03'
+    for i in $(seq 2000); do printf '\x7a\xf6\xf2\xc7\xd3\x27\x0b\x01'; done
+    printf '\x03\x00'
+    gzip -c target/test-fixed-orig | tail -c 8
+} > target/test-fixed.gz
+start=$(date +%s%N)
+timeout 10 ./target/debug/gzip -d target/test-fixed.gz > /dev/null 2>&1
+actual_status=$?
+echo "2000 fixed blocks took $(( ($(date +%s%N) - start) / 1000000 )) ms"
+if [ "$actual_status" -eq 0 ] && cmp -s target/test-fixed-orig target/test-fixed; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Fixed blocks did not decode (status $actual_status)"
+fi
+((total++))
+rm -f target/test-fixed-orig target/test-fixed target/test-fixed.gz
+
+echo "Testing decompression throughput"
+# Runs of zeros are matches that overlap their own output, the repeated
+# sources are matches that do not; the timing is for comparing changes
+for i in 1 2 3 4 5 6 7 8 9 10; do cat src/*.rs; head -c 100000 /dev/zero; done > target/test-speed-orig
+gzip -6 -c target/test-speed-orig > target/test-speed.gz
+start=$(date +%s%N)
+timeout 20 ./target/debug/gzip -d -k target/test-speed.gz > /dev/null 2>&1
+actual_status=$?
+echo "$(stat -c %s target/test-speed-orig) bytes took $(( ($(date +%s%N) - start) / 1000000 )) ms"
+if [ "$actual_status" -eq 0 ] && cmp -s target/test-speed-orig target/test-speed; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Overlapping and plain matches did not decode (status $actual_status)"
+fi
+((total++))
+rm -f target/test-speed-orig target/test-speed target/test-speed.gz
+
+echo "Testing files larger than the window"
+# 200 KB of text wraps the 32 KB window several times; the CRC and length in the
+# trailer only match if they were taken over the bytes that were written
+cat src/*.rs | head -c 200000 > target/test-large-orig
+gzip -9 -c target/test-large-orig > target/test-large.gz
+timeout 10 ./target/debug/gzip -d -k target/test-large.gz > /dev/null 2>&1
+actual_status=$?
+timeout 10 ./target/debug/gzip -t target/test-large.gz > /dev/null 2>&1
+test_status=$?
+if [ "$actual_status" -eq 0 ] && [ "$test_status" -eq 0 ] && cmp -s target/test-large-orig target/test-large; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. A file larger than 32 KB did not decode (status $actual_status, -t status $test_status)"
+fi
+((total++))
+rm -f target/test-large
+# Flip a bit of the stored CRC
+size=$(stat -c %s target/test-large.gz)
+byte=$(od -An -tu1 -j $((size - 8)) -N1 target/test-large.gz | tr -d ' ')
+printf "$(printf '\\%03o' $((byte ^ 1)))" | dd of=target/test-large.gz bs=1 seek=$((size - 8)) conv=notrunc 2>/dev/null
+timeout 10 ./target/debug/gzip -t target/test-large.gz > /dev/null 2>&1
+if [ $? -ne 0 ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. A wrong CRC in the trailer was accepted"
+fi
+((total++))
+rm -f target/test-large-orig target/test-large target/test-large.gz
+
+echo "Testing --format=raw"
+# Raw data is the deflate stream alone: no header, CRC or length
+cat src/*.rs | head -c 100000 > target/test-raw-orig
+cp target/test-raw-orig target/test-raw
+timeout 10 ./target/debug/gzip -1 --format=raw target/test-raw > /dev/null 2>&1
+first=$(od -An -tx1 -N2 target/test-raw.gz | tr -d ' ')
+timeout 10 ./target/debug/gzip -d --format=raw target/test-raw.gz > /dev/null 2>&1
+actual_status=$?
+if [ "$actual_status" -eq 0 ] && [ "$first" != "1f8b" ] && cmp -s target/test-raw-orig target/test-raw; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Raw data did not round trip (status $actual_status, starts with $first)"
+fi
+((total++))
+rm -f target/test-raw.gz
+# Cross-check against zlib's raw deflate in both directions
+if command -v python3 > /dev/null; then
+    timeout 10 ./target/debug/gzip -1 --format=raw -k target/test-raw > /dev/null 2>&1
+    if python3 -c 'import sys, zlib; sys.exit(zlib.decompress(open(sys.argv[1], "rb").read(), -15) != open(sys.argv[2], "rb").read())' \
+        target/test-raw.gz target/test-raw-orig; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. zlib could not read our raw data"
+    fi
+    ((total++))
+    rm -f target/test-raw target/test-raw.gz
+    python3 -c 'import sys, zlib; c = zlib.compressobj(9, zlib.DEFLATED, -15); sys.stdout.buffer.write(c.compress(open(sys.argv[1], "rb").read()) + c.flush())' \
+        target/test-raw-orig > target/test-raw.gz
+    timeout 10 ./target/debug/gzip -d --format=raw target/test-raw.gz > /dev/null 2>&1
+    actual_status=$?
+    if [ "$actual_status" -eq 0 ] && cmp -s target/test-raw-orig target/test-raw; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. zlib's raw data did not decode (status $actual_status)"
+    fi
+    ((total++))
+fi
+rm -f target/test-raw target/test-raw.gz
+# A gzip file is not raw deflate data, and -l has nothing to list
+gzip -c target/test-raw-orig > target/test-raw.gz
+timeout 10 ./target/debug/gzip -t --format=raw target/test-raw.gz > /dev/null 2>&1
+test_status=$?
+timeout 10 ./target/debug/gzip -l --format=raw target/test-raw.gz > /dev/null 2>&1
+list_status=$?
+if [ "$test_status" -ne 0 ] && [ "$list_status" -ne 0 ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. --format=raw accepted a gzip file (-t status $test_status, -l status $list_status)"
+fi
+((total++))
+rm -f target/test-raw-orig target/test-raw.gz
+
+echo "Testing --format=zlib"
+# A two-byte header, the deflate data, then the Adler-32 of the data big-endian
+cat src/*.rs | head -c 100000 > target/test-zlib-orig
+cp target/test-zlib-orig target/test-zlib
+timeout 10 ./target/debug/gzip -1 --format=zlib target/test-zlib > /dev/null 2>&1
+first=$(od -An -tx1 -N2 target/test-zlib.gz | tr -d ' ')
+timeout 10 ./target/debug/gzip -d --format=zlib target/test-zlib.gz > /dev/null 2>&1
+actual_status=$?
+if [ "$actual_status" -eq 0 ] && [ "$first" = "7801" ] && cmp -s target/test-zlib-orig target/test-zlib; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. zlib data did not round trip (status $actual_status, header $first)"
+fi
+((total++))
+rm -f target/test-zlib.gz
+if command -v python3 > /dev/null; then
+    timeout 10 ./target/debug/gzip -1 --format=zlib -k target/test-zlib > /dev/null 2>&1
+    if python3 -c 'import sys, zlib; sys.exit(zlib.decompress(open(sys.argv[1], "rb").read()) != open(sys.argv[2], "rb").read())' \
+        target/test-zlib.gz target/test-zlib-orig; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. zlib could not read our zlib data"
+    fi
+    ((total++))
+    rm -f target/test-zlib target/test-zlib.gz
+    for level in 1 6 9; do
+        python3 -c 'import sys, zlib; sys.stdout.buffer.write(zlib.compress(open(sys.argv[1], "rb").read(), int(sys.argv[2])))' \
+            target/test-zlib-orig $level > target/test-zlib.gz
+        timeout 10 ./target/debug/gzip -d --format=zlib target/test-zlib.gz > /dev/null 2>&1
+        actual_status=$?
+        if [ "$actual_status" -eq 0 ] && cmp -s target/test-zlib-orig target/test-zlib; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. zlib's level $level data did not decode (status $actual_status)"
+        fi
+        ((total++))
+        rm -f target/test-zlib target/test-zlib.gz
+    done
+fi
+# A wrong Adler-32, a bad header check, and a header asking for a preset dictionary
+cp target/test-zlib-orig target/test-zlib
+timeout 10 ./target/debug/gzip -1 --format=zlib target/test-zlib > /dev/null 2>&1
+size=$(stat -c %s target/test-zlib.gz)
+byte=$(od -An -tu1 -j $((size - 1)) -N1 target/test-zlib.gz | tr -d ' ')
+printf "$(printf '\\%03o' $((byte ^ 1)))" | dd of=target/test-zlib.gz bs=1 seek=$((size - 1)) conv=notrunc 2>/dev/null
+for bad in "adler-32 error" "incorrect header check" "needs a preset dictionary"; do
+    case "$bad" in
+        "incorrect header check") printf '\x78\x02\x03\x00\x00\x00\x00\x01' > target/test-zlib.gz ;;
+        "needs a preset dictionary") printf '\x78\xbb\x00\x00\x00\x00\x03\x00\x00\x00\x00\x01' > target/test-zlib.gz ;;
+    esac
+    actual_output=$(timeout 10 ./target/debug/gzip -t --format=zlib target/test-zlib.gz 2>&1 > /dev/null)
+    if [ $? -ne 0 ] && [[ "$actual_output" == *"$bad"* ]]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. Expected '$bad', got '$actual_output'"
+    fi
+    ((total++))
+done
+rm -f target/test-zlib-orig target/test-zlib target/test-zlib.gz
+
+echo "Testing --dictionary"
+# Data made of pieces of the dictionary compresses far better with it, and only
+# decodes to the original with the same dictionary
+cat src/zip.rs > target/test-dict-dict
+head -c 3000 src/zip.rs | tail -c 2000 > target/test-dict-orig
+head -c 2000 src/unzip.rs > target/test-dict-other
+for format in zlib raw; do
+    plain=$(timeout 10 ./target/debug/gzip -1 --format=$format -c target/test-dict-orig 2>/dev/null | wc -c)
+    cp target/test-dict-orig target/test-dict
+    timeout 10 ./target/debug/gzip -1 --format=$format --dictionary target/test-dict-dict target/test-dict > /dev/null 2>&1
+    with=$(stat -c %s target/test-dict.gz)
+    cp target/test-dict.gz target/test-dict-copy.gz
+    timeout 10 ./target/debug/gzip -d --format=$format --dictionary target/test-dict-dict target/test-dict.gz > /dev/null 2>&1
+    actual_status=$?
+    if [ "$actual_status" -eq 0 ] && [ "$with" -lt $((plain / 4)) ] && cmp -s target/test-dict-orig target/test-dict; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. $format with a dictionary: status $actual_status, $with bytes against $plain without"
+    fi
+    ((total++))
+    rm -f target/test-dict
+    for dict in none other; do
+        cp target/test-dict-copy.gz target/test-dict.gz
+        if [ "$dict" = none ]; then
+            timeout 10 ./target/debug/gzip -d --format=$format target/test-dict.gz > /dev/null 2>&1
+        else
+            timeout 10 ./target/debug/gzip -d --format=$format --dictionary target/test-dict-other target/test-dict.gz > /dev/null 2>&1
+        fi
+        # Either decoding fails and leaves no output, or it gives something else
+        if ! cmp -s target/test-dict-orig target/test-dict; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. $format data decoded with dictionary '$dict'"
+        fi
+        ((total++))
+        rm -f target/test-dict target/test-dict.gz
+    done
+    rm -f target/test-dict-copy.gz
+done
+if command -v python3 > /dev/null; then
+    for format in zlib raw; do
+        [ "$format" = zlib ] && wbits=15 || wbits=-15
+        timeout 10 ./target/debug/gzip -1 --format=$format --dictionary target/test-dict-dict -c target/test-dict-orig > target/test-dict.gz 2>/dev/null
+        if python3 -c 'import sys, zlib; d = zlib.decompressobj(int(sys.argv[3]), zdict=open(sys.argv[4], "rb").read()); sys.exit(d.decompress(open(sys.argv[1], "rb").read()) != open(sys.argv[2], "rb").read())' \
+            target/test-dict.gz target/test-dict-orig $wbits target/test-dict-dict; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. zlib could not read our $format data with the dictionary"
+        fi
+        ((total++))
+        python3 -c 'import sys, zlib; c = zlib.compressobj(9, zlib.DEFLATED, int(sys.argv[2]), zdict=open(sys.argv[3], "rb").read()); sys.stdout.buffer.write(c.compress(open(sys.argv[1], "rb").read()) + c.flush())' \
+            target/test-dict-orig $wbits target/test-dict-dict > target/test-dict.gz
+        timeout 10 ./target/debug/gzip -d --format=$format --dictionary target/test-dict-dict target/test-dict.gz > /dev/null 2>&1
+        actual_status=$?
+        if [ "$actual_status" -eq 0 ] && cmp -s target/test-dict-orig target/test-dict; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. zlib's $format data with the dictionary did not decode (status $actual_status)"
+        fi
+        ((total++))
+        rm -f target/test-dict target/test-dict.gz
+    done
+fi
+# The gzip format cannot record that a dictionary is needed
+timeout 10 ./target/debug/gzip -1 --dictionary target/test-dict-dict -c target/test-dict-orig > /dev/null 2>&1
+if [ $? -ne 0 ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. --dictionary was accepted for gzip output"
+fi
+((total++))
+rm -f target/test-dict-dict target/test-dict-orig target/test-dict-other
+
+echo "Testing single-entry zip files"
+# Entries are written by Python's zipfile: to an unseekable file for data
+# descriptors, with force_zip64 for zip64 sizes
+cat src/*.rs | head -c 150000 > target/test-zip-orig
+if command -v python3 > /dev/null; then
+    python3 - target/test-zip-orig target/test-zip <<'PYEOF'
+import io, sys, zipfile
+data = open(sys.argv[1], "rb").read()
+class Unseekable(io.RawIOBase):
+    def __init__(self, f): self.f = f
+    def writable(self): return True
+    def write(self, b): return self.f.write(b)
+def write(name, method, seekable=True, zip64=False, entries=1):
+    with open(sys.argv[2] + "-" + name + ".zip", "wb") as out:
+        with zipfile.ZipFile(out if seekable else Unseekable(out), "w", method) as z:
+            for i in range(entries):
+                with z.open("entry%d" % i, "w", force_zip64=zip64) as e:
+                    e.write(data)
+write("deflated", zipfile.ZIP_DEFLATED)
+write("stored", zipfile.ZIP_STORED)
+write("descriptor", zipfile.ZIP_DEFLATED, seekable=False)
+write("zip64", zipfile.ZIP_DEFLATED, seekable=False, zip64=True)
+write("bzip2", zipfile.ZIP_BZIP2)
+write("two", zipfile.ZIP_DEFLATED, entries=2)
+PYEOF
+    for kind in deflated stored descriptor zip64; do
+        timeout 10 ./target/debug/gzip -d -S .zip target/test-zip-$kind.zip > /dev/null 2>&1
+        actual_status=$?
+        if [ "$actual_status" -eq 0 ] && cmp -s target/test-zip-orig target/test-zip-$kind; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. The $kind zip entry did not extract (status $actual_status)"
+        fi
+        ((total++))
+        rm -f target/test-zip-$kind target/test-zip-$kind.zip
+    done
+    for kind in bzip2 two; do
+        [ "$kind" = bzip2 ] && expected_output="compressed with bzip2 -- not supported" || expected_output="has more than one entry"
+        actual_output=$(timeout 10 ./target/debug/gzip -d -S .zip target/test-zip-$kind.zip 2>&1 > /dev/null)
+        if [ $? -ne 0 ] && [[ "$actual_output" == *"$expected_output"* ]]; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. Expected '$expected_output', got '$actual_output'"
+        fi
+        ((total++))
+        rm -f target/test-zip-$kind target/test-zip-$kind.zip
+    done
+fi
+# zip itself, and a flipped bit of the CRC in the local header
+if command -v zip > /dev/null; then
+    cp target/test-zip-orig target/test-zip
+    (cd target && zip -q test-zip.zip test-zip)
+    rm -f target/test-zip
+    cp target/test-zip.zip target/test-zip-copy.zip
+    timeout 10 ./target/debug/gzip -d -S .zip target/test-zip.zip > /dev/null 2>&1
+    actual_status=$?
+    if [ "$actual_status" -eq 0 ] && cmp -s target/test-zip-orig target/test-zip; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. zip's file did not extract (status $actual_status)"
+    fi
+    ((total++))
+    byte=$(od -An -tu1 -j 14 -N1 target/test-zip-copy.zip | tr -d ' ')
+    printf "$(printf '\\%03o' $((byte ^ 1)))" | dd of=target/test-zip-copy.zip bs=1 seek=14 conv=notrunc 2>/dev/null
+    actual_output=$(timeout 10 ./target/debug/gzip -t target/test-zip-copy.zip 2>&1 > /dev/null)
+    if [ $? -ne 0 ] && [[ "$actual_output" == *"crc error"* ]]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. A wrong CRC in the local header was accepted: '$actual_output'"
+    fi
+    ((total++))
+    rm -f target/test-zip target/test-zip.zip target/test-zip-copy.zip
+fi
+rm -f target/test-zip-orig
+
+echo "Testing multi-entry zip files"
+# Every entry goes to stdout with --zip-entries, where the decoder's debug output is
+# mixed in, so python3 compares what is left once that output is taken out
+cat src/*.rs | head -c 150000 > target/test-zips-orig
+if command -v python3 > /dev/null; then
+    python3 - target/test-zips-orig target/test-zips <<'PYEOF'
+import io, sys, zipfile
+data = open(sys.argv[1], "rb").read()
+class Unseekable(io.RawIOBase):
+    def __init__(self, f): self.f = f
+    def writable(self): return True
+    def write(self, b): return self.f.write(b)
+def write(name, methods, seekable=True):
+    with open(sys.argv[2] + "-" + name + ".zip", "wb") as out:
+        with zipfile.ZipFile(out if seekable else Unseekable(out), "w") as z:
+            for i, method in enumerate(methods):
+                z.writestr("entry%d" % i, data[i * 1000:], compress_type=method)
+write("mixed", [zipfile.ZIP_DEFLATED, zipfile.ZIP_STORED, zipfile.ZIP_DEFLATED])
+write("descriptor", [zipfile.ZIP_DEFLATED, zipfile.ZIP_DEFLATED], seekable=False)
+write("bzip2", [zipfile.ZIP_DEFLATED, zipfile.ZIP_BZIP2, zipfile.ZIP_DEFLATED])
+PYEOF
+    check_entries() {
+        python3 - "$1" target/test-zips-orig "$2" <<'PYEOF'
+import re, sys
+out = re.sub(rb"dynamic!\n|fixed!\n|<\d+> \n", b"", open(sys.argv[1], "rb").read())
+data = open(sys.argv[2], "rb").read()
+sys.exit(out != b"".join(data[int(i) * 1000:] for i in sys.argv[3].split(",")))
+PYEOF
+    }
+    for kind in mixed:0,1,2 descriptor:0,1 bzip2:0,2; do
+        name=${kind%%:*}
+        timeout 10 ./target/debug/gzip -d -c --zip-entries target/test-zips-$name.zip > target/test-zips-out 2> /dev/null
+        actual_status=$?
+        [ "$name" = bzip2 ] && expected_status=2 || expected_status=0
+        if [ "$actual_status" -eq "$expected_status" ] && check_entries target/test-zips-out "${kind#*:}"; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. The entries of the $name zip did not all extract (status $actual_status)"
+        fi
+        ((total++))
+    done
+    expected_output="entry entry1 compressed with bzip2 -- skipped"
+    actual_output=$(timeout 10 ./target/debug/gzip -t --zip-entries target/test-zips-bzip2.zip 2>&1 > /dev/null)
+    if [ $? -eq 2 ] && [[ "$actual_output" == *"$expected_output"* ]]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. Expected '$expected_output', got '$actual_output'"
+    fi
+    ((total++))
+    # -l has a line for each entry, with its sizes, even those it cannot extract
+    for name in mixed descriptor bzip2; do
+        actual_output=$(timeout 10 ./target/debug/gzip -l target/test-zips-$name.zip 2> /dev/null | grep -c " entry[0-9]$")
+        [ "$name" = descriptor ] && expected_output=2 || expected_output=3
+        if [ "$actual_output" = "$expected_output" ]; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. -l listed $actual_output entries of the $name zip, not $expected_output"
+        fi
+        ((total++))
+    done
+    actual_output=$(timeout 10 ./target/debug/gzip -l target/test-zips-descriptor.zip 2> /dev/null | grep " entry1$")
+    if [[ "$actual_output" == *" 149000 "* ]]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. -l did not take the size from the data descriptor: '$actual_output'"
+    fi
+    ((total++))
+    rm -f target/test-zips-out target/test-zips-mixed.zip target/test-zips-descriptor.zip target/test-zips-bzip2.zip
+fi
+rm -f target/test-zips-orig
+
+echo "Testing zip64 sizes in a local header"
+# Hand-made local headers whose 32-bit sizes are 0xffffffff, with the real sizes in a
+# zip64 extra field; an uncompressed size 4 GiB too large must not pass for the right one
+if command -v python3 > /dev/null; then
+    python3 - target/test-zip64 <<'PYEOF'
+import struct, sys, zlib
+data = b"zip64 sizes for a small entry\n" * 50
+def write(name, method, size_delta=0):
+    body = data if method == 0 else zlib.compress(data)[2:-4]
+    sizes = struct.pack("<QQ", len(data) + size_delta, len(body))
+    extra = struct.pack("<HH", 1, len(sizes)) + sizes
+    header = struct.pack("<IHHHHHIIIHH", 0x04034b50, 45, 0, method, 0, 0x21,
+                         zlib.crc32(data), 0xffffffff, 0xffffffff, len(name), len(extra))
+    with open(sys.argv[1] + "-" + name + ".zip", "wb") as out:
+        out.write(header + name.encode() + extra + body)
+write("stored", 0)
+write("deflated", 8)
+write("stored-long", 0, 1 << 32)
+write("deflated-long", 8, 1 << 32)
+PYEOF
+    printf 'zip64 sizes for a small entry\n%.0s' $(seq 50) > target/test-zip64-orig
+    for kind in stored deflated; do
+        timeout 10 ./target/debug/gzip -d -S .zip target/test-zip64-$kind.zip > /dev/null 2>&1
+        actual_status=$?
+        if [ "$actual_status" -eq 0 ] && cmp -s target/test-zip64-orig target/test-zip64-$kind; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. The $kind entry with zip64 sizes did not extract (status $actual_status)"
+        fi
+        ((total++))
+        rm -f target/test-zip64-$kind target/test-zip64-$kind.zip
+    done
+    for kind in stored-long deflated-long; do
+        [ "$kind" = stored-long ] && expected_output="length mismatch" || expected_output="length error"
+        actual_output=$(timeout 10 ./target/debug/gzip -t target/test-zip64-$kind.zip 2>&1 > /dev/null)
+        if [ $? -ne 0 ] && [[ "$actual_output" == *"$expected_output"* ]]; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. Expected '$expected_output', got '$actual_output'"
+        fi
+        ((total++))
+        rm -f target/test-zip64-$kind.zip
+    done
+    rm -f target/test-zip64-orig
+fi
+
+echo "Testing sizes of 4 GiB and more"
+# ISIZE keeps the length modulo 2^32 and -l shows it as stored, as gzip does: a long
+# member cannot be told from the other members of a multi-member file. Only -l reads
+# this hand-made member, so its deflate data is left as zeros
+printf '\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03' > target/test-wrap4g.gz
+head -c 3000000 /dev/zero >> target/test-wrap4g.gz
+printf '\x00\x00\x00\x00\x01\x00\x00\x00' >> target/test-wrap4g.gz
+seq 1 400000 > target/test-wrap-members.txt
+timeout 10 ./target/debug/gzip -1 -c --parallel=65536 target/test-wrap-members.txt > target/test-wrap-members.gz
+members_sizes=$(gzip -l target/test-wrap-members.gz | awk 'END { print $1, $2 }')
+for file in "test-wrap4g:3000018 1" "test-wrap-members:$members_sizes"; do
+    expected_output=${file#*:}
+    file=${file%%:*}
+    actual_output=$(timeout 10 ./target/debug/gzip -l target/$file.gz 2> /dev/null | awk 'END { print $1, $2 }')
+    if [ "$actual_output" = "$expected_output" ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. -l gave sizes '$actual_output' for $file, not '$expected_output'"
+    fi
+    ((total++))
+done
+rm -f target/test-wrap4g.gz target/test-wrap-members.txt target/test-wrap-members.gz
+# Compressing 4 GiB + 1 byte takes minutes in a debug build, so it only runs when
+# GZIP_TEST_LARGE is set
+if [ -n "$GZIP_TEST_LARGE" ]; then
+    head -c 4294967297 /dev/zero | timeout 900 ./target/debug/gzip -1 > target/test-4g.gz 2> /dev/null
+    actual_status=$?
+    actual_output=$(tail -c 4 target/test-4g.gz | od -An -tu4 | tr -d ' ')
+    if [ "$actual_status" -eq 0 ] && [ "$actual_output" = 1 ] \
+        && timeout 900 ./target/debug/gzip -t target/test-4g.gz > /dev/null 2>&1; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. 4 GiB + 1 byte gave status $actual_status and ISIZE '$actual_output'"
+    fi
+    ((total++))
+    rm -f target/test-4g.gz
+fi
+
+echo "Testing MTIME range and defaults"
+# MTIME holds 32 bits of seconds, with 0 for none: a file from 2110 or from the epoch
+# itself gets 0 and a warning. stdin only has a time stamp when it is a regular file
+echo "time stamps" > target/test-mtime.txt
+for stamp in "2110-01-01" "@0"; do
+    touch -d "$stamp" target/test-mtime.txt
+    actual_output=$(timeout 10 ./target/debug/gzip -1 -c target/test-mtime.txt 2> target/test-mtime-stderr.txt | od -An -tu4 -j4 -N4 | tr -d ' ')
+    if [ "$actual_output" = 0 ] && grep -qx "gzip: target/test-mtime.txt: warning: file timestamp out of range for gzip format" target/test-mtime-stderr.txt; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. A file dated $stamp got MTIME '$actual_output'"
+    fi
+    ((total++))
+done
+touch -d @1700000000 target/test-mtime.txt
+for input in pipe file; do
+    if [ "$input" = pipe ]; then
+        actual_output=$(cat target/test-mtime.txt | timeout 10 ./target/debug/gzip -1 2> /dev/null | od -An -tu4 -j4 -N4 | tr -d ' ')
+        expected_output=0
+    else
+        actual_output=$(timeout 10 ./target/debug/gzip -1 < target/test-mtime.txt 2> /dev/null | od -An -tu4 -j4 -N4 | tr -d ' ')
+        expected_output=1700000000
+    fi
+    if [ "$actual_output" = "$expected_output" ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. Compressing stdin from a $input gave MTIME '$actual_output', expected $expected_output"
+    fi
+    ((total++))
+done
+# An MTIME of 0xffffffff is far in the future but restorable; -v remarks on it
+printf '\x1f\x8b\x08\x00\xff\xff\xff\xff\x00\x03' > target/test-mtime.gz
+head -c 20 target/test-mtime.txt | ./target/debug/gzip -1 2> /dev/null | tail -c +11 >> target/test-mtime.gz
+rm -f target/test-mtime
+timeout 10 ./target/debug/gzip -d -N -v target/test-mtime.gz > /dev/null 2> target/test-mtime-stderr.txt
+actual_status=$?
+if [ "$actual_status" -eq 0 ] && grep -q "MTIME 4294967295 is in the future" target/test-mtime-stderr.txt \
+    && [ "$(date -r target/test-mtime +%Y)" = 2106 ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. An MTIME of 0xffffffff gave status $actual_status"
+fi
+((total++))
+rm -f target/test-mtime target/test-mtime.txt target/test-mtime.gz target/test-mtime-stderr.txt
+
+echo "Testing --mtime and SOURCE_DATE_EPOCH"
+# Two builds a year apart give inputs with different time stamps; SOURCE_DATE_EPOCH
+# caps both at the same time, so the .gz files must be byte for byte the same
+mkdir -p target/test-epoch-1 target/test-epoch-2
+for build in 1 2; do
+    echo "reproducible" > target/test-epoch-$build/data.txt
+    touch -d "202$((3 + build))-06-01" target/test-epoch-$build/data.txt
+done
+for flags in "" "-n"; do
+    for build in 1 2; do
+        SOURCE_DATE_EPOCH=1600000000 timeout 10 ./target/debug/gzip -1 $flags -c target/test-epoch-$build/data.txt > target/test-epoch-$build.gz 2> /dev/null
+    done
+    if cmp -s target/test-epoch-1.gz target/test-epoch-2.gz; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. Builds with SOURCE_DATE_EPOCH and '$flags' differ"
+    fi
+    ((total++))
+done
+# A time stamp before SOURCE_DATE_EPOCH is kept
+actual_output=$(SOURCE_DATE_EPOCH=2000000000 timeout 10 ./target/debug/gzip -1 -c target/test-epoch-1/data.txt 2> /dev/null | od -An -tu4 -j4 -N4 | tr -d ' ')
+expected_output=$(stat -c %Y target/test-epoch-1/data.txt)
+if [ "$actual_output" = "$expected_output" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. SOURCE_DATE_EPOCH changed an earlier MTIME to '$actual_output'"
+fi
+((total++))
+touch -d @1234567 target/test-epoch-2/data.txt
+for mtime in 1234:1234 none:0 @target/test-epoch-2/data.txt:1234567 4294967296: soon:; do
+    expected_output=${mtime##*:}
+    actual_output=$(timeout 10 ./target/debug/gzip -1 -c --mtime "${mtime%:*}" target/test-epoch-1/data.txt 2> /dev/null | od -An -tu4 -j4 -N4 2> /dev/null | tr -d ' ')
+    if [ "$actual_output" = "$expected_output" ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. --mtime ${mtime%:*} gave MTIME '$actual_output', expected '$expected_output'"
+    fi
+    ((total++))
+done
+rm -rf target/test-epoch-1 target/test-epoch-2 target/test-epoch-1.gz target/test-epoch-2.gz
+
+echo "Testing --output and --output-dir"
+# Decompressing a/b.gz into another directory with -k leaves a/ as it was
+mkdir -p target/test-outdir-a
+seq 1 2000 > target/test-outdir-orig
+cp target/test-outdir-orig target/test-outdir-a/b
+./target/debug/gzip -1 target/test-outdir-a/b > /dev/null 2>&1
+rm -rf target/test-outdir-out
+timeout 10 ./target/debug/gzip -d -k --output-dir target/test-outdir-out target/test-outdir-a/b.gz > /dev/null 2>&1
+actual_status=$?
+if [ "$actual_status" -eq 0 ] && cmp -s target/test-outdir-orig target/test-outdir-out/b \
+    && [ "$(ls target/test-outdir-a)" = "b.gz" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. --output-dir with -k gave status $actual_status"
+fi
+((total++))
+# Without -k the input goes, as it would without --output-dir
+timeout 10 ./target/debug/gzip -1 -f --output-dir=target/test-outdir-a target/test-outdir-out/b > /dev/null 2>&1
+actual_status=$?
+if [ "$actual_status" -eq 0 ] && [ ! -e target/test-outdir-out/b ] && [ -e target/test-outdir-a/b.gz ] \
+    && ./target/debug/gzip -d -c target/test-outdir-a/b.gz 2> /dev/null | grep -v "dynamic!\|fixed!\|^<" | cmp -s - target/test-outdir-orig; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. --output-dir without -k gave status $actual_status"
+fi
+((total++))
+# -o names the output, and a name stored with -N does not override it
+timeout 10 ./target/debug/gzip -d -N -o target/test-outdir-named target/test-outdir-a/b.gz > /dev/null 2>&1
+actual_status=$?
+if [ "$actual_status" -eq 0 ] && cmp -s target/test-outdir-orig target/test-outdir-named && [ ! -e target/test-outdir-a/b.gz ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. -o gave status $actual_status"
+fi
+((total++))
+for args in "-o target/test-outdir-x.gz target/test-outdir-orig target/test-outdir-named" \
+    "-c -o target/test-outdir-x.gz target/test-outdir-orig" \
+    "--output-dir target/test-outdir-none/sub target/test-outdir-orig"; do
+    timeout 10 ./target/debug/gzip -1 $args > /dev/null 2>&1
+    actual_status=$?
+    if [ "$actual_status" -eq 1 ] && [ -e target/test-outdir-orig ] && [ ! -e target/test-outdir-x.gz ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. 'gzip $args' gave status $actual_status, expected 1"
+    fi
+    ((total++))
+done
+rm -rf target/test-outdir-a target/test-outdir-out target/test-outdir-orig target/test-outdir-named target/test-outdir-x.gz
+
+echo "Testing a closed or full stdout"
+# A reader that stops after 1 KB closes the pipe under gzip: it stops quietly with
+# status 2. A full device is a write error, with status 1
+seq 1 300000 > target/test-epipe.txt
+./target/debug/gzip -1 -c target/test-epipe.txt > target/test-epipe.txt.gz 2> /dev/null
+for flags in "-1 -c target/test-epipe.txt" "-d -c target/test-epipe.txt.gz"; do
+    timeout 10 ./target/debug/gzip $flags 2> target/test-epipe-stderr.txt | head -c 1024 > /dev/null
+    actual_status=${PIPESTATUS[0]}
+    if [ "$actual_status" -eq 2 ] && ! grep -q "panicked\|Broken pipe\|write error" target/test-epipe-stderr.txt; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. 'gzip $flags | head' gave status $actual_status: $(cat target/test-epipe-stderr.txt)"
+    fi
+    ((total++))
+    if [ -w /dev/full ]; then
+        timeout 10 ./target/debug/gzip $flags > /dev/full 2> target/test-epipe-stderr.txt
+        actual_status=$?
+        if [ "$actual_status" -eq 1 ] && grep -q "stdout: write error: No space left on device" target/test-epipe-stderr.txt; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. 'gzip $flags > /dev/full' gave status $actual_status: $(cat target/test-epipe-stderr.txt)"
+        fi
+        ((total++))
+    fi
+done
+rm -f target/test-epipe.txt target/test-epipe.txt.gz target/test-epipe-stderr.txt
+
+echo "Testing stdout is flushed and checked at exit"
+# An output small enough to still be buffered at exit must not vanish without a word
+printf 'abc' > target/test-flush.txt
+./target/debug/gzip -1 -c target/test-flush.txt > target/test-flush.txt.gz 2> /dev/null
+if [ -w /dev/full ]; then
+    for flags in "-1 -c target/test-flush.txt" "-d -c target/test-flush.txt.gz" "-1"; do
+        timeout 10 ./target/debug/gzip $flags < target/test-flush.txt > /dev/full 2> target/test-flush-stderr.txt
+        actual_status=$?
+        if [ "$actual_status" -eq 1 ] && grep -q "write error on standard output: No space left on device" target/test-flush-stderr.txt; then
+            echo "Test passed."
+            ((passed++))
+        else
+            echo "Test failed. 'gzip $flags > /dev/full' gave status $actual_status: $(cat target/test-flush-stderr.txt)"
+        fi
+        ((total++))
+    done
+fi
+rm -f target/test-flush.txt target/test-flush.txt.gz target/test-flush-stderr.txt
+
+echo "Testing input that arrives a few bytes at a time"
+# A pipe fed in small pieces hands out short reads; none of them may be taken for the end
+# of the input, when decompressing or when compressing
+seq 1 20000 > target/test-trickle.txt
+./target/debug/gzip -1 -c target/test-trickle.txt > target/test-trickle.txt.gz 2> /dev/null
+trickle='import sys, time
+data = open(sys.argv[1], "rb").read()
+for i in range(0, len(data), 97):
+    sys.stdout.buffer.write(data[i:i + 97])
+    sys.stdout.flush()
+    if i % 9700 == 0:
+        time.sleep(0.01)'
+python3 -c "$trickle" target/test-trickle.txt.gz | timeout 10 ./target/debug/gzip -d -c > target/test-trickle.out 2> target/test-trickle-stderr.txt
+actual_status=$?
+if [ "$actual_status" -eq 0 ] && cmp -s target/test-trickle.txt target/test-trickle.out; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Decompressing a trickled stream gave status $actual_status: $(cat target/test-trickle-stderr.txt)"
+fi
+((total++))
+python3 -c "$trickle" target/test-trickle.txt | timeout 10 ./target/debug/gzip -1 -c 2> target/test-trickle-stderr.txt | ./target/debug/gzip -d -c > target/test-trickle.out 2> /dev/null
+actual_status=${PIPESTATUS[1]}
+if [ "$actual_status" -eq 0 ] && cmp -s target/test-trickle.txt target/test-trickle.out; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Compressing a trickled stream gave status $actual_status: $(cat target/test-trickle-stderr.txt)"
+fi
+((total++))
+rm -f target/test-trickle.txt target/test-trickle.txt.gz target/test-trickle.out target/test-trickle-stderr.txt
+
+echo "Testing members that follow one another within a buffer"
+# The first member ends partway through the first buffer of input; the rest of that
+# buffer is where the second member starts
+printf 'first member\n' > target/test-members-1.txt
+head -c 100000 /dev/urandom > target/test-members-2.bin
+./target/debug/gzip -1 -c target/test-members-1.txt > target/test-members.gz 2> /dev/null
+./target/debug/gzip -1 -c target/test-members-2.bin >> target/test-members.gz 2> /dev/null
+cat target/test-members-1.txt target/test-members-2.bin > target/test-members.expected
+for how in "file" "stdin"; do
+    if [ "$how" = "file" ]; then
+        timeout 10 ./target/debug/gzip -d -c target/test-members.gz > target/test-members.out 2> /dev/null
+    else
+        timeout 10 ./target/debug/gzip -d -c < target/test-members.gz > target/test-members.out 2> /dev/null
+    fi
+    actual_status=$?
+    if [ "$actual_status" -eq 0 ] && cmp -s target/test-members.expected target/test-members.out; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. Two members read from $how gave status $actual_status or the wrong output"
+    fi
+    ((total++))
+done
+if timeout 10 ./target/debug/gzip -t target/test-members.gz 2> /dev/null; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. -t rejected two members"
+fi
+((total++))
+rm -f target/test-members-1.txt target/test-members-2.bin target/test-members.gz target/test-members.expected target/test-members.out
+
+echo "Testing ratios count the whole header"
+# -l and -v take the header and trailer off the compressed size: a long stored name, and
+# an extra field long enough that the header goes on past the first buffer of input
+python3 -c '
+import struct, sys, zlib
+data = b"".join(b"%d\n" % i for i in range(1, 3001))
+deflate = zlib.compressobj(1, zlib.DEFLATED, -15)
+body = deflate.compress(data) + deflate.flush()
+trailer = struct.pack("<II", zlib.crc32(data), len(data))
+for name, extra in ((b"n" * 200, b""), (b"extra.txt", b"x" * 40000)):
+    flags = 8 | (4 if extra else 0)
+    header = b"\x1f\x8b\x08" + bytes([flags]) + bytes(4) + b"\x00\x03"
+    if extra:
+        header += struct.pack("<H", len(extra)) + extra
+    header += name + b"\x00"
+    open("target/test-hdr-%d.gz" % len(extra), "wb").write(header + body + trailer)
+    print("target/test-hdr-%d.gz %.1f%%" % (len(extra), 100 * (len(data) - len(body)) / len(data)))
+' > target/test-hdr-expected.txt
+while read -r file ratio; do
+    listed=$(timeout 10 ./target/debug/gzip -l "$file" 2> /dev/null | awk '$NF != "uncompressed_name" { print $3 }')
+    verbose=$(timeout 10 ./target/debug/gzip -d -c -v "$file" 2>&1 > /dev/null | grep -o '[0-9.]*%')
+    if [ "$listed" = "$ratio" ] && [ "$verbose" = "$ratio" ]; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. $file: expected $ratio, -l gave '$listed' and -v '$verbose'"
+    fi
+    ((total++))
+done < target/test-hdr-expected.txt
+rm -f target/test-hdr-0.gz target/test-hdr-40000.gz target/test-hdr-expected.txt
+
+echo "Testing long stored names"
+# A stored name of any length that fits in a path is kept; a longer one is ignored with
+# a warning, and the output gets the name derived from the input's
+python3 -c '
+import struct, zlib
+data = b"hello\n"
+deflate = zlib.compressobj(1, zlib.DEFLATED, -15)
+body = deflate.compress(data) + deflate.flush() + struct.pack("<II", zlib.crc32(data), len(data))
+for n in (300, 5000):
+    header = b"\x1f\x8b\x08\x08" + bytes(4) + b"\x00\x03" + b"n" * n + b"\x00"
+    open("target/test-longname-%d.gz" % n, "wb").write(header + body)
+'
+long_name=$(printf 'n%.0s' $(seq 1 300))
+listed=$(timeout 10 ./target/debug/gzip -l -N target/test-longname-300.gz 2> /dev/null | awk 'END { print $NF }')
+if [ "$listed" = "target/$long_name" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. A 300-byte stored name was listed as '$listed'"
+fi
+((total++))
+timeout 10 ./target/debug/gzip -d -N -f target/test-longname-5000.gz 2> target/test-longname-stderr.txt
+actual_status=$?
+if [ "$actual_status" -eq 2 ] && [ -f target/test-longname-5000 ] && grep -q "stored name of 5000 bytes too long -- ignored" target/test-longname-stderr.txt; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. A 5000-byte stored name gave status $actual_status: $(cat target/test-longname-stderr.txt)"
+fi
+((total++))
+rm -f target/test-longname-300.gz target/test-longname-5000.gz target/test-longname-5000 target/test-longname-stderr.txt
+
+echo "Testing header CRCs"
+# FHCRC holds the low 16 bits of the CRC-32 of the header before it. A right one passes
+# without a word; a wrong one is an error
+python3 -c '
+import struct, zlib
+data = b"hello, header crc\n"
+deflate = zlib.compressobj(1, zlib.DEFLATED, -15)
+body = deflate.compress(data) + deflate.flush() + struct.pack("<II", zlib.crc32(data), len(data))
+header = b"\x1f\x8b\x08" + bytes([2 | 4 | 8 | 16]) + bytes(4) + b"\x00\x03"
+header += struct.pack("<H", 4) + b"abcd" + b"fhcrc.txt\x00" + b"a comment\x00"
+crc16 = zlib.crc32(header) & 0xffff
+open("target/test-fhcrc-good.gz", "wb").write(header + struct.pack("<H", crc16) + body)
+open("target/test-fhcrc-bad.gz", "wb").write(header + struct.pack("<H", crc16 ^ 0x0101) + body)
+old = b"\x1f\x9e" + header[2:]
+open("target/test-fhcrc-old.gz", "wb").write(old + struct.pack("<H", zlib.crc32(old) & 0xffff) + body)
+huge = b"\x1f\x8b\x08\x08" + bytes(4) + b"\x00\x03" + b"n" * 70000 + b"\x00"
+open("target/test-fhcrc-huge.gz", "wb").write(huge + body)
+'
+if command -v gzip > /dev/null && ! gzip -t target/test-fhcrc-good.gz 2> /dev/null; then
+    echo "Test failed. System gzip rejected the header CRC the test made"
+else
+    timeout 10 ./target/debug/gzip -d -c target/test-fhcrc-good.gz > target/test-fhcrc.out 2> target/test-fhcrc-stderr.txt
+    actual_status=$?
+    if [ "$actual_status" -eq 0 ] && [ "$(cat target/test-fhcrc.out)" = "hello, header crc" ] && ! grep -q "header checksum" target/test-fhcrc-stderr.txt; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. A right header CRC gave status $actual_status: $(cat target/test-fhcrc-stderr.txt)"
+    fi
+fi
+((total++))
+timeout 10 ./target/debug/gzip -d -c target/test-fhcrc-bad.gz > /dev/null 2> target/test-fhcrc-stderr.txt
+actual_status=$?
+if [ "$actual_status" -eq 1 ] && grep -q "header checksum 0x[0-9a-f]* != computed checksum" target/test-fhcrc-stderr.txt; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. A wrong header CRC gave status $actual_status: $(cat target/test-fhcrc-stderr.txt)"
+fi
+((total++))
+# The CRC covers the magic as read, which may be gzip 0.5's 1f 9e
+timeout 10 ./target/debug/gzip -d -c target/test-fhcrc-old.gz > target/test-fhcrc.out 2> target/test-fhcrc-stderr.txt
+actual_status=$?
+if [ "$actual_status" -eq 0 ] && [ "$(cat target/test-fhcrc.out)" = "hello, header crc" ] && [ ! -s target/test-fhcrc-stderr.txt ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. A right header CRC after the old magic gave status $actual_status: $(cat target/test-fhcrc-stderr.txt)"
+fi
+((total++))
+# A name that runs on for 64 KiB is damage, not something to read to the end of the input
+timeout 10 ./target/debug/gzip -d -c target/test-fhcrc-huge.gz > /dev/null 2> target/test-fhcrc-stderr.txt
+actual_status=$?
+if [ "$actual_status" -eq 1 ] && grep -q "name or comment too long" target/test-fhcrc-stderr.txt; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. A 70000-byte name gave status $actual_status: $(cat target/test-fhcrc-stderr.txt)"
+fi
+((total++))
+rm -f target/test-fhcrc-good.gz target/test-fhcrc-bad.gz target/test-fhcrc-old.gz target/test-fhcrc-huge.gz target/test-fhcrc.out target/test-fhcrc-stderr.txt
+
+echo "Testing header traces"
+# A plain decompression says nothing on stderr; -v -v and up trace each member's header
+printf 'traced\n' > target/test-trace.txt
+./target/debug/gzip -1 -f target/test-trace.txt 2> /dev/null
+timeout 10 ./target/debug/gzip -d -c target/test-trace.txt.gz > /dev/null 2> target/test-trace-stderr.txt
+if [ $? -eq 0 ] && [ ! -s target/test-trace-stderr.txt ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. gzip -d wrote to stderr: $(cat target/test-trace-stderr.txt)"
+fi
+((total++))
+timeout 10 ./target/debug/gzip -d -c -v -v -v target/test-trace.txt.gz > /dev/null 2> target/test-trace-stderr.txt
+if grep -q 'target/test-trace.txt.gz: member 1: method=8 flags=0x08 mtime=[0-9]* xfl=[0-9]* os=3 name="test-trace.txt"$' target/test-trace-stderr.txt; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. -v -v -v gave no header trace: $(cat target/test-trace-stderr.txt)"
+fi
+((total++))
+rm -f target/test-trace.txt target/test-trace.txt.gz target/test-trace-stderr.txt
+
+echo "Testing -v levels"
+# -v is one line per file, as in GNU gzip; block statistics wait for -v -v, which also
+# traces each block inflate decodes
+seq 1 50000 > target/test-levels.txt
+size=$(wc -c < target/test-levels.txt)
+timeout 10 ./target/debug/gzip -1 -v -f target/test-levels.txt 2> target/test-levels-stderr.txt
+if [ "$(wc -l < target/test-levels-stderr.txt)" -eq 1 ] && grep -qP '^target/test-levels.txt:\t *-?[0-9.]+% -- replaced with target/test-levels.txt.gz$' target/test-levels-stderr.txt; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. gzip -v printed: $(cat target/test-levels-stderr.txt)"
+fi
+((total++))
+timeout 10 ./target/debug/gzip -d -c -v -v target/test-levels.txt.gz 2> target/test-levels-stderr.txt > /dev/null
+blocks=$(grep -c ': block type=[a-z]* last=[01] in_bits=[0-9]* out_bytes=[0-9]*$' target/test-levels-stderr.txt)
+last_blocks=$(grep -c ': block type=[a-z]* last=1 ' target/test-levels-stderr.txt)
+out=$(grep -o 'out_bytes=[0-9]*' target/test-levels-stderr.txt | awk -F= '{ sum += $2 } END { print sum }')
+if [ "$blocks" -gt 1 ] && [ "$last_blocks" -eq 1 ] && [ "$out" = "$size" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. -d -v -v traced $blocks blocks ($last_blocks last) of $out bytes, not $size"
+fi
+((total++))
+timeout 10 ./target/debug/gzip -d -v target/test-levels.txt.gz 2> target/test-levels-stderr.txt
+if [ "$(wc -l < target/test-levels-stderr.txt)" -eq 1 ] && grep -qP '^target/test-levels.txt.gz:\t *-?[0-9.]+% -- replaced with target/test-levels.txt$' target/test-levels-stderr.txt; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. gzip -d -v printed: $(cat target/test-levels-stderr.txt)"
+fi
+((total++))
+rm -f target/test-levels.txt target/test-levels.txt.gz target/test-levels-stderr.txt
+
+echo "Testing -v lines against GNU gzip"
+# Captured from GNU gzip 1.12 running the same commands on the same files, but for the
+# note on -k with -c, which GNU gzip does not give
+mkdir -p target/test-golden
+cp tests/test-empty.txt tests/test-paragraphs.txt tests/test-sentence.txt tests/test-word.txt target/test-golden/
+{
+    ./target/debug/gzip -1 -v -n target/test-golden/test-*.txt
+    ./target/debug/gzip -d -v target/test-golden/*.gz
+    ./target/debug/gzip -1 -k -n target/test-golden/test-sentence.txt
+    ./target/debug/gzip -t -v target/test-golden/test-sentence.txt.gz
+    ./target/debug/gzip -c -d -v target/test-golden/test-sentence.txt.gz > /dev/null
+    ./target/debug/gzip -c -d -k -v target/test-golden/test-sentence.txt.gz > /dev/null
+} 2> target/test-golden-actual.txt
+printf '%s\n' \
+    $'target/test-golden/test-empty.txt:\t  0.0% -- replaced with target/test-golden/test-empty.txt.gz' \
+    $'target/test-golden/test-paragraphs.txt:\t 51.0% -- replaced with target/test-golden/test-paragraphs.txt.gz' \
+    $'target/test-golden/test-sentence.txt:\t -2.3% -- replaced with target/test-golden/test-sentence.txt.gz' \
+    $'target/test-golden/test-word.txt:\t-50.0% -- replaced with target/test-golden/test-word.txt.gz' \
+    $'target/test-golden/test-empty.txt.gz:\t  0.0% -- replaced with target/test-golden/test-empty.txt' \
+    $'target/test-golden/test-paragraphs.txt.gz:\t 51.0% -- replaced with target/test-golden/test-paragraphs.txt' \
+    $'target/test-golden/test-sentence.txt.gz:\t -2.3% -- replaced with target/test-golden/test-sentence.txt' \
+    $'target/test-golden/test-word.txt.gz:\t-50.0% -- replaced with target/test-golden/test-word.txt' \
+    $'target/test-golden/test-sentence.txt.gz:\t OK' \
+    $'target/test-golden/test-sentence.txt.gz:\t -2.3% -- replaced with stdout' \
+    'gzip: --keep is redundant with --stdout; input files are kept' \
+    $'target/test-golden/test-sentence.txt.gz:\t -2.3% -- created stdout' > target/test-golden-expected.txt
+if diff target/test-golden-expected.txt target/test-golden-actual.txt > /dev/null; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. -v lines differ from GNU gzip's: $(diff target/test-golden-expected.txt target/test-golden-actual.txt)"
+fi
+((total++))
+rm -rf target/test-golden target/test-golden-expected.txt target/test-golden-actual.txt
+
+echo "Testing ratios and sizes for files, pipes and empty input"
+# -l reads a file's sizes from its trailer, whether named or on stdin; a pipe cannot be
+# seeked, so as GNU gzip does it is read through. -v on a pipe works from the bytes counted
+seq 1 20000 > target/test-pipe-ratio.txt
+./target/debug/gzip -1 -c target/test-pipe-ratio.txt > target/test-pipe-ratio.txt.gz 2> /dev/null
+in_size=$(wc -c < target/test-pipe-ratio.txt)
+gz_size=$(wc -c < target/test-pipe-ratio.txt.gz)
+named=$(./target/debug/gzip -l target/test-pipe-ratio.txt.gz 2> /dev/null | awk '$1 ~ /^[0-9-]+$/ { print $1, $2 }')
+redirected=$(./target/debug/gzip -l < target/test-pipe-ratio.txt.gz 2> /dev/null | awk '$1 ~ /^[0-9-]+$/ { print $1, $2 }')
+piped=$(cat target/test-pipe-ratio.txt.gz | ./target/debug/gzip -l 2> /dev/null | awk '$1 ~ /^[0-9-]+$/ { print $1, $2 }')
+if [ "$named" = "$gz_size $in_size" ] && [ "$redirected" = "$gz_size $in_size" ] && [ "$piped" = "$gz_size $in_size" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. -l gave '$named' for a file, '$redirected' on stdin and '$piped' on a pipe"
+fi
+((total++))
+# Several members show the last one's length; bad data, or data after the last member,
+# lists nothing
+cat target/test-pipe-ratio.txt.gz target/test-pipe-ratio.txt.gz > target/test-pipe-ratio.twice.gz
+head -c 300 target/test-pipe-ratio.txt.gz > target/test-pipe-ratio.cut.gz
+printf 'garbage' | cat target/test-pipe-ratio.txt.gz - > target/test-pipe-ratio.trailing.gz
+for input in twice cut trailing; do
+    cat target/test-pipe-ratio.$input.gz | gzip -l > target/test-pipe-ratio-expected.txt 2> /dev/null
+    expected_status=$?
+    cat target/test-pipe-ratio.$input.gz | ./target/debug/gzip -l > target/test-pipe-ratio-actual.txt 2> /dev/null
+    actual_status=$?
+    if [ "$actual_status" -eq "$expected_status" ] && diff -u target/test-pipe-ratio-expected.txt target/test-pipe-ratio-actual.txt; then
+        echo "Test passed."
+        ((passed++))
+    else
+        echo "Test failed. -l of the $input pipe gave status $actual_status, not $expected_status"
+    fi
+    ((total++))
+done
+rm -f target/test-pipe-ratio.twice.gz target/test-pipe-ratio.cut.gz target/test-pipe-ratio.trailing.gz
+rm -f target/test-pipe-ratio-expected.txt target/test-pipe-ratio-actual.txt
+cat target/test-pipe-ratio.txt | ./target/debug/gzip -1 -v 2> target/test-pipe-ratio-stderr.txt > target/test-pipe-ratio.out.gz
+# No name is stored for a pipe: 10 header bytes and 8 trailer bytes
+expected=$(python3 -c "import sys; i, o = int(sys.argv[1]), int(sys.argv[2]); print('%5.1f%%' % (100 * (i - (o - 18)) / i))" "$in_size" "$(wc -c < target/test-pipe-ratio.out.gz)")
+if [ "$(cat target/test-pipe-ratio-stderr.txt)" = "$expected" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. -v on a pipe printed '$(cat target/test-pipe-ratio-stderr.txt)', not '$expected'"
+fi
+((total++))
+: | ./target/debug/gzip -1 -v 2> target/test-pipe-ratio-stderr.txt > /dev/null
+if [ "$(cat target/test-pipe-ratio-stderr.txt)" = "  0.0%" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. -v on empty input printed '$(cat target/test-pipe-ratio-stderr.txt)'"
+fi
+((total++))
+rm -f target/test-pipe-ratio.txt target/test-pipe-ratio.txt.gz target/test-pipe-ratio.out.gz target/test-pipe-ratio-stderr.txt
+
+echo "Testing --progress"
+head -c 6000000 /dev/urandom | base64 > target/progress.txt
+timeout 30 ./target/debug/gzip -1 -c --progress target/progress.txt > target/progress.gz 2> target/progress.err
+if [ -s target/progress.err ]; then
+    echo "Test failed. --progress drew on a stderr that is not a terminal"
+else
+    echo "Test passed."
+    ((passed++))
+fi
+((total++))
+if command -v script > /dev/null; then
+    shown=$(timeout 60 script -qec "./target/debug/gzip -1 -c --progress target/progress.txt > target/progress.out" /dev/null | tr '\r' '\n')
+    if ! cmp -s target/progress.gz target/progress.out; then
+        echo "Test failed. output changed with --progress"
+    elif ! echo "$shown" | grep -q "^target/progress.txt: [0-9]* bytes read ([0-9]*%)"; then
+        echo "Test failed. no progress line on a terminal"
+    else
+        echo "Test passed."
+        ((passed++))
+    fi
+    ((total++))
+fi
+rm -f target/progress.txt target/progress.gz target/progress.err target/progress.out
+
+echo "Testing -v and -l figures against the files on disk"
+mkdir -p target/test-stats
+head -c 300000 /dev/urandom | base64 > target/test-stats/random.txt
+cp tests/test-paragraphs.txt tests/test-sentence.txt target/test-stats/
+for file in target/test-stats/random.txt target/test-stats/test-paragraphs.txt target/test-stats/test-sentence.txt; do
+    size=$(stat -c %s "$file")
+    shown=$(timeout 10 ./target/debug/gzip -1 -n -v "$file" 2>&1 | cut -f2 | sed 's/ --.*//')
+    packed=$(stat -c %s "$file.gz")
+    # A -n header is 10 bytes and the trailer 8, which the ratio leaves out
+    expected=$(awk -v u="$size" -v c="$packed" 'BEGIN { printf "%5.1f%%", 100 * (u - (c - 18)) / u }')
+    listed=$(timeout 10 ./target/debug/gzip -l "$file.gz" | awk '$NF != "uncompressed_name" { print $1, $2 }')
+    timeout 10 ./target/debug/gzip -d "$file.gz"
+    if [ "$shown" != "$expected" ]; then
+        echo "Test failed. -v showed '$shown' for $file, the sizes on disk give '$expected'"
+    elif [ "$listed" != "$packed $size" ]; then
+        echo "Test failed. -l listed '$listed' for $file, the sizes on disk are '$packed $size'"
+    else
+        echo "Test passed."
+        ((passed++))
+    fi
+    ((total++))
+done
+size=$(stat -c %s target/test-stats/random.txt)
+shown=$(timeout 10 ./target/debug/gzip -1 -n -v --parallel=65536 --threads=4 target/test-stats/random.txt 2>&1 | cut -f2 | sed 's/ --.*//')
+# Each 64K chunk is a member with a header and trailer of its own
+expected=$(awk -v u="$size" -v c="$(stat -c %s target/test-stats/random.txt.gz)" \
+    'BEGIN { printf "%5.1f%%", 100 * (u - (c - 18 * int((u + 65535) / 65536))) / u }')
+if [ "$shown" = "$expected" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. -v showed '$shown' for parallel members, the sizes on disk give '$expected'"
+fi
+((total++))
+rm -rf target/test-stats
+
+echo "Testing gzip header fields"
+# Headers made with every mix of FTEXT, FHCRC, FEXTRA, FNAME and FCOMMENT, each with
+# the -v -v trace it should give; then one with a reserved flag
+mkdir -p target/test-header
+python3 -c '
+import random, struct, zlib
+random.seed(613)
+data = b"header fields\n"
+deflate = zlib.compressobj(1, zlib.DEFLATED, -15)
+body = deflate.compress(data) + deflate.flush() + struct.pack("<II", zlib.crc32(data), len(data))
+def text():
+    return bytes(random.choice(b"abcdefghij.-_ ") for _ in range(random.randint(1, 40)))
+traces = open("target/test-header/expected.txt", "w")
+for flags in range(32):
+    mtime, xfl, os = random.randint(1, 2**31), random.choice([0, 2, 4]), random.randint(0, 13)
+    header = b"\x1f\x8b\x08" + bytes([flags]) + struct.pack("<I", mtime) + bytes([xfl, os])
+    if flags & 4:
+        header += struct.pack("<H", 8) + b"AP" + struct.pack("<H", 4) + bytes(random.randrange(256) for _ in range(4))
+    name = text() if flags & 8 else None
+    if name:
+        header += name + b"\x00"
+    if flags & 16:
+        header += text() + b"\x00"
+    if flags & 2:
+        header += struct.pack("<H", zlib.crc32(header) & 0xffff)
+    open("target/test-header/h%02d.gz" % flags, "wb").write(header + body)
+    shown = "\"%s\"" % name.decode() if name else "-"
+    traces.write("gzip: target/test-header/h%02d.gz: member 1: method=8 flags=0x%02x mtime=%d xfl=%d os=%d name=%s\n" % (flags, flags, mtime, xfl, os, shown))
+open("target/test-header/minimal.gz", "wb").write(b"\x1f\x8b\x08\x00" + bytes(6) + body)
+open("target/test-header/reserved.gz", "wb").write(b"\x1f\x8b\x08\x40" + bytes(6) + body)
+'
+actual=$(timeout 10 ./target/debug/gzip -d -c target/test-header/minimal.gz 2>&1)
+if [ "$actual" = "header fields" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. A minimal header gave: $actual"
+fi
+((total++))
+: > target/test-header/actual.txt
+for file in target/test-header/h*.gz; do
+    actual=$(timeout 10 ./target/debug/gzip -d -c -v -v "$file" 2>> target/test-header/actual.txt)
+    [ "$actual" = "header fields" ] || failed="$failed $file"
+done
+grep "member 1:" target/test-header/actual.txt | sed 's/^[^:]*: /gzip: /' > target/test-header/traced.txt
+if ! diff target/test-header/expected.txt target/test-header/traced.txt > /dev/null; then
+    failed="$failed traces: $(diff target/test-header/expected.txt target/test-header/traced.txt | head -5)"
+fi
+report_cases "Header fields were misread for"
+timeout 10 ./target/debug/gzip -d -c target/test-header/reserved.gz > /dev/null 2> target/test-header/stderr.txt
+actual_status=$?
+forced=$(timeout 10 ./target/debug/gzip -d -c -f -f target/test-header/reserved.gz 2> /dev/null)
+if [ "$actual_status" -ne 1 ] || ! grep -q "reserved.gz has flags 0x40 -- not supported" target/test-header/stderr.txt; then
+    echo "Test failed. A reserved flag gave status $actual_status: $(cat target/test-header/stderr.txt)"
+elif [ "$forced" != "header fields" ]; then
+    echo "Test failed. -f -f did not read on past a reserved flag"
+else
+    echo "Test passed."
+    ((passed++))
+fi
+((total++))
+# What gzip writes reads back the same: the name, the time stamp and the OS
+printf 'written\n' > target/test-header/written.txt
+touch -d @1700000000 target/test-header/written.txt
+timeout 10 ./target/debug/gzip -1 -k target/test-header/written.txt
+actual=$(python3 -c '
+import struct
+raw = open("target/test-header/written.txt.gz", "rb").read()
+flags, mtime = raw[3], struct.unpack("<I", raw[4:8])[0]
+print(flags, mtime, raw[10:raw.index(b"\x00", 10)].decode())
+')
+if [ "$actual" = "8 1700000000 written.txt" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. The header written read back as '$actual'"
+fi
+((total++))
+rm -rf target/test-header
+
+echo "Testing --sync-flush"
+# What was written before the input pauses can be decoded before any more arrives, and
+# the stream goes on after it
+actual=$(timeout 30 python3 -c '
+import os, select, subprocess, zlib
+gzip = subprocess.Popen(["./target/debug/gzip", "-1", "-c", "--sync-flush"], stdin=subprocess.PIPE, stdout=subprocess.PIPE)
+inflate = zlib.decompressobj(31)
+got = b""
+def decoded(want):
+    global got
+    while got != want:
+        if not select.select([gzip.stdout], [], [], 5)[0]:
+            return False
+        chunk = os.read(gzip.stdout.fileno(), 65536)
+        if not chunk:
+            return False
+        got += inflate.decompress(chunk)
+    return True
+sent = b"hello"
+gzip.stdin.write(sent)
+gzip.stdin.flush()
+if not decoded(sent):
+    print("hello was held back: %r" % got)
+else:
+    sent += b" world\n" * 1000
+    gzip.stdin.write(b" world\n" * 1000)
+    gzip.stdin.flush()
+    if not decoded(sent):
+        print("the second write was held back")
+    else:
+        gzip.stdin.close()
+        got += inflate.decompress(gzip.stdout.read())
+        print("ok" if inflate.eof and got == sent and gzip.wait() == 0 else "the stream did not end right")
+')
+if [ "$actual" = "ok" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. $actual"
+fi
+((total++))
+head -c 500000 /dev/urandom | base64 > target/test-sync.txt
+if cat target/test-sync.txt | timeout 10 ./target/debug/gzip -1 -c --sync-flush | timeout 10 ./target/debug/gzip -d -c | cmp -s - target/test-sync.txt; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Data piped through --sync-flush did not come back the same"
+fi
+((total++))
+rm -f target/test-sync.txt
+# -d --sync-flush passes on what it has decoded before it waits for more input
+actual=$(timeout 30 python3 -c '
+import os, select, subprocess
+gzip = subprocess.Popen("./target/debug/gzip -6 -c --sync-flush | ./target/debug/gzip -d -c --sync-flush",
+                        shell=True, stdin=subprocess.PIPE, stdout=subprocess.PIPE)
+gzip.stdin.write(b"hello")
+gzip.stdin.flush()
+got = b""
+while got != b"hello" and select.select([gzip.stdout], [], [], 5)[0]:
+    chunk = os.read(gzip.stdout.fileno(), 100)
+    if not chunk:
+        break
+    got += chunk
+early = got
+gzip.stdin.write(b" world")
+gzip.stdin.close()
+got += gzip.stdout.read()
+if early != b"hello":
+    print("hello was held back: %r" % early)
+else:
+    print("ok" if got == b"hello world" and gzip.wait() == 0 else "got %r" % got)
+')
+if [ "$actual" = "ok" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. -d --sync-flush: $actual"
+fi
+((total++))
+
+echo "Testing --append"
+# Each --append adds a member; the file then decompresses to what went in, in order
+mkdir -p target/test-append
+printf 'first\n' | timeout 10 ./target/debug/gzip -1 --append target/test-append/log.gz
+printf 'second\n' > target/test-append/second.txt
+timeout 10 ./target/debug/gzip -1 --append target/test-append/log.gz target/test-append/second.txt
+printf 'third\n' | timeout 10 ./target/debug/gzip -1 --append target/test-append/log.gz
+expected=$(printf 'first\nsecond\nthird')
+actual=$(timeout 10 ./target/debug/gzip -d -c target/test-append/log.gz)
+if [ "$actual" != "$expected" ]; then
+    echo "Test failed. The appended members decompressed to: $actual"
+elif [ -e target/test-append/second.txt ]; then
+    echo "Test failed. An appended input file was not removed"
+elif command -v gzip > /dev/null && [ "$(gzip -d -c target/test-append/log.gz)" != "$expected" ]; then
+    echo "Test failed. System gzip read the appended members differently"
+else
+    echo "Test passed."
+    ((passed++))
+fi
+((total++))
+printf 'not gzip data at all\n' > target/test-append/plain.txt
+printf 'more\n' | timeout 10 ./target/debug/gzip -1 --append target/test-append/plain.txt 2> target/test-append/stderr.txt
+actual_status=$?
+if [ "$actual_status" -ne 1 ] || ! grep -q "not in gzip format" target/test-append/stderr.txt; then
+    echo "Test failed. Appending to a file that is not gzip gave status $actual_status"
+elif [ "$(cat target/test-append/plain.txt)" != "not gzip data at all" ]; then
+    echo "Test failed. A refused --append changed the file"
+elif ! printf 'more\n' | timeout 10 ./target/debug/gzip -1 -f --append target/test-append/plain.txt; then
+    echo "Test failed. -f did not allow appending to a file that is not gzip"
+else
+    echo "Test passed."
+    ((passed++))
+fi
+((total++))
+rm -rf target/test-append
+
+echo "Testing --bgzf"
+# BGZF: blocks of at most 65280 input bytes, each a gzip member whose BC subfield holds
+# its size less one, then the fixed 28-byte EOF block
+bgzf_eof=1f8b08040000000000ff0600424302001b0003000000000000000000
+mkdir -p target/test-bgzf
+head -c 200000 /dev/urandom | base64 > target/test-bgzf/input.txt
+timeout 10 ./target/debug/gzip -1 -k --bgzf target/test-bgzf/input.txt
+actual=$(python3 -c '
+import struct, sys, zlib
+raw = open("target/test-bgzf/input.txt.gz", "rb").read()
+data, pos, blocks = b"", 0, 0
+while pos < len(raw):
+    if raw[pos:pos + 4] != b"\x1f\x8b\x08\x04" or raw[pos + 10:pos + 16] != b"\x06\x00BC\x02\x00":
+        sys.exit("no BC subfield at %d" % pos)
+    bsize = struct.unpack("<H", raw[pos + 16:pos + 18])[0] + 1
+    block = raw[pos:pos + bsize]
+    chunk = zlib.decompress(block[18:-8], -15)
+    if struct.unpack("<II", block[-8:]) != (zlib.crc32(chunk), len(chunk)) or len(chunk) > 65280:
+        sys.exit("bad block at %d" % pos)
+    data, pos, blocks = data + chunk, pos + bsize, blocks + 1
+print(data == open("target/test-bgzf/input.txt", "rb").read(), blocks, raw[-28:].hex())
+' 2>&1)
+if [ "$actual" = "True 6 $bgzf_eof" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. The BGZF blocks did not walk: $actual"
+fi
+((total++))
+actual=$(printf '' | timeout 10 ./target/debug/gzip -1 -c --bgzf | od -An -v -tx1 | tr -d ' \n')
+if [ "$actual" = "$bgzf_eof" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Empty input gave $actual, not the BGZF EOF block alone"
+fi
+((total++))
+# A BGZF file as bgzip lays it out decompresses like any multi-member gzip file. It is
+# built here with zlib, at the level and block size bgzip uses; the checks against htslib
+# itself follow where bgzip is installed
+python3 -c '
+import struct, zlib
+out = b""
+for text in [b"chr1\t100\t200\n" * 300, b"chr2\t300\t400\n" * 10]:
+    deflate = zlib.compressobj(6, zlib.DEFLATED, -15)
+    body = deflate.compress(text) + deflate.flush()
+    out += b"\x1f\x8b\x08\x04" + bytes(5) + b"\xff\x06\x00BC\x02\x00" + struct.pack("<H", 18 + len(body) + 8 - 1)
+    out += body + struct.pack("<II", zlib.crc32(text), len(text))
+open("target/test-bgzf/known.gz", "wb").write(out + bytes.fromhex("'$bgzf_eof'"))
+'
+expected=$(python3 -c 'import sys; sys.stdout.write("chr1\t100\t200\n" * 300 + "chr2\t300\t400\n" * 10)' | cksum)
+if [ "$(timeout 10 ./target/debug/gzip -d -c target/test-bgzf/known.gz | cksum)" = "$expected" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. A known BGZF file did not decompress"
+fi
+((total++))
+# htslib reads our BGZF, and we read bgzip's, over several blocks. Skipped without bgzip
+if command -v bgzip > /dev/null; then
+    bgzip -t target/test-bgzf/input.txt.gz 2> /dev/null || failed="$failed bgzip-t"
+    if command -v htsfile > /dev/null && ! htsfile target/test-bgzf/input.txt.gz | grep -q "BGZF"; then
+        failed="$failed htsfile"
+    fi
+    bgzip -c target/test-bgzf/input.txt > target/test-bgzf/bgzip.gz
+    timeout 10 ./target/debug/gzip -d -c target/test-bgzf/bgzip.gz | cmp -s - target/test-bgzf/input.txt \
+        || failed="$failed decompressing-bgzip-output"
+    report_cases "BGZF did not pass between htslib and us:"
+fi
+rm -rf target/test-bgzf
+
+echo "Testing --index, --seek and --length"
+# Ranges read through the index of a 50 MB file match the same bytes of a full
+# decompression, at the start, across checkpoints, across members and past the end
+mkdir -p target/test-index
+python3 -c '
+import random
+random.seed(617)
+words = [bytes(random.choice(b"abcdefghij") for _ in range(random.randint(2, 9))) for _ in range(5000)]
+out = b" ".join(random.choice(words) for _ in range(9000000))
+open("target/test-index/big", "wb").write(out[:50000000])
+'
+timeout 30 ./target/debug/gzip -1 -k --index=target/test-index/big.idx target/test-index/big
+timeout 30 ./target/debug/gzip -d -c target/test-index/big.gz > target/test-index/full
+for range in 0:100 1048570:20 12345678:1000000 49999990:100 49999990:0 60000000:5 3:; do
+    offset=${range%:*}
+    length=${range#*:}
+    if [ -n "$length" ]; then
+        timeout 10 ./target/debug/gzip --index=target/test-index/big.idx --seek=$offset --length=$length target/test-index/big.gz > target/test-index/range
+    else
+        timeout 10 ./target/debug/gzip --index=target/test-index/big.idx --seek=$offset target/test-index/big.gz > target/test-index/range
+    fi
+    expected=$(tail -c +$((offset+1)) target/test-index/full | head -c ${length:-50000000} | cksum)
+    if [ "$(cksum < target/test-index/range)" != "$expected" ]; then
+        failed="$failed $range"
+    fi
+done
+cmp -s target/test-index/full target/test-index/big || failed="$failed whole-file"
+report_cases "These ranges did not match a full decompression:"
+# A pass of -t over the .gz finds the same checkpoints as compression did
+timeout 10 ./target/debug/gzip -t --index=target/test-index/again.idx target/test-index/big.gz
+if cmp -s target/test-index/big.idx target/test-index/again.idx; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. -t --index wrote a different index"
+fi
+((total++))
+# BGZF members are 64K each, so a long range runs on through many of them
+head -c 3000000 target/test-index/big > target/test-index/mid
+timeout 10 ./target/debug/gzip -1 --bgzf --index=target/test-index/mid.idx target/test-index/mid
+actual=$(timeout 10 ./target/debug/gzip --index=target/test-index/mid.idx --seek=1500000 --length=300000 target/test-index/mid.gz | cksum)
+if [ "$actual" = "$(tail -c +1500001 target/test-index/full | head -c 300000 | cksum)" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. A range across BGZF members did not match"
+fi
+((total++))
+# The index of another file is refused
+actual=$(timeout 10 ./target/debug/gzip --index=target/test-index/big.idx --seek=5 target/test-index/mid.gz 2>&1)
+status=$?
+if [ $status -eq 1 ] && [ "${actual#*: }" = "target/test-index/big.idx: index does not match target/test-index/mid.gz" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. The index of another file gave: $actual"
+fi
+((total++))
+rm -rf target/test-index
+
+echo "Testing files compressed one after another in a run"
+# What zip keeps from one file to the next must not show in the output: each file
+# compresses as it would in a run of its own, after a big file or before one
+mkdir -p target/test-reuse
+head -c 300000 /dev/urandom | base64 > target/test-reuse/big
+head -c 700 target/test-reuse/big > target/test-reuse/small
+printf 'a' > target/test-reuse/one
+: > target/test-reuse/empty
+for level in -1 -3; do
+    timeout 10 ./target/debug/gzip $level -n -c target/test-reuse/big target/test-reuse/small target/test-reuse/one \
+        target/test-reuse/empty target/test-reuse/big target/test-reuse/small > target/test-reuse/together.gz
+    : > target/test-reuse/apart.gz
+    for name in big small one empty big small; do
+        timeout 10 ./target/debug/gzip $level -n -c target/test-reuse/$name >> target/test-reuse/apart.gz
+    done
+    cmp -s target/test-reuse/together.gz target/test-reuse/apart.gz || failed="$failed $level"
+done
+report_cases "One run over several files differed from separate runs at:"
+rm -rf target/test-reuse
+
+echo "Testing match lengths against a byte-by-byte comparison"
+# Debug builds check each match length found eight bytes at a time against a byte loop.
+# The input repeats earlier strings with one byte changed at a random place, so that
+# matches end at every offset within a word, and runs up to the end of the input
+mkdir -p target/test-match
+python3 -c '
+import random
+random.seed(619)
+out = bytearray(random.randbytes(64))
+while len(out) < 1500000:
+    start = random.randrange(max(0, len(out) - 32000), len(out) - 8)
+    copy = bytearray(out[start:start + random.randint(3, 300)])
+    copy[random.randrange(len(copy))] ^= 1 << random.randrange(8)
+    out += copy
+open("target/test-match/input", "wb").write(out[:1500000] + out[-290:])
+'
+for level in -1 -2 -3; do
+    if ! timeout 30 ./target/debug/gzip $level -c target/test-match/input > target/test-match/input.gz 2> target/test-match/err \
+        || ! gzip -d -c target/test-match/input.gz | cmp -s - target/test-match/input; then
+        failed="$failed $level $(grep -m 1 -A 1 panicked target/test-match/err | tr "\n" " ")"
+    fi
+done
+report_cases "Matches went wrong at:"
+rm -rf target/test-match
+
+echo "Testing --readahead against reading in line"
+# The reader thread must hand over the same bytes in the same order, from a file or a
+# pipe, and the end of a truncated input must show up the same as without it
+mkdir -p target/test-readahead
+head -c 3000000 /dev/urandom | od -An -tx1 | head -c 2000000 > target/test-readahead/input
+./target/debug/gzip -1 -n -c target/test-readahead/input > target/test-readahead/plain.gz
+./target/debug/gzip -1 -n --readahead -c target/test-readahead/input > target/test-readahead/file.gz
+cat target/test-readahead/input | ./target/debug/gzip -1 -n --readahead > target/test-readahead/pipe.gz
+cmp -s target/test-readahead/plain.gz target/test-readahead/file.gz || failed="$failed compressing-a-file"
+cmp -s target/test-readahead/plain.gz target/test-readahead/pipe.gz || failed="$failed compressing-a-pipe"
+./target/debug/gzip -d --readahead -c target/test-readahead/plain.gz | cmp -s - target/test-readahead/input || failed="$failed decompressing-a-file"
+cat target/test-readahead/plain.gz | ./target/debug/gzip -d --readahead | cmp -s - target/test-readahead/input || failed="$failed decompressing-a-pipe"
+head -c 500000 target/test-readahead/plain.gz > target/test-readahead/short.gz
+expected=$(./target/debug/gzip -t target/test-readahead/short.gz 2>&1; echo "exit $?")
+actual=$(./target/debug/gzip -t --readahead target/test-readahead/short.gz 2>&1; echo "exit $?")
+[ "$expected" = "$actual" ] || failed="$failed truncated-input"
+report_cases "Output differed with --readahead when:"
+rm -rf target/test-readahead
+
+echo "Testing memory-mapped input against reading it"
+# Regular files of 16 MiB or more are mapped, and smaller ones with --mmap; pipes and
+# FIFOs are read even then. Every way in gives the same output, and a mapped file cut
+# short while it is read is a read error, not a crash
+mkdir -p target/test-mmap
+head -c 9000000 /dev/urandom | od -An -tx1 | head -c 17000000 > target/test-mmap/large
+head -c 300000 target/test-mmap/large > target/test-mmap/small
+gzip -1 -n -c target/test-mmap/large > target/test-mmap/expected.gz
+./target/debug/gzip -1 -n -c target/test-mmap/large | cmp -s - target/test-mmap/expected.gz || failed="$failed large"
+./target/debug/gzip -1 -n -c --no-mmap target/test-mmap/large | cmp -s - target/test-mmap/expected.gz || failed="$failed --no-mmap"
+./target/debug/gzip -d -c target/test-mmap/expected.gz | cmp -s - target/test-mmap/large || failed="$failed decompressing"
+[ "$(./target/debug/gzip -l --mmap target/test-mmap/expected.gz)" = "$(./target/debug/gzip -l --no-mmap target/test-mmap/expected.gz)" ] \
+    || failed="$failed listing"
+./target/debug/gzip -t --mmap target/test-mmap/expected.gz || failed="$failed testing"
+gzip -4 -n -c target/test-mmap/small > target/test-mmap/small.gz
+./target/debug/gzip -4 -n -c --mmap target/test-mmap/small | cmp -s - target/test-mmap/small.gz || failed="$failed --mmap"
+./target/debug/gzip -4 -n -c --mmap < target/test-mmap/small | cmp -s - target/test-mmap/small.gz || failed="$failed stdin"
+cat target/test-mmap/small | ./target/debug/gzip -4 -n -c --mmap | cmp -s - target/test-mmap/small.gz || failed="$failed pipe"
+mkfifo target/test-mmap/fifo
+cat target/test-mmap/small > target/test-mmap/fifo &
+timeout 10 ./target/debug/gzip -4 -n -c -f --mmap target/test-mmap/fifo | ./target/debug/gzip -d | cmp -s - target/test-mmap/small \
+    || failed="$failed fifo"
+wait
+./target/debug/gzip -1 -c target/test-mmap/large 2> target/test-mmap/err \
+    | { head -c 100000 > /dev/null; truncate -s 1000 target/test-mmap/large; cat > /dev/null; }
+grep -q "file shrank while being read" target/test-mmap/err || failed="$failed truncating:$(head -c 200 target/test-mmap/err)"
+report_cases "Mapped input went wrong when:"
+rm -rf target/test-mmap
+
+echo "Testing the crc-simd CRC against the table code"
+# A debug build with the feature checks each CRC the processor computes against the
+# tables. Members of every length from 0 to 4096 bytes put each tail length through it
+mkdir -p target/test-crc
+cargo build --features crc-simd --target-dir target/crc-simd > /dev/null 2>&1
+python3 -c '
+import random, zlib
+random.seed(623)
+out = bytearray()
+for n in range(4097):
+    c = zlib.compressobj(1, zlib.DEFLATED, 31)
+    out += c.compress(random.randbytes(n)) + c.flush()
+open("target/test-crc/members.gz", "wb").write(out)
+'
+head -c 3000000 /dev/urandom | od -An -tx1 > target/test-crc/input
+timeout 30 ./target/crc-simd/debug/gzip -t target/test-crc/members.gz 2> target/test-crc/err || failed="$failed members"
+timeout 30 ./target/crc-simd/debug/gzip -1 -c target/test-crc/input 2>> target/test-crc/err | gzip -t 2> /dev/null || failed="$failed compressing"
+[ -z "$failed" ] || failed="$failed $(grep -m 1 -A 1 panicked target/test-crc/err | tr "\n" " ")"
+report_cases "CRC went wrong for:"
+rm -rf target/test-crc
+
+echo "Testing --verify of --parallel output"
+# The CRC --verify checks is put together from the members' own CRCs, for inputs that
+# end inside a member, on a member boundary, and at once
+mkdir -p target/test-pverify
+head -c 1000000 /dev/urandom > target/test-pverify/inside
+head -c 65536 /dev/urandom > target/test-pverify/boundary
+: > target/test-pverify/empty
+for file in inside boundary empty; do
+    if ! timeout 30 ./target/debug/gzip -1 -k --parallel=16384 --threads=4 --verify target/test-pverify/$file 2> /dev/null \
+        || ! gzip -d -c target/test-pverify/$file.gz 2> /dev/null | cmp -s - target/test-pverify/$file; then
+        failed="$failed $file"
+    fi
+done
+rm -f target/test-pverify/inside.gz
+GZIP_TEST_CORRUPT_OUTPUT=500000 timeout 30 ./target/debug/gzip -1 -k --parallel=16384 --threads=4 --verify target/test-pverify/inside 2> /dev/null \
+    && failed="$failed damaged"
+report_cases "--verify with --parallel went wrong for:"
+rm -rf target/test-pverify
+
+echo "Testing the program name in messages"
+# Messages are headed with the base name gzip was run as, without any extension
+mkdir -p target/test-name
+cp target/debug/gzip target/test-name/zipper.exe
+actual=$(./target/test-name/zipper.exe target/test-name/missing 2>&1)
+if [ "$actual" = "zipper: target/test-name/missing: No such file or directory" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Got '$actual'"
+fi
+((total++))
+rm -rf target/test-name
+
+echo "Testing write errors on standard output outside the data"
+# -h, -V and -l go through the same standard output as the data, and a write that
+# fails there is an error like any other
+mkdir -p target/test-full
+seq 1 1000 > target/test-full/input
+./target/debug/gzip -1 -c target/test-full/input > target/test-full/input.gz
+for args in "-h" "-V" "-l target/test-full/input.gz" "-1 -c target/test-full/input"; do
+    ./target/debug/gzip $args > /dev/full 2> target/test-full/err
+    status=$?
+    if [ "$status" -ne 1 ] || ! grep -q "No space left on device" target/test-full/err; then
+        failed="$failed '$args' (status $status)"
+    fi
+done
+report_cases "No write error reported for:"
+rm -rf target/test-full
+
+echo "Testing -q against warnings and errors"
+# -q silences every warning but still leaves the warning status; errors are still shown.
+# Messages and statuses are GNU gzip's
+mkdir -p target/test-quiet/dir
+echo hello > target/test-quiet/input
+./target/debug/gzip -1 -c target/test-quiet/input > target/test-quiet/garbage.gz
+echo junk >> target/test-quiet/garbage.gz
+printf 'xx' > target/test-quiet/bad.gz
+python3 -c "
+import zipfile
+with zipfile.ZipFile('target/test-quiet/two.zip', 'w', zipfile.ZIP_DEFLATED) as z:
+    z.writestr('a', 'aaaa' * 100)
+    z.writestr('b', 'bbbb')"
+for quiet in "" "-q" "-v -q"; do
+    for args in "-1 target/test-quiet/dir" "-d -c target/test-quiet/garbage.gz" "-d -c target/test-quiet/two.zip" \
+                "-1 target/test-quiet/missing" "-d -c target/test-quiet/bad.gz"; do
+        compare_exit_codes $quiet $args
+        # Unless -q holds the warning back, it may come out before or after the data
+        if [ -n "$quiet" ] || [ "$args" != "-d -c target/test-quiet/two.zip" ]; then
+            compare_gzip_outputs_no_file $quiet $args
+        fi
+    done
+done
+rm -rf target/test-quiet
+
+echo "Testing names without a known suffix"
+# -d passes over them with a warning, or silently with -q or -r; -f changes nothing.
+# -c, -t and -l take any name. Messages and statuses are GNU gzip's
+mkdir -p target/test-suffix
+echo hello | ./target/debug/gzip -1 > target/test-suffix/input.gz
+for name in data data.txt data.gz.bak; do
+    for args in "-d" "-d -v" "-d -f" "-d -q" "-d -r" "-d -r -v" "-d -c" "-t" "-l" "-t -r -v"; do
+        cp target/test-suffix/input.gz target/test-suffix/$name
+        compare_gzip_outputs_no_file $args target/test-suffix/$name
+        compare_exit_codes $args target/test-suffix/$name
+        [ -f target/test-suffix/$name ] || failed="$failed '$args $name' (input gone)"
+        rm -f target/test-suffix/$name
+    done
+done
+# -S names the suffix to take off
+cp target/test-suffix/input.gz target/test-suffix/data.gz.bak
+if ! ./target/debug/gzip -d -S .bak target/test-suffix/data.gz.bak || [ ! -f target/test-suffix/data.gz ]; then
+    failed="$failed '-d -S .bak data.gz.bak'"
+fi
+report_cases "Wrong for"
+rm -rf target/test-suffix
+
+echo "Testing -S '' on decompression"
+# An empty suffix takes any name: known suffixes still come off, and other names need
+# -N's stored name or -c to get an output apart from the input
+mkdir -p target/test-empty-suffix
+cd target/test-empty-suffix
+echo hello > orig.txt
+../debug/gzip -1 -N orig.txt
+cp orig.txt.gz data
+cp orig.txt.gz data2
+rm orig.txt
+../debug/gzip -d -S '' data 2> err
+if [ $? -ne 1 ] || ! grep -q "data: cannot decompress onto itself" err || [ ! -f data ]; then
+    failed="$failed 'data'"
+fi
+if [ "$(../debug/gzip -d -c -S '' data)" != "hello" ]; then
+    failed="$failed '-c data'"
+fi
+if ! ../debug/gzip -d -N -S '' data2 || [ "$(cat orig.txt)" != "hello" ] || [ -f data2 ]; then
+    failed="$failed '-N data2'"
+fi
+cp orig.txt.gz other.gz
+if ! ../debug/gzip -d -S '' other.gz || [ "$(cat other)" != "hello" ] || [ -f other.gz ]; then
+    failed="$failed 'other.gz'"
+fi
+if ../debug/gzip -S '' orig.txt 2> err || ! grep -q "invalid suffix ''" err; then
+    failed="$failed 'compressing'"
+fi
+cd ../..
+report_cases "Wrong for"
+rm -rf target/test-empty-suffix
+
+echo "Testing .Z decompression and -b"
+# .Z files as compress(1) writes them: codes widen up to the header's bits in groups of
+# eight, and block mode may clear the table. -b caps the width that is accepted
+mkdir -p target/test-lzw
+cat > target/test-lzw/compress.py <<'PYTHON'
+import sys
+def compress(data, maxbits, clear, block):
+    maxmax = 1 << maxbits
+    segs = [[9, []]]
+    st = {'n': 9, 'maxcode': 511, 'free': 257 if block else 256}
+    def emit(code, is_clear=False):
+        segs[-1][1].append(code)
+        if is_clear:
+            st['n'], st['maxcode'] = 9, 511
+            segs.append([9, []])
+        elif st['free'] > st['maxcode']:
+            st['n'] += 1
+            st['maxcode'] = maxmax if st['n'] == maxbits else (1 << st['n']) - 1
+            segs.append([st['n'], []])
+    table = {}
+    if data:
+        ent = data[0]
+        for c in data[1:]:
+            if (ent, c) in table:
+                ent = table[(ent, c)]
+                continue
+            emit(ent)
+            if st['free'] < maxmax:
+                table[(ent, c)] = st['free']
+                st['free'] += 1
+            elif clear:
+                table = {}
+                st['free'] = 257
+                emit(256, True)
+            ent = c
+        emit(ent)
+    out = bytearray([0x1f, 0x9d, (0x80 if block else 0) | maxbits])
+    for i, (width, codes) in enumerate(segs):
+        for g in range(0, len(codes), 8):
+            group = codes[g:g + 8]
+            value = sum(c << (k * width) for k, c in enumerate(group))
+            size = width if len(group) == 8 or i < len(segs) - 1 else (len(group) * width + 7) // 8
+            out += value.to_bytes(width, 'little')[:size]
+    return bytes(out)
+mode = sys.argv[2]
+sys.stdout.buffer.write(compress(sys.stdin.buffer.read(), int(sys.argv[1]), mode == 'clear', mode != 'noblock'))
+PYTHON
+cat tests/*.txt tests/*.txt tests/*.txt > target/test-lzw/input
+head -c 100000 /dev/urandom >> target/test-lzw/input
+for bits in 9 12 16; do
+    for mode in plain clear noblock; do
+        python3 target/test-lzw/compress.py $bits $mode < target/test-lzw/input > target/test-lzw/input.Z
+        if ! ./target/debug/gzip -d -c target/test-lzw/input.Z | cmp -s - target/test-lzw/input; then
+            failed="$failed $bits-bit $mode"
+        fi
+    done
+done
+python3 target/test-lzw/compress.py 12 clear < target/test-lzw/input > target/test-lzw/input.Z
+if ! ./target/debug/gzip -d -c -b 12 target/test-lzw/input.Z | cmp -s - target/test-lzw/input; then
+    failed="$failed '-b 12'"
+fi
+./target/debug/gzip -d -c -b 11 target/test-lzw/input.Z > /dev/null 2> target/test-lzw/err
+if [ $? -ne 1 ] || ! grep -q "compressed with 12 bits, can only handle 11 bits" target/test-lzw/err; then
+    failed="$failed '-b 11'"
+fi
+./target/debug/gzip -b 17 target/test-lzw/input 2> target/test-lzw/err
+if [ $? -ne 1 ] || ! grep -q "^gzip: -b operand must be between 9 and 16$" target/test-lzw/err; then
+    failed="$failed '-b 17'"
+fi
+./target/debug/gzip -1 -c -b 12 target/test-lzw/input > /dev/null 2> target/test-lzw/err
+if [ $? -ne 2 ] || ! grep -q "^gzip: -b has no effect without -Z$" target/test-lzw/err; then
+    failed="$failed '-b 12' with deflate"
+fi
+report_cases "Wrong for"
+rm -rf target/test-lzw
+
+echo "Testing -Z against its own decompression and gzip's"
+# compress(1) output, with the table cleared when text gives way to random bytes
+mkdir -p target/test-lzw-out
+cat tests/*.txt tests/*.txt tests/*.txt src/*.rs > target/test-lzw-out/input
+head -c 200000 /dev/urandom >> target/test-lzw-out/input
+cat src/*.rs >> target/test-lzw-out/input
+for bits in 9 12 16; do
+    ./target/debug/gzip -Z -b $bits -c target/test-lzw-out/input > target/test-lzw-out/input.Z
+    if [ "$(head -c 3 target/test-lzw-out/input.Z | od -An -tx1 | tr -d ' ')" != "1f9d$(printf '%x' $((128 + bits)))" ]; then
+        failed="$failed '-b $bits' header"
+    fi
+    if ! ./target/debug/gzip -d -c target/test-lzw-out/input.Z | cmp -s - target/test-lzw-out/input; then
+        failed="$failed '-b $bits'"
+    fi
+    if command -v gzip > /dev/null && ! gzip -d -c target/test-lzw-out/input.Z | cmp -s - target/test-lzw-out/input; then
+        failed="$failed '-b $bits' with gzip -d"
+    fi
+done
+# In place, .Z is the suffix unless -S gives another
+cp target/test-lzw-out/input target/test-lzw-out/copy
+cp target/test-lzw-out/input target/test-lzw-out/other
+if ! ./target/debug/gzip -Z --verify target/test-lzw-out/copy || [ ! -f target/test-lzw-out/copy.Z ] \
+    || ! ./target/debug/gzip -d target/test-lzw-out/copy.Z || ! cmp -s target/test-lzw-out/copy target/test-lzw-out/input; then
+    failed="$failed 'in place'"
+fi
+if ! ./target/debug/gzip --lzw -S .lzw target/test-lzw-out/other || [ ! -f target/test-lzw-out/other.lzw ]; then
+    failed="$failed '-S .lzw'"
+fi
+: > target/test-lzw-out/empty
+if [ "$(./target/debug/gzip -Z -c target/test-lzw-out/empty | ./target/debug/gzip -d -c | wc -c)" -ne 0 ]; then
+    failed="$failed 'empty'"
+fi
+report_cases "Wrong for"
+rm -rf target/test-lzw-out
+
+echo "Testing messages for other compressed formats"
+# Named with the tool to use, exit 1; -d -c -f still copies them through whole
+mkdir -p target/test-foreign
+for case in 'zst:\x28\xb5\x2f\xfd:zstd compressed data; use unzstd' \
+            'xz:\xfd7zXZ\x00:xz compressed data; use unxz' \
+            'bz2:BZh9:bzip2 compressed data; use bunzip2' \
+            'lz4:\x04\x22\x4d\x18:lz4 compressed data; use unlz4' \
+            'zip:PK\x05\x06:an empty zip file; use unzip' \
+            'cd.zip:PK\x01\x02:a zip central directory without its entries; use unzip'; do
+    suffix=${case%%:*}
+    rest=${case#*:}
+    magic=${rest%%:*}
+    message=${rest#*:}
+    file=target/test-foreign/data.$suffix
+    printf "$magic" > $file
+    printf 'some more bytes' >> $file
+    # In place, only a name with a gzip suffix gets as far as the data
+    cp $file $file.gz
+    for args in "-d -c" "-t" "-d"; do
+        if [ "$args" = "-d" ]; then
+            file=$file.gz
+        fi
+        ./target/debug/gzip $args $file > /dev/null 2> target/test-foreign/err
+        status=$?
+        if [ $status -ne 1 ] || [ "$(cat target/test-foreign/err)" != "
+gzip: $file: not in gzip format ($message)" ]; then
+            failed="$failed '$args $suffix' (status $status: $(cat target/test-foreign/err))"
+        fi
+    done
+    file=target/test-foreign/data.$suffix
+    if ! ./target/debug/gzip -d -c -f $file | cmp -s - $file; then
+        failed="$failed '-d -c -f $suffix'"
+    fi
+done
+# Too short for any magic number
+printf 'x' > target/test-foreign/short.gz
+./target/debug/gzip -d -c target/test-foreign/short.gz 2> target/test-foreign/err
+if [ $? -ne 1 ] || [ "$(cat target/test-foreign/err)" != "
+gzip: target/test-foreign/short.gz: unexpected end of file" ]; then
+    failed="$failed 'short'"
+fi
+# A bzip2 magic needs its block size digit
+printf 'BZhx and on' > target/test-foreign/text
+if [ "$(./target/debug/gzip -d -c target/test-foreign/text 2>&1)" != "
+gzip: target/test-foreign/text: not in gzip format" ]; then
+    failed="$failed 'BZhx'"
+fi
+report_cases "Wrong for"
+rm -rf target/test-foreign
+
+echo "Testing --ignore-leading"
+# A member after 100 KB of junk, after a false start, and cut by the end of a read
+mkdir -p target/test-leading
+seq 1 20000 > target/test-leading/plain
+./target/debug/gzip -1 -c target/test-leading/plain > target/test-leading/member.gz
+for size in 102400 32766 32767 1; do
+    head -c $((size * 2)) /dev/urandom | tr -d '\037' | head -c $size > target/test-leading/junk
+    cat target/test-leading/junk target/test-leading/member.gz > target/test-leading/input
+    ./target/debug/gzip -d -c --ignore-leading target/test-leading/input > target/test-leading/out 2> target/test-leading/err
+    status=$?
+    if [ $status -ne 2 ] || ! cmp -s target/test-leading/out target/test-leading/plain \
+        || [ "$(cat target/test-leading/err)" != "gzip: target/test-leading/input: skipped $size bytes of leading garbage" ]; then
+        failed="$failed '$size bytes' (status $status)"
+    fi
+done
+printf 'ab\037\213\001xyz' > target/test-leading/junk
+cat target/test-leading/junk target/test-leading/member.gz > target/test-leading/input
+if ! ./target/debug/gzip -d -c --ignore-leading=8 < target/test-leading/input 2> /dev/null | cmp -s - target/test-leading/plain; then
+    failed="$failed 'false start'"
+fi
+./target/debug/gzip -d -c --ignore-leading=7 target/test-leading/input > /dev/null 2> /dev/null
+if [ $? -ne 1 ]; then
+    failed="$failed 'beyond MAXBYTES'"
+fi
+if ! ./target/debug/gzip -d -c --ignore-leading target/test-leading/member.gz 2> target/test-leading/err \
+    | cmp -s - target/test-leading/plain || [ -s target/test-leading/err ]; then
+    failed="$failed 'no garbage'"
+fi
+if ./target/debug/gzip -d --ignore-leading target/test-leading/input 2> /dev/null; then
+    failed="$failed 'without -c'"
+fi
+report_cases "Wrong for"
+rm -rf target/test-leading
+
+echo "Testing data after the last member"
+# Zero padding is let go, other data is a warning, and with --strict an error. The
+# boundary member is padded with a comment to end where the first read of input does
+mkdir -p target/test-trailing
+seq 1 2000 > target/test-trailing/plain
+./target/debug/gzip -1 -c target/test-trailing/plain > target/test-trailing/member.gz
+python3 -c '
+import sys, zlib
+data = open(sys.argv[1], "rb").read()
+c = zlib.compressobj(1, zlib.DEFLATED, -15)
+body = c.compress(data) + c.flush() + zlib.crc32(data).to_bytes(4, "little") + len(data).to_bytes(4, "little")
+comment = b"c" * (32768 - 10 - 1 - len(body))
+sys.stdout.buffer.write(b"\x1f\x8b\x08\x10\0\0\0\0\0\x03" + comment + b"\0" + body)
+' target/test-trailing/plain > target/test-trailing/boundary.gz
+for member in member boundary; do
+    for case in "zeros:0:" "garbage:2:decompression OK, trailing garbage ignored" \
+        "strict:1:trailing garbage after the last member"; do
+        name=${case%%:*}
+        expected=${case#*:}
+        status_wanted=${expected%%:*}
+        message=${expected#*:}
+        cp target/test-trailing/$member.gz target/test-trailing/input.gz
+        options=""
+        if [ "$name" = "zeros" ]; then
+            head -c 1000 /dev/zero >> target/test-trailing/input.gz
+        else
+            printf 'x' >> target/test-trailing/input.gz
+        fi
+        if [ "$name" = "strict" ]; then
+            options="--strict"
+        fi
+        ./target/debug/gzip -d $options target/test-trailing/input.gz 2> target/test-trailing/err
+        status=$?
+        if [ $status -ne $status_wanted ] || [ "$(tr -d '\n' < target/test-trailing/err)" != "${message:+gzip: target/test-trailing/input.gz: $message}" ]; then
+            failed="$failed '$member $name' (status $status)"
+        elif [ "$name" = "strict" ]; then
+            if [ -e target/test-trailing/input ] || [ ! -e target/test-trailing/input.gz ]; then
+                failed="$failed '$member $name' (files)"
+            fi
+        elif ! cmp -s target/test-trailing/input target/test-trailing/plain || [ -e target/test-trailing/input.gz ]; then
+            failed="$failed '$member $name' (files)"
+        fi
+        if ./target/debug/gzip -d -c $options < target/test-trailing/$member.gz 2> /dev/null | cmp -s - target/test-trailing/plain; then :; else
+            failed="$failed '$member $name' (stdin)"
+        fi
+        rm -f target/test-trailing/input target/test-trailing/input.gz
+    done
+done
+cp target/test-trailing/member.gz target/test-trailing/input.gz
+echo "more" >> target/test-trailing/input.gz
+./target/debug/gzip -d -c --strict < target/test-trailing/input.gz > /dev/null 2>&1
+if [ $? -ne 1 ]; then
+    failed="$failed 'stdin strict'"
+fi
+./target/debug/gzip -d -c < target/test-trailing/input.gz > /dev/null 2>&1
+if [ $? -ne 2 ]; then
+    failed="$failed 'stdin garbage'"
+fi
+report_cases "Wrong for"
+rm -rf target/test-trailing
+
+echo "Testing standard input to a file named with -o"
+mkdir -p target/test-stdin-o
+seq 1 5000 > target/test-stdin-o/a
+touch -d '2001-02-03 04:05:06' target/test-stdin-o/a
+./target/debug/gzip -1 -k target/test-stdin-o/a
+# -N restores the time stamp from the header, even through a pipe
+cat target/test-stdin-o/a.gz | ./target/debug/gzip -d -N -o target/test-stdin-o/b
+if [ $? -ne 0 ] || ! cmp -s target/test-stdin-o/a target/test-stdin-o/b \
+    || [ "$(stat -c %Y target/test-stdin-o/b)" != "$(stat -c %Y target/test-stdin-o/a)" ]; then
+    failed="$failed 'decompress -N'"
+fi
+cat target/test-stdin-o/a | ./target/debug/gzip -1 -o target/test-stdin-o/c.gz
+if [ $? -ne 0 ] || ! gzip -d -c target/test-stdin-o/c.gz | cmp -s - target/test-stdin-o/a; then
+    failed="$failed 'compress'"
+fi
+# An existing output is kept, and bad input leaves no output behind
+./target/debug/gzip -1 -o target/test-stdin-o/c.gz < target/test-stdin-o/a > /dev/null 2>&1
+if [ $? -ne 2 ]; then
+    failed="$failed 'existing output'"
+fi
+(cat target/test-stdin-o/a.gz; echo junk) | ./target/debug/gzip -d --strict -o target/test-stdin-o/d > /dev/null 2>&1
+if [ $? -ne 1 ] || [ -e target/test-stdin-o/d ]; then
+    failed="$failed 'bad input'"
+fi
+./target/debug/gzip -d -f -o target/test-stdin-o/a.gz < target/test-stdin-o/a.gz > /dev/null 2>&1
+if [ $? -ne 1 ] || ! gzip -t target/test-stdin-o/a.gz; then
+    failed="$failed 'same file'"
+fi
+./target/debug/gzip -d -o target/test-stdin-o/e - < target/test-stdin-o/a.gz
+if [ $? -ne 0 ] || ! cmp -s target/test-stdin-o/a target/test-stdin-o/e; then
+    failed="$failed '-'"
+fi
+report_cases "Wrong for"
+rm -rf target/test-stdin-o
+
+echo "Testing the terminal check"
+# Compressed data is not read from a terminal or written to one without -f. stdin is
+# presumed a terminal with --presume-input-tty, and script(1) gives stdout a real one
+mkdir -p target/test-tty
+echo "terminal" > target/test-tty/plain
+gzip -c target/test-tty/plain > target/test-tty/plain.gz
+./target/debug/gzip -d --presume-input-tty < target/test-tty/plain.gz > /dev/null 2> target/test-tty/err
+if [ $? -ne 1 ] || ! grep -q "compressed data not read from a terminal" target/test-tty/err; then
+    failed="$failed 'decompress, stdin'"
+fi
+for options in "-d -f" "-t" "-l"; do
+    if ! ./target/debug/gzip $options --presume-input-tty < target/test-tty/plain.gz > /dev/null 2>&1; then
+        failed="$failed '$options, stdin'"
+    fi
+done
+if ! ./target/debug/gzip -1 --presume-input-tty < target/test-tty/plain > /dev/null 2>&1; then
+    failed="$failed 'compress, stdin'"
+fi
+if command -v script > /dev/null; then
+    script -qec "./target/debug/gzip -1 < target/test-tty/plain; echo status \$?" /dev/null < /dev/null > target/test-tty/out 2>&1
+    if ! grep -q "compressed data not written to a terminal" target/test-tty/out || ! grep -q "status 1" target/test-tty/out; then
+        failed="$failed 'compress, stdout'"
+    fi
+    script -qec "./target/debug/gzip -1 -f < target/test-tty/plain > /dev/null; echo status \$?" /dev/null < /dev/null > target/test-tty/out 2>&1
+    if ! grep -q "status 0" target/test-tty/out; then
+        failed="$failed 'compress -f, stdout'"
+    fi
+    script -qec "./target/debug/gzip -d < target/test-tty/plain.gz; echo status \$?" /dev/null < /dev/null > target/test-tty/out 2>&1
+    if ! grep -q "^terminal" target/test-tty/out || ! grep -q "status 0" target/test-tty/out; then
+        failed="$failed 'decompress, stdout'"
+    fi
+fi
+report_cases "Wrong for"
+rm -rf target/test-tty
+
+echo "Testing - among named files"
+# stdin goes to stdout, and the files around it are still replaced by their .gz; with -c
+# everything goes to stdout and nothing is removed
+mkdir -p target/test-dash/gnu target/test-dash/ours
+for options in "-1" "-1 -c" "-1 -k" "-1 -c -k"; do
+    for dir in gnu ours; do
+        echo "first" > target/test-dash/$dir/a
+        echo "second" > target/test-dash/$dir/b
+        echo "from stdin" > target/test-dash/$dir/x
+    done
+    (cd target/test-dash/gnu && gzip -n $options a - b < x > out 2> /dev/null; echo $? > status)
+    (cd target/test-dash/ours && ../../debug/gzip -n $options a - b < x > out 2> /dev/null; echo $? > status)
+    if ! diff -r target/test-dash/gnu target/test-dash/ours > /dev/null; then
+        failed="$failed '$options'"
+    fi
+    rm -f target/test-dash/gnu/* target/test-dash/ours/*
+done
+report_cases "Wrong for"
+rm -rf target/test-dash
+
+echo "Testing the OS byte and --os-code"
+# Byte 9 of the header: 3 (Unix) here unless --os-code says otherwise, and 255 for BGZF
+case "$(uname -s)" in
+    Darwin) os_code=7 ;;
+    *) os_code=3 ;;
+esac
+for case in ":$os_code" "--os-code=0:0" "--os-code 11:11" "--os-code=255:255" "--bgzf:255" \
+    "--bgzf --os-code=3:3" "--parallel=1000 --os-code=11:11"; do
+    options=${case%:*}
+    seq 1 1000 | ./target/debug/gzip -1 $options > target/test-os-code.gz
+    actual=$(od -An -tu1 -j9 -N1 target/test-os-code.gz | tr -d ' ')
+    if [ "$actual" != "${case##*:}" ]; then
+        failed="$failed '$options' ($actual)"
+    fi
+done
+for value in 256 -1 x ""; do
+    ./target/debug/gzip -1 --os-code="$value" < /dev/null > /dev/null 2>&1
+    if [ $? -ne 1 ]; then
+        failed="$failed 'invalid $value'"
+    fi
+done
+report_cases "Wrong for"
+rm -f target/test-os-code.gz
+
+echo "Testing stored names by Windows' rules"
+# --windows-names applies them anywhere: device names get a leading _, control characters
+# and a trailing dot or space go, and a name left with nothing keeps the input's
+mkdir -p target/test-legal
+for case in "CON:_CON" "prn:_prn" "Aux.tar:_Aux.tar" "NUL.gz:_NUL.gz" "com1:_com1" "COM9.txt:_COM9.txt" \
+    "lpt1:_lpt1" "LPT9:_LPT9" "con .txt:_con .txt" "COM0:COM0" "CONSOLE:CONSOLE" "a. .:a" \
+    "x y :x y" $'a\x01b\x7f:ab' "a?b:a_b" $'\x01\x02:input' "...:input" ". :input"; do
+    stored=${case%:*}
+    python3 -c '
+import sys, zlib
+c = zlib.compressobj(1, zlib.DEFLATED, -15)
+body = c.compress(b"legal\n") + c.flush()
+trailer = zlib.crc32(b"legal\n").to_bytes(4, "little") + (6).to_bytes(4, "little")
+sys.stdout.buffer.write(b"\x1f\x8b\x08\x08\0\0\0\0\0\x03" + sys.argv[1].encode() + b"\0" + body + trailer)
+' "$stored" > target/test-legal/input.gz
+    ./target/debug/gzip -d -N --windows-names target/test-legal/input.gz 2> target/test-legal/err
+    status=$?
+    wanted=${case##*:}
+    if [ "$wanted" = "$stored" ]; then
+        status_wanted=0
+    else
+        status_wanted=2
+    fi
+    if [ $status -ne $status_wanted ] || [ "$(cat "target/test-legal/$wanted" 2> /dev/null)" != "legal" ] \
+        || [ "$(ls target/test-legal | grep -vc '^err$')" -ne 1 ]; then
+        failed="$failed '$stored' (status $status)"
+    fi
+    find target/test-legal -type f ! -name err -delete
+done
+report_cases "Wrong for"
+# The warning shows the stored name with its control characters escaped, as GNU tools do
+python3 -c '
+import sys, zlib
+c = zlib.compressobj(1, zlib.DEFLATED, -15)
+body = c.compress(b"legal\n") + c.flush()
+trailer = zlib.crc32(b"legal\n").to_bytes(4, "little") + (6).to_bytes(4, "little")
+sys.stdout.buffer.write(b"\x1f\x8b\x08\x08\0\0\0\0\0\x03a\x01b\n\xe9\0" + body + trailer)
+' > target/test-legal/input.gz
+./target/debug/gzip -d -N --windows-names target/test-legal/input.gz 2> target/test-legal/err
+expected="gzip: target/test-legal/input.gz: stored name 'a'\$'\\001''b'\$'\\n\\351' is not a legal file name -- using 'ab'\$'\\351'"
+if [ "$(cat target/test-legal/err)" = "$expected" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. The warning was: $(cat -v target/test-legal/err)"
+fi
+((total++))
+rm -rf target/test-legal
+
+echo "Testing an output that is a symbolic link"
+# -f does not write through it, -f -f replaces the link itself, and the file it pointed to
+# is never touched
+mkdir -p target/test-outlink
+for mode in compress decompress; do
+    echo "secret" > target/test-outlink/target
+    echo "data" > target/test-outlink/a
+    if [ "$mode" = "compress" ]; then
+        input=target/test-outlink/a
+        output=target/test-outlink/a.gz
+        options="-1"
+    else
+        gzip target/test-outlink/a
+        input=target/test-outlink/a.gz
+        output=target/test-outlink/a
+        options="-d"
+    fi
+    ln -s target $output
+    ./target/debug/gzip $options $input > /dev/null 2>&1
+    status_plain=$?
+    ./target/debug/gzip $options -f $input 2> target/test-outlink/err
+    status_force=$?
+    if [ $status_plain -ne 2 ] || [ $status_force -ne 1 ] || [ ! -L $output ] || [ ! -e $input ] \
+        || ! grep -q "output is a symbolic link; use -f -f to replace it" target/test-outlink/err; then
+        failed="$failed '$mode -f' ($status_plain, $status_force)"
+    fi
+    ./target/debug/gzip $options -f -f $input
+    if [ $? -ne 0 ] || [ -L $output ] || [ ! -f $output ] || [ -e $input ]; then
+        failed="$failed '$mode -f -f'"
+    fi
+    if [ "$(cat target/test-outlink/target)" != "secret" ]; then
+        failed="$failed '$mode target'"
+    fi
+    rm -f target/test-outlink/*
+done
+report_cases "Wrong for"
+rm -rf target/test-outlink
+
+echo "Testing each -f gate at levels 0, 1 and 2"
+# Every gate runs in a fresh directory at each level; the three statuses are for no -f,
+# -f and -f -f. For the header checks, which fail either way, it is whether data came out
+mkdir -p target/test-force
+python3 -c '
+import sys, zlib
+c = zlib.compressobj(1, zlib.DEFLATED, -15)
+body = c.compress(b"force\n") + c.flush() + zlib.crc32(b"force\n").to_bytes(4, "little") + (6).to_bytes(4, "little")
+open(sys.argv[1] + "/reserved.gz", "wb").write(b"\x1f\x8b\x08\x40\0\0\0\0\0\x03" + body)
+open(sys.argv[1] + "/hcrc.gz", "wb").write(b"\x1f\x8b\x08\x02\0\0\0\0\0\x03\x12\x34" + body)
+' target/test-force
+force_gate() {
+    case "$1" in
+        overwrite) touch a.gz; ../../debug/gzip -1 $2 a < /dev/null ;;
+        input-link) ln -s a link; ../../debug/gzip -1 $2 link ;;
+        other-links) ln a b; ../../debug/gzip -1 $2 a ;;
+        sticky) chmod +t a; ../../debug/gzip -1 $2 a ;;
+        suffix) mv a a.gz; ../../debug/gzip -1 $2 a.gz && [ -e a.gz.gz ] ;;
+        terminal) gzip -c a > a.gz; ../../debug/gzip -d $2 --presume-input-tty < a.gz > /dev/null ;;
+        append) echo "b" > b; ../../debug/gzip -1 --append $2 a b ;;
+        pass-through) ../../debug/gzip -d -c $2 a > out && cmp -s a out ;;
+        reserved-flags) ../../debug/gzip -d -c $2 ../reserved.gz 2> /dev/null | grep -q force ;;
+        header-crc) ../../debug/gzip -d -c $2 ../hcrc.gz 2> /dev/null | grep -q force ;;
+        output-link) echo "secret" > elsewhere; ln -s elsewhere a.gz; ../../debug/gzip -1 $2 a < /dev/null ;;
+    esac
+}
+for case in "overwrite:2 0 0" "input-link:1 0 0" "other-links:2 0 0" "sticky:2 0 0" "suffix:1 0 0" \
+    "terminal:1 0 0" "append:1 0 0" "pass-through:1 0 0" "reserved-flags:1 1 0" "header-crc:1 1 0" \
+    "output-link:2 1 0"; do
+    gate=${case%%:*}
+    actual=""
+    for options in "" "-f" "-f -f"; do
+        mkdir target/test-force/run
+        echo "force" > target/test-force/run/a
+        (cd target/test-force/run && force_gate $gate "$options" > /dev/null 2>&1)
+        actual="$actual $?"
+        rm -rf target/test-force/run
+    done
+    if [ "${actual# }" != "${case#*:}" ]; then
+        failed="$failed '$gate' ($actual )"
+    fi
+done
+report_cases "Wrong for"
+rm -rf target/test-force
+
+echo "Testing -l with a stored name and comment"
+# -N -l lists the name stored in the header rather than the one made from the file's,
+# and -v adds the comment on a line of its own
+mkdir -p target/test-list-name
+echo "hello world" > target/test-list-name/original_name.txt
+gzip target/test-list-name/original_name.txt
+mv target/test-list-name/original_name.txt.gz target/test-list-name/renamed.gz
+python3 -c '
+import sys, zlib
+data = b"hello world\n"
+c = zlib.compressobj(1, zlib.DEFLATED, -15)
+body = c.compress(data) + c.flush() + zlib.crc32(data).to_bytes(4, "little") + len(data).to_bytes(4, "little")
+sys.stdout.buffer.write(b"\x1f\x8b\x08\x18\0\0\0\0\0\x03original_name.txt\0a note\0" + body)
+' > target/test-list-name/commented.gz
+name_of() {
+    ./target/debug/gzip "$@" | grep -v "^ *compressed\|^method\|comment:" | awk '{ print $NF }'
+}
+if [ "$(name_of -l target/test-list-name/renamed.gz)" != "target/test-list-name/renamed" ]; then
+    failed="$failed '-l'"
+fi
+if [ "$(name_of -N -l target/test-list-name/renamed.gz)" != "target/test-list-name/original_name.txt" ]; then
+    failed="$failed '-N -l'"
+fi
+if [ "$(name_of -N -l -v target/test-list-name/renamed.gz)" != "target/test-list-name/original_name.txt" ]; then
+    failed="$failed '-N -l -v'"
+fi
+if [ "$(./target/debug/gzip -N -l -v target/test-list-name/commented.gz | grep -c '^    comment: a note$')" -ne 1 ] \
+    || ./target/debug/gzip -N -l target/test-list-name/commented.gz | grep -q "comment:"; then
+    failed="$failed 'comment'"
+fi
+report_cases "Wrong for"
+rm -rf target/test-list-name
+
+echo "Testing -r on a mixed directory"
+# -d -r takes only names with a compression suffix, and -r only names without one; the
+# rest are passed over before their links, mode or type could be warned about
+rm -rf target/test-mixed
+mkdir -p target/test-mixed/sub
+echo "plain" > target/test-mixed/plain.txt
+ln target/test-mixed/plain.txt target/test-mixed/sub/linked.txt
+echo "setuid" > target/test-mixed/setuid.txt
+chmod u+s target/test-mixed/setuid.txt
+mkfifo target/test-mixed/sub/fifo
+echo "one" > target/test-mixed/one.txt
+echo "two" > target/test-mixed/sub/two.dat
+gzip target/test-mixed/one.txt target/test-mixed/sub/two.dat
+./target/debug/gzip -d -r target/test-mixed 2> target/test-mixed.err
+status=$?
+if [ $status -ne 0 ] || [ -s target/test-mixed.err ] || [ "$(cat target/test-mixed/one.txt)" != "one" ] \
+    || [ "$(cat target/test-mixed/sub/two.dat)" != "two" ] || [ "$(cat target/test-mixed/plain.txt)" != "plain" ]; then
+    failed="$failed '-d -r' (status $status)"
+fi
+./target/debug/gzip -d -r -v target/test-mixed 2> target/test-mixed.err
+if [ $? -ne 2 ] || ! grep -q "plain.txt: unknown suffix -- ignored" target/test-mixed.err; then
+    failed="$failed '-d -r -v'"
+fi
+rm target/test-mixed/sub/linked.txt target/test-mixed/setuid.txt target/test-mixed/sub/fifo
+gzip target/test-mixed/one.txt
+cp target/test-mixed/one.txt.gz target/test-mixed-one.gz
+./target/debug/gzip -1 -r target/test-mixed 2> target/test-mixed.err
+status=$?
+if [ $status -ne 0 ] || [ -s target/test-mixed.err ] || [ -e target/test-mixed/one.txt.gz.gz ] \
+    || ! cmp -s target/test-mixed/one.txt.gz target/test-mixed-one.gz; then
+    failed="$failed '-r' (status $status)"
+fi
+report_cases "Wrong for"
+rm -rf target/test-mixed target/test-mixed.err target/test-mixed-one.gz
+
+echo "Testing --exclude and --include"
+# Patterns without a / match the last part of the path, those with one the path below
+# the directory -r started from; an excluded directory is not walked at all
+for case in "--exclude *.png --exclude .git/**:.git/HEAD .git/objects/ab img/a.png top.png" \
+    "--exclude .git:.git/HEAD .git/objects/ab" \
+    "--include *.rs --include top.* --exclude *.png:.git/HEAD .git/objects/ab img/a.png img/b.txt top.png" \
+    "--exclude src/*.rs --exclude **/b.txt:img/b.txt src/m.rs" \
+    "--exclude [!a-m]*:.git/HEAD .git/objects/ab src/m.rs top.png top.txt" \
+    "--exclude ?op.???:top.png top.txt"; do
+    rm -rf target/test-exclude
+    for file in .git/HEAD .git/objects/ab img/a.png img/b.txt src/m.rs top.png top.txt; do
+        mkdir -p "$(dirname target/test-exclude/$file)"
+        echo "$file" > target/test-exclude/$file
+    done
+    set -f
+    options=()
+    for word in ${case%%:*}; do
+        options+=("$word")
+    done
+    set +f
+    ./target/debug/gzip -1 -r "${options[@]}" target/test-exclude 2> /dev/null
+    status=$?
+    untouched=$(cd target/test-exclude && find . -type f ! -name '*.gz' | sed 's|^\./||' | sort | tr '\n' ' ')
+    if [ $status -ne 0 ] || [ "$untouched" != "${case#*:} " ]; then
+        failed="$failed '${case%%:*}' ($untouched)"
+    fi
+done
+for pattern in "[a" "a\\" "[z-a]" ""; do
+    ./target/debug/gzip -1 -r --exclude "$pattern" target/test-exclude 2> target/test-exclude.err
+    if [ $? -ne 1 ] || ! grep -q "invalid --exclude pattern" target/test-exclude.err \
+        || [ -n "$(find target/test-exclude -name '*.gz.gz')" ]; then
+        failed="$failed 'invalid $pattern'"
+    fi
+done
+report_cases "Wrong for"
+rm -rf target/test-exclude target/test-exclude.err
+
+echo "Testing --totals"
+# Directories without -r and names that already have the suffix are skipped, a missing
+# file fails, and only what went through is added to the bytes
+rm -rf target/test-totals
+mkdir -p target/test-totals/dir
+echo "hello" > target/test-totals/a
+seq 1000 > target/test-totals/dir/b
+echo "x" > target/test-totals/c.gz
+./target/debug/gzip -1 --totals target/test-totals/a target/test-totals/dir target/test-totals/c.gz \
+    target/test-totals/missing 2> target/test-totals.err
+status=$?
+first=$(tail -n 1 target/test-totals.err)
+./target/debug/gzip -1 -r -q --totals target/test-totals 2> target/test-totals.err
+second=$(cat target/test-totals.err)
+size=$(($(wc -c < target/test-totals/dir/b.gz)))
+if [ $status -eq 1 ] && [ "$first" = "gzip: 1 processed, 2 skipped, 1 failed, 6 bytes in, 28 bytes out, ratio -33.3%" ] \
+    && [ "$second" = "gzip: 1 processed, 2 skipped, 0 failed, 3893 bytes in, $size bytes out, ratio 55.3%" ]; then
+    echo "Test passed."
+    ((passed++))
+else
+    echo "Test failed. Got '$first' and '$second'"
+fi
+((total++))
+rm -rf target/test-totals target/test-totals.err
+
 echo "Testing version"
 compare_gzip_outputs_no_file -L
 